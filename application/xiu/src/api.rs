@@ -2,6 +2,7 @@ use {
     anyhow::Result,
     axum::{
         extract::Query,
+        http::StatusCode,
         routing::{get, post},
         Json, Router,
     },
@@ -42,6 +43,13 @@ struct QueryStreamParams {
     uuid: Option<String>,
 }
 
+#[derive(Deserialize)]
+struct ExportClipParams {
+    identifier: StreamIdentifier,
+    // the last seconds of the stream to export
+    duration: u64,
+}
+
 #[derive(Deserialize)]
 struct RelayStreamParams {
     //guaranteed by the user to be unique
@@ -62,7 +70,8 @@ impl ApiService {
             "Usage of xiu http api:
                 ./api/query_whole_streams(get) query whole streams' information or top streams' information.
                 ./api/query_stream(post) query stream information by identifier and uuid.
-                ./api/kick_off_client(post) kick off client by publish/subscribe id.\n",
+                ./api/kick_off_client(post) kick off client by publish/subscribe id.
+                ./api/export_clip(post) export the last seconds of a stream to a flv file.\n",
         )
     }
 
@@ -155,6 +164,58 @@ impl ApiService {
         Ok(String::from("ok"))
     }
 
+    async fn export_clip(
+        &self,
+        params: ExportClipParams,
+    ) -> (StatusCode, Json<ApiResponse<Value>>) {
+        let Some(duration_ms) = params
+            .duration
+            .checked_mul(1000)
+            .filter(|duration_ms| *duration_ms > 0)
+        else {
+            let api_response = ApiResponse {
+                error_code: -1,
+                desp: String::from("the duration must be a positive number of seconds"),
+                data: Value::Null,
+            };
+            return (StatusCode::BAD_REQUEST, Json(api_response));
+        };
+
+        let (result_sender, result_receiver) = oneshot::channel();
+        let hub_event = define::StreamHubEvent::ApiExportClip {
+            identifier: params.identifier,
+            duration_ms,
+            result_sender,
+        };
+
+        if let Err(err) = self.channel_event_producer.send(hub_event) {
+            log::error!("send api export_clip event error: {}", err);
+        }
+
+        let result = match result_receiver.await {
+            Ok(val) => val.map_err(|err| err.to_string()),
+            Err(err) => Err(err.to_string()),
+        };
+        match result {
+            Ok(clip) => {
+                let api_response = ApiResponse {
+                    error_code: 0,
+                    desp: String::from("succ"),
+                    data: serde_json::json!(clip),
+                };
+                (StatusCode::OK, Json(api_response))
+            }
+            Err(err) => {
+                let api_response = ApiResponse {
+                    error_code: -1,
+                    desp: String::from("failed"),
+                    data: serde_json::json!(err),
+                };
+                (StatusCode::OK, Json(api_response))
+            }
+        }
+    }
+
     async fn start_relay_stream(&self, relay_info: RelayStreamParams) -> Json<ApiResponse<Value>> {
         if relay_info.identifier.is_none() || relay_info.server_address.is_none() {
             let api_response = ApiResponse {
@@ -289,13 +350,19 @@ pub async fn run(producer: StreamHubEventSender, port: usize) {
         api_stop_relay_stream.stop_relay_stream(params).await
     };
 
+    let api_export_clip = api.clone();
+    let export_clip = move |Json(params): Json<ExportClipParams>| async move {
+        api_export_clip.export_clip(params).await
+    };
+
     let app = Router::new()
         .route("/", get(root))
         .route("/api/query_whole_streams", get(query_streams))
         .route("/api/query_stream", post(query_stream))
         .route("/api/kick_off_client", post(kick_off))
         .route("/api/start_relay_stream", post(start_relay_stream))
-        .route("/api/stop_relay_stream", post(stop_relay_stream));
+        .route("/api/stop_relay_stream", post(stop_relay_stream))
+        .route("/api/export_clip", post(export_clip));
 
     log::info!("Http api server listening on http://0.0.0.0:{}", port);
     axum::Server::bind(&([0, 0, 0, 0], port as u16).into())
@@ -0,0 +1,173 @@
+use {
+    crate::{
+        config::{
+            AuthSecretConfig, Config, DashConfig, HlsConfig, HttpApiConfig, HttpFlvConfig,
+            RtmpConfig, RtspConfig, StreamHubConfig, WebRTCConfig,
+        },
+        service::Service,
+    },
+    commonlib::auth::TAuthenticator,
+    std::sync::Arc,
+    streamhub::{
+        notify::{http::HttpNotifier, Notifier},
+        processor::TStreamProcessorFactory,
+    },
+};
+
+//Builds a `Service` in code for the applications which embed xiu. The servers which
+//are not set are not started.
+//
+//    let handle = ServiceBuilder::new()
+//        .rtmp(RtmpConfig::new(1935))
+//        .httpflv(HttpFlvConfig::new(8080))
+//        .notifier(Arc::new(MyNotifier))
+//        .build()
+//        .run()
+//        .await?;
+//    let hub_event_sender = handle.get_hub_event_sender();
+//    ...
+//    handle.shutdown().await;
+pub struct ServiceBuilder {
+    cfg: Config,
+    notifier: Option<Arc<dyn Notifier>>,
+    authenticator: Option<Arc<dyn TAuthenticator>>,
+    processors: Vec<(Option<String>, Arc<dyn TStreamProcessorFactory>)>,
+}
+
+impl Default for ServiceBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ServiceBuilder {
+    pub fn new() -> Self {
+        Self::from_config(Config::new(0, 0, 0, 0, 0, String::from("info")))
+    }
+
+    //Starts from a loaded configuration, the http notifier is created from its
+    //httpnotify section.
+    pub fn from_config(cfg: Config) -> Self {
+        let notifier: Option<Arc<dyn Notifier>> = match &cfg.httpnotify {
+            Some(httpnotifier) if httpnotifier.enabled => Some(Arc::new(HttpNotifier::new(
+                httpnotifier.on_publish.clone(),
+                httpnotifier.on_unpublish.clone(),
+                httpnotifier.on_play.clone(),
+                httpnotifier.on_stop.clone(),
+                httpnotifier.on_clip.clone(),
+            ))),
+            _ => None,
+        };
+
+        Self {
+            cfg,
+            notifier,
+            authenticator: None,
+            processors: Vec::new(),
+        }
+    }
+
+    pub fn rtmp(mut self, rtmp: RtmpConfig) -> Self {
+        self.cfg.rtmp = Some(rtmp);
+        self
+    }
+
+    pub fn rtsp(mut self, rtsp: RtspConfig) -> Self {
+        self.cfg.rtsp = Some(rtsp);
+        self
+    }
+
+    pub fn webrtc(mut self, webrtc: WebRTCConfig) -> Self {
+        self.cfg.webrtc = Some(webrtc);
+        self
+    }
+
+    pub fn httpflv(mut self, httpflv: HttpFlvConfig) -> Self {
+        self.cfg.httpflv = Some(httpflv);
+        self
+    }
+
+    pub fn hls(mut self, hls: HlsConfig) -> Self {
+        self.cfg.hls = Some(hls);
+        self
+    }
+
+    pub fn dash(mut self, dash: DashConfig) -> Self {
+        self.cfg.dash = Some(dash);
+        self
+    }
+
+    //the http api server listens on 8000 if it is not set
+    pub fn http_api(mut self, port: usize) -> Self {
+        self.cfg.httpapi = Some(HttpApiConfig { port });
+        self
+    }
+
+    pub fn streamhub(mut self, streamhub: StreamHubConfig) -> Self {
+        self.cfg.streamhub = Some(streamhub);
+        self
+    }
+
+    //the key and passwords of the token check
+    pub fn auth_secret(mut self, authsecret: AuthSecretConfig) -> Self {
+        self.cfg.authsecret = authsecret;
+        self
+    }
+
+    //replaces the notifier created from the configuration
+    pub fn notifier(mut self, notifier: Arc<dyn Notifier>) -> Self {
+        self.notifier = Some(notifier);
+        self
+    }
+
+    //replaces the token check of all the servers
+    pub fn authenticator(mut self, authenticator: Arc<dyn TAuthenticator>) -> Self {
+        self.authenticator = Some(authenticator);
+        self
+    }
+
+    //processes the streams of an app, or of all the apps if the app name is None
+    pub fn processor(
+        mut self,
+        app_name: Option<String>,
+        factory: Arc<dyn TStreamProcessorFactory>,
+    ) -> Self {
+        self.processors.push((app_name, factory));
+        self
+    }
+
+    pub fn build(self) -> Service {
+        Service::from_parts(self.cfg, self.notifier, self.authenticator, self.processors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ServiceBuilder;
+    use streamhub::define::StreamHubEvent;
+    use tokio::sync::oneshot;
+
+    #[tokio::test]
+    async fn test_embedded_service() {
+        let handle = ServiceBuilder::new()
+            .http_api(0)
+            .build()
+            .run()
+            .await
+            .unwrap();
+
+        let (result_sender, result_receiver) = oneshot::channel();
+        let event = StreamHubEvent::ApiStatistic {
+            top_n: None,
+            identifier: None,
+            uuid: None,
+            result_sender,
+        };
+        assert!(handle.get_hub_event_sender().send(event).is_ok());
+        assert_eq!(result_receiver.await.unwrap(), serde_json::json!({}));
+
+        let hub_event_sender = handle.get_hub_event_sender();
+        handle.shutdown().await;
+        assert!(hub_event_sender.is_closed());
+    }
+}
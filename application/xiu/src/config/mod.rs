@@ -2,9 +2,11 @@ pub mod errors;
 
 use commonlib::auth::AuthAlgorithm;
 use errors::ConfigError;
+use hls::define::SegmentFormat;
 use serde_derive::Deserialize;
 use std::fs;
 use std::vec::Vec;
+use streamhub::define::PublishPolicy;
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct Config {
@@ -13,10 +15,12 @@ pub struct Config {
     pub webrtc: Option<WebRTCConfig>,
     pub httpflv: Option<HttpFlvConfig>,
     pub hls: Option<HlsConfig>,
+    pub dash: Option<DashConfig>,
     pub httpapi: Option<HttpApiConfig>,
     pub httpnotify: Option<HttpNotifierConfig>,
     pub authsecret: AuthSecretConfig,
     pub log: Option<LogConfig>,
+    pub streamhub: Option<StreamHubConfig>,
 }
 
 impl Config {
@@ -28,55 +32,11 @@ impl Config {
         hls_port: usize,
         log_level: String,
     ) -> Self {
-        let mut rtmp_config: Option<RtmpConfig> = None;
-        if rtmp_port > 0 {
-            rtmp_config = Some(RtmpConfig {
-                enabled: true,
-                gop_num: Some(1),
-                port: rtmp_port,
-                pull: None,
-                push: None,
-                auth: None,
-            });
-        }
-
-        let mut rtsp_config: Option<RtspConfig> = None;
-        if rtsp_port > 0 {
-            rtsp_config = Some(RtspConfig {
-                enabled: true,
-                relay_enabled: false,
-                port: rtsp_port,
-                auth: None,
-            });
-        }
-
-        let mut webrtc_config: Option<WebRTCConfig> = None;
-        if webrtc_port > 0 {
-            webrtc_config = Some(WebRTCConfig {
-                enabled: true,
-                port: webrtc_port,
-                auth: None,
-            });
-        }
-
-        let mut httpflv_config: Option<HttpFlvConfig> = None;
-        if httpflv_port > 0 {
-            httpflv_config = Some(HttpFlvConfig {
-                enabled: true,
-                port: httpflv_port,
-                auth: None,
-            });
-        }
-
-        let mut hls_config: Option<HlsConfig> = None;
-        if hls_port > 0 {
-            hls_config = Some(HlsConfig {
-                enabled: true,
-                port: hls_port,
-                need_record: false,
-                auth: None,
-            });
-        }
+        let rtmp_config = (rtmp_port > 0).then(|| RtmpConfig::new(rtmp_port));
+        let rtsp_config = (rtsp_port > 0).then(|| RtspConfig::new(rtsp_port));
+        let webrtc_config = (webrtc_port > 0).then(|| WebRTCConfig::new(webrtc_port));
+        let httpflv_config = (httpflv_port > 0).then(|| HttpFlvConfig::new(httpflv_port));
+        let hls_config = (hls_port > 0).then(|| HlsConfig::new(hls_port));
 
         let log_config = Some(LogConfig {
             level: log_level,
@@ -89,10 +49,12 @@ impl Config {
             webrtc: webrtc_config,
             httpflv: httpflv_config,
             hls: hls_config,
+            dash: None,
             httpapi: None,
             httpnotify: None,
             authsecret: AuthSecretConfig::default(),
             log: log_config,
+            streamhub: None,
         }
     }
 }
@@ -102,10 +64,34 @@ pub struct RtmpConfig {
     pub enabled: bool,
     pub port: usize,
     pub gop_num: Option<usize>,
+    pub gop_max_duration_ms: Option<u32>,
+    pub gop_max_bytes: Option<usize>,
+    pub low_latency_join: Option<bool>,
+    pub remux_on_demand: Option<bool>,
+    pub remux_idle_timeout_ms: Option<u64>,
     pub pull: Option<RtmpPullConfig>,
     pub push: Option<Vec<RtmpPushConfig>>,
     pub auth: Option<AuthConfig>,
 }
+
+impl RtmpConfig {
+    pub fn new(port: usize) -> Self {
+        Self {
+            enabled: true,
+            port,
+            gop_num: Some(1),
+            gop_max_duration_ms: None,
+            gop_max_bytes: None,
+            low_latency_join: None,
+            remux_on_demand: None,
+            remux_idle_timeout_ms: None,
+            pull: None,
+            push: None,
+            auth: None,
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct RtmpPullConfig {
     pub enabled: bool,
@@ -127,6 +113,17 @@ pub struct RtspConfig {
     pub relay_enabled: bool,
 }
 
+impl RtspConfig {
+    pub fn new(port: usize) -> Self {
+        Self {
+            enabled: true,
+            port,
+            auth: None,
+            relay_enabled: false,
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct WebRTCConfig {
     pub enabled: bool,
@@ -134,6 +131,16 @@ pub struct WebRTCConfig {
     pub auth: Option<AuthConfig>,
 }
 
+impl WebRTCConfig {
+    pub fn new(port: usize) -> Self {
+        Self {
+            enabled: true,
+            port,
+            auth: None,
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct HttpFlvConfig {
     pub enabled: bool,
@@ -141,15 +148,105 @@ pub struct HttpFlvConfig {
     pub auth: Option<AuthConfig>,
 }
 
+impl HttpFlvConfig {
+    pub fn new(port: usize) -> Self {
+        Self {
+            enabled: true,
+            port,
+            auth: None,
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct HlsConfig {
     pub enabled: bool,
     pub port: usize,
     //record or not
     pub need_record: bool,
+    //serve LL-HLS: partial segments, preload hints, blocking and delta playlist reloads
+    pub low_latency: Option<bool>,
+    //the target duration(ms) of the LL-HLS partial segments
+    pub part_duration_ms: Option<i64>,
+    //the container of the segments, ts or fmp4
+    pub segment_format: Option<SegmentFormat>,
+    pub auth: Option<AuthConfig>,
+}
+
+impl HlsConfig {
+    pub fn new(port: usize) -> Self {
+        Self {
+            enabled: true,
+            port,
+            need_record: false,
+            low_latency: None,
+            part_duration_ms: None,
+            segment_format: None,
+            auth: None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct DashConfig {
+    pub enabled: bool,
+    pub port: usize,
     pub auth: Option<AuthConfig>,
 }
 
+impl DashConfig {
+    pub fn new(port: usize) -> Self {
+        Self {
+            enabled: true,
+            port,
+            auth: None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct StreamHubConfig {
+    //the capacity of each subscriber's queue, counted in frames or packets
+    pub subscriber_queue_size: Option<usize>,
+    //disconnect a subscriber whose queue stays full for this duration(ms), 0 means never
+    pub subscriber_max_lag_ms: Option<u64>,
+    //what to do when a stream which is already published is published again: reject, replace or standby
+    pub publish_policy: Option<PublishPolicy>,
+    //keep the subscribers of a stream for this duration(ms) after its publisher drops, 0 means no wait
+    pub reconnect_grace_ms: Option<u64>,
+    //the FLV file which is looped to the subscribers while the publisher is gone
+    pub fallback_flv: Option<String>,
+    //a player of a stream which is not published yet waits for it for this duration(ms), 0 means no wait
+    pub subscribe_wait_ms: Option<u64>,
+    //rebase the timestamps to start from 0, keep them monotonic and remove the jumps, off by default
+    pub timestamp_normalization: Option<bool>,
+    //a timestamp jump larger than this(ms) is removed and flagged as a discontinuity, 0 means keep the jumps
+    pub max_timestamp_jump_ms: Option<u32>,
+    //keep the last seconds of each stream for the time shifted players, 0 means no time shift
+    pub dvr_window_secs: Option<u64>,
+    //write the time-shift buffer into this directory instead of holding it in memory
+    pub dvr_spill_dir: Option<String>,
+    //where the clips exported by the http api are written
+    pub clip_dir: Option<String>,
+    //the memory budget(bytes) of the gop caches of all the streams, 0 means no limit
+    pub gop_cache_budget_bytes: Option<usize>,
+    //the policies of the apps which override the defaults above
+    pub apps: Option<Vec<StreamHubAppConfig>>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct StreamHubAppConfig {
+    pub name: String,
+    pub reconnect_grace_ms: Option<u64>,
+    pub fallback_flv: Option<String>,
+    pub subscribe_wait_ms: Option<u64>,
+    pub publish_policy: Option<PublishPolicy>,
+    pub timestamp_normalization: Option<bool>,
+    pub max_timestamp_jump_ms: Option<u32>,
+    pub dvr_window_secs: Option<u64>,
+    pub dvr_spill_dir: Option<String>,
+}
+
 pub enum LogLevel {
     Info,
     Warn,
@@ -183,6 +280,7 @@ pub struct HttpNotifierConfig {
     pub on_unpublish: Option<String>,
     pub on_play: Option<String>,
     pub on_stop: Option<String>,
+    pub on_clip: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Clone, Default)]
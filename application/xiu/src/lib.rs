@@ -1,5 +1,6 @@
 extern crate rtmp;
 extern crate serde_derive;
 pub mod api;
+pub mod builder;
 pub mod config;
 pub mod service;
@@ -183,8 +183,8 @@ async fn main() -> Result<()> {
     };
 
     /*run the service*/
-    let mut service = Service::new(config);
-    service.run().await?;
+    let service = Service::new(config);
+    let service_handle = service.run().await?;
 
     // log::info!("log info...");
     // log::warn!("log warn...");
@@ -193,6 +193,7 @@ async fn main() -> Result<()> {
     // log::debug!("log debug...");
 
     signal::ctrl_c().await?;
+    service_handle.shutdown().await;
     logger.stop();
     Ok(())
 }
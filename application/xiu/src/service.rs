@@ -1,5 +1,6 @@
-use crate::config::{AuthConfig, AuthSecretConfig};
-use commonlib::auth::AuthType;
+use crate::builder::ServiceBuilder;
+use crate::config::AuthConfig;
+use commonlib::auth::{AuthType, TAuthenticator};
 use rtmp::remuxer::RtmpRemuxer;
 use std::sync::Arc;
 use xrtsp::relay::pull_client_manager::RtspPullClientManager;
@@ -10,29 +11,106 @@ use {
     //https://rustcc.cn/article?id=6dcbf032-0483-4980-8bfe-c64a7dfb33c7
     anyhow::Result,
     commonlib::auth::Auth,
-    hls::remuxer::HlsRemuxer,
+    dash::{remuxer::DashRemuxer, server as dash_server},
     hls::server as hls_server,
+    hls::{
+        ll_hls::{LivePlaylists, LowLatencyHls, DEFAULT_PART_DURATION_MS},
+        remuxer::HlsRemuxer,
+        storage::SegmentStore,
+    },
     httpflv::server as httpflv_server,
     rtmp::{
+        cache::gop::GopCachePolicy,
         relay::{pull_client::PullClient, push_client::PushClient},
         rtmp::RtmpServer,
     },
-    streamhub::{notify::http::HttpNotifier, notify::Notifier, StreamsHub},
-    tokio,
+    streamhub::{
+        define::{AppPolicy, DvrPolicy, RemuxPolicy, StreamHubEventSender, SubscriberQueuePolicy},
+        notify::Notifier,
+        processor::TStreamProcessorFactory,
+        slate::FlvSlate,
+        StreamsHub,
+    },
+    tokio::{self, task::JoinHandle},
     xrtsp::rtsp::RtspServer,
     xwebrtc::webrtc::WebRTCServer,
 };
 
 pub struct Service {
     cfg: Config,
+    notifier: Option<Arc<dyn Notifier>>,
+    //replaces the token check of all the servers if it is set
+    authenticator: Option<Arc<dyn TAuthenticator>>,
+    //the app name(None for all the apps) to the processor factory
+    processors: Vec<(Option<String>, Arc<dyn TStreamProcessorFactory>)>,
+    //the servers and the stream hub which are started
+    handles: Vec<JoinHandle<()>>,
+}
+
+//The started service, it is used to wait for the service or to shut it down.
+pub struct ServiceHandle {
+    hub_event_sender: StreamHubEventSender,
+    handles: Vec<JoinHandle<()>>,
+}
+
+impl ServiceHandle {
+    //the sender used to publish, subscribe or query the streams of the stream hub
+    pub fn get_hub_event_sender(&self) -> StreamHubEventSender {
+        self.hub_event_sender.clone()
+    }
+
+    //waits until the servers and the stream hub end
+    pub async fn wait(self) {
+        for handle in self.handles {
+            if let Err(err) = handle.await {
+                log::error!("service task err: {}", err);
+            }
+        }
+    }
+
+    //Stops the servers and the stream hub. The connected sessions end when they
+    //fail to talk to the stream hub.
+    pub async fn shutdown(self) {
+        for handle in &self.handles {
+            handle.abort();
+        }
+        for handle in self.handles {
+            if let Err(err) = handle.await {
+                if !err.is_cancelled() {
+                    log::error!("service task err: {}", err);
+                }
+            }
+        }
+        log::info!("service is shut down");
+    }
 }
 
 impl Service {
     pub fn new(cfg: Config) -> Self {
-        Service { cfg }
+        ServiceBuilder::from_config(cfg).build()
     }
 
-    fn gen_auth(auth_config: &Option<AuthConfig>, authsecret: &AuthSecretConfig) -> Option<Auth> {
+    pub(crate) fn from_parts(
+        cfg: Config,
+        notifier: Option<Arc<dyn Notifier>>,
+        authenticator: Option<Arc<dyn TAuthenticator>>,
+        processors: Vec<(Option<String>, Arc<dyn TStreamProcessorFactory>)>,
+    ) -> Self {
+        Self {
+            cfg,
+            notifier,
+            authenticator,
+            processors,
+            handles: Vec::new(),
+        }
+    }
+
+    fn gen_auth(&self, auth_config: &Option<AuthConfig>) -> Option<Auth> {
+        if let Some(authenticator) = &self.authenticator {
+            return Some(Auth::with_authenticator(authenticator.clone()));
+        }
+
+        let authsecret = &self.cfg.authsecret;
         if let Some(cfg) = auth_config {
             let auth_type = if let Some(push_enabled) = cfg.push_enabled {
                 if push_enabled && cfg.pull_enabled {
@@ -62,37 +140,129 @@ impl Service {
         }
     }
 
-    pub async fn run(&mut self) -> Result<()> {
-        let notifier: Option<Arc<dyn Notifier>> = if let Some(httpnotifier) = &self.cfg.httpnotify {
-            if !httpnotifier.enabled {
+    fn load_fallback_slate(path: &str) -> Option<Arc<FlvSlate>> {
+        match FlvSlate::load(path) {
+            Ok(slate) => Some(Arc::new(slate)),
+            Err(err) => {
+                log::error!("load the fallback flv file {} err: {}", path, err);
                 None
-            } else {
-                Some(Arc::new(HttpNotifier::new(
-                    httpnotifier.on_publish.clone(),
-                    httpnotifier.on_unpublish.clone(),
-                    httpnotifier.on_play.clone(),
-                    httpnotifier.on_stop.clone(),
-                )))
             }
-        } else {
-            None
-        };
+        }
+    }
 
-        let mut stream_hub = StreamsHub::new(notifier);
+    fn dvr_policy(window_secs: u64, spill_dir: Option<String>) -> Option<DvrPolicy> {
+        if window_secs == 0 {
+            return None;
+        }
+        if let Some(dir) = &spill_dir {
+            if let Err(err) = std::fs::create_dir_all(dir) {
+                log::error!("create the dvr spill directory {} err: {}", dir, err);
+            }
+        }
+        Some(DvrPolicy {
+            window_ms: window_secs * 1000,
+            spill_dir,
+        })
+    }
+
+    //Starts the enabled servers and the stream hub.
+    pub async fn run(mut self) -> Result<ServiceHandle> {
+        let mut stream_hub = StreamsHub::new(self.notifier.clone());
+        for (app_name, factory) in self.processors.drain(..) {
+            stream_hub.register_processor(app_name, factory);
+        }
+
+        if let Some(streamhub_cfg) = &self.cfg.streamhub {
+            let mut queue_policy = SubscriberQueuePolicy::default();
+            if let Some(queue_size) = streamhub_cfg.subscriber_queue_size {
+                queue_policy.queue_size = queue_size;
+            }
+            if let Some(max_lag_ms) = streamhub_cfg.subscriber_max_lag_ms {
+                queue_policy.max_lag_ms = max_lag_ms;
+            }
+            stream_hub.set_subscriber_queue_policy(queue_policy);
+
+            let mut default_app_policy = AppPolicy::default();
+            if let Some(reconnect_grace_ms) = streamhub_cfg.reconnect_grace_ms {
+                default_app_policy.reconnect_grace_ms = reconnect_grace_ms;
+            }
+            if let Some(fallback_flv) = &streamhub_cfg.fallback_flv {
+                default_app_policy.fallback_slate = Self::load_fallback_slate(fallback_flv);
+            }
+            if let Some(subscribe_wait_ms) = streamhub_cfg.subscribe_wait_ms {
+                default_app_policy.subscribe_wait_ms = subscribe_wait_ms;
+            }
+            if let Some(publish_policy) = &streamhub_cfg.publish_policy {
+                default_app_policy.publish_policy = publish_policy.clone();
+            }
+            if let Some(enabled) = streamhub_cfg.timestamp_normalization {
+                default_app_policy.timestamp_policy.enabled = enabled;
+            }
+            if let Some(max_jump_ms) = streamhub_cfg.max_timestamp_jump_ms {
+                default_app_policy.timestamp_policy.max_jump_ms = max_jump_ms;
+            }
+            if let Some(clip_dir) = &streamhub_cfg.clip_dir {
+                stream_hub.set_clip_dir(clip_dir.clone());
+            }
+            if let Some(gop_cache_budget_bytes) = streamhub_cfg.gop_cache_budget_bytes {
+                stream_hub.set_cache_budget(gop_cache_budget_bytes);
+            }
+            if let Some(dvr_window_secs) = streamhub_cfg.dvr_window_secs {
+                default_app_policy.dvr_policy =
+                    Self::dvr_policy(dvr_window_secs, streamhub_cfg.dvr_spill_dir.clone());
+            }
+            if let Some(apps) = &streamhub_cfg.apps {
+                for app in apps {
+                    let mut app_policy = default_app_policy.clone();
+                    if let Some(reconnect_grace_ms) = app.reconnect_grace_ms {
+                        app_policy.reconnect_grace_ms = reconnect_grace_ms;
+                    }
+                    if let Some(fallback_flv) = &app.fallback_flv {
+                        app_policy.fallback_slate = Self::load_fallback_slate(fallback_flv);
+                    }
+                    if let Some(subscribe_wait_ms) = app.subscribe_wait_ms {
+                        app_policy.subscribe_wait_ms = subscribe_wait_ms;
+                    }
+                    if let Some(publish_policy) = &app.publish_policy {
+                        app_policy.publish_policy = publish_policy.clone();
+                    }
+                    if let Some(enabled) = app.timestamp_normalization {
+                        app_policy.timestamp_policy.enabled = enabled;
+                    }
+                    if let Some(max_jump_ms) = app.max_timestamp_jump_ms {
+                        app_policy.timestamp_policy.max_jump_ms = max_jump_ms;
+                    }
+                    if let Some(dvr_window_secs) = app.dvr_window_secs {
+                        let spill_dir = app
+                            .dvr_spill_dir
+                            .clone()
+                            .or_else(|| streamhub_cfg.dvr_spill_dir.clone());
+                        app_policy.dvr_policy = Self::dvr_policy(dvr_window_secs, spill_dir);
+                    }
+                    stream_hub.set_app_policy(app.name.clone(), app_policy);
+                }
+            }
+            stream_hub.set_default_app_policy(default_app_policy);
+        }
 
         self.start_httpflv(&mut stream_hub).await?;
         self.start_hls(&mut stream_hub).await?;
+        self.start_dash(&mut stream_hub).await?;
         self.start_rtmp(&mut stream_hub).await?;
         self.start_rtsp(&mut stream_hub).await?;
         self.start_webrtc(&mut stream_hub).await?;
         self.start_http_api_server(&mut stream_hub).await?;
         self.start_rtmp_remuxer(&mut stream_hub).await?;
 
-        tokio::spawn(async move {
+        let hub_event_sender = stream_hub.get_hub_event_sender();
+        self.handles.push(tokio::spawn(async move {
             stream_hub.run().await;
             log::info!("stream hub end...");
-        });
-        Ok(())
+        }));
+        Ok(ServiceHandle {
+            hub_event_sender,
+            handles: std::mem::take(&mut self.handles),
+        })
     }
 
     async fn start_http_api_server(&mut self, stream_hub: &mut StreamsHub) -> Result<()> {
@@ -104,9 +274,9 @@ impl Service {
             8000
         };
 
-        tokio::spawn(async move {
+        self.handles.push(tokio::spawn(async move {
             api::run(producer, http_api_port).await;
-        });
+        }));
         Ok(())
     }
 
@@ -123,6 +293,13 @@ impl Service {
             } else {
                 1
             };
+            let gop_policy = GopCachePolicy {
+                gop_num,
+                max_duration_ms: rtmp_cfg_value.gop_max_duration_ms.unwrap_or(0),
+                max_bytes: rtmp_cfg_value.gop_max_bytes.unwrap_or(0),
+                low_latency_join: rtmp_cfg_value.low_latency_join.unwrap_or(false),
+                budget: Some(stream_hub.get_cache_budget()),
+            };
 
             let producer = stream_hub.get_hub_event_sender();
 
@@ -144,11 +321,11 @@ impl Service {
                         stream_hub.get_client_event_consumer(),
                         producer.clone(),
                     );
-                    tokio::spawn(async move {
+                    self.handles.push(tokio::spawn(async move {
                         if let Err(err) = push_client.run().await {
                             log::error!("push client error {}", err);
                         }
-                    });
+                    }));
 
                     stream_hub.set_rtmp_push_enabled(true);
                 }
@@ -168,11 +345,11 @@ impl Service {
                         producer.clone(),
                     );
 
-                    tokio::spawn(async move {
+                    self.handles.push(tokio::spawn(async move {
                         if let Err(err) = pull_client.run().await {
                             log::error!("pull client error {}", err);
                         }
-                    });
+                    }));
 
                     stream_hub.set_rtmp_pull_enabled(true);
                 }
@@ -181,13 +358,13 @@ impl Service {
             let listen_port = rtmp_cfg_value.port;
             let address = format!("0.0.0.0:{listen_port}");
 
-            let auth = Self::gen_auth(&rtmp_cfg_value.auth, &self.cfg.authsecret);
-            let mut rtmp_server = RtmpServer::new(address, producer, gop_num, auth);
-            tokio::spawn(async move {
+            let auth = self.gen_auth(&rtmp_cfg_value.auth);
+            let mut rtmp_server = RtmpServer::new(address, producer, gop_policy, auth);
+            self.handles.push(tokio::spawn(async move {
                 if let Err(err) = rtmp_server.run().await {
                     log::error!("rtmp server error: {}", err);
                 }
-            });
+            }));
         }
 
         Ok(())
@@ -213,26 +390,32 @@ impl Service {
             return Ok(());
         }
 
-        let mut rtmp_enabled: bool = false;
-        if let Some(rtmp_cfg_value) = &self.cfg.rtmp {
-            if rtmp_cfg_value.enabled {
-                rtmp_enabled = true;
+        let mut remux_policy = RemuxPolicy::default();
+        match &self.cfg.rtmp {
+            Some(rtmp_cfg_value) if rtmp_cfg_value.enabled => {
+                remux_policy.on_demand = rtmp_cfg_value.remux_on_demand.unwrap_or(false);
+                if let Some(idle_timeout_ms) = rtmp_cfg_value.remux_idle_timeout_ms {
+                    remux_policy.idle_timeout_ms = idle_timeout_ms;
+                }
             }
-        }
-        if !rtmp_enabled {
-            return Ok(());
+            _ => return Ok(()),
         }
 
         let event_producer = stream_hub.get_hub_event_sender();
         let broadcast_event_receiver = stream_hub.get_client_event_consumer();
-        let mut remuxer = RtmpRemuxer::new(broadcast_event_receiver, event_producer);
+        let mut remuxer = RtmpRemuxer::new(
+            broadcast_event_receiver,
+            event_producer,
+            remux_policy.on_demand,
+        );
         stream_hub.set_rtmp_remuxer_enabled(true);
+        stream_hub.set_remux_policy(remux_policy);
 
-        tokio::spawn(async move {
+        self.handles.push(tokio::spawn(async move {
             if let Err(err) = remuxer.run().await {
                 log::error!("rtmp remuxer server error: {}", err);
             }
-        });
+        }));
         Ok(())
     }
 
@@ -249,13 +432,13 @@ impl Service {
             let listen_port = rtsp_cfg_value.port;
             let address = format!("0.0.0.0:{listen_port}");
 
-            let auth = Self::gen_auth(&rtsp_cfg_value.auth, &self.cfg.authsecret);
+            let auth = self.gen_auth(&rtsp_cfg_value.auth);
             let mut rtsp_server = RtspServer::new(address, producer, auth);
-            tokio::spawn(async move {
+            self.handles.push(tokio::spawn(async move {
                 if let Err(err) = rtsp_server.run().await {
                     log::error!("rtsp server error: {}", err);
                 }
-            });
+            }));
 
             if rtsp_cfg_value.relay_enabled {
                 let mut rtsp_relay_manager = RtspPullClientManager::new(
@@ -263,11 +446,11 @@ impl Service {
                     stream_hub.get_hub_event_sender(),
                 );
 
-                tokio::spawn(async move {
+                self.handles.push(tokio::spawn(async move {
                     if let Err(err) = rtsp_relay_manager.run().await {
                         log::error!("rtsp relay manager error: {}", err);
                     }
-                });
+                }));
             }
         }
 
@@ -287,13 +470,13 @@ impl Service {
             let listen_port = webrtc_cfg_value.port;
             let address = format!("0.0.0.0:{listen_port}");
 
-            let auth = Self::gen_auth(&webrtc_cfg_value.auth, &self.cfg.authsecret);
+            let auth = self.gen_auth(&webrtc_cfg_value.auth);
             let mut webrtc_server = WebRTCServer::new(address, producer, auth);
-            tokio::spawn(async move {
+            self.handles.push(tokio::spawn(async move {
                 if let Err(err) = webrtc_server.run().await {
                     log::error!("webrtc server error: {}", err);
                 }
-            });
+            }));
         }
 
         Ok(())
@@ -308,13 +491,16 @@ impl Service {
             }
             let port = httpflv_cfg_value.port;
             let event_producer = stream_hub.get_hub_event_sender();
+            let queue_policy = stream_hub.get_subscriber_queue_policy();
 
-            let auth = Self::gen_auth(&httpflv_cfg_value.auth, &self.cfg.authsecret);
-            tokio::spawn(async move {
-                if let Err(err) = httpflv_server::run(event_producer, port, auth).await {
+            let auth = self.gen_auth(&httpflv_cfg_value.auth);
+            self.handles.push(tokio::spawn(async move {
+                if let Err(err) =
+                    httpflv_server::run(event_producer, port, auth, queue_policy).await
+                {
                     log::error!("httpflv server error: {}", err);
                 }
-            });
+            }));
         }
 
         Ok(())
@@ -328,30 +514,75 @@ impl Service {
                 return Ok(());
             }
 
+            let playlists = LivePlaylists::default();
+            let store = SegmentStore::default();
+            let low_latency = hls_cfg_value.low_latency.unwrap_or(false).then(|| {
+                LowLatencyHls::new(
+                    hls_cfg_value
+                        .part_duration_ms
+                        .unwrap_or(DEFAULT_PART_DURATION_MS),
+                    playlists.clone(),
+                )
+            });
+
             let event_producer = stream_hub.get_hub_event_sender();
             let cient_event_consumer = stream_hub.get_client_event_consumer();
             let mut hls_remuxer = HlsRemuxer::new(
                 cient_event_consumer,
                 event_producer,
                 hls_cfg_value.need_record,
+                hls_cfg_value.segment_format.unwrap_or_default(),
+                low_latency,
+                store.clone(),
             );
 
-            tokio::spawn(async move {
+            self.handles.push(tokio::spawn(async move {
                 if let Err(err) = hls_remuxer.run().await {
                     log::error!("rtmp event processor error: {}", err);
                 }
-            });
+            }));
 
             let port = hls_cfg_value.port;
-            let auth = Self::gen_auth(&hls_cfg_value.auth, &self.cfg.authsecret);
-            tokio::spawn(async move {
-                if let Err(err) = hls_server::run(port, auth).await {
+            let auth = self.gen_auth(&hls_cfg_value.auth);
+            self.handles.push(tokio::spawn(async move {
+                if let Err(err) = hls_server::run(port, auth, playlists, store).await {
                     log::error!("hls server error: {}", err);
                 }
-            });
+            }));
             stream_hub.set_hls_enabled(true);
         }
 
         Ok(())
     }
+
+    async fn start_dash(&mut self, stream_hub: &mut StreamsHub) -> Result<()> {
+        let dash_cfg = &self.cfg.dash;
+
+        if let Some(dash_cfg_value) = dash_cfg {
+            if !dash_cfg_value.enabled {
+                return Ok(());
+            }
+
+            let event_producer = stream_hub.get_hub_event_sender();
+            let cient_event_consumer = stream_hub.get_client_event_consumer();
+            let mut dash_remuxer = DashRemuxer::new(cient_event_consumer, event_producer);
+
+            self.handles.push(tokio::spawn(async move {
+                if let Err(err) = dash_remuxer.run().await {
+                    log::error!("dash remuxer error: {}", err);
+                }
+            }));
+
+            let port = dash_cfg_value.port;
+            let auth = self.gen_auth(&dash_cfg_value.auth);
+            self.handles.push(tokio::spawn(async move {
+                if let Err(err) = dash_server::run(port, auth).await {
+                    log::error!("dash server error: {}", err);
+                }
+            }));
+            stream_hub.set_dash_enabled(true);
+        }
+
+        Ok(())
+    }
 }
@@ -1,6 +1,8 @@
 use indexmap::IndexMap;
 use md5;
 use serde_derive::Deserialize;
+use std::fmt;
+use std::sync::Arc;
 
 use crate::errors::{AuthError, AuthErrorValue};
 use crate::scanf;
@@ -70,6 +72,23 @@ pub fn get_secret(carrier: &SecretCarrier) -> Result<String, AuthError> {
     }
 }
 
+//A custom authentication of the publishers and players which replaces the token
+//check, e.g. asking an external service. It is called for every publish and play.
+pub trait TAuthenticator: Send + Sync {
+    fn authenticate(
+        &self,
+        stream_name: &str,
+        secret: &Option<SecretCarrier>,
+        is_pull: bool,
+    ) -> Result<(), AuthError>;
+}
+
+impl fmt::Debug for dyn TAuthenticator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "TAuthenticator")
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum AuthType {
     Pull,
@@ -84,6 +103,7 @@ pub struct Auth {
     password: String,
     push_password: Option<String>,
     pub auth_type: AuthType,
+    authenticator: Option<Arc<dyn TAuthenticator>>,
 }
 
 impl Auth {
@@ -100,6 +120,18 @@ impl Auth {
             password,
             push_password,
             auth_type,
+            authenticator: None,
+        }
+    }
+
+    pub fn with_authenticator(authenticator: Arc<dyn TAuthenticator>) -> Self {
+        Self {
+            algorithm: AuthAlgorithm::default(),
+            key: String::new(),
+            password: String::new(),
+            push_password: None,
+            auth_type: AuthType::Both,
+            authenticator: Some(authenticator),
         }
     }
 
@@ -109,6 +141,10 @@ impl Auth {
         secret: &Option<SecretCarrier>,
         is_pull: bool,
     ) -> Result<(), AuthError> {
+        if let Some(authenticator) = &self.authenticator {
+            return authenticator.authenticate(stream_name, secret, is_pull);
+        }
+
         if self.auth_type == AuthType::Both
             || is_pull && (self.auth_type == AuthType::Pull)
             || !is_pull && (self.auth_type == AuthType::Push)
@@ -13,7 +13,9 @@ pub enum AuthErrorValue {
     #[fail(display = "no token found.")]
     NoTokenFound,
     #[fail(display = "invalid token format.")]
-    InvalidTokenFormat
+    InvalidTokenFormat,
+    #[fail(display = "rejected: {}", _0)]
+    Rejected(String),
 }
 
 impl fmt::Display for AuthError {
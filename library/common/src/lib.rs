@@ -2,4 +2,5 @@ pub mod auth;
 pub mod define;
 pub mod errors;
 pub mod http;
+pub mod media;
 pub mod utils;
@@ -0,0 +1,61 @@
+use bytes::Bytes;
+
+//The codec neutral media model which is shared by the protocols and containers,
+//a producer describes its frames with it instead of encoding them into a
+//container format such as FLV.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MediaCodec {
+    H264,
+    H265,
+    Aac,
+    Opus,
+    G711A,
+    G711U,
+}
+
+impl MediaCodec {
+    pub fn is_video(&self) -> bool {
+        matches!(self, MediaCodec::H264 | MediaCodec::H265)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PayloadFormat {
+    //video NAL units separated by start codes
+    AnnexB,
+    //video NAL units prefixed with their 4 bytes big endian lengths
+    LengthPrefixed,
+    //one audio access unit without any framing(no ADTS header for AAC)
+    Raw,
+}
+
+//One video or audio frame, the timestamps are in milliseconds.
+#[derive(Debug, Clone)]
+pub struct MediaFrame {
+    pub codec: MediaCodec,
+    pub pts: u32,
+    pub dts: u32,
+    pub key_frame: bool,
+    pub format: PayloadFormat,
+    pub data: Bytes,
+}
+
+impl MediaFrame {
+    //the composition time offset
+    pub fn cts(&self) -> i32 {
+        self.pts.wrapping_sub(self.dts) as i32
+    }
+}
+
+//The out of band codec configuration which a decoder needs before the frames:
+//  H264: AVCDecoderConfigurationRecord
+//  H265: HEVCDecoderConfigurationRecord
+//  AAC: AudioSpecificConfig
+//  Opus: OpusHead
+#[derive(Debug, Clone)]
+pub struct CodecConfig {
+    pub codec: MediaCodec,
+    pub timestamp: u32,
+    pub data: Bytes,
+}
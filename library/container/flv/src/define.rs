@@ -3,6 +3,8 @@ use serde::Serialize;
 
 #[derive(Debug, Clone, Serialize, Default)]
 pub enum SoundFormat {
+    G711A = 7,
+    G711U = 8,
     #[default]
     AAC = 10,
     OPUS = 13,
@@ -59,6 +61,16 @@ pub mod h264_nal_type {
     pub const H264_NAL_PPS: u8 = 8;
     pub const H264_NAL_AUD: u8 = 9;
 }
+
+pub mod hevc_nal_type {
+    //the IRAP(BLA/IDR/CRA) nal unit types are in [16, 23]
+    pub const HEVC_NAL_BLA_W_LP: u8 = 16;
+    pub const HEVC_NAL_RSV_IRAP_23: u8 = 23;
+    pub const HEVC_NAL_VPS: u8 = 32;
+    pub const HEVC_NAL_SPS: u8 = 33;
+    pub const HEVC_NAL_PPS: u8 = 34;
+    pub const HEVC_NAL_AUD: u8 = 35;
+}
 #[derive(Debug, Clone, Serialize, Default)]
 pub enum AacProfile {
     // @see @see ISO_IEC_14496-3-AAC-2001.pdf, page 23
@@ -7,8 +7,9 @@ use {
     super::{
         define::{aac_packet_type, avc_packet_type, tag_type, AvcCodecId, FlvData, SoundFormat},
         errors::FlvDemuxerError,
-        mpeg4_aac::Mpeg4AacProcessor,
+        mpeg4_aac::{Mpeg4Aac, Mpeg4AacProcessor},
         mpeg4_avc::Mpeg4AvcProcessor,
+        mpeg4_hevc::Mpeg4HevcProcessor,
     },
     byteorder::BigEndian,
     bytes::BytesMut,
@@ -114,66 +115,144 @@ impl FlvDemuxerVideoData {
 #[derive(Default)]
 pub struct FlvVideoTagDemuxer {
     avc_processor: Mpeg4AvcProcessor,
+    hevc_processor: Mpeg4HevcProcessor,
+    //the codec of the last sequence header
+    codec_id: u8,
+    //the AVCDecoderConfigurationRecord/HEVCDecoderConfigurationRecord of the last
+    //sequence header
+    decoder_configuration_record: BytesMut,
+    //keep the NALUs length prefixed(as ISO-BMFF stores them) instead of Annex-B
+    length_prefixed: bool,
 }
 
 impl FlvVideoTagDemuxer {
     pub fn new() -> Self {
         Self {
             avc_processor: Mpeg4AvcProcessor::new(),
+            hevc_processor: Mpeg4HevcProcessor::new(),
+            codec_id: AvcCodecId::UNKNOWN as u8,
+            decoder_configuration_record: BytesMut::new(),
+            length_prefixed: false,
         }
     }
+
+    //The demuxed frames keep the length prefixed NALUs of the FLV tags.
+    pub fn new_length_prefixed() -> Self {
+        Self {
+            length_prefixed: true,
+            ..Self::new()
+        }
+    }
+
+    pub fn decoder_configuration_record(&self) -> &BytesMut {
+        &self.decoder_configuration_record
+    }
+
+    //the width and height parsed from the SPS of the last sequence header
+    pub fn resolution(&self) -> (u32, u32) {
+        if self.codec_id == AvcCodecId::HEVC as u8 {
+            let hevc = &self.hevc_processor.mpeg4_hevc;
+            (hevc.width, hevc.height)
+        } else {
+            let avc = &self.avc_processor.mpeg4_avc;
+            (avc.width, avc.height)
+        }
+    }
+
+    //the video codec told by the sequence header, UNKNOWN before it is received
+    pub fn codec_id(&self) -> u8 {
+        self.codec_id
+    }
+
     pub fn demux(
         &mut self,
-        timestamp: u32,
+        timestamp: i64,
         data: BytesMut,
     ) -> Result<Option<FlvDemuxerVideoData>, FlvDemuxerError> {
         let mut reader = BytesReader::new(data);
 
         let tag_header = VideoTagHeader::unmarshal(&mut reader)?;
-        if tag_header.codec_id == AvcCodecId::H264 as u8 {
-            match tag_header.avc_packet_type {
-                avc_packet_type::AVC_SEQHDR => {
+        if tag_header.codec_id != AvcCodecId::H264 as u8
+            && tag_header.codec_id != AvcCodecId::HEVC as u8
+        {
+            return Ok(None);
+        }
+
+        match tag_header.avc_packet_type {
+            avc_packet_type::AVC_SEQHDR => {
+                self.decoder_configuration_record = reader.get_remaining_bytes();
+                if tag_header.codec_id == AvcCodecId::H264 as u8 {
                     self.avc_processor
                         .decoder_configuration_record_load(&mut reader)?;
-
-                    return Ok(None);
-                }
-                avc_packet_type::AVC_NALU => {
-                    let data = self.avc_processor.h264_mp4toannexb(&mut reader)?;
-
-                    let video_data = FlvDemuxerVideoData {
-                        codec_id: AvcCodecId::H264 as u8,
-                        pts: timestamp as i64 + tag_header.composition_time as i64,
-                        dts: timestamp as i64,
-                        frame_type: tag_header.frame_type,
-                        data,
-                    };
-                    //print!("flv demux video payload length {}\n", video_data.data.len());
-                    return Ok(Some(video_data));
+                } else {
+                    self.hevc_processor
+                        .decoder_configuration_record_load(&mut reader)?;
                 }
-                _ => {}
+                self.codec_id = tag_header.codec_id;
+
+                Ok(None)
+            }
+            avc_packet_type::AVC_NALU => {
+                let data = if self.length_prefixed {
+                    reader.extract_remaining_bytes()
+                } else if tag_header.codec_id == AvcCodecId::H264 as u8 {
+                    self.avc_processor.h264_mp4toannexb(&mut reader)?
+                } else {
+                    self.hevc_processor.h265_mp4toannexb(&mut reader)?
+                };
+
+                let video_data = FlvDemuxerVideoData {
+                    codec_id: tag_header.codec_id,
+                    pts: timestamp + tag_header.composition_time as i64,
+                    dts: timestamp,
+                    frame_type: tag_header.frame_type,
+                    data,
+                };
+                //print!("flv demux video payload length {}\n", video_data.data.len());
+                Ok(Some(video_data))
             }
+            _ => Ok(None),
         }
-
-        Ok(None)
     }
 }
 
 #[derive(Default)]
 pub struct FlvAudioTagDemuxer {
     aac_processor: Mpeg4AacProcessor,
+    //the AudioSpecificConfig of the last sequence header
+    audio_specific_config: BytesMut,
+    //keep the raw AAC frames(as ISO-BMFF stores them) instead of adding the ADTS headers
+    raw: bool,
 }
 
 impl FlvAudioTagDemuxer {
     pub fn new() -> Self {
         Self {
             aac_processor: Mpeg4AacProcessor::new(),
+            audio_specific_config: BytesMut::new(),
+            raw: false,
         }
     }
 
+    //The demuxed frames are the raw AAC frames of the FLV tags.
+    pub fn new_raw() -> Self {
+        Self {
+            raw: true,
+            ..Self::new()
+        }
+    }
+
+    pub fn audio_specific_config(&self) -> &BytesMut {
+        &self.audio_specific_config
+    }
+
+    pub fn mpeg4_aac(&self) -> &Mpeg4Aac {
+        &self.aac_processor.mpeg4_aac
+    }
+
     pub fn demux(
         &mut self,
-        timestamp: u32,
+        timestamp: i64,
         data: BytesMut,
     ) -> Result<FlvDemuxerAudioData, FlvDemuxerError> {
         let mut reader = BytesReader::new(data);
@@ -186,20 +265,27 @@ impl FlvAudioTagDemuxer {
             match tag_header.aac_packet_type {
                 aac_packet_type::AAC_SEQHDR => {
                     if self.aac_processor.bytes_reader.len() >= 2 {
+                        self.audio_specific_config =
+                            self.aac_processor.bytes_reader.get_remaining_bytes();
                         self.aac_processor.audio_specific_config_load()?;
                     }
 
                     return Ok(FlvDemuxerAudioData::new());
                 }
                 aac_packet_type::AAC_RAW => {
-                    self.aac_processor.adts_save()?;
+                    let data = if self.raw {
+                        self.aac_processor.bytes_reader.extract_remaining_bytes()
+                    } else {
+                        self.aac_processor.adts_save()?;
+                        self.aac_processor.bytes_writer.extract_current_bytes()
+                    };
 
                     let audio_data = FlvDemuxerAudioData {
                         has_data: true,
                         sound_format: tag_header.sound_format,
-                        pts: timestamp as i64,
-                        dts: timestamp as i64,
-                        data: self.aac_processor.bytes_writer.extract_current_bytes(),
+                        pts: timestamp,
+                        dts: timestamp,
+                        data,
                     };
                     //print!("flv demux audio payload length {}\n", audio_data.data.len());
                     return Ok(audio_data);
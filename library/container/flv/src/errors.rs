@@ -195,6 +195,14 @@ impl From<H264Error> for Mpeg4AvcHevcError {
     }
 }
 
+impl From<BitError> for Mpeg4AvcHevcError {
+    fn from(error: BitError) -> Self {
+        Mpeg4AvcHevcError {
+            value: MpegErrorValue::BitError(error),
+        }
+    }
+}
+
 impl fmt::Display for Mpeg4AvcHevcError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         fmt::Display::fmt(&self.value, f)
@@ -265,3 +273,32 @@ pub enum BitVecErrorValue {
 pub struct BitVecError {
     pub value: BitVecErrorValue,
 }
+
+#[derive(Debug, Fail)]
+pub enum MediaAdapterErrorValue {
+    #[fail(display = "the codec is not supported by flv")]
+    UnsupportedCodec,
+    #[fail(display = "the payload is not correct")]
+    InvalidPayload,
+}
+
+#[derive(Debug)]
+pub struct MediaAdapterError {
+    pub value: MediaAdapterErrorValue,
+}
+
+impl fmt::Display for MediaAdapterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.value, f)
+    }
+}
+
+impl Fail for MediaAdapterError {
+    fn cause(&self) -> Option<&dyn Fail> {
+        self.value.cause()
+    }
+
+    fn backtrace(&self) -> Option<&Backtrace> {
+        self.value.backtrace()
+    }
+}
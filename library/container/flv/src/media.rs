@@ -0,0 +1,353 @@
+use {
+    super::{
+        define::{aac_packet_type, avc_packet_type, frame_type, AvcCodecId, SoundFormat},
+        errors::{MediaAdapterError, MediaAdapterErrorValue},
+    },
+    bytes::{BufMut, Bytes, BytesMut},
+    commonlib::media::{CodecConfig, MediaCodec, MediaFrame, PayloadFormat},
+};
+
+/*
+ The adapters between the FLV video/audio tag bodies and the codec neutral media
+ frames, so a protocol which does not speak FLV needs not to build or parse them.
+
+ video tag body: FrameType(4 bits) | CodecID(4 bits) | PacketType(1 byte) | CompositionTime(3 bytes) | Data
+ audio tag body: SoundFormat(4 bits) | SoundRate(2 bits) | SoundSize(1 bit) | SoundType(1 bit) | [AACPacketType(1 byte)] | Data
+*/
+
+pub enum FlvMediaData {
+    Frame(MediaFrame),
+    Config(CodecConfig),
+}
+
+const ANNEXB_START_CODE: [u8; 4] = [0x00, 0x00, 0x00, 0x01];
+
+//Converts the FLV tag bodies to the codec neutral frames, the video NAL units are
+//converted to Annex-B and the AAC frames are raw access units.
+pub struct FlvToMedia {
+    //the size of the NAL unit length fields, read from the decoder configuration record
+    nalu_length_size: usize,
+}
+
+impl Default for FlvToMedia {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FlvToMedia {
+    pub fn new() -> Self {
+        Self {
+            nalu_length_size: 4,
+        }
+    }
+
+    pub fn video(
+        &mut self,
+        timestamp: u32,
+        body: &Bytes,
+    ) -> Result<Option<FlvMediaData>, MediaAdapterError> {
+        if body.len() < 5 {
+            return Ok(None);
+        }
+
+        let codec_id = body[0] & 0x0F;
+        let codec = if codec_id == AvcCodecId::H264 as u8 {
+            MediaCodec::H264
+        } else if codec_id == AvcCodecId::HEVC as u8 {
+            MediaCodec::H265
+        } else {
+            return Err(MediaAdapterError {
+                value: MediaAdapterErrorValue::UnsupportedCodec,
+            });
+        };
+        //a signed 24 bits integer
+        let composition_time =
+            (((body[2] as u32) << 24 | (body[3] as u32) << 16 | (body[4] as u32) << 8) as i32) >> 8;
+        let payload = body.slice(5..);
+
+        match body[1] {
+            avc_packet_type::AVC_SEQHDR => {
+                //lengthSizeMinusOne is in the 5th byte of an AVC record and the 22th byte of a HEVC record.
+                let offset = if codec == MediaCodec::H264 { 4 } else { 21 };
+                if let Some(length_size_minus_one) = payload.get(offset) {
+                    self.nalu_length_size = (length_size_minus_one & 0x03) as usize + 1;
+                }
+                Ok(Some(FlvMediaData::Config(CodecConfig {
+                    codec,
+                    timestamp,
+                    data: payload,
+                })))
+            }
+            avc_packet_type::AVC_NALU => Ok(Some(FlvMediaData::Frame(MediaFrame {
+                codec,
+                pts: timestamp.wrapping_add(composition_time as u32),
+                dts: timestamp,
+                key_frame: (body[0] >> 4) == frame_type::KEY_FRAME,
+                format: PayloadFormat::AnnexB,
+                data: length_prefixed_to_annexb(&payload, self.nalu_length_size)?,
+            }))),
+            _ => Ok(None),
+        }
+    }
+
+    pub fn audio(
+        &mut self,
+        timestamp: u32,
+        body: &Bytes,
+    ) -> Result<Option<FlvMediaData>, MediaAdapterError> {
+        if body.is_empty() {
+            return Ok(None);
+        }
+
+        let sound_format = body[0] >> 4;
+        if sound_format == SoundFormat::AAC as u8 {
+            if body.len() < 2 {
+                return Ok(None);
+            }
+            let payload = body.slice(2..);
+            let data = match body[1] {
+                aac_packet_type::AAC_SEQHDR => FlvMediaData::Config(CodecConfig {
+                    codec: MediaCodec::Aac,
+                    timestamp,
+                    data: payload,
+                }),
+                _ => FlvMediaData::Frame(raw_audio_frame(MediaCodec::Aac, timestamp, payload)),
+            };
+            return Ok(Some(data));
+        }
+
+        let codec = if sound_format == SoundFormat::G711A as u8 {
+            MediaCodec::G711A
+        } else if sound_format == SoundFormat::G711U as u8 {
+            MediaCodec::G711U
+        } else {
+            return Err(MediaAdapterError {
+                value: MediaAdapterErrorValue::UnsupportedCodec,
+            });
+        };
+        Ok(Some(FlvMediaData::Frame(raw_audio_frame(
+            codec,
+            timestamp,
+            body.slice(1..),
+        ))))
+    }
+}
+
+fn raw_audio_frame(codec: MediaCodec, timestamp: u32, data: Bytes) -> MediaFrame {
+    MediaFrame {
+        codec,
+        pts: timestamp,
+        dts: timestamp,
+        key_frame: true,
+        format: PayloadFormat::Raw,
+        data,
+    }
+}
+
+//Builds the FLV tag body of a frame, the video frames are written with 4 bytes NAL unit lengths.
+pub fn media_frame_to_flv(frame: &MediaFrame) -> Result<Bytes, MediaAdapterError> {
+    let mut body = BytesMut::new();
+
+    match frame.codec {
+        MediaCodec::H264 | MediaCodec::H265 => {
+            let frame_type = if frame.key_frame {
+                frame_type::KEY_FRAME
+            } else {
+                frame_type::INTER_FRAME
+            };
+            body.put_u8(frame_type << 4 | video_codec_id(frame.codec));
+            body.put_u8(avc_packet_type::AVC_NALU);
+            body.put_uint((frame.cts() as u32 & 0xFFFFFF) as u64, 3);
+
+            match frame.format {
+                PayloadFormat::AnnexB => {
+                    for nalu in split_annexb(&frame.data) {
+                        body.put_u32(nalu.len() as u32);
+                        body.put_slice(nalu);
+                    }
+                }
+                PayloadFormat::LengthPrefixed => body.put_slice(&frame.data),
+                PayloadFormat::Raw => {
+                    return Err(MediaAdapterError {
+                        value: MediaAdapterErrorValue::InvalidPayload,
+                    })
+                }
+            }
+        }
+        MediaCodec::Aac => {
+            body.put_u8(aac_sound_flags());
+            body.put_u8(aac_packet_type::AAC_RAW);
+            body.put_slice(&frame.data);
+        }
+        MediaCodec::G711A | MediaCodec::G711U => {
+            let sound_format = if frame.codec == MediaCodec::G711A {
+                SoundFormat::G711A
+            } else {
+                SoundFormat::G711U
+            };
+            //8kHz is not representable by the sound rate bits, they are ignored for G.711.
+            body.put_u8((sound_format as u8) << 4 | 0x02);
+            body.put_slice(&frame.data);
+        }
+        MediaCodec::Opus => {
+            return Err(MediaAdapterError {
+                value: MediaAdapterErrorValue::UnsupportedCodec,
+            })
+        }
+    }
+
+    Ok(body.freeze())
+}
+
+//Builds the FLV sequence header tag body of a codec configuration.
+pub fn codec_config_to_flv(config: &CodecConfig) -> Result<Bytes, MediaAdapterError> {
+    let mut body = BytesMut::new();
+
+    match config.codec {
+        MediaCodec::H264 | MediaCodec::H265 => {
+            body.put_u8(frame_type::KEY_FRAME << 4 | video_codec_id(config.codec));
+            body.put_u8(avc_packet_type::AVC_SEQHDR);
+            body.put_uint(0, 3);
+        }
+        MediaCodec::Aac => {
+            body.put_u8(aac_sound_flags());
+            body.put_u8(aac_packet_type::AAC_SEQHDR);
+        }
+        MediaCodec::Opus | MediaCodec::G711A | MediaCodec::G711U => {
+            return Err(MediaAdapterError {
+                value: MediaAdapterErrorValue::UnsupportedCodec,
+            })
+        }
+    }
+    body.put_slice(&config.data);
+
+    Ok(body.freeze())
+}
+
+fn video_codec_id(codec: MediaCodec) -> u8 {
+    if codec == MediaCodec::H265 {
+        AvcCodecId::HEVC as u8
+    } else {
+        AvcCodecId::H264 as u8
+    }
+}
+
+//AAC, 44kHz, 16 bits, stereo: the decoders read the real values from the AudioSpecificConfig.
+fn aac_sound_flags() -> u8 {
+    (SoundFormat::AAC as u8) << 4 | 0x0F
+}
+
+pub fn length_prefixed_to_annexb(
+    data: &[u8],
+    nalu_length_size: usize,
+) -> Result<Bytes, MediaAdapterError> {
+    let mut annexb = BytesMut::with_capacity(data.len() + 16);
+    let mut offset = 0;
+
+    while offset + nalu_length_size <= data.len() {
+        let nalu_length = data[offset..offset + nalu_length_size]
+            .iter()
+            .fold(0_usize, |length, byte| length << 8 | *byte as usize);
+        offset += nalu_length_size;
+
+        if offset + nalu_length > data.len() {
+            return Err(MediaAdapterError {
+                value: MediaAdapterErrorValue::InvalidPayload,
+            });
+        }
+        annexb.put_slice(&ANNEXB_START_CODE);
+        annexb.put_slice(&data[offset..offset + nalu_length]);
+        offset += nalu_length;
+    }
+
+    Ok(annexb.freeze())
+}
+
+//Splits the NAL units separated by 3 or 4 bytes start codes, data without any
+//start code is taken as one NAL unit.
+pub fn split_annexb(data: &[u8]) -> Vec<&[u8]> {
+    let mut nalus = Vec::new();
+    let mut nalu_start: Option<usize> = None;
+    let mut index = 0;
+
+    while index + 3 <= data.len() {
+        if data[index] == 0 && data[index + 1] == 0 && data[index + 2] == 1 {
+            if let Some(start) = nalu_start {
+                //the zero bytes before a start code are not a part of the NAL unit.
+                let mut end = index;
+                while end > start && data[end - 1] == 0 {
+                    end -= 1;
+                }
+                nalus.push(&data[start..end]);
+            }
+            index += 3;
+            nalu_start = Some(index);
+        } else {
+            index += 1;
+        }
+    }
+
+    match nalu_start {
+        Some(start) => nalus.push(&data[start..]),
+        None => nalus.push(data),
+    }
+    nalus.retain(|nalu| !nalu.is_empty());
+    nalus
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{codec_config_to_flv, media_frame_to_flv, FlvMediaData, FlvToMedia};
+    use bytes::Bytes;
+    use commonlib::media::{CodecConfig, MediaCodec, MediaFrame, PayloadFormat};
+
+    #[test]
+    fn test_video_round_trip() {
+        let frame = MediaFrame {
+            codec: MediaCodec::H264,
+            pts: 1080,
+            dts: 1000,
+            key_frame: true,
+            format: PayloadFormat::AnnexB,
+            data: Bytes::from_static(&[0, 0, 0, 1, 0x65, 0xAA, 0, 0, 1, 0x06, 0xBB, 0xCC]),
+        };
+        let body = media_frame_to_flv(&frame).unwrap();
+        assert_eq!(
+            &body[..],
+            &[0x17, 1, 0, 0, 80, 0, 0, 0, 2, 0x65, 0xAA, 0, 0, 0, 3, 0x06, 0xBB, 0xCC]
+        );
+
+        match FlvToMedia::new().video(1000, &body).unwrap() {
+            Some(FlvMediaData::Frame(demuxed)) => {
+                assert_eq!(demuxed.codec, MediaCodec::H264);
+                assert_eq!((demuxed.pts, demuxed.dts), (1080, 1000));
+                assert!(demuxed.key_frame);
+                assert_eq!(
+                    &demuxed.data[..],
+                    &[0, 0, 0, 1, 0x65, 0xAA, 0, 0, 0, 1, 0x06, 0xBB, 0xCC]
+                );
+            }
+            _ => panic!("not a video frame"),
+        }
+    }
+
+    #[test]
+    fn test_audio_round_trip() {
+        let config = CodecConfig {
+            codec: MediaCodec::Aac,
+            timestamp: 0,
+            data: Bytes::from_static(&[0x12, 0x10]),
+        };
+        let body = codec_config_to_flv(&config).unwrap();
+        assert_eq!(&body[..], &[0xAF, 0x00, 0x12, 0x10]);
+
+        match FlvToMedia::new().audio(0, &body).unwrap() {
+            Some(FlvMediaData::Config(demuxed)) => {
+                assert_eq!(demuxed.codec, MediaCodec::Aac);
+                assert_eq!(&demuxed.data[..], &[0x12, 0x10]);
+            }
+            _ => panic!("not an audio config"),
+        }
+    }
+}
@@ -1,4 +1,13 @@
-use {super::errors::Mpeg4AvcHevcError, byteorder::BigEndian, bytesio::bytes_reader::BytesReader};
+use {
+    super::{define::hevc_nal_type, errors::Mpeg4AvcHevcError},
+    byteorder::BigEndian,
+    bytes::BytesMut,
+    bytesio::{bits_reader::BitsReader, bytes_reader::BytesReader, bytes_writer::BytesWriter},
+    h264_decoder::utils::read_uev,
+};
+
+const H265_START_CODE: [u8; 4] = [0x00, 0x00, 0x00, 0x01];
+
 #[allow(dead_code)]
 #[derive(Default)]
 
@@ -20,6 +29,11 @@ pub struct Mpeg4Hevc {
     num_temporal_layers: u8,   // 3bit,[0,7]
     temporal_id_nested: u8,    // 1bit,[0,1]
     length_size_minus_one: u8, // 2bit,[0,3]
+
+    pub width: u32,
+    pub height: u32,
+
+    pub vps_sps_pps_annexb_data: BytesWriter, // pice together all the vps/sps/pps data
 }
 
 #[derive(Default)]
@@ -28,6 +42,10 @@ pub struct Mpeg4HevcProcessor {
 }
 
 impl Mpeg4HevcProcessor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
     pub fn decoder_configuration_record_load(
         &mut self,
         bytes_reader: &mut BytesReader,
@@ -47,7 +65,230 @@ impl Mpeg4HevcProcessor {
         self.mpeg4_hevc.parallelism_type = bytes_reader.read_u8()? & 0x03;
         self.mpeg4_hevc.chroma_format = bytes_reader.read_u8()? & 0x03;
         self.mpeg4_hevc.bit_depth_luma_minus8 = bytes_reader.read_u8()? & 0x07;
+        self.mpeg4_hevc.bit_depth_chroma_minus8 = bytes_reader.read_u8()? & 0x07;
+        self.mpeg4_hevc.avg_frame_rate = bytes_reader.read_u16::<BigEndian>()?;
+
+        let byte_21 = bytes_reader.read_u8()?;
+        self.mpeg4_hevc.constant_frame_rate = (byte_21 >> 6) & 0x03;
+        self.mpeg4_hevc.num_temporal_layers = (byte_21 >> 3) & 0x07;
+        self.mpeg4_hevc.temporal_id_nested = (byte_21 >> 2) & 0x01;
+        self.mpeg4_hevc.length_size_minus_one = byte_21 & 0x03;
+
+        /*the arrays of the VPS/SPS/PPS/SEI NALUs*/
+        let num_of_arrays = bytes_reader.read_u8()?;
+        self.mpeg4_hevc.vps_sps_pps_annexb_data.clear();
+
+        for _ in 0..num_of_arrays {
+            let nal_type = bytes_reader.read_u8()? & 0x3F;
+            let num_nalus = bytes_reader.read_u16::<BigEndian>()?;
+
+            for _ in 0..num_nalus {
+                let nalu_size = bytes_reader.read_u16::<BigEndian>()?;
+                let nalu = bytes_reader.read_bytes(nalu_size as usize)?;
+
+                if nal_type == hevc_nal_type::HEVC_NAL_SPS {
+                    (self.mpeg4_hevc.width, self.mpeg4_hevc.height) = parse_sps_resolution(&nalu)?;
+                }
+                if matches!(
+                    nal_type,
+                    hevc_nal_type::HEVC_NAL_VPS
+                        | hevc_nal_type::HEVC_NAL_SPS
+                        | hevc_nal_type::HEVC_NAL_PPS
+                ) {
+                    self.mpeg4_hevc
+                        .vps_sps_pps_annexb_data
+                        .write(&H265_START_CODE)?;
+                    self.mpeg4_hevc.vps_sps_pps_annexb_data.write(&nalu[..])?;
+                }
+            }
+        }
+        /*clear the left bytes*/
+        bytes_reader.extract_remaining_bytes();
+
+        log::info!(
+            "mpeg4 hevc profile: {}, level: {}, resolution: {}x{}",
+            self.mpeg4_hevc.general_profile_idc,
+            self.mpeg4_hevc.general_level_idc,
+            self.mpeg4_hevc.width,
+            self.mpeg4_hevc.height
+        );
 
         Ok(self)
     }
+
+    //Turns the length prefixed NALUs into Annex-B, and inserts the VPS/SPS/PPS
+    //before the IRAP frames which do not carry them.
+    pub fn h265_mp4toannexb(
+        &mut self,
+        bytes_reader: &mut BytesReader,
+    ) -> Result<BytesMut, Mpeg4AvcHevcError> {
+        let mut bytes_writer = BytesWriter::new();
+
+        let mut vps_sps_pps_flag = false;
+        while !bytes_reader.is_empty() {
+            let size = self.read_nalu_size(bytes_reader)?;
+            let nalu_type = (bytes_reader.advance_u8()? >> 1) & 0x3F;
+
+            match nalu_type {
+                hevc_nal_type::HEVC_NAL_VPS
+                | hevc_nal_type::HEVC_NAL_SPS
+                | hevc_nal_type::HEVC_NAL_PPS => {
+                    vps_sps_pps_flag = true;
+                }
+                hevc_nal_type::HEVC_NAL_BLA_W_LP..=hevc_nal_type::HEVC_NAL_RSV_IRAP_23
+                    if !vps_sps_pps_flag =>
+                {
+                    vps_sps_pps_flag = true;
+
+                    bytes_writer.prepend(
+                        &self.mpeg4_hevc.vps_sps_pps_annexb_data.get_current_bytes()[..],
+                    )?;
+                }
+                _ => {}
+            }
+
+            bytes_writer.write(&H265_START_CODE)?;
+            let data = bytes_reader.read_bytes(size as usize)?;
+            bytes_writer.write(&data[..])?;
+        }
+
+        Ok(bytes_writer.extract_current_bytes())
+    }
+
+    fn read_nalu_size(&mut self, bytes_reader: &mut BytesReader) -> Result<u32, Mpeg4AvcHevcError> {
+        let mut size: u32 = 0;
+
+        for _ in 0..=self.mpeg4_hevc.length_size_minus_one {
+            size = bytes_reader.read_u8()? as u32 + (size << 8);
+        }
+        Ok(size)
+    }
+}
+
+//Reads the cropped picture size from a SPS NALU.
+//ITU-T H.265 7.3.2.2 Sequence parameter set RBSP syntax
+fn parse_sps_resolution(nalu: &[u8]) -> Result<(u32, u32), Mpeg4AvcHevcError> {
+    //remove the emulation prevention bytes(0x000003) and the 2 bytes nal unit header
+    let mut rbsp = BytesMut::with_capacity(nalu.len());
+    let mut zero_count = 0;
+    for &byte in nalu.iter().skip(2) {
+        if zero_count >= 2 && byte == 0x03 {
+            zero_count = 0;
+            continue;
+        }
+        zero_count = if byte == 0 { zero_count + 1 } else { 0 };
+        rbsp.extend_from_slice(&[byte]);
+    }
+    let mut bits_reader = BitsReader::new(BytesReader::new(rbsp));
+
+    /*sps_video_parameter_set_id*/
+    bits_reader.read_n_bits(4)?;
+    let max_sub_layers_minus1 = bits_reader.read_n_bits(3)? as usize;
+    /*sps_temporal_id_nesting_flag*/
+    bits_reader.read_bit()?;
+
+    /*profile_tier_level: the general profile(88 bits) and level(8 bits)*/
+    bits_reader.read_n_bits(48)?;
+    bits_reader.read_n_bits(48)?;
+    let mut sub_layer_flags = Vec::with_capacity(max_sub_layers_minus1);
+    for _ in 0..max_sub_layers_minus1 {
+        let profile_present = bits_reader.read_bit()?;
+        let level_present = bits_reader.read_bit()?;
+        sub_layer_flags.push((profile_present, level_present));
+    }
+    if max_sub_layers_minus1 > 0 {
+        /*reserved_zero_2bits*/
+        bits_reader.read_n_bits(2 * (8 - max_sub_layers_minus1))?;
+    }
+    for (profile_present, level_present) in sub_layer_flags {
+        if profile_present > 0 {
+            bits_reader.read_n_bits(44)?;
+            bits_reader.read_n_bits(44)?;
+        }
+        if level_present > 0 {
+            bits_reader.read_n_bits(8)?;
+        }
+    }
+
+    /*sps_seq_parameter_set_id*/
+    read_uev(&mut bits_reader)?;
+    let chroma_format_idc = read_uev(&mut bits_reader)?;
+    if chroma_format_idc == 3 {
+        /*separate_colour_plane_flag*/
+        bits_reader.read_bit()?;
+    }
+    let mut width = read_uev(&mut bits_reader)?;
+    let mut height = read_uev(&mut bits_reader)?;
+
+    /*conformance_window_flag*/
+    if bits_reader.read_bit()? > 0 {
+        let (sub_width, sub_height) = match chroma_format_idc {
+            1 => (2, 2),
+            2 => (2, 1),
+            _ => (1, 1),
+        };
+        let left = read_uev(&mut bits_reader)?;
+        let right = read_uev(&mut bits_reader)?;
+        let top = read_uev(&mut bits_reader)?;
+        let bottom = read_uev(&mut bits_reader)?;
+        width = width.saturating_sub(sub_width * (left + right));
+        height = height.saturating_sub(sub_height * (top + bottom));
+    }
+
+    Ok((width, height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Mpeg4HevcProcessor;
+    use bytes::BytesMut;
+    use bytesio::bytes_reader::BytesReader;
+
+    #[test]
+    fn test_h265_mp4toannexb() {
+        let mut hvcc = vec![0x01, 0x01, 0x60, 0x00, 0x00, 0x00, 0x90, 0x00, 0x00, 0x00];
+        hvcc.extend_from_slice(&[0x00, 0x00, 0x5d, 0xf0, 0x00, 0xfc, 0xfd, 0xf8, 0xf8]);
+        hvcc.extend_from_slice(&[0x00, 0x00, 0x0f]);
+        //the VPS, SPS and PPS arrays with one NALU each, the VPS and PPS are truncated
+        hvcc.push(0x03);
+        hvcc.extend_from_slice(&[0xa0, 0x00, 0x01, 0x00, 0x02, 0x40, 0x01]);
+        //a 1920x1080 SPS
+        let sps = [
+            0x42, 0x01, 0x01, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0x90, 0x00, 0x00, 0x03, 0x00,
+            0x00, 0x03, 0x00, 0x78, 0xa0, 0x03, 0xc0, 0x80, 0x10, 0xe5, 0x96, 0x56, 0x69, 0x24,
+            0xca, 0xe0, 0x10, 0x00, 0x00, 0x03, 0x00, 0x10, 0x00, 0x00, 0x03, 0x01, 0xe0, 0x80,
+        ];
+        hvcc.extend_from_slice(&[0xa1, 0x00, 0x01, 0x00, sps.len() as u8]);
+        hvcc.extend_from_slice(&sps);
+        hvcc.extend_from_slice(&[0xa2, 0x00, 0x01, 0x00, 0x02, 0x44, 0x01]);
+
+        let mut processor = Mpeg4HevcProcessor::new();
+        processor
+            .decoder_configuration_record_load(&mut BytesReader::new(BytesMut::from(&hvcc[..])))
+            .unwrap();
+        assert_eq!(processor.mpeg4_hevc.width, 1920);
+        assert_eq!(processor.mpeg4_hevc.height, 1080);
+
+        //an IDR_W_RADL frame gets the parameter sets
+        let frame = BytesMut::from(&[0x00, 0x00, 0x00, 0x03, 0x26, 0x01, 0xaf][..]);
+        let annexb = processor
+            .h265_mp4toannexb(&mut BytesReader::new(frame))
+            .unwrap();
+        assert_eq!(
+            &annexb[..],
+            &[
+                &[0, 0, 0, 1, 0x40, 0x01, 0, 0, 0, 1][..],
+                &sps[..],
+                &[0, 0, 0, 1, 0x44, 0x01, 0, 0, 0, 1, 0x26, 0x01, 0xaf][..],
+            ]
+            .concat()[..]
+        );
+
+        //a TRAIL_R frame does not
+        let frame = BytesMut::from(&[0x00, 0x00, 0x00, 0x02, 0x02, 0x01][..]);
+        let annexb = processor
+            .h265_mp4toannexb(&mut BytesReader::new(frame))
+            .unwrap();
+        assert_eq!(&annexb[..], &[0, 0, 0, 1, 0x02, 0x01]);
+    }
 }
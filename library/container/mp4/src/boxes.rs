@@ -0,0 +1,369 @@
+use {
+    super::{
+        define::{AudioConfig, VideoCodec, VideoConfig, MOVIE_TIMESCALE},
+        errors::Mp4Error,
+    },
+    byteorder::BigEndian,
+    bytes::BytesMut,
+    bytesio::bytes_writer::BytesWriter,
+};
+
+//ISO/IEC 14496-12, the transformation matrix of the video
+const UNITY_MATRIX: [u32; 9] = [0x0001_0000, 0, 0, 0, 0x0001_0000, 0, 0, 0, 0x4000_0000];
+
+//size(4 bytes) | type(4 bytes) | payload
+pub fn mp4_box(box_type: &[u8; 4], payload: &[u8]) -> Result<BytesMut, Mp4Error> {
+    let mut writer = BytesWriter::new();
+    writer.write_u32::<BigEndian>(payload.len() as u32 + 8)?;
+    writer.write(box_type)?;
+    writer.write(payload)?;
+    Ok(writer.extract_current_bytes())
+}
+
+//size(4 bytes) | type(4 bytes) | version(1 byte) | flags(3 bytes) | payload
+pub fn full_box(
+    box_type: &[u8; 4],
+    version: u8,
+    flags: u32,
+    payload: &[u8],
+) -> Result<BytesMut, Mp4Error> {
+    let mut writer = BytesWriter::new();
+    writer.write_u8(version)?;
+    writer.write_u24::<BigEndian>(flags)?;
+    writer.write(payload)?;
+    mp4_box(box_type, &writer.extract_current_bytes())
+}
+
+fn concat(boxes: &[BytesMut]) -> BytesMut {
+    let mut data = BytesMut::new();
+    for mp4_box in boxes {
+        data.extend_from_slice(&mp4_box[..]);
+    }
+    data
+}
+
+pub fn ftyp() -> Result<BytesMut, Mp4Error> {
+    let mut writer = BytesWriter::new();
+    /*major brand and minor version*/
+    writer.write(b"iso6")?;
+    writer.write_u32::<BigEndian>(0)?;
+    /*compatible brands*/
+    for brand in [b"iso6", b"cmfc", b"mp41"] {
+        writer.write(brand)?;
+    }
+    mp4_box(b"ftyp", &writer.extract_current_bytes())
+}
+
+pub fn mvhd(next_track_id: u32) -> Result<BytesMut, Mp4Error> {
+    let mut writer = BytesWriter::new();
+    /*creation_time and modification_time*/
+    writer.write_u32::<BigEndian>(0)?;
+    writer.write_u32::<BigEndian>(0)?;
+    writer.write_u32::<BigEndian>(MOVIE_TIMESCALE)?;
+    /*duration, unknown for the fragmented movie*/
+    writer.write_u32::<BigEndian>(0)?;
+    /*rate 1.0 and volume 1.0*/
+    writer.write_u32::<BigEndian>(0x0001_0000)?;
+    writer.write_u16::<BigEndian>(0x0100)?;
+    /*reserved*/
+    writer.write(&[0; 10])?;
+    for value in UNITY_MATRIX {
+        writer.write_u32::<BigEndian>(value)?;
+    }
+    /*pre_defined*/
+    writer.write(&[0; 24])?;
+    writer.write_u32::<BigEndian>(next_track_id)?;
+    full_box(b"mvhd", 0, 0, &writer.extract_current_bytes())
+}
+
+//the track is enabled(0x01) and in the movie(0x02)
+fn tkhd(track_id: u32, is_audio: bool, width: u32, height: u32) -> Result<BytesMut, Mp4Error> {
+    let mut writer = BytesWriter::new();
+    /*creation_time and modification_time*/
+    writer.write_u32::<BigEndian>(0)?;
+    writer.write_u32::<BigEndian>(0)?;
+    writer.write_u32::<BigEndian>(track_id)?;
+    /*reserved*/
+    writer.write_u32::<BigEndian>(0)?;
+    /*duration*/
+    writer.write_u32::<BigEndian>(0)?;
+    /*reserved*/
+    writer.write(&[0; 8])?;
+    /*layer and alternate_group*/
+    writer.write_u16::<BigEndian>(0)?;
+    writer.write_u16::<BigEndian>(0)?;
+    /*volume*/
+    writer.write_u16::<BigEndian>(if is_audio { 0x0100 } else { 0 })?;
+    /*reserved*/
+    writer.write_u16::<BigEndian>(0)?;
+    for value in UNITY_MATRIX {
+        writer.write_u32::<BigEndian>(value)?;
+    }
+    /*width and height in 16.16 fixed point*/
+    writer.write_u32::<BigEndian>(width << 16)?;
+    writer.write_u32::<BigEndian>(height << 16)?;
+    full_box(b"tkhd", 0, 0x03, &writer.extract_current_bytes())
+}
+
+fn mdhd(timescale: u32) -> Result<BytesMut, Mp4Error> {
+    let mut writer = BytesWriter::new();
+    /*creation_time and modification_time*/
+    writer.write_u32::<BigEndian>(0)?;
+    writer.write_u32::<BigEndian>(0)?;
+    writer.write_u32::<BigEndian>(timescale)?;
+    /*duration*/
+    writer.write_u32::<BigEndian>(0)?;
+    /*language "und" packed in 3x5 bits*/
+    writer.write_u16::<BigEndian>(0x55C4)?;
+    /*pre_defined*/
+    writer.write_u16::<BigEndian>(0)?;
+    full_box(b"mdhd", 0, 0, &writer.extract_current_bytes())
+}
+
+fn hdlr(is_audio: bool) -> Result<BytesMut, Mp4Error> {
+    let (handler_type, name): (&[u8; 4], &[u8]) = if is_audio {
+        (b"soun", b"SoundHandler\0")
+    } else {
+        (b"vide", b"VideoHandler\0")
+    };
+    let mut writer = BytesWriter::new();
+    /*pre_defined*/
+    writer.write_u32::<BigEndian>(0)?;
+    writer.write(handler_type)?;
+    /*reserved*/
+    writer.write(&[0; 12])?;
+    writer.write(name)?;
+    full_box(b"hdlr", 0, 0, &writer.extract_current_bytes())
+}
+
+//the media data is in the same file(the url flag 0x01)
+fn dinf() -> Result<BytesMut, Mp4Error> {
+    let url = full_box(b"url ", 0, 0x01, &[])?;
+    let mut writer = BytesWriter::new();
+    /*entry_count*/
+    writer.write_u32::<BigEndian>(1)?;
+    writer.write(&url[..])?;
+    let dref = full_box(b"dref", 0, 0, &writer.extract_current_bytes())?;
+    mp4_box(b"dinf", &dref)
+}
+
+fn visual_sample_entry(config: &VideoConfig) -> Result<BytesMut, Mp4Error> {
+    //hvc1 keeps the parameter sets in hvcC only, which Safari asks for
+    let (entry_type, config_type) = match config.codec {
+        VideoCodec::H264 => (b"avc1", b"avcC"),
+        VideoCodec::H265 => (b"hvc1", b"hvcC"),
+    };
+
+    let mut writer = BytesWriter::new();
+    /*reserved*/
+    writer.write(&[0; 6])?;
+    /*data_reference_index*/
+    writer.write_u16::<BigEndian>(1)?;
+    /*pre_defined and reserved*/
+    writer.write(&[0; 16])?;
+    writer.write_u16::<BigEndian>(config.width as u16)?;
+    writer.write_u16::<BigEndian>(config.height as u16)?;
+    /*horizresolution and vertresolution, 72 dpi*/
+    writer.write_u32::<BigEndian>(0x0048_0000)?;
+    writer.write_u32::<BigEndian>(0x0048_0000)?;
+    /*reserved*/
+    writer.write_u32::<BigEndian>(0)?;
+    /*frame_count*/
+    writer.write_u16::<BigEndian>(1)?;
+    /*compressorname*/
+    writer.write(&[0; 32])?;
+    /*depth and pre_defined(-1)*/
+    writer.write_u16::<BigEndian>(0x0018)?;
+    writer.write_u16::<BigEndian>(0xFFFF)?;
+    writer.write(&mp4_box(config_type, &config.decoder_configuration_record)?[..])?;
+    mp4_box(entry_type, &writer.extract_current_bytes())
+}
+
+//ISO/IEC 14496-1 a descriptor with a one byte size
+fn descriptor(tag: u8, payload: &[u8]) -> Result<BytesMut, Mp4Error> {
+    let mut writer = BytesWriter::new();
+    writer.write_u8(tag)?;
+    writer.write_u8(payload.len() as u8)?;
+    writer.write(payload)?;
+    Ok(writer.extract_current_bytes())
+}
+
+fn esds(track_id: u32, config: &AudioConfig) -> Result<BytesMut, Mp4Error> {
+    /*DecoderSpecificInfo*/
+    let decoder_specific_info = descriptor(0x05, &config.audio_specific_config)?;
+
+    /*DecoderConfigDescriptor*/
+    let mut writer = BytesWriter::new();
+    /*objectTypeIndication: MPEG-4 audio*/
+    writer.write_u8(0x40)?;
+    /*streamType: audio stream(0x05), upStream 0, reserved 1*/
+    writer.write_u8(0x15)?;
+    /*bufferSizeDB, maxBitrate and avgBitrate*/
+    writer.write_u24::<BigEndian>(0)?;
+    writer.write_u32::<BigEndian>(0)?;
+    writer.write_u32::<BigEndian>(0)?;
+    writer.write(&decoder_specific_info[..])?;
+    let decoder_config = descriptor(0x04, &writer.extract_current_bytes())?;
+
+    /*ES_Descriptor*/
+    writer.write_u16::<BigEndian>(track_id as u16)?;
+    /*flags*/
+    writer.write_u8(0)?;
+    writer.write(&decoder_config[..])?;
+    /*SLConfigDescriptor, predefined for mp4*/
+    writer.write(&descriptor(0x06, &[0x02])?[..])?;
+    let es_descriptor = descriptor(0x03, &writer.extract_current_bytes())?;
+
+    full_box(b"esds", 0, 0, &es_descriptor)
+}
+
+fn audio_sample_entry(track_id: u32, config: &AudioConfig) -> Result<BytesMut, Mp4Error> {
+    let mut writer = BytesWriter::new();
+    /*reserved*/
+    writer.write(&[0; 6])?;
+    /*data_reference_index*/
+    writer.write_u16::<BigEndian>(1)?;
+    /*reserved*/
+    writer.write(&[0; 8])?;
+    writer.write_u16::<BigEndian>(config.channels as u16)?;
+    /*samplesize*/
+    writer.write_u16::<BigEndian>(16)?;
+    /*pre_defined and reserved*/
+    writer.write_u32::<BigEndian>(0)?;
+    /*samplerate in 16.16 fixed point*/
+    writer.write_u32::<BigEndian>(config.sample_rate << 16)?;
+    writer.write(&esds(track_id, config)?[..])?;
+    mp4_box(b"mp4a", &writer.extract_current_bytes())
+}
+
+//The sample tables are empty, the samples are in the fragments.
+fn stbl(sample_entry: BytesMut) -> Result<BytesMut, Mp4Error> {
+    let mut writer = BytesWriter::new();
+    /*entry_count*/
+    writer.write_u32::<BigEndian>(1)?;
+    writer.write(&sample_entry[..])?;
+    let stsd = full_box(b"stsd", 0, 0, &writer.extract_current_bytes())?;
+
+    let no_entry = [0; 4];
+    let stts = full_box(b"stts", 0, 0, &no_entry)?;
+    let stsc = full_box(b"stsc", 0, 0, &no_entry)?;
+    /*sample_size and sample_count*/
+    let stsz = full_box(b"stsz", 0, 0, &[0; 8])?;
+    let stco = full_box(b"stco", 0, 0, &no_entry)?;
+
+    mp4_box(b"stbl", &concat(&[stsd, stts, stsc, stsz, stco]))
+}
+
+pub fn video_trak(
+    track_id: u32,
+    timescale: u32,
+    config: &VideoConfig,
+) -> Result<BytesMut, Mp4Error> {
+    /*graphicsmode and opcolor*/
+    let vmhd = full_box(b"vmhd", 0, 0x01, &[0; 8])?;
+    let stbl = stbl(visual_sample_entry(config)?)?;
+    let minf = mp4_box(b"minf", &concat(&[vmhd, dinf()?, stbl]))?;
+    let mdia = mp4_box(b"mdia", &concat(&[mdhd(timescale)?, hdlr(false)?, minf]))?;
+    let tkhd = tkhd(track_id, false, config.width, config.height)?;
+    mp4_box(b"trak", &concat(&[tkhd, mdia]))
+}
+
+pub fn audio_trak(track_id: u32, config: &AudioConfig) -> Result<BytesMut, Mp4Error> {
+    /*balance and reserved*/
+    let smhd = full_box(b"smhd", 0, 0, &[0; 4])?;
+    let stbl = stbl(audio_sample_entry(track_id, config)?)?;
+    let minf = mp4_box(b"minf", &concat(&[smhd, dinf()?, stbl]))?;
+    let mdia = mp4_box(
+        b"mdia",
+        &concat(&[mdhd(config.sample_rate)?, hdlr(true)?, minf]),
+    )?;
+    let tkhd = tkhd(track_id, true, 0, 0)?;
+    mp4_box(b"trak", &concat(&[tkhd, mdia]))
+}
+
+pub fn mvex(track_ids: &[u32]) -> Result<BytesMut, Mp4Error> {
+    let mut trexs = Vec::new();
+    for track_id in track_ids {
+        let mut writer = BytesWriter::new();
+        writer.write_u32::<BigEndian>(*track_id)?;
+        /*default_sample_description_index*/
+        writer.write_u32::<BigEndian>(1)?;
+        /*default_sample_duration, default_sample_size and default_sample_flags*/
+        writer.write(&[0; 12])?;
+        trexs.push(full_box(b"trex", 0, 0, &writer.extract_current_bytes())?);
+    }
+    mp4_box(b"mvex", &concat(&trexs))
+}
+
+pub fn moov(traks: Vec<BytesMut>, track_ids: &[u32]) -> Result<BytesMut, Mp4Error> {
+    let next_track_id = track_ids.iter().max().copied().unwrap_or(0) + 1;
+    let mut boxes = vec![mvhd(next_track_id)?];
+    boxes.extend(traks);
+    boxes.push(mvex(track_ids)?);
+    mp4_box(b"moov", &concat(&boxes))
+}
+
+//A sample of a track fragment.
+pub struct TrunSample {
+    pub duration: u32,
+    pub size: u32,
+    pub flags: u32,
+    pub composition_time_offset: i32,
+}
+
+//The track fragment of a track, the samples start at data_offset from the moof.
+pub fn traf(
+    track_id: u32,
+    base_media_decode_time: u64,
+    data_offset: u32,
+    samples: &[TrunSample],
+) -> Result<BytesMut, Mp4Error> {
+    /*default-base-is-moof*/
+    let tfhd = full_box(b"tfhd", 0, 0x02_0000, &track_id.to_be_bytes())?;
+    let tfdt = full_box(b"tfdt", 1, 0, &base_media_decode_time.to_be_bytes())?;
+
+    let mut writer = BytesWriter::new();
+    writer.write_u32::<BigEndian>(samples.len() as u32)?;
+    writer.write_u32::<BigEndian>(data_offset)?;
+    for sample in samples {
+        writer.write_u32::<BigEndian>(sample.duration)?;
+        writer.write_u32::<BigEndian>(sample.size)?;
+        writer.write_u32::<BigEndian>(sample.flags)?;
+        writer.write_u32::<BigEndian>(sample.composition_time_offset as u32)?;
+    }
+    //data-offset, sample-duration, sample-size, sample-flags and
+    //sample-composition-time-offsets(signed in version 1) are present
+    let trun = full_box(b"trun", 1, 0x00_0F01, &writer.extract_current_bytes())?;
+
+    mp4_box(b"traf", &concat(&[tfhd, tfdt, trun]))
+}
+
+pub fn moof(sequence_number: u32, trafs: Vec<BytesMut>) -> Result<BytesMut, Mp4Error> {
+    let mfhd = full_box(b"mfhd", 0, 0, &sequence_number.to_be_bytes())?;
+    let mut boxes = vec![mfhd];
+    boxes.extend(trafs);
+    mp4_box(b"moof", &concat(&boxes))
+}
+
+//An event message(ISO/IEC 23009-1 5.10.3.3) of version 1, the presentation time is
+//on the timeline of the media.
+pub fn emsg(
+    scheme_id_uri: &str,
+    timescale: u32,
+    presentation_time: u64,
+    id: u32,
+    message_data: &[u8],
+) -> Result<BytesMut, Mp4Error> {
+    let mut writer = BytesWriter::new();
+    writer.write_u32::<BigEndian>(timescale)?;
+    writer.write_u64::<BigEndian>(presentation_time)?;
+    /*event_duration, unknown*/
+    writer.write_u32::<BigEndian>(0xFFFF_FFFF)?;
+    writer.write_u32::<BigEndian>(id)?;
+    writer.write(scheme_id_uri.as_bytes())?;
+    writer.write_u8(0)?;
+    /*value*/
+    writer.write_u8(0)?;
+    writer.write(message_data)?;
+    full_box(b"emsg", 1, 0, &writer.extract_current_bytes())
+}
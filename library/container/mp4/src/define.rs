@@ -0,0 +1,139 @@
+use bytes::BytesMut;
+
+pub const VIDEO_TRACK_ID: u32 = 1;
+pub const AUDIO_TRACK_ID: u32 = 2;
+//the timescale of the video track, the same as the mpegts clock
+pub const VIDEO_TIMESCALE: u32 = 90000;
+pub const MOVIE_TIMESCALE: u32 = 1000;
+//the samples of an AAC frame
+pub const AAC_FRAME_SAMPLES: u32 = 1024;
+
+pub mod sample_flags {
+    //sample_depends_on = 2, it does not depend on others
+    pub const SYNC_SAMPLE: u32 = 0x0200_0000;
+    //sample_depends_on = 1 and sample_is_non_sync_sample = 1
+    pub const NON_SYNC_SAMPLE: u32 = 0x0101_0000;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VideoCodec {
+    H264,
+    H265,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VideoConfig {
+    pub codec: VideoCodec,
+    pub width: u32,
+    pub height: u32,
+    //the AVCDecoderConfigurationRecord(avcC) or HEVCDecoderConfigurationRecord(hvcC)
+    pub decoder_configuration_record: BytesMut,
+}
+
+impl VideoConfig {
+    //the RFC 6381 codecs parameter, e.g. avc1.64001f or hvc1.1.6.L93.B0
+    pub fn codec_string(&self) -> String {
+        let record = &self.decoder_configuration_record;
+        match self.codec {
+            VideoCodec::H264 if record.len() >= 4 => {
+                format!("avc1.{:02x}{:02x}{:02x}", record[1], record[2], record[3])
+            }
+            VideoCodec::H265 if record.len() >= 13 => {
+                let profile_space = ["", "A", "B", "C"][(record[1] >> 6) as usize];
+                let tier = if record[1] & 0x20 > 0 { "H" } else { "L" };
+                let profile_idc = record[1] & 0x1f;
+                //the compatibility flags are written in the reverse bit order
+                let compatibility =
+                    u32::from_be_bytes([record[2], record[3], record[4], record[5]]).reverse_bits();
+                let mut codec = format!(
+                    "hvc1.{profile_space}{profile_idc}.{compatibility:x}.{tier}{}",
+                    record[12]
+                );
+                //the constraint flags without the trailing zero bytes
+                let constraints = &record[6..12];
+                let len = constraints
+                    .iter()
+                    .rposition(|b| *b != 0)
+                    .map_or(0, |i| i + 1);
+                for constraint in &constraints[..len] {
+                    codec += format!(".{constraint:x}").as_str();
+                }
+                codec
+            }
+            VideoCodec::H264 => String::from("avc1"),
+            VideoCodec::H265 => String::from("hvc1"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AudioConfig {
+    pub sample_rate: u32,
+    pub channels: u8,
+    //the AAC AudioSpecificConfig
+    pub audio_specific_config: BytesMut,
+}
+
+impl AudioConfig {
+    //the RFC 6381 codecs parameter, mp4a.40.{audio object type}
+    pub fn codec_string(&self) -> String {
+        let object_type = self
+            .audio_specific_config
+            .first()
+            .map_or(2, |first| first >> 3);
+        format!("mp4a.40.{object_type}")
+    }
+}
+
+//A video frame of length prefixed NALUs or a raw AAC frame, the timestamps are in
+//milliseconds.
+#[derive(Debug, Clone)]
+pub struct Mp4Sample {
+    pub dts: i64,
+    pub pts: i64,
+    pub is_key: bool,
+    pub data: BytesMut,
+}
+
+//the scheme of the emsg boxes which carry ID3 tags
+pub const ID3_SCHEME_ID_URI: &str = "https://aomedia.org/emsg/ID3";
+
+//A timed ID3 tag written as an emsg box before the fragment which holds its dts(ms).
+#[derive(Debug, Clone)]
+pub struct Mp4Id3Event {
+    pub dts: i64,
+    pub data: BytesMut,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AudioConfig, VideoCodec, VideoConfig};
+    use bytes::BytesMut;
+
+    #[test]
+    fn test_codec_string() {
+        let mut config = VideoConfig {
+            codec: VideoCodec::H264,
+            width: 1280,
+            height: 720,
+            decoder_configuration_record: BytesMut::from(&[0x01, 0x64, 0x00, 0x1f, 0xff][..]),
+        };
+        assert_eq!(config.codec_string(), "avc1.64001f");
+
+        //main profile, level 3.1
+        config.codec = VideoCodec::H265;
+        config.decoder_configuration_record = BytesMut::from(
+            &[
+                0x01, 0x01, 0x60, 0x00, 0x00, 0x00, 0x90, 0x00, 0x00, 0x00, 0x00, 0x00, 0x5d,
+            ][..],
+        );
+        assert_eq!(config.codec_string(), "hvc1.1.6.L93.90");
+
+        let audio = AudioConfig {
+            sample_rate: 44100,
+            channels: 2,
+            audio_specific_config: BytesMut::from(&[0x12, 0x10][..]),
+        };
+        assert_eq!(audio.codec_string(), "mp4a.40.2");
+    }
+}
@@ -0,0 +1,42 @@
+use {
+    bytesio::bytes_errors::BytesWriteError,
+    failure::{Backtrace, Fail},
+    std::fmt,
+};
+
+#[derive(Debug, Fail)]
+pub enum Mp4ErrorValue {
+    #[fail(display = "bytes write error")]
+    BytesWriteError(BytesWriteError),
+
+    #[fail(display = "no track is configured")]
+    NoTrack,
+}
+#[derive(Debug)]
+pub struct Mp4Error {
+    pub value: Mp4ErrorValue,
+}
+
+impl From<BytesWriteError> for Mp4Error {
+    fn from(error: BytesWriteError) -> Self {
+        Mp4Error {
+            value: Mp4ErrorValue::BytesWriteError(error),
+        }
+    }
+}
+
+impl fmt::Display for Mp4Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.value, f)
+    }
+}
+
+impl Fail for Mp4Error {
+    fn cause(&self) -> Option<&dyn Fail> {
+        self.value.cause()
+    }
+
+    fn backtrace(&self) -> Option<&Backtrace> {
+        self.value.backtrace()
+    }
+}
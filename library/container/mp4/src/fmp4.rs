@@ -0,0 +1,289 @@
+use {
+    super::{
+        boxes::{self, TrunSample},
+        define::{
+            sample_flags, AudioConfig, Mp4Id3Event, Mp4Sample, VideoConfig, AAC_FRAME_SAMPLES,
+            AUDIO_TRACK_ID, ID3_SCHEME_ID_URI, MOVIE_TIMESCALE, VIDEO_TIMESCALE, VIDEO_TRACK_ID,
+        },
+        errors::{Mp4Error, Mp4ErrorValue},
+    },
+    bytes::BytesMut,
+};
+
+//Muxes a H.264/H.265 track and an AAC track into fragmented mp4. The init segment
+//holds the tracks configured when it is created, the samples of the other tracks are
+//dropped until the next init segment.
+#[derive(Default)]
+pub struct Fmp4Muxer {
+    video_config: Option<VideoConfig>,
+    audio_config: Option<AudioConfig>,
+    //the tracks of the current init segment
+    video_enabled: bool,
+    audio_enabled: bool,
+
+    video_samples: Vec<Mp4Sample>,
+    audio_samples: Vec<Mp4Sample>,
+    id3_events: Vec<Mp4Id3Event>,
+    //the duration(ms) of the last video sample of the previous fragment
+    last_video_duration: i64,
+    sequence_number: u32,
+    event_id: u32,
+}
+
+impl Fmp4Muxer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    //returns true if the init segment changes
+    pub fn set_video_config(&mut self, config: VideoConfig) -> bool {
+        let changed = self.video_config.as_ref() != Some(&config);
+        self.video_config = Some(config);
+        changed
+    }
+
+    //returns true if the init segment changes
+    pub fn set_audio_config(&mut self, config: AudioConfig) -> bool {
+        let changed = self.audio_config.as_ref() != Some(&config);
+        self.audio_config = Some(config);
+        changed
+    }
+
+    //ftyp and moov of the configured tracks
+    pub fn init_segment(&mut self) -> Result<BytesMut, Mp4Error> {
+        let mut traks = Vec::new();
+        let mut track_ids = Vec::new();
+        if let Some(config) = &self.video_config {
+            traks.push(boxes::video_trak(VIDEO_TRACK_ID, VIDEO_TIMESCALE, config)?);
+            track_ids.push(VIDEO_TRACK_ID);
+        }
+        if let Some(config) = &self.audio_config {
+            traks.push(boxes::audio_trak(AUDIO_TRACK_ID, config)?);
+            track_ids.push(AUDIO_TRACK_ID);
+        }
+        if traks.is_empty() {
+            return Err(Mp4Error {
+                value: Mp4ErrorValue::NoTrack,
+            });
+        }
+        self.video_enabled = self.video_config.is_some();
+        self.audio_enabled = self.audio_config.is_some();
+
+        let mut data = boxes::ftyp()?;
+        data.extend_from_slice(&boxes::moov(traks, &track_ids)?[..]);
+        Ok(data)
+    }
+
+    pub fn push_video(&mut self, sample: Mp4Sample) {
+        if self.video_enabled {
+            self.video_samples.push(sample);
+        }
+    }
+
+    pub fn push_audio(&mut self, sample: Mp4Sample) {
+        if self.audio_enabled {
+            self.audio_samples.push(sample);
+        }
+    }
+
+    pub fn push_id3_event(&mut self, event: Mp4Id3Event) {
+        self.id3_events.push(event);
+    }
+
+    //Writes the pushed samples as a fragment(moof and mdat) after the emsg boxes of
+    //the pushed ID3 events, the last video sample
+    //lasts until end_dts(ms). It is empty if there is no sample.
+    pub fn flush_fragment(&mut self, end_dts: i64) -> Result<BytesMut, Mp4Error> {
+        if self.video_samples.is_empty() && self.audio_samples.is_empty() {
+            return Ok(BytesMut::new());
+        }
+        let video_samples = std::mem::take(&mut self.video_samples);
+        let audio_samples = std::mem::take(&mut self.audio_samples);
+
+        let mut video_trun = Vec::with_capacity(video_samples.len());
+        for (index, sample) in video_samples.iter().enumerate() {
+            let next_dts = video_samples
+                .get(index + 1)
+                .map_or(end_dts, |next| next.dts);
+            let mut duration = next_dts - sample.dts;
+            if duration <= 0 {
+                duration = self.last_video_duration;
+            }
+            self.last_video_duration = duration;
+
+            video_trun.push(TrunSample {
+                duration: (duration * VIDEO_TIMESCALE as i64 / 1000) as u32,
+                size: sample.data.len() as u32,
+                flags: if sample.is_key {
+                    sample_flags::SYNC_SAMPLE
+                } else {
+                    sample_flags::NON_SYNC_SAMPLE
+                },
+                composition_time_offset: ((sample.pts - sample.dts) * VIDEO_TIMESCALE as i64 / 1000)
+                    as i32,
+            });
+        }
+        let audio_trun: Vec<TrunSample> = audio_samples
+            .iter()
+            .map(|sample| TrunSample {
+                duration: AAC_FRAME_SAMPLES,
+                size: sample.data.len() as u32,
+                flags: sample_flags::SYNC_SAMPLE,
+                composition_time_offset: 0,
+            })
+            .collect();
+
+        let video_data_size: usize = video_samples.iter().map(|sample| sample.data.len()).sum();
+        let sample_rate = self
+            .audio_config
+            .as_ref()
+            .map_or(0, |config| config.sample_rate as i64);
+        //the moof size does not change with the data offsets, get it with the zero ones
+        let build_moof = |video_offset: u32, audio_offset: u32| {
+            let mut trafs = Vec::new();
+            if let Some(first) = video_samples.first() {
+                let decode_time = (first.dts * VIDEO_TIMESCALE as i64 / 1000) as u64;
+                trafs.push(boxes::traf(
+                    VIDEO_TRACK_ID,
+                    decode_time,
+                    video_offset,
+                    &video_trun,
+                )?);
+            }
+            if let Some(first) = audio_samples.first() {
+                let decode_time = (first.dts * sample_rate / 1000) as u64;
+                trafs.push(boxes::traf(
+                    AUDIO_TRACK_ID,
+                    decode_time,
+                    audio_offset,
+                    &audio_trun,
+                )?);
+            }
+            boxes::moof(self.sequence_number, trafs)
+        };
+        let moof_size = build_moof(0, 0)?.len() as u32;
+        /*the mdat header is 8 bytes*/
+        let video_offset = moof_size + 8;
+        let moof = build_moof(video_offset, video_offset + video_data_size as u32)?;
+        self.sequence_number += 1;
+
+        let mut mdat = BytesMut::new();
+        for sample in video_samples.iter().chain(audio_samples.iter()) {
+            mdat.extend_from_slice(&sample.data[..]);
+        }
+
+        let mut data = BytesMut::new();
+        for event in std::mem::take(&mut self.id3_events) {
+            data.extend_from_slice(
+                &boxes::emsg(
+                    ID3_SCHEME_ID_URI,
+                    MOVIE_TIMESCALE,
+                    event.dts.max(0) as u64,
+                    self.event_id,
+                    &event.data,
+                )?[..],
+            );
+            self.event_id = self.event_id.wrapping_add(1);
+        }
+        data.extend_from_slice(&moof[..]);
+        data.extend_from_slice(&boxes::mp4_box(b"mdat", &mdat)?[..]);
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Fmp4Muxer;
+    use crate::define::{AudioConfig, Mp4Id3Event, Mp4Sample, VideoCodec, VideoConfig};
+    use bytes::BytesMut;
+    use std::convert::TryInto;
+
+    //the type and size of the boxes in data
+    fn boxes(data: &[u8]) -> Vec<(String, usize)> {
+        let mut result = Vec::new();
+        let mut offset = 0;
+        while offset + 8 <= data.len() {
+            let size = u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap()) as usize;
+            let box_type = String::from_utf8_lossy(&data[offset + 4..offset + 8]).to_string();
+            result.push((box_type, size));
+            offset += size;
+        }
+        assert_eq!(offset, data.len());
+        result
+    }
+
+    fn sample(dts: i64, is_key: bool, data: &[u8]) -> Mp4Sample {
+        Mp4Sample {
+            dts,
+            pts: dts,
+            is_key,
+            data: BytesMut::from(data),
+        }
+    }
+
+    #[test]
+    fn test_fmp4_muxer() {
+        let mut muxer = Fmp4Muxer::new();
+        assert!(muxer.init_segment().is_err());
+
+        assert!(muxer.set_video_config(VideoConfig {
+            codec: VideoCodec::H264,
+            width: 1280,
+            height: 720,
+            decoder_configuration_record: BytesMut::from(&[0x01, 0x64, 0x00, 0x1f][..]),
+        }));
+        //the audio is dropped before it is in the init segment
+        muxer.push_audio(sample(0, true, &[0x21, 0x00]));
+
+        let init = muxer.init_segment().unwrap();
+        let init_boxes: Vec<String> = boxes(&init).into_iter().map(|(name, _)| name).collect();
+        assert_eq!(init_boxes, vec!["ftyp", "moov"]);
+        assert!(init.windows(4).any(|window| window == b"avcC"));
+
+        assert!(!muxer.set_video_config(VideoConfig {
+            codec: VideoCodec::H264,
+            width: 1280,
+            height: 720,
+            decoder_configuration_record: BytesMut::from(&[0x01, 0x64, 0x00, 0x1f][..]),
+        }));
+        assert!(muxer.set_audio_config(AudioConfig {
+            sample_rate: 44100,
+            channels: 2,
+            audio_specific_config: BytesMut::from(&[0x12, 0x10][..]),
+        }));
+
+        muxer.push_video(sample(0, true, &[0, 0, 0, 2, 0x65, 0x88]));
+        muxer.push_video(sample(40, false, &[0, 0, 0, 2, 0x41, 0x9a]));
+        let fragment = muxer.flush_fragment(80).unwrap();
+        let fragment_boxes = boxes(&fragment);
+        assert_eq!(fragment_boxes[0].0, "moof");
+        assert_eq!(fragment_boxes[1], (String::from("mdat"), 8 + 12));
+
+        //the data offset of the trun points to the first sample in the mdat
+        let moof_size = fragment_boxes[0].1;
+        let trun = fragment
+            .windows(4)
+            .position(|window| window == b"trun")
+            .unwrap();
+        let data_offset =
+            u32::from_be_bytes(fragment[trun + 12..trun + 16].try_into().unwrap()) as usize;
+        assert_eq!(data_offset, moof_size + 8);
+        assert_eq!(
+            &fragment[data_offset..data_offset + 6],
+            &[0, 0, 0, 2, 0x65, 0x88]
+        );
+
+        assert!(muxer.flush_fragment(120).unwrap().is_empty());
+
+        //the id3 events are written before the next fragment
+        muxer.push_id3_event(Mp4Id3Event {
+            dts: 120,
+            data: BytesMut::from(&b"ID3"[..]),
+        });
+        muxer.push_video(sample(120, true, &[0, 0, 0, 2, 0x65, 0x88]));
+        let fragment = muxer.flush_fragment(160).unwrap();
+        let fragment_boxes: Vec<String> =
+            boxes(&fragment).into_iter().map(|(name, _)| name).collect();
+        assert_eq!(fragment_boxes, vec!["emsg", "moof", "mdat"]);
+    }
+}
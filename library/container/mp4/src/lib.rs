@@ -0,0 +1,4 @@
+pub mod boxes;
+pub mod define;
+pub mod errors;
+pub mod fmp4;
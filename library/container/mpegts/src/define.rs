@@ -12,9 +12,11 @@ pub mod epsi_stream_type {
     pub const PSI_STREAM_MP3: u8 = 0x04; // ISO/IEC 13818-3 Audio
     pub const PSI_STREAM_PRIVATE_DATA: u8 = 0x06;
     pub const PSI_STREAM_H264: u8 = 0x1b; // H.264
+    pub const PSI_STREAM_H265: u8 = 0x24; // H.265
     pub const PSI_STREAM_AAC: u8 = 0x0f;
     pub const PSI_STREAM_MPEG4_AAC: u8 = 0x1c;
     pub const PSI_STREAM_AUDIO_OPUS: u8 = 0x9c;
+    pub const PSI_STREAM_METADATA: u8 = 0x15; // metadata carried in PES packets, e.g. ID3
 }
 
 pub mod epes_stream_id {
@@ -39,3 +41,6 @@ pub const MPEG_FLAG_IDR_FRAME: u16 = 0x0001;
 pub const MPEG_FLAG_H264_H265_WITH_AUD: u16 = 0x8000;
 
 pub const PAT_PERIOD: i64 = 400 * 90;
+
+pub const METADATA_POINTER_DESCRIPTOR_TAG: u8 = 0x25;
+pub const METADATA_DESCRIPTOR_TAG: u8 = 0x26;
@@ -158,10 +158,19 @@ impl PesMuxer {
             self.bytes_writer.write_u8(b18)?;
         }
 
-        if define::epsi_stream_type::PSI_STREAM_H264 == stream_data.codec_id && !h264_h265_with_aud
-        {
-            let header: [u8; 6] = [0x00, 0x00, 0x00, 0x01, 0x09, 0xF0];
-            self.bytes_writer.write(&header)?;
+        if !h264_h265_with_aud {
+            match stream_data.codec_id {
+                define::epsi_stream_type::PSI_STREAM_H264 => {
+                    let header: [u8; 6] = [0x00, 0x00, 0x00, 0x01, 0x09, 0xF0];
+                    self.bytes_writer.write(&header)?;
+                }
+                define::epsi_stream_type::PSI_STREAM_H265 => {
+                    //AUD_NUT(35) with pic_type 2(I, P and B slices)
+                    let header: [u8; 7] = [0x00, 0x00, 0x00, 0x01, 0x46, 0x01, 0x50];
+                    self.bytes_writer.write(&header)?;
+                }
+                _ => {}
+            }
         }
 
         let pes_payload_length =
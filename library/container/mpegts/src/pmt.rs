@@ -91,7 +91,11 @@ impl PmtMuxer {
             /*elementary_PID*/
             tmp_bytes_writer.write_u16::<BigEndian>(0xE000 | stream.pid)?;
             /*ES_info_length*/
-            tmp_bytes_writer.write_u16::<BigEndian>(0xF000)?;
+            let esinfo_length = stream.esinfo.len() as u16;
+            tmp_bytes_writer.write_u16::<BigEndian>(0xF000 | esinfo_length)?;
+            if esinfo_length > 0 {
+                tmp_bytes_writer.write(&stream.esinfo[..])?;
+            }
         }
 
         /*section_length*/
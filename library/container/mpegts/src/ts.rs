@@ -81,7 +81,8 @@ impl TsMuxer {
         let cur_pmt = self.pat.pmt.get_mut(self.cur_pmt_index).unwrap();
         let cur_stream = cur_pmt.streams.get_mut(self.cur_stream_index).unwrap();
 
-        if 0x1FFF == cur_pmt.pcr_pid
+        if (0x1FFF == cur_pmt.pcr_pid
+            && cur_stream.codec_id != define::epsi_stream_type::PSI_STREAM_METADATA)
             || (define::epes_stream_id::PES_SID_VIDEO
                 == (cur_stream.stream_id & define::epes_stream_id::PES_SID_VIDEO)
                 && (cur_pmt.pcr_pid != cur_stream.pid))
@@ -361,6 +362,63 @@ impl TsMuxer {
         self.pmt_add_stream(0, codecid, extra_data)
     }
 
+    //Adds a timed ID3 metadata stream, the PMT describes it with the metadata pointer
+    //and metadata descriptors of the HLS timed metadata spec.
+    pub fn add_id3_stream(&mut self) -> Result<u16, MpegTsError> {
+        if self.pat.pmt.is_empty() {
+            self.add_program(1, BytesMut::new())?;
+        }
+
+        /*metadata_descriptor*/
+        let mut esinfo = BytesMut::new();
+        esinfo.put_u8(define::METADATA_DESCRIPTOR_TAG);
+        esinfo.put_u8(13);
+        esinfo.put_u16(0xFFFF);
+        esinfo.put_slice(b"ID3 ");
+        esinfo.put_u8(0xFF);
+        esinfo.put_slice(b"ID3 ");
+        /*metadata_service_id*/
+        esinfo.put_u8(0x00);
+        /*decoder_config_flags and DSM-CC_flag are 0*/
+        esinfo.put_u8(0x0F);
+        let pid = self.pmt_add_stream(0, define::epsi_stream_type::PSI_STREAM_METADATA, esinfo)?;
+
+        /*metadata_pointer_descriptor*/
+        let pmt = &mut self.pat.pmt[0];
+        pmt.program_info
+            .put_u8(define::METADATA_POINTER_DESCRIPTOR_TAG);
+        pmt.program_info.put_u8(15);
+        pmt.program_info.put_u16(0xFFFF);
+        pmt.program_info.put_slice(b"ID3 ");
+        pmt.program_info.put_u8(0xFF);
+        pmt.program_info.put_slice(b"ID3 ");
+        /*metadata_service_id*/
+        pmt.program_info.put_u8(0x00);
+        /*no metadata locator record and the metadata is carried in the same transport stream*/
+        pmt.program_info.put_u8(0x1F);
+        pmt.program_info.put_u16(pmt.program_number);
+
+        Ok(pid)
+    }
+
+    //Changes the codec of a stream, e.g. when the video sequence header tells the codec
+    //after the stream is added. The PAT/PMT are written again before the next pes.
+    pub fn set_stream_codec(&mut self, pid: u16, codecid: u8) -> Result<(), MpegTsError> {
+        self.find_stream(pid)?;
+
+        let cur_pmt = &mut self.pat.pmt[self.cur_pmt_index];
+        let cur_stream = &mut cur_pmt.streams[self.cur_stream_index];
+        if cur_stream.codec_id == codecid {
+            return Ok(());
+        }
+        cur_stream.codec_id = codecid;
+        cur_pmt.version_number = (cur_pmt.version_number + 1) % 32;
+
+        self.reset();
+
+        Ok(())
+    }
+
     pub fn pmt_add_stream(
         &mut self,
         pmt_index: usize,
@@ -433,3 +491,67 @@ impl TsMuxer {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::TsMuxer;
+    use crate::define::{epsi_stream_type, MPEG_FLAG_IDR_FRAME, TS_PACKET_SIZE};
+    use bytes::BytesMut;
+
+    #[test]
+    fn test_h265_stream() {
+        let mut muxer = TsMuxer::new();
+        let video_pid = muxer
+            .add_stream(epsi_stream_type::PSI_STREAM_H264, BytesMut::new())
+            .unwrap();
+        muxer
+            .set_stream_codec(video_pid, epsi_stream_type::PSI_STREAM_H265)
+            .unwrap();
+
+        //an IDR_W_RADL nal unit
+        let payload = BytesMut::from(&[0x00, 0x00, 0x00, 0x01, 0x26, 0x01, 0xaf][..]);
+        muxer
+            .write(video_pid, 90000, 90000, MPEG_FLAG_IDR_FRAME, payload)
+            .unwrap();
+
+        let data = muxer.get_data();
+        //the PAT, the PMT and the PES
+        assert_eq!(data.len(), 3 * TS_PACKET_SIZE);
+
+        let pmt = &data[TS_PACKET_SIZE..2 * TS_PACKET_SIZE];
+        //the stream type follows the header(4), the pointer field(1) and the 12 bytes
+        //from the table id to the program info length
+        assert_eq!(pmt[17], epsi_stream_type::PSI_STREAM_H265);
+
+        let pes = &data[2 * TS_PACKET_SIZE..];
+        let aud: [u8; 7] = [0x00, 0x00, 0x00, 0x01, 0x46, 0x01, 0x50];
+        assert!(pes.windows(aud.len()).any(|window| window == aud));
+    }
+
+    #[test]
+    fn test_id3_stream() {
+        let mut muxer = TsMuxer::new();
+        let id3_pid = muxer.add_id3_stream().unwrap();
+
+        let payload = BytesMut::from(&b"ID3\x04\x00\x00\x00\x00\x00\x00"[..]);
+        muxer.write(id3_pid, 90000, 90000, 0, payload).unwrap();
+
+        let data = muxer.get_data();
+        assert_eq!(data.len(), 3 * TS_PACKET_SIZE);
+
+        let pmt = &data[TS_PACKET_SIZE..2 * TS_PACKET_SIZE];
+        //the program info starts with the metadata pointer descriptor
+        assert_eq!(pmt[17], 0x25);
+        //the stream type follows the 17 bytes of the program info
+        assert_eq!(pmt[34], epsi_stream_type::PSI_STREAM_METADATA);
+        //the metadata descriptor follows the pid and the es info length
+        assert_eq!(pmt[39], 0x26);
+
+        //the tag is carried in a private stream 1 pes
+        let pes = &data[2 * TS_PACKET_SIZE..];
+        assert!(pes
+            .windows(4)
+            .any(|window| window == [0x00, 0x00, 0x01, 0xBD]));
+        assert!(pes.windows(3).any(|window| window == b"ID3"));
+    }
+}
@@ -18,7 +18,10 @@ pub fn pcr_write(pcr_result: &mut BytesWriter, pcr: i64) -> Result<(), BytesWrit
 }
 
 pub fn is_steam_type_video(stream_type: u8) -> bool {
-    matches!(stream_type, epsi_stream_type::PSI_STREAM_H264)
+    matches!(
+        stream_type,
+        epsi_stream_type::PSI_STREAM_H264 | epsi_stream_type::PSI_STREAM_H265
+    )
 }
 
 pub fn is_steam_type_audio(stream_type: u8) -> bool {
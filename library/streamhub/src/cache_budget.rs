@@ -0,0 +1,205 @@
+use {
+    crate::stream::StreamIdentifier,
+    std::{
+        cmp::Reverse,
+        collections::HashMap,
+        sync::{Arc, Mutex},
+    },
+};
+
+#[derive(Debug)]
+struct CacheEntry {
+    identifier: StreamIdentifier,
+    bytes: usize,
+    //the cache is chosen to be emptied, it is done when the cache is updated next time.
+    evict: bool,
+}
+
+#[derive(Debug, Default)]
+struct BudgetState {
+    //0 means no limit
+    max_bytes: usize,
+    next_id: u64,
+    caches: HashMap<u64, CacheEntry>,
+    //the bytes of the caches which are not chosen to be emptied
+    total_bytes: usize,
+    //stream identifier to its subscriber count, the streams without subscribers are absent.
+    subscriber_counts: HashMap<StreamIdentifier, usize>,
+}
+
+impl BudgetState {
+    //The caches of the streams without subscribers are chosen first, the larger ones
+    //before the smaller ones, until the left caches fit in the budget.
+    fn choose_evicted_caches(&mut self) {
+        let mut candidates: Vec<(bool, usize, u64)> = self
+            .caches
+            .iter()
+            .filter(|(_, entry)| !entry.evict && entry.bytes > 0)
+            .map(|(id, entry)| {
+                let subscribed = self.subscriber_counts.contains_key(&entry.identifier);
+                (subscribed, entry.bytes, *id)
+            })
+            .collect();
+        candidates.sort_by_key(|&(subscribed, bytes, _)| (subscribed, Reverse(bytes)));
+
+        for (_, bytes, id) in candidates {
+            if self.total_bytes <= self.max_bytes {
+                break;
+            }
+            if let Some(entry) = self.caches.get_mut(&id) {
+                log::info!(
+                    "the gop cache budget {} is exceeded, evict the cache of {}: {} bytes",
+                    self.max_bytes,
+                    entry.identifier,
+                    bytes
+                );
+                entry.evict = true;
+                self.total_bytes -= bytes;
+            }
+        }
+    }
+}
+
+//The process wide memory budget of the gop caches. The usage of each cache is
+//tracked, and when the budget is exceeded the caches of the streams without
+//subscribers are emptied first.
+#[derive(Debug, Clone, Default)]
+pub struct CacheBudget {
+    state: Arc<Mutex<BudgetState>>,
+}
+
+impl CacheBudget {
+    pub fn new(max_bytes: usize) -> Self {
+        let budget = Self::default();
+        budget.set_max_bytes(max_bytes);
+        budget
+    }
+
+    pub fn set_max_bytes(&self, max_bytes: usize) {
+        self.state.lock().unwrap().max_bytes = max_bytes;
+    }
+
+    pub fn register(&self, identifier: StreamIdentifier) -> CacheUsage {
+        let mut state = self.state.lock().unwrap();
+        let id = state.next_id;
+        state.next_id += 1;
+        state.caches.insert(
+            id,
+            CacheEntry {
+                identifier,
+                bytes: 0,
+                evict: false,
+            },
+        );
+        CacheUsage {
+            id,
+            budget: self.clone(),
+        }
+    }
+
+    pub fn set_subscriber_count(&self, identifier: &StreamIdentifier, count: usize) {
+        let mut state = self.state.lock().unwrap();
+        if count > 0 {
+            state.subscriber_counts.insert(identifier.clone(), count);
+        } else {
+            state.subscriber_counts.remove(identifier);
+        }
+    }
+
+    pub fn stream_bytes(&self, identifier: &StreamIdentifier) -> usize {
+        let state = self.state.lock().unwrap();
+        state
+            .caches
+            .values()
+            .filter(|entry| !entry.evict && &entry.identifier == identifier)
+            .map(|entry| entry.bytes)
+            .sum()
+    }
+
+    pub fn total_bytes(&self) -> usize {
+        self.state.lock().unwrap().total_bytes
+    }
+}
+
+//The registration of a gop cache in the budget, it is removed when dropped.
+#[derive(Debug)]
+pub struct CacheUsage {
+    id: u64,
+    budget: CacheBudget,
+}
+
+impl CacheUsage {
+    //Updates the bytes held by the cache, returns true if the cache should be emptied.
+    pub fn update(&self, bytes: usize) -> bool {
+        let mut state = self.budget.state.lock().unwrap();
+        let Some(entry) = state.caches.get_mut(&self.id) else {
+            return false;
+        };
+        if entry.evict {
+            entry.evict = false;
+            entry.bytes = 0;
+            return true;
+        }
+        let old_bytes = std::mem::replace(&mut entry.bytes, bytes);
+        state.total_bytes = state.total_bytes - old_bytes + bytes;
+
+        if state.max_bytes > 0 && state.total_bytes > state.max_bytes {
+            state.choose_evicted_caches();
+        }
+        false
+    }
+}
+
+impl Drop for CacheUsage {
+    fn drop(&mut self) {
+        let mut state = self.budget.state.lock().unwrap();
+        if let Some(entry) = state.caches.remove(&self.id) {
+            if !entry.evict {
+                state.total_bytes -= entry.bytes;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CacheBudget;
+    use crate::stream::StreamIdentifier;
+
+    fn identifier(stream_name: &str) -> StreamIdentifier {
+        StreamIdentifier::Rtmp {
+            app_name: String::from("live"),
+            stream_name: String::from(stream_name),
+        }
+    }
+
+    #[test]
+    fn test_evict_streams_without_subscribers_first() {
+        let budget = CacheBudget::new(1000);
+        let watched = budget.register(identifier("watched"));
+        let idle = budget.register(identifier("idle"));
+        budget.set_subscriber_count(&identifier("watched"), 1);
+
+        assert!(!idle.update(400));
+        assert!(!watched.update(500));
+        assert_eq!(budget.total_bytes(), 900);
+
+        //the watched stream exceeds the budget, the idle one is chosen
+        assert!(!watched.update(700));
+        assert_eq!(budget.total_bytes(), 700);
+        assert_eq!(budget.stream_bytes(&identifier("idle")), 0);
+        assert!(idle.update(450));
+        assert!(!idle.update(0));
+
+        //the idle cache alone is not enough, the watched one is chosen as well
+        assert!(!idle.update(200));
+        assert!(!watched.update(1100));
+        assert_eq!(budget.total_bytes(), 0);
+        assert!(idle.update(200));
+        assert!(watched.update(1100));
+
+        assert!(!watched.update(300));
+        drop(watched);
+        assert_eq!(budget.total_bytes(), 0);
+    }
+}
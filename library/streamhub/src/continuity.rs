@@ -0,0 +1,264 @@
+use {
+    crate::{
+        define::{FrameData, TimestampPolicy},
+        normalizer::TimestampNormalizer,
+        subscriber::TQueueData,
+    },
+    xflv::define::{aac_packet_type, avc_packet_type, AvcCodecId, SoundFormat},
+};
+
+//Keeps the frames of a stream continuous for its subscribers when the publisher
+//of the stream is switched: the a/v frames of the new publisher are dropped until its
+//first video key frame(or its first audio frame if no video comes before it), and the
+//timestamps are rebased on that frame to continue one frame interval after the last sent
+//timestamp. The video frames are dropped until the first key frame even if the rebase is
+//done on an audio frame. The timestamps of each publisher are normalized first.
+pub struct StreamContinuity {
+    normalizer: TimestampNormalizer,
+    //added to the timestamps of the current publisher
+    offset: u32,
+    last_timestamp: u32,
+    //the interval between the last two video frames sent
+    frame_interval: u32,
+    last_video_timestamp: Option<u32>,
+    rebase_pending: bool,
+    //the new publisher has sent video, the rebase waits for its first key frame
+    publisher_has_video: bool,
+    //the video frames of the new publisher are dropped until its first key frame
+    wait_video_key_frame: bool,
+}
+
+impl Default for StreamContinuity {
+    fn default() -> Self {
+        Self::new(TimestampPolicy {
+            enabled: false,
+            max_jump_ms: 0,
+        })
+    }
+}
+
+impl StreamContinuity {
+    pub fn new(timestamp_policy: TimestampPolicy) -> Self {
+        Self {
+            normalizer: TimestampNormalizer::new(timestamp_policy),
+            offset: 0,
+            last_timestamp: 0,
+            frame_interval: 0,
+            last_video_timestamp: None,
+            rebase_pending: false,
+            publisher_has_video: false,
+            wait_video_key_frame: false,
+        }
+    }
+
+    pub fn switch_publisher(&mut self) {
+        self.normalizer.reset();
+        self.rebase_pending = true;
+        self.publisher_has_video = false;
+        self.wait_video_key_frame = true;
+    }
+
+    pub fn last_timestamp(&self) -> u32 {
+        self.last_timestamp
+    }
+
+    //Returns the marker which is sent before the last processed frame if there is a
+    //discontinuity in front of it.
+    pub fn take_discontinuity(&mut self) -> Option<FrameData> {
+        if self.normalizer.take_discontinuity() {
+            Some(FrameData::Discontinuity {
+                timestamp: self.last_timestamp,
+            })
+        } else {
+            None
+        }
+    }
+
+    //Maps the timestamp of a frame which the current publisher has sent before, e.g.
+    //a cached frame, to the timeline of the subscribers.
+    pub fn translate(&self, frame: &mut FrameData) {
+        if let Some(timestamp) = frame.timestamp() {
+            let timestamp = self.normalizer.translate(timestamp);
+            frame.set_timestamp(timestamp.wrapping_add(self.offset));
+        }
+    }
+
+    //Returns None if the frame cannot be sent to the subscribers.
+    pub fn process(&mut self, mut frame: FrameData) -> Option<FrameData> {
+        self.normalizer.process(&mut frame);
+
+        let is_av = !is_sequence_header(&frame) && (frame.is_video() || frame.is_audio());
+        if self.rebase_pending {
+            if frame.is_video() {
+                self.publisher_has_video = true;
+            }
+            //the sequence headers and data messages of the new publisher are sent at once
+            if !is_av {
+                if frame.timestamp().is_some() {
+                    frame.set_timestamp(self.last_timestamp);
+                }
+                return Some(frame);
+            }
+            let starts_stream = if frame.is_video() {
+                frame.is_key_frame()
+            } else {
+                !self.publisher_has_video
+            };
+            if !starts_stream {
+                return None;
+            }
+            if let Some(timestamp) = frame.timestamp() {
+                let next_timestamp = match self.last_video_timestamp {
+                    Some(_) => self.last_timestamp.wrapping_add(self.frame_interval.max(1)),
+                    None => self.last_timestamp,
+                };
+                self.offset = next_timestamp.wrapping_sub(timestamp);
+            }
+            self.rebase_pending = false;
+        }
+
+        if self.wait_video_key_frame && is_av && frame.is_video() {
+            if !frame.is_key_frame() {
+                return None;
+            }
+            self.wait_video_key_frame = false;
+        }
+
+        if let Some(timestamp) = frame.timestamp() {
+            self.last_timestamp = timestamp.wrapping_add(self.offset);
+            frame.set_timestamp(self.last_timestamp);
+            if is_av && frame.is_video() {
+                if let Some(last_video_timestamp) = self.last_video_timestamp {
+                    let interval = self.last_timestamp.wrapping_sub(last_video_timestamp);
+                    if interval > 0 && interval < 1000 {
+                        self.frame_interval = interval;
+                    }
+                }
+                self.last_video_timestamp = Some(self.last_timestamp);
+            }
+        }
+
+        Some(frame)
+    }
+}
+
+//The FLV sequence headers(AVC/HEVC decoder configuration records and AAC audio
+//specific configs) and the codec configurations which a decoder needs before the first frame.
+pub fn is_sequence_header(frame: &FrameData) -> bool {
+    match frame {
+        FrameData::CodecConfig { config: _ } => true,
+        FrameData::Video { timestamp: _, data } => {
+            if data.len() < 2 {
+                return false;
+            }
+            let codec_id = data[0] & 0x0F;
+            (codec_id == AvcCodecId::H264 as u8 || codec_id == AvcCodecId::HEVC as u8)
+                && data[1] == avc_packet_type::AVC_SEQHDR
+        }
+        FrameData::Audio { timestamp: _, data } => {
+            data.len() >= 2
+                && (data[0] >> 4) == SoundFormat::AAC as u8
+                && data[1] == aac_packet_type::AAC_SEQHDR
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StreamContinuity;
+    use crate::define::FrameData;
+    use bytes::Bytes;
+
+    fn video(timestamp: u32, first_byte: u8) -> FrameData {
+        FrameData::Video {
+            timestamp,
+            data: Bytes::copy_from_slice(&[first_byte, 0x01]),
+        }
+    }
+
+    fn audio(timestamp: u32) -> FrameData {
+        FrameData::Audio {
+            timestamp,
+            data: Bytes::from_static(&[0xaf, 0x01]),
+        }
+    }
+
+    fn timestamp_of(frame: Option<FrameData>) -> Option<u32> {
+        frame.and_then(|frame| frame.timestamp())
+    }
+
+    #[test]
+    fn test_switch_publisher() {
+        let mut continuity = StreamContinuity::default();
+
+        assert_eq!(
+            timestamp_of(continuity.process(video(1000, 0x17))),
+            Some(1000)
+        );
+        assert_eq!(
+            timestamp_of(continuity.process(video(1040, 0x27))),
+            Some(1040)
+        );
+
+        continuity.switch_publisher();
+        //the sequence header of the new publisher is sent at once
+        assert_eq!(
+            timestamp_of(continuity.process(FrameData::Video {
+                timestamp: 0,
+                data: Bytes::from_static(&[0x17, 0x00]),
+            })),
+            Some(1040)
+        );
+        //the a/v frames of the new publisher are dropped until its first key frame,
+        //which continues one frame interval after the last sent frame
+        assert_eq!(timestamp_of(continuity.process(audio(10))), None);
+        assert_eq!(timestamp_of(continuity.process(video(20, 0x27))), None);
+        assert_eq!(
+            timestamp_of(continuity.process(video(60, 0x17))),
+            Some(1080)
+        );
+        assert_eq!(timestamp_of(continuity.process(audio(70))), Some(1090));
+        assert_eq!(
+            timestamp_of(continuity.process(video(100, 0x27))),
+            Some(1120)
+        );
+    }
+
+    #[test]
+    fn test_switch_publisher_audio_first() {
+        let mut continuity = StreamContinuity::default();
+        assert_eq!(
+            timestamp_of(continuity.process(video(1000, 0x17))),
+            Some(1000)
+        );
+        assert_eq!(
+            timestamp_of(continuity.process(video(1040, 0x27))),
+            Some(1040)
+        );
+
+        //the new publisher resumes in the middle of a gop and its audio comes first.
+        continuity.switch_publisher();
+        assert_eq!(timestamp_of(continuity.process(audio(200))), Some(1080));
+        assert_eq!(timestamp_of(continuity.process(video(210, 0x27))), None);
+        assert_eq!(timestamp_of(continuity.process(audio(223))), Some(1103));
+        assert_eq!(
+            timestamp_of(continuity.process(video(250, 0x17))),
+            Some(1130)
+        );
+        assert_eq!(
+            timestamp_of(continuity.process(video(290, 0x27))),
+            Some(1170)
+        );
+    }
+
+    #[test]
+    fn test_switch_to_audio_only_publisher() {
+        let mut continuity = StreamContinuity::default();
+        assert_eq!(timestamp_of(continuity.process(audio(500))), Some(500));
+
+        continuity.switch_publisher();
+        assert_eq!(timestamp_of(continuity.process(audio(3000))), Some(500));
+        assert_eq!(timestamp_of(continuity.process(audio(3023))), Some(523));
+    }
+}
@@ -1,16 +1,24 @@
 use chrono::{DateTime, Local};
 use serde::Deserialize;
 use serde_json::Value;
-use xflv::define::{AacProfile, AvcCodecId, AvcLevel, AvcProfile, SoundFormat};
+use xflv::{
+    amf0::{amf0_reader::Amf0Reader, Amf0ValueType},
+    define::{AacProfile, AvcCodecId, AvcLevel, AvcProfile, SoundFormat},
+    media::{codec_config_to_flv, media_frame_to_flv},
+};
 
 use crate::utils;
 
 use {
     super::errors::StreamHubError,
+    crate::slate::FlvSlate,
     crate::statistics::StatisticsStream,
     crate::stream::StreamIdentifier,
+    crate::subscriber::TQueueData,
     async_trait::async_trait,
-    bytes::BytesMut,
+    bytes::{BufMut, Bytes, BytesMut},
+    bytesio::bytes_reader::BytesReader,
+    commonlib::media::{CodecConfig, MediaFrame},
     serde::ser::SerializeStruct,
     serde::Serialize,
     serde::Serializer,
@@ -27,8 +35,13 @@ pub enum SubscribeType {
     RtmpPull,
     /* Remote request to play httpflv triggers remux from RTMP to httpflv. */
     RtmpRemux2HttpFlv,
+    /* The FLV muxing stage shared by the httpflv viewers of a stream, the viewers join it
+    with their own RtmpRemux2HttpFlv registrations.*/
+    RtmpRemux2HttpFlvStage,
     /* The publishing of RTMP stream triggers remuxing from RTMP to HLS protocol.(NOTICE:It is not triggerred by players.)*/
     RtmpRemux2Hls,
+    /* The publishing of RTMP stream triggers remuxing from RTMP to DASH protocol.(NOTICE:It is not triggerred by players.)*/
+    RtmpRemux2Dash,
     /* Relay(Push) local RTMP stream from stream hub to other RTMP nodes.*/
     RtmpRelay,
     /* Remote client request pulling(play) a rtsp stream.*/
@@ -45,6 +58,16 @@ pub enum SubscribeType {
     WhipRelay,
     /* Pull rtp stream by subscribing from stream hub.*/
     RtpPull,
+    /* Record the stream into its time-shift buffer.*/
+    DvrRecord,
+}
+
+impl SubscribeType {
+    //The subscription feeds the viewers joining it, it is not counted or notified as a
+    //subscriber itself.
+    pub fn is_shared(&self) -> bool {
+        matches!(self, SubscribeType::RtmpRemux2HttpFlvStage)
+    }
 }
 
 /* Publish streams to stream hub */
@@ -66,7 +89,7 @@ pub enum PublishType {
     RtpPush,
 }
 
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Clone, Default)]
 pub struct NotifyInfo {
     pub request_url: String,
     pub remote_addr: String,
@@ -78,6 +101,55 @@ pub struct SubscriberInfo {
     pub sub_type: SubscribeType,
     pub notify_info: NotifyInfo,
     pub sub_data_type: SubDataType,
+    pub track_filter: TrackFilter,
+}
+
+//The tracks a subscriber receives, it is set from the query of the subscriber's
+//url: `only_audio=1` or `only_video=1`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub enum TrackFilter {
+    #[default]
+    All,
+    AudioOnly,
+    VideoOnly,
+}
+
+impl TrackFilter {
+    pub fn from_request_url(request_url: &str) -> Self {
+        let Some((_, query)) = request_url.split_once('?') else {
+            return Self::All;
+        };
+        let enabled = |param: &str| {
+            query
+                .split('&')
+                .filter_map(|pair| pair.split_once('='))
+                .any(|(key, value)| key == param && (value == "1" || value == "true"))
+        };
+        if enabled("only_audio") {
+            Self::AudioOnly
+        } else if enabled("only_video") {
+            Self::VideoOnly
+        } else {
+            Self::All
+        }
+    }
+
+    pub fn has_audio(&self) -> bool {
+        *self != Self::VideoOnly
+    }
+
+    pub fn has_video(&self) -> bool {
+        *self != Self::AudioOnly
+    }
+
+    //the data of the other tracks(metadata etc.) is always accepted
+    pub fn accepts<T: TQueueData>(&self, data: &T) -> bool {
+        match self {
+            Self::All => true,
+            Self::AudioOnly => !data.is_video(),
+            Self::VideoOnly => !data.is_audio(),
+        }
+    }
 }
 
 impl Serialize for SubscriberInfo {
@@ -118,7 +190,7 @@ impl Serialize for PublisherInfo {
     }
 }
 
-#[derive(Clone, PartialEq)]
+#[derive(Clone, Copy, PartialEq)]
 pub enum VideoCodecType {
     H264,
     H265,
@@ -131,19 +203,176 @@ pub struct MediaInfo {
     pub vcodec: VideoCodecType,
 }
 
+//The payloads are frozen and reference counted, so sending a frame to
+//many subscribers only increases the reference count instead of copying data.
 #[derive(Clone)]
 pub enum FrameData {
-    Video { timestamp: u32, data: BytesMut },
-    Audio { timestamp: u32, data: BytesMut },
-    MetaData { timestamp: u32, data: BytesMut },
+    Video { timestamp: u32, data: Bytes },
+    Audio { timestamp: u32, data: Bytes },
+    //the AMF0 body of a data message, e.g. @setDataFrame/onMetaData, onTextData or onCuePoint.
+    MetaData { timestamp: u32, data: Bytes },
     MediaInfo { media_info: MediaInfo },
+    //the codec neutral frames and codec configurations of a producer, the stream hub
+    //converts them to FLV with `into_flv` once before they are sent to the subscribers.
+    Media { frame: MediaFrame },
+    CodecConfig { config: CodecConfig },
+    //Sent before the first frame after a timestamp jump or a publisher switch, the
+    //muxers which care, e.g. HLS, start a new segment after a discontinuity.
+    Discontinuity { timestamp: u32 },
+}
+
+impl FrameData {
+    //the decoding timestamp
+    pub fn timestamp(&self) -> Option<u32> {
+        match self {
+            FrameData::Video { timestamp, data: _ }
+            | FrameData::Audio { timestamp, data: _ }
+            | FrameData::MetaData { timestamp, data: _ }
+            | FrameData::Discontinuity { timestamp } => Some(*timestamp),
+            FrameData::Media { frame } => Some(frame.dts),
+            FrameData::CodecConfig { config } => Some(config.timestamp),
+            FrameData::MediaInfo { media_info: _ } => None,
+        }
+    }
+
+    //sets the decoding timestamp, the composition time offset of a media frame is kept.
+    pub fn set_timestamp(&mut self, new_timestamp: u32) {
+        match self {
+            FrameData::Video { timestamp, data: _ }
+            | FrameData::Audio { timestamp, data: _ }
+            | FrameData::MetaData { timestamp, data: _ }
+            | FrameData::Discontinuity { timestamp } => *timestamp = new_timestamp,
+            FrameData::Media { frame } => {
+                frame.pts = new_timestamp.wrapping_add(frame.cts() as u32);
+                frame.dts = new_timestamp;
+            }
+            FrameData::CodecConfig { config } => config.timestamp = new_timestamp,
+            FrameData::MediaInfo { media_info: _ } => {}
+        }
+    }
+
+    //Converts a codec neutral frame or codec configuration to its FLV tag body,
+    //the other frames are returned as they are. None if FLV can not carry it.
+    pub fn into_flv(self) -> Option<FrameData> {
+        let (result, timestamp, is_video) = match &self {
+            FrameData::Media { frame } => {
+                (media_frame_to_flv(frame), frame.dts, frame.codec.is_video())
+            }
+            FrameData::CodecConfig { config } => (
+                codec_config_to_flv(config),
+                config.timestamp,
+                config.codec.is_video(),
+            ),
+            //FLV has no discontinuity, the FLV consumers just continue.
+            FrameData::Discontinuity { timestamp: _ } => return None,
+            _ => return Some(self),
+        };
+
+        match result {
+            Ok(data) if is_video => Some(FrameData::Video { timestamp, data }),
+            Ok(data) => Some(FrameData::Audio { timestamp, data }),
+            Err(err) => {
+                log::warn!("convert the media frame to flv err: {}", err);
+                None
+            }
+        }
+    }
+}
+
+//A timed data message decoded from FrameData::MetaData, used by the consumers
+//which do not mux FLV and so can not use the raw AMF0 body.
+#[derive(Debug, Clone)]
+pub struct TimedMetadata {
+    pub timestamp: u32,
+    //the handler name, e.g. onMetaData, onTextData or onCuePoint
+    pub name: String,
+    pub values: Vec<Amf0ValueType>,
+}
+
+impl TimedMetadata {
+    pub fn from_frame(frame: &FrameData) -> Option<Self> {
+        let FrameData::MetaData { timestamp, data } = frame else {
+            return None;
+        };
+        let reader = BytesReader::new(BytesMut::from(&data[..]));
+        let mut values = Amf0Reader::new(reader).read_all().ok()?.into_iter();
+
+        let mut name = match values.next()? {
+            Amf0ValueType::UTF8String(name) => name,
+            _ => return None,
+        };
+        //RTMP publishers send the metadata wrapped with @setDataFrame
+        if name == "@setDataFrame" {
+            name = match values.next()? {
+                Amf0ValueType::UTF8String(name) => name,
+                _ => return None,
+            };
+        }
+
+        Some(Self {
+            timestamp: *timestamp,
+            name,
+            values: values.collect(),
+        })
+    }
+
+    pub fn is_stream_metadata(&self) -> bool {
+        self.name == "onMetaData"
+    }
+
+    //An ID3v2.4 tag with a TXXX frame, which is described with the handler name and holds
+    //the values as a JSON array. HLS and DASH carry it as timed metadata.
+    pub fn to_id3(&self) -> BytesMut {
+        let values: Vec<Value> = self.values.iter().map(amf0_to_json).collect();
+        let text = Value::Array(values).to_string();
+
+        /*text encoding UTF-8 | description | value*/
+        let mut frame = BytesMut::new();
+        frame.put_u8(0x03);
+        frame.put_slice(self.name.as_bytes());
+        frame.put_u8(0x00);
+        frame.put_slice(text.as_bytes());
+
+        let mut tag = BytesMut::new();
+        tag.put_slice(b"ID3");
+        /*version 2.4.0 without flags*/
+        tag.put_slice(&[0x04, 0x00, 0x00]);
+        tag.put_u32(syncsafe(frame.len() as u32 + 10));
+        tag.put_slice(b"TXXX");
+        tag.put_u32(syncsafe(frame.len() as u32));
+        tag.put_u16(0x00);
+        tag.put_slice(&frame[..]);
+        tag
+    }
+}
+
+//the sizes of ID3v2.4 use 7 bits of each byte
+fn syncsafe(size: u32) -> u32 {
+    (size & 0x7F) | ((size & 0x3F80) << 1) | ((size & 0x1F_C000) << 2) | ((size & 0x0FE0_0000) << 3)
+}
+
+fn amf0_to_json(value: &Amf0ValueType) -> Value {
+    match value {
+        Amf0ValueType::Number(number) => serde_json::json!(number),
+        Amf0ValueType::Boolean(boolean) => Value::Bool(*boolean),
+        Amf0ValueType::UTF8String(string) | Amf0ValueType::LongUTF8String(string) => {
+            Value::String(string.clone())
+        }
+        Amf0ValueType::Object(properties) | Amf0ValueType::EcmaArray(properties) => Value::Object(
+            properties
+                .iter()
+                .map(|(key, value)| (key.clone(), amf0_to_json(value)))
+                .collect(),
+        ),
+        Amf0ValueType::Null | Amf0ValueType::END => Value::Null,
+    }
 }
 
 //Used to pass rtp raw data.
 #[derive(Clone)]
 pub enum PacketData {
-    Video { timestamp: u32, data: BytesMut },
-    Audio { timestamp: u32, data: BytesMut },
+    Video { timestamp: u32, data: Bytes },
+    Audio { timestamp: u32, data: Bytes },
 }
 
 //used to save data which needs to be transferred between client/server sessions
@@ -163,6 +392,13 @@ pub type FrameDataReceiver = mpsc::UnboundedReceiver<FrameData>;
 pub type PacketDataSender = mpsc::UnboundedSender<PacketData>;
 pub type PacketDataReceiver = mpsc::UnboundedReceiver<PacketData>;
 
+//used to transfer a/v frame or rtp packet data from stream hub to a subscriber,
+//the channel is bounded so that a stalled subscriber cannot grow the memory without limit.
+pub type SubFrameDataSender = mpsc::Sender<FrameData>;
+pub type SubFrameDataReceiver = mpsc::Receiver<FrameData>;
+pub type SubPacketDataSender = mpsc::Sender<PacketData>;
+pub type SubPacketDataReceiver = mpsc::Receiver<PacketData>;
+
 pub type InformationSender = mpsc::UnboundedSender<Information>;
 pub type InformationReceiver = mpsc::UnboundedReceiver<Information>;
 
@@ -185,7 +421,7 @@ pub type StatisticApiResultSender = oneshot::Sender<Value>;
 pub type StatisticApiResultReceiver = oneshot::Receiver<Value>;
 
 pub type SubEventExecuteResultSender =
-    oneshot::Sender<Result<(DataReceiver, Option<StatisticDataSender>), StreamHubError>>;
+    oneshot::Sender<Result<(SubDataReceiver, Option<StatisticDataSender>), StreamHubError>>;
 pub type PubEventExecuteResultSender = oneshot::Sender<
     Result<
         (
@@ -199,6 +435,7 @@ pub type PubEventExecuteResultSender = oneshot::Sender<
 // The trait bound `BroadcastEvent: Clone` should be satisfied, so here we cannot use oneshot.
 pub type BroadcastEventExecuteResultSender = mpsc::Sender<Result<(), StreamHubError>>;
 pub type ApiRelayStreamResultSender = oneshot::Sender<Result<(), StreamHubError>>;
+pub type ApiExportClipResultSender = oneshot::Sender<Result<ClipInfo, StreamHubError>>;
 pub type TransceiverEventExecuteResultSender = oneshot::Sender<StatisticDataSender>;
 
 #[async_trait]
@@ -212,17 +449,135 @@ pub trait TStreamHandler: Send + Sync {
     async fn send_information(&self, sender: InformationSender);
 }
 
+impl fmt::Debug for dyn TStreamHandler {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "TStreamHandler")
+    }
+}
+
 //A publisher can publish one or two kinds of av stream at a time.
+#[derive(Debug)]
 pub struct DataReceiver {
     pub frame_receiver: Option<FrameDataReceiver>,
     pub packet_receiver: Option<PacketDataReceiver>,
 }
 
+//A subscriber only needs to subscribe to one type of stream at a time,
+//so only one of the receivers is set.
+pub struct SubDataReceiver {
+    pub frame_receiver: Option<SubFrameDataReceiver>,
+    pub packet_receiver: Option<SubPacketDataReceiver>,
+}
+
 //A subscriber only needs to subscribe to one type of stream at a time
 #[derive(Debug, Clone)]
 pub enum DataSender {
-    Frame { sender: FrameDataSender },
-    Packet { sender: PacketDataSender },
+    Frame { sender: SubFrameDataSender },
+    Packet { sender: SubPacketDataSender },
+}
+
+//How the stream hub treats a subscriber which cannot consume the data as fast as
+//the publisher produces it.
+#[derive(Debug, Clone)]
+pub struct SubscriberQueuePolicy {
+    //the capacity of each subscriber's queue, counted in frames or packets
+    pub queue_size: usize,
+    //When a subscriber's queue is full, the following video frames are dropped until the
+    //next key frame can be queued. If the queue stays full for this duration, the subscriber
+    //will be disconnected. 0 means never disconnect.
+    pub max_lag_ms: u64,
+}
+
+impl Default for SubscriberQueuePolicy {
+    fn default() -> Self {
+        Self {
+            queue_size: 1024,
+            max_lag_ms: 10000,
+        }
+    }
+}
+
+//Whether the rtsp/whip streams are remuxed to rtmp as soon as they are published,
+//or only while their rtmp stream is played(RTMP, HTTP-FLV).
+#[derive(Debug, Clone)]
+pub struct RemuxPolicy {
+    pub on_demand: bool,
+    //an on demand remuxing is stopped after its rtmp stream has no subscribers for
+    //this duration
+    pub idle_timeout_ms: u64,
+}
+
+impl Default for RemuxPolicy {
+    fn default() -> Self {
+        Self {
+            on_demand: false,
+            idle_timeout_ms: 30000,
+        }
+    }
+}
+
+//How the stream hub normalizes the timestamps of the publishers before they are
+//sent to the subscribers, see `TimestampNormalizer`.
+#[derive(Debug, Clone)]
+pub struct TimestampPolicy {
+    //rebase the streams to start from 0, keep the DTS monotonic and remove the jumps,
+    //it is enabled per app and the timestamps are passed through by default.
+    pub enabled: bool,
+    //a timestamp step forwards or backwards larger than this is removed and flagged as a
+    //discontinuity, 0 means the jumps are kept.
+    pub max_jump_ms: u32,
+}
+
+impl Default for TimestampPolicy {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_jump_ms: 10000,
+        }
+    }
+}
+
+//The rolling buffer of a stream which the subscribers can join at a past offset with
+//the `timeshift` query parameter of their play urls, see `DvrBuffer`.
+#[derive(Debug, Clone)]
+pub struct DvrPolicy {
+    //how much of the stream is kept
+    pub window_ms: u64,
+    //the closed gops are written to this directory instead of being held in memory
+    pub spill_dir: Option<String>,
+}
+
+//What the stream hub does when a stream which is already published is published again.
+#[derive(Debug, Deserialize, Clone, Default, PartialEq)]
+pub enum PublishPolicy {
+    //the new publisher is rejected with `Exists`
+    #[default]
+    #[serde(rename = "reject")]
+    Reject,
+    //the current publisher is kicked and the new one takes over the stream
+    #[serde(rename = "replace")]
+    Replace,
+    //the new publisher is held as a hot standby which takes over when the current one leaves
+    #[serde(rename = "standby")]
+    Standby,
+}
+
+//The stream hub behaviours which can be configured per app.
+#[derive(Debug, Clone, Default)]
+pub struct AppPolicy {
+    //After the publisher of a stream disconnects, the stream and its subscribers are kept
+    //for this duration, and a republish within it continues the stream. 0 means no grace period.
+    pub reconnect_grace_ms: u64,
+    //Looped to the subscribers while the publisher is gone, the stream waits for the
+    //publisher without a time limit if there is no grace period.
+    pub fallback_slate: Option<Arc<FlvSlate>>,
+    //A subscriber of a stream which is not published yet waits for it for this duration
+    //instead of failing immediately. 0 means no wait.
+    pub subscribe_wait_ms: u64,
+    pub publish_policy: PublishPolicy,
+    pub timestamp_policy: TimestampPolicy,
+    //no time-shift buffer if it is None
+    pub dvr_policy: Option<DvrPolicy>,
 }
 //we can only sub one kind of stream.
 #[derive(Debug, Clone, Serialize)]
@@ -256,9 +611,22 @@ pub enum StreamHubEventMessage {
         identifier: StreamIdentifier,
         info: PublisherInfo,
     },
+    //a clip of the stream has been exported
+    Clip {
+        identifier: StreamIdentifier,
+        clip: ClipInfo,
+    },
     NotSupport {},
 }
 
+//An FLV file which holds the last seconds of a stream.
+#[derive(Debug, Clone, Serialize)]
+pub struct ClipInfo {
+    pub path: String,
+    pub duration_ms: u64,
+    pub size: u64,
+}
+
 //we can pub frame or packet or both.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum RelayType {
@@ -290,6 +658,25 @@ pub enum StreamHubEvent {
         identifier: StreamIdentifier,
         info: PublisherInfo,
     },
+    //a viewer joins or leaves a shared subscription(the http-flv muxing stage), it is
+    //counted and notified as a subscriber but receives no data from the stream hub.
+    ViewerJoin {
+        identifier: StreamIdentifier,
+        info: SubscriberInfo,
+    },
+    ViewerLeave {
+        identifier: StreamIdentifier,
+        info: SubscriberInfo,
+    },
+    //the subscriber waiting for the stream to be published has timed out.
+    #[serde(skip_serializing)]
+    SubscribeTimeout {
+        identifier: StreamIdentifier,
+        id: Uuid,
+    },
+    //the on demand remuxed stream may have been idle for the idle timeout.
+    #[serde(skip_serializing)]
+    RemuxIdleTimeout { identifier: StreamIdentifier },
     #[serde(skip_serializing)]
     ApiStatistic {
         top_n: Option<usize>,
@@ -313,6 +700,13 @@ pub enum StreamHubEvent {
         relay_type: RelayType,
         result_sender: ApiRelayStreamResultSender,
     },
+    //export the last seconds of a stream from its time-shift buffer to a file
+    #[serde(skip_serializing)]
+    ApiExportClip {
+        identifier: StreamIdentifier,
+        duration_ms: u64,
+        result_sender: ApiExportClipResultSender,
+    },
     #[serde(skip_serializing)]
     Request {
         identifier: StreamIdentifier,
@@ -331,12 +725,17 @@ impl StreamHubEvent {
                 identifier: identifier.clone(),
                 info: info.clone(),
             },
-            StreamHubEvent::UnSubscribe { identifier, info } => {
+            StreamHubEvent::UnSubscribe { identifier, info }
+            | StreamHubEvent::ViewerLeave { identifier, info } => {
                 StreamHubEventMessage::UnSubscribe {
                     identifier: identifier.clone(),
                     info: info.clone(),
                 }
             }
+            StreamHubEvent::ViewerJoin { identifier, info } => StreamHubEventMessage::Subscribe {
+                identifier: identifier.clone(),
+                info: info.clone(),
+            },
             StreamHubEvent::Publish {
                 identifier,
                 info,
@@ -365,12 +764,32 @@ pub enum TransceiverEvent {
     UnSubscribe {
         info: SubscriberInfo,
     },
+    ViewerJoin {
+        info: SubscriberInfo,
+    },
+    ViewerLeave {
+        info: SubscriberInfo,
+    },
     UnPublish {},
+    //the publisher has left, the subscribers are kept until it republishes or
+    //the reconnect grace period ends.
+    Suspend {},
+    //the standby publisher or the reconnected publisher takes over the stream,
+    //the subscribers are kept.
+    Republish {
+        data_receiver: DataReceiver,
+        stream_handler: Arc<dyn TStreamHandler>,
+    },
 
     Api {
         sender: StatisticStreamSender,
         uuid: Option<Uuid>,
     },
+    ExportClip {
+        duration_ms: u64,
+        path: String,
+        result_sender: ApiExportClipResultSender,
+    },
     Request {
         sender: InformationSender,
     },
@@ -387,10 +806,17 @@ pub enum BroadcastEvent {
     /*Need publish(push) a stream to other rtmp server*/
     Publish {
         identifier: StreamIdentifier,
+        //the stream hub normalizes the timestamps of the stream and flags its discontinuities
+        timestamp_normalized: bool,
     },
     UnPublish {
         identifier: StreamIdentifier,
     },
+    /*Need remux a rtsp/whip stream to rtmp, it is sent when the rtmp stream is subscribed
+    if the remuxing is on demand*/
+    Remux {
+        identifier: StreamIdentifier,
+    },
     /*Need subscribe(pull) a stream from other rtmp server*/
     Subscribe {
         id: String,
@@ -444,4 +870,10 @@ pub enum StatisticData {
         sub_type: SubscribeType,
         start_time: DateTime<Local>,
     },
+    /*frames(packets) dropped by stream hub because the subscriber's queue is full*/
+    SubscriberDrop {
+        id: Uuid,
+        frame_count: usize,
+        data_size: usize,
+    },
 }
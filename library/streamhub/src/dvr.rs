@@ -0,0 +1,743 @@
+use {
+    crate::{
+        continuity::is_sequence_header,
+        define::{
+            ClipInfo, DvrPolicy, FrameData, SubFrameDataReceiver, SubFrameDataSender,
+            TimedMetadata, TrackFilter,
+        },
+        errors::{StreamHubError, StreamHubErrorValue},
+        stream::StreamIdentifier,
+        subscriber::TQueueData,
+        utils::{RandomDigitCount, Uuid},
+    },
+    bytes::{Buf, BufMut, Bytes, BytesMut},
+    std::{
+        collections::VecDeque,
+        fs, io,
+        path::{Path, PathBuf},
+        sync::Arc,
+    },
+    tokio::{
+        sync::{Mutex, Notify},
+        time::{sleep_until, Duration, Instant},
+    },
+    xflv::muxer::{FlvMuxer, HEADER_LENGTH},
+};
+
+//the query parameter of a play url which asks for the stream of some seconds ago.
+const TIME_SHIFT_PARAM: &str = "timeshift";
+
+const TAG_AUDIO: u8 = 8;
+const TAG_VIDEO: u8 = 9;
+const TAG_SCRIPT_DATA: u8 = 18;
+//the spilled gops which are read back and kept for the other time shifted subscribers
+const RELOADED_GOP_COUNT: usize = 4;
+//the AMF0 string "@setDataFrame"
+const SET_DATA_FRAME: [u8; 16] = [
+    0x02, 0x00, 0x0d, b'@', b's', b'e', b't', b'D', b'a', b't', b'a', b'F', b'r', b'a', b'm', b'e',
+];
+
+//Returns the time shift in milliseconds which a play url requests, e.g.
+//rtmp://host/live/test?timeshift=30 plays the stream of 30 seconds ago.
+pub fn time_shift_ms(request_url: &str) -> Option<u64> {
+    let (_, query) = request_url.split_once('?')?;
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == TIME_SHIFT_PARAM)
+        .and_then(|(_, value)| value.parse::<u64>().ok())
+        .filter(|seconds| *seconds > 0)
+        .map(|seconds| seconds * 1000)
+}
+
+struct DvrGop {
+    seq: u64,
+    //the sequence headers and stream metadata in effect when the gop starts
+    headers: Vec<FrameData>,
+    //the extended timestamp of its key frame
+    start: u64,
+    frames: Vec<FrameData>,
+    //the frames are moved to this file after the gop is closed
+    path: Option<PathBuf>,
+    closed: bool,
+}
+
+enum DvrRead {
+    Frames {
+        frames: Vec<FrameData>,
+        //no more frames will be added to the gop
+        closed: bool,
+    },
+    //the gop has been spilled, its frames are read from the file without the store locked
+    Spilled {
+        path: PathBuf,
+    },
+    //the gop has been evicted or the stream is finished
+    End,
+}
+
+//The file operations of the store, they are done after the store is unlocked.
+#[derive(Default)]
+struct DvrFileOps {
+    //a closed gop to be spilled: its seq, file and frames
+    spill: Option<(u64, PathBuf, Vec<FrameData>)>,
+    //the files of the evicted gops
+    remove: Vec<PathBuf>,
+}
+
+impl DvrFileOps {
+    fn is_empty(&self) -> bool {
+        self.spill.is_none() && self.remove.is_empty()
+    }
+}
+
+struct DvrStore {
+    policy: DvrPolicy,
+    //the prefix of the spilled files
+    name: String,
+    video_seq: Option<FrameData>,
+    audio_seq: Option<FrameData>,
+    metadata: Option<FrameData>,
+    gops: VecDeque<DvrGop>,
+    next_seq: u64,
+    //the last timestamp extended to 64 bits
+    last_timestamp: Option<u64>,
+    finished: bool,
+    //the frames of the last spilled gops which are read back
+    reloaded: VecDeque<(u64, Arc<Vec<FrameData>>)>,
+}
+
+impl DvrStore {
+    fn extend(&mut self, timestamp: u32) -> u64 {
+        let extended = match self.last_timestamp {
+            Some(last) => {
+                let delta = timestamp.wrapping_sub(last as u32) as i32;
+                (last as i64 + delta as i64).max(0) as u64
+            }
+            None => timestamp as u64,
+        };
+        self.last_timestamp = Some(self.last_timestamp.unwrap_or(0).max(extended));
+        extended
+    }
+
+    fn push(&mut self, frame: FrameData) -> DvrFileOps {
+        let mut file_ops = DvrFileOps::default();
+        //a discontinuity is not replayed.
+        if let FrameData::Discontinuity { .. } = frame {
+            return file_ops;
+        }
+        let Some(timestamp) = frame.timestamp() else {
+            return file_ops;
+        };
+        let timestamp = self.extend(timestamp);
+
+        match &frame {
+            FrameData::Video { .. } if is_sequence_header(&frame) => {
+                self.video_seq = Some(frame.clone())
+            }
+            FrameData::Audio { .. } if is_sequence_header(&frame) => {
+                self.audio_seq = Some(frame.clone())
+            }
+            FrameData::Video { .. } if frame.is_key_frame() => {
+                file_ops.spill = self.start_gop(timestamp)
+            }
+            FrameData::MetaData { .. }
+                if TimedMetadata::from_frame(&frame)
+                    .is_some_and(|data| data.is_stream_metadata()) =>
+            {
+                self.metadata = Some(frame.clone())
+            }
+            _ => {}
+        }
+
+        //the frames before the first key frame are dropped.
+        if let Some(gop) = self.gops.back_mut() {
+            gop.frames.push(frame);
+        }
+        file_ops.remove = self.evict();
+        file_ops
+    }
+
+    //Closes the last gop and returns it to be spilled if there is a spill dir, its
+    //frames are kept in memory until it is written.
+    fn start_gop(&mut self, timestamp: u64) -> Option<(u64, PathBuf, Vec<FrameData>)> {
+        let mut spill = None;
+        if let Some(gop) = self.gops.back_mut() {
+            gop.closed = true;
+            if let Some(dir) = &self.policy.spill_dir {
+                let path = PathBuf::from(dir).join(format!("{}-{}.dvr", self.name, gop.seq));
+                spill = Some((gop.seq, path, gop.frames.clone()));
+            }
+        }
+
+        let headers = [&self.metadata, &self.video_seq, &self.audio_seq]
+            .iter()
+            .copied()
+            .flatten()
+            .cloned()
+            .collect();
+        self.gops.push_back(DvrGop {
+            seq: self.next_seq,
+            headers,
+            start: timestamp,
+            frames: Vec::new(),
+            path: None,
+            closed: false,
+        });
+        self.next_seq += 1;
+        spill
+    }
+
+    //The gop has been written to the file, its frames are dropped from the memory.
+    //Returns false if the gop has been evicted meanwhile.
+    fn spilled(&mut self, seq: u64, path: PathBuf) -> bool {
+        match self.gops.iter_mut().find(|gop| gop.seq == seq) {
+            Some(gop) => {
+                gop.frames = Vec::new();
+                gop.path = Some(path);
+                true
+            }
+            None => false,
+        }
+    }
+
+    //Keeps the gops which cover the window, the second gop must still start before it.
+    //Returns the files of the evicted gops.
+    fn evict(&mut self) -> Vec<PathBuf> {
+        let mut paths = Vec::new();
+        let last_timestamp = self.last_timestamp.unwrap_or(0);
+        while self.gops.len() > 1
+            && last_timestamp.saturating_sub(self.gops[1].start) >= self.policy.window_ms
+        {
+            if let Some(gop) = self.gops.pop_front() {
+                self.reloaded.retain(|(seq, _)| *seq != gop.seq);
+                paths.extend(gop.path);
+            }
+        }
+        paths
+    }
+
+    fn cache_reloaded(&mut self, seq: u64, frames: Arc<Vec<FrameData>>) {
+        if self.gops.iter().all(|gop| gop.seq != seq) {
+            return;
+        }
+        if self.reloaded.len() == RELOADED_GOP_COUNT {
+            self.reloaded.pop_front();
+        }
+        self.reloaded.push_back((seq, frames));
+    }
+
+    //Finds the gop whose key frame is the nearest to the time shifted position.
+    fn seek(&self, time_shift_ms: u64) -> Option<(u64, Vec<FrameData>, u64)> {
+        let target = self.last_timestamp?.saturating_sub(time_shift_ms);
+        self.gops
+            .iter()
+            .min_by_key(|gop| gop.start.abs_diff(target))
+            .map(|gop| (gop.seq, gop.headers.clone(), gop.start))
+    }
+
+    fn read(&self, seq: u64, from: usize) -> DvrRead {
+        let Some(gop) = self
+            .gops
+            .front()
+            .and_then(|front| seq.checked_sub(front.seq))
+            .and_then(|index| self.gops.get(index as usize))
+        else {
+            return DvrRead::End;
+        };
+
+        let frames: Vec<FrameData> = match &gop.path {
+            Some(path) => match self.reloaded.iter().find(|(reloaded, _)| *reloaded == seq) {
+                Some((_, frames)) => frames.iter().skip(from).cloned().collect(),
+                None => return DvrRead::Spilled { path: path.clone() },
+            },
+            None => gop.frames.iter().skip(from).cloned().collect(),
+        };
+        if self.finished && !gop.closed && frames.is_empty() {
+            return DvrRead::End;
+        }
+
+        DvrRead::Frames {
+            frames,
+            closed: gop.closed,
+        }
+    }
+}
+
+impl Drop for DvrStore {
+    fn drop(&mut self) {
+        for gop in &self.gops {
+            if let Some(path) = &gop.path {
+                if let Err(err) = fs::remove_file(path) {
+                    log::error!("dvr: remove {:?} err: {}", path, err);
+                }
+            }
+        }
+    }
+}
+
+//A rolling buffer of the last minutes of a stream which lets a subscriber join the
+//stream some seconds ago. It records the stream as an internal subscriber, and the
+//time shifted subscribers are fed from it at the real-time pace, so they stay behind
+//the live edge by the time shift.
+#[derive(Clone)]
+pub struct DvrBuffer {
+    store: Arc<Mutex<DvrStore>>,
+    //notified when a frame is recorded or the stream is finished
+    notify: Arc<Notify>,
+}
+
+impl DvrBuffer {
+    pub fn new(identifier: &StreamIdentifier, policy: DvrPolicy) -> Self {
+        let name = format!(
+            "{}-{}",
+            identifier.to_string().replace(['/', ':', ' '], "_"),
+            Uuid::new(RandomDigitCount::Four)
+        );
+        Self {
+            store: Arc::new(Mutex::new(DvrStore {
+                policy,
+                name,
+                video_seq: None,
+                audio_seq: None,
+                metadata: None,
+                gops: VecDeque::new(),
+                next_seq: 0,
+                last_timestamp: None,
+                finished: false,
+                reloaded: VecDeque::new(),
+            })),
+            notify: Arc::new(Notify::new()),
+        }
+    }
+
+    //Records the frames of the stream until the receiver is closed.
+    pub fn record(&self, mut receiver: SubFrameDataReceiver) {
+        let buffer = self.clone();
+        tokio::spawn(async move {
+            while let Some(frame) = receiver.recv().await {
+                let file_ops = buffer.store.lock().await.push(frame);
+                buffer.notify.notify_waiters();
+                if !file_ops.is_empty() {
+                    tokio::spawn(buffer.clone().run_file_ops(file_ops));
+                }
+            }
+            buffer.store.lock().await.finished = true;
+            buffer.notify.notify_waiters();
+        });
+    }
+
+    //Spills the closed gop and removes the files of the evicted gops, the store is
+    //only locked to drop the spilled frames from the memory.
+    async fn run_file_ops(self, file_ops: DvrFileOps) {
+        for path in file_ops.remove {
+            if let Err(err) = tokio::fs::remove_file(&path).await {
+                log::error!("dvr: remove {:?} err: {}", path, err);
+            }
+        }
+
+        let Some((seq, path, frames)) = file_ops.spill else {
+            return;
+        };
+        if let Err(err) = tokio::fs::write(&path, encode_frames(&frames)).await {
+            log::error!("dvr: spill gop to {:?} err: {}", path, err);
+            return;
+        }
+        if !self.store.lock().await.spilled(seq, path.clone()) {
+            //evicted while it was written
+            if let Err(err) = tokio::fs::remove_file(&path).await {
+                log::error!("dvr: remove {:?} err: {}", path, err);
+            }
+        }
+    }
+
+    //Reads the frames of a gop from `from`, a spilled gop is read back without the
+    //store locked and kept for the other time shifted subscribers.
+    async fn read(&self, seq: u64, from: usize) -> DvrRead {
+        let path = match self.store.lock().await.read(seq, from) {
+            DvrRead::Spilled { path } => path,
+            read => return read,
+        };
+
+        let frames = match tokio::fs::read(&path).await.and_then(decode_frames) {
+            Ok(frames) => Arc::new(frames),
+            Err(err) => {
+                log::error!("dvr: read {:?} err: {}", path, err);
+                return DvrRead::End;
+            }
+        };
+        self.store.lock().await.cache_reloaded(seq, frames.clone());
+
+        DvrRead::Frames {
+            frames: frames.iter().skip(from).cloned().collect(),
+            closed: true,
+        }
+    }
+
+    //The frames from the key frame which is the nearest to `duration_ms` ago to the
+    //live edge, with the sequence headers and the timestamp of the key frame.
+    async fn snapshot(&self, duration_ms: u64) -> Option<(Vec<FrameData>, Vec<FrameData>, u64)> {
+        let (first_seq, headers, start, next_seq) = {
+            let store = self.store.lock().await;
+            let (first_seq, headers, start) = store.seek(duration_ms)?;
+            (first_seq, headers, start, store.next_seq)
+        };
+
+        let mut frames = Vec::new();
+        for seq in first_seq..next_seq {
+            match self.read(seq, 0).await {
+                DvrRead::Frames {
+                    frames: gop_frames,
+                    closed: _,
+                } => frames.extend(gop_frames),
+                _ => break,
+            }
+        }
+        Some((headers, frames, start))
+    }
+
+    //Feeds a subscriber from the key frame which is the nearest to `time_shift_ms` ago,
+    //it stops when the subscriber quits or the stream is finished.
+    pub fn replay(
+        &self,
+        sender: SubFrameDataSender,
+        time_shift_ms: u64,
+        track_filter: TrackFilter,
+    ) {
+        let buffer = self.clone();
+        tokio::spawn(async move {
+            let (mut seq, headers, start_timestamp) = loop {
+                let notified = buffer.notify.notified();
+                let store = buffer.store.lock().await;
+                if let Some(position) = store.seek(time_shift_ms) {
+                    break position;
+                }
+                if store.finished {
+                    return;
+                }
+                drop(store);
+                notified.await;
+            };
+
+            for header in headers.into_iter().filter(|h| track_filter.accepts(h)) {
+                if sender.send(header).await.is_err() {
+                    return;
+                }
+            }
+
+            let start = Instant::now();
+            let mut index = 0;
+            loop {
+                let notified = buffer.notify.notified();
+                let (frames, closed) = match buffer.read(seq, index).await {
+                    DvrRead::Frames { frames, closed } => (frames, closed),
+                    _ => {
+                        log::info!("dvr: replay finished");
+                        return;
+                    }
+                };
+
+                index += frames.len();
+                let caught_up = frames.is_empty() && !closed;
+                for frame in frames.into_iter().filter(|f| track_filter.accepts(f)) {
+                    if let Some(timestamp) = frame.timestamp() {
+                        let elapsed = elapsed_ms(timestamp, start_timestamp);
+                        sleep_until(start + Duration::from_millis(elapsed as u64)).await;
+                    }
+                    if sender.send(frame).await.is_err() {
+                        return;
+                    }
+                }
+
+                if closed {
+                    seq += 1;
+                    index = 0;
+                } else if caught_up {
+                    notified.await;
+                }
+            }
+        });
+    }
+
+    //Writes the last `duration_ms` of the stream to an FLV file, the clip starts
+    //from the nearest key frame and its timestamps start from 0.
+    pub async fn export_clip(
+        &self,
+        duration_ms: u64,
+        path: &str,
+    ) -> Result<ClipInfo, StreamHubError> {
+        let Some((headers, frames, start)) = self.snapshot(duration_ms).await else {
+            return Err(StreamHubError {
+                value: StreamHubErrorValue::NoKeyFrameInClip,
+            });
+        };
+
+        let has_audio = frames
+            .iter()
+            .any(|frame| matches!(frame, FrameData::Audio { .. }));
+        let mut muxer = FlvMuxer::new();
+        muxer.write_flv_header(has_audio, true)?;
+        muxer.write_previous_tag_size(0)?;
+
+        let mut duration = 0;
+        for frame in headers.iter().chain(frames.iter()) {
+            let (tag_type, timestamp, data) = match frame {
+                FrameData::Audio { timestamp, data } => (TAG_AUDIO, *timestamp, data.clone()),
+                FrameData::Video { timestamp, data } => (TAG_VIDEO, *timestamp, data.clone()),
+                FrameData::MetaData { timestamp, data } => {
+                    //the files carry onMetaData without the RTMP @setDataFrame wrapper.
+                    let data = match data.strip_prefix(&SET_DATA_FRAME[..]) {
+                        Some(stripped) => data.slice(data.len() - stripped.len()..),
+                        None => data.clone(),
+                    };
+                    (TAG_SCRIPT_DATA, *timestamp, data)
+                }
+                _ => continue,
+            };
+            //the headers may be older than the first key frame.
+            let timestamp = elapsed_ms(timestamp, start);
+            duration = duration.max(timestamp);
+
+            muxer.write_flv_tag_header(tag_type, data.len() as u32, timestamp)?;
+            muxer.write_flv_tag_body(&data)?;
+            muxer.write_previous_tag_size(data.len() as u32 + HEADER_LENGTH)?;
+        }
+
+        let data = muxer.writer.extract_current_bytes();
+        if let Some(dir) = Path::new(path).parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        tokio::fs::write(path, &data[..]).await?;
+        log::info!("dvr: export clip {} of {} ms", path, duration);
+
+        Ok(ClipInfo {
+            path: path.to_string(),
+            duration_ms: duration as u64,
+            size: data.len() as u64,
+        })
+    }
+}
+
+//The milliseconds from the extended timestamp of a start key frame to a frame, the
+//frames a little older than the key frame(headers, interleaved audio) are taken as 0.
+fn elapsed_ms(timestamp: u32, start: u64) -> u32 {
+    (timestamp.wrapping_sub(start as u32) as i32).max(0) as u32
+}
+
+//one frame: tag type(1 byte) | timestamp(4 bytes) | data size(4 bytes) | data
+fn encode_frames(frames: &[FrameData]) -> Bytes {
+    let mut buffer = BytesMut::new();
+    for frame in frames {
+        let (tag_type, timestamp, data) = match frame {
+            FrameData::Audio { timestamp, data } => (TAG_AUDIO, timestamp, data),
+            FrameData::Video { timestamp, data } => (TAG_VIDEO, timestamp, data),
+            FrameData::MetaData { timestamp, data } => (TAG_SCRIPT_DATA, timestamp, data),
+            _ => continue,
+        };
+        buffer.put_u8(tag_type);
+        buffer.put_u32(*timestamp);
+        buffer.put_u32(data.len() as u32);
+        buffer.put_slice(data);
+    }
+    buffer.freeze()
+}
+
+fn decode_frames(data: Vec<u8>) -> io::Result<Vec<FrameData>> {
+    let mut data = Bytes::from(data);
+    let mut frames = Vec::new();
+
+    while data.remaining() >= 9 {
+        let tag_type = data.get_u8();
+        let timestamp = data.get_u32();
+        let size = data.get_u32() as usize;
+        if data.remaining() < size {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "truncated dvr file",
+            ));
+        }
+        let body = data.split_to(size);
+        frames.push(match tag_type {
+            TAG_AUDIO => FrameData::Audio {
+                timestamp,
+                data: body,
+            },
+            TAG_VIDEO => FrameData::Video {
+                timestamp,
+                data: body,
+            },
+            _ => FrameData::MetaData {
+                timestamp,
+                data: body,
+            },
+        });
+    }
+    Ok(frames)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        decode_frames, elapsed_ms, encode_frames, time_shift_ms, DvrBuffer, DvrRead, DvrStore,
+    };
+    use crate::{
+        define::{DvrPolicy, FrameData, TrackFilter},
+        stream::StreamIdentifier,
+    };
+    use bytes::Bytes;
+    use std::{collections::VecDeque, path::PathBuf, sync::Arc, time::Duration};
+    use tokio::{sync::mpsc, time::timeout};
+
+    fn video(timestamp: u32, first_byte: u8) -> FrameData {
+        FrameData::Video {
+            timestamp,
+            data: Bytes::copy_from_slice(&[first_byte, 0x01]),
+        }
+    }
+
+    fn audio(timestamp: u32) -> FrameData {
+        FrameData::Audio {
+            timestamp,
+            data: Bytes::from_static(&[0xaf, 0x01, 0x21]),
+        }
+    }
+
+    #[test]
+    fn test_time_shift_ms() {
+        assert_eq!(
+            time_shift_ms("rtmp://host/live/test?timeshift=30"),
+            Some(30000)
+        );
+        assert_eq!(
+            time_shift_ms("/live/test.flv?token=a&timeshift=5"),
+            Some(5000)
+        );
+        assert_eq!(time_shift_ms("/live/test.flv?timeshift=0"), None);
+        assert_eq!(time_shift_ms("/live/test.flv"), None);
+    }
+
+    fn new_store(spill_dir: Option<String>) -> DvrStore {
+        DvrStore {
+            policy: DvrPolicy {
+                window_ms: 3000,
+                spill_dir,
+            },
+            name: String::from("test"),
+            video_seq: None,
+            audio_seq: None,
+            metadata: None,
+            gops: VecDeque::new(),
+            next_seq: 0,
+            last_timestamp: None,
+            finished: false,
+            reloaded: VecDeque::new(),
+        }
+    }
+
+    #[test]
+    fn test_rolling_window() {
+        let mut store = new_store(None);
+
+        store.push(video(0, 0x17));
+        store.push(video(0, 0x27));
+        //a key frame every second
+        for second in 1..=5 {
+            store.push(video(second * 1000, 0x17));
+            store.push(video(second * 1000 + 500, 0x27));
+        }
+
+        //the gops from 2s cover the last 3 seconds
+        assert_eq!(store.gops.front().map(|gop| gop.start), Some(2000));
+        //snaps to the nearest key frame
+        let (seq, _, start) = store.seek(1800).unwrap();
+        assert_eq!(start, 4000);
+        match store.read(seq, 1) {
+            DvrRead::Frames { frames, closed } => {
+                assert!(closed);
+                assert_eq!(frames.len(), 1);
+                assert_eq!(frames[0].timestamp(), Some(4500));
+            }
+            _ => panic!("the gop is evicted"),
+        }
+    }
+
+    #[test]
+    fn test_reload_spilled_gop() {
+        let mut store = new_store(Some(String::from("./dvr")));
+        store.push(video(0, 0x17));
+        store.push(video(500, 0x27));
+        let spill = store.push(video(1000, 0x17)).spill;
+        let (seq, path, frames) = spill.expect("the closed gop is not spilled");
+        assert_eq!(seq, 0);
+        assert_eq!(frames.len(), 2);
+
+        //the frames are kept in memory until the gop is written
+        assert!(matches!(store.read(0, 0), DvrRead::Frames { .. }));
+        let data = encode_frames(&frames);
+        assert!(store.spilled(seq, path.clone()));
+        assert!(store.gops[0].frames.is_empty());
+        match store.read(0, 0) {
+            DvrRead::Spilled { path: spilled } => assert_eq!(spilled, path),
+            _ => panic!("the gop is not spilled"),
+        }
+
+        let reloaded = decode_frames(data.to_vec()).unwrap();
+        assert_eq!(reloaded.len(), 2);
+        assert_eq!(reloaded[1].timestamp(), Some(500));
+        store.cache_reloaded(seq, Arc::new(reloaded));
+        match store.read(0, 1) {
+            DvrRead::Frames { frames, closed } => {
+                assert!(closed);
+                assert_eq!(frames.len(), 1);
+                assert_eq!(frames[0].timestamp(), Some(500));
+            }
+            _ => panic!("the reloaded gop is not cached"),
+        }
+
+        //an evicted gop is not spilled
+        assert!(!store.spilled(5, PathBuf::from("evicted.dvr")));
+    }
+
+    #[test]
+    fn test_elapsed_ms() {
+        assert_eq!(elapsed_ms(1040, 1000), 40);
+        //an audio frame a little before the key frame
+        assert_eq!(elapsed_ms(990, 1000), 0);
+        //the start key frame is right before the rollover
+        assert_eq!(elapsed_ms(20, (1 << 32) - 20), 40);
+        assert_eq!(elapsed_ms(u32::MAX - 30, (1 << 32) - 20), 0);
+    }
+
+    #[tokio::test]
+    async fn test_replay_audio_before_key_frame() {
+        let identifier = StreamIdentifier::Rtmp {
+            app_name: String::from("live"),
+            stream_name: String::from("test"),
+        };
+        let buffer = DvrBuffer::new(
+            &identifier,
+            DvrPolicy {
+                window_ms: 3000,
+                spill_dir: None,
+            },
+        );
+        {
+            let mut store = buffer.store.lock().await;
+            store.push(video(1000, 0x17));
+            store.push(audio(990));
+            store.push(video(1040, 0x27));
+            store.finished = true;
+        }
+
+        let (sender, mut receiver) = mpsc::channel(8);
+        buffer.replay(sender, 1000, TrackFilter::All);
+        let mut timestamps = Vec::new();
+        while let Some(frame) = timeout(Duration::from_secs(1), receiver.recv())
+            .await
+            .expect("the replay stalls")
+        {
+            timestamps.push(frame.timestamp().unwrap());
+        }
+        assert_eq!(timestamps, vec![1000, 990, 1040]);
+    }
+}
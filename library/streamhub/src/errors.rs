@@ -3,6 +3,7 @@ use bytesio::bytes_errors::BytesWriteError;
 use failure::Backtrace;
 use serde_json::error::Error;
 use tokio::sync::oneshot::error::RecvError;
+use xflv::errors::{FlvDemuxerError, FlvMuxerError};
 
 use {failure::Fail, std::fmt};
 #[derive(Debug, Fail)]
@@ -33,6 +34,18 @@ pub enum StreamHubErrorValue {
     SerdeError(Error),
     #[fail(display = "the client session error: {}", _0)]
     RtspClientSessionError(String),
+    #[fail(display = "io error: {}", _0)]
+    IOError(std::io::Error),
+    #[fail(display = "flv demuxer error: {}", _0)]
+    FlvDemuxerError(FlvDemuxerError),
+    #[fail(display = "no video key frame in the fallback slate")]
+    NoKeyFrameInSlate,
+    #[fail(display = "flv muxer error: {}", _0)]
+    FlvMuxerError(FlvMuxerError),
+    #[fail(display = "the stream has no time-shift buffer")]
+    NoDvrBuffer,
+    #[fail(display = "no video key frame in the clip")]
+    NoKeyFrameInClip,
 }
 #[derive(Debug)]
 pub struct StreamHubError {
@@ -102,3 +115,27 @@ impl From<String> for StreamHubError {
 //         }
 //     }
 // }
+
+impl From<std::io::Error> for StreamHubError {
+    fn from(error: std::io::Error) -> Self {
+        StreamHubError {
+            value: StreamHubErrorValue::IOError(error),
+        }
+    }
+}
+
+impl From<FlvDemuxerError> for StreamHubError {
+    fn from(error: FlvDemuxerError) -> Self {
+        StreamHubError {
+            value: StreamHubErrorValue::FlvDemuxerError(error),
+        }
+    }
+}
+
+impl From<FlvMuxerError> for StreamHubError {
+    fn from(error: FlvMuxerError) -> Self {
+        StreamHubError {
+            value: StreamHubErrorValue::FlvMuxerError(error),
+        }
+    }
+}
@@ -1,6 +1,8 @@
 use define::{
-    FrameDataReceiver, PacketDataReceiver, PacketDataSender, RelayType, StatisticData,
-    StatisticDataReceiver, StatisticDataSender,
+    ApiExportClipResultSender, AppPolicy, DvrPolicy, FrameDataReceiver, PacketDataReceiver,
+    PublishPolicy, PublisherInfo, RelayType, RemuxPolicy, StatisticData, StatisticDataReceiver,
+    StatisticDataSender, SubDataReceiver, SubEventExecuteResultSender, SubscriberQueuePolicy,
+    TimestampPolicy, TrackFilter,
 };
 use serde_json::{json, Value};
 use statistics::{StatisticSubscriber, StatisticsStream};
@@ -9,27 +11,47 @@ use xflv::define::aac_packet_type;
 
 use crate::define::PacketData;
 
+pub mod cache_budget;
+pub mod continuity;
 pub mod define;
+pub mod dvr;
 pub mod errors;
+pub mod normalizer;
 pub mod notify;
+pub mod processor;
+pub mod remux;
+pub mod rtp_cache;
+pub mod slate;
+pub mod standby;
 pub mod statistics;
 pub mod stream;
+pub mod subscriber;
 pub mod utils;
 
 use {
     crate::notify::Notifier,
+    cache_budget::CacheBudget,
+    continuity::{is_sequence_header, StreamContinuity},
     define::{
         BroadcastEvent, BroadcastEventReceiver, BroadcastEventSender, DataReceiver, DataSender,
-        FrameData, FrameDataSender, Information, StreamHubEvent, StreamHubEventReceiver,
+        FrameData, Information, StreamHubEvent, StreamHubEventMessage, StreamHubEventReceiver,
         StreamHubEventSender, SubscribeType, SubscriberInfo, TStreamHandler, TransceiverEvent,
         TransceiverEventReceiver, TransceiverEventSender,
     },
+    dvr::DvrBuffer,
     errors::{StreamHubError, StreamHubErrorValue},
+    processor::{ProcessorChain, ProcessorRegistry, TStreamProcessorFactory},
+    remux::{RemuxedStream, REMUX_START_WAIT_MS},
+    rtp_cache::RtpGopCache,
+    standby::StandbyPublisher,
     std::collections::HashMap,
     std::sync::Arc,
+    std::time::Duration,
     stream::StreamIdentifier,
-    tokio::sync::{broadcast, mpsc, mpsc::UnboundedReceiver, Mutex},
-    utils::Uuid,
+    subscriber::{SendResult, SubscriberSender, TQueueData},
+    tokio::sync::{broadcast, mpsc, mpsc::error::TrySendError, mpsc::UnboundedReceiver, Mutex},
+    tokio::task::JoinHandle,
+    utils::{RandomDigitCount, Uuid},
 };
 
 //Receive audio data/video data/meta data/media info from a publisher and send to players/subscribers
@@ -40,9 +62,11 @@ pub struct StreamDataTransceiver {
     //used for receiving event
     event_receiver: TransceiverEventReceiver,
     //used for sending audio/video frame data to players/subscribers
-    id_to_frame_sender: Arc<Mutex<HashMap<Uuid, FrameDataSender>>>,
+    id_to_frame_sender: Arc<Mutex<HashMap<Uuid, SubscriberSender<FrameData>>>>,
     //used for sending audio/video packet data to players/subscribers
-    id_to_packet_sender: Arc<Mutex<HashMap<Uuid, PacketDataSender>>>,
+    id_to_packet_sender: Arc<Mutex<HashMap<Uuid, SubscriberSender<PacketData>>>>,
+    //how to deal with the subscribers which cannot keep up with the stream
+    queue_policy: SubscriberQueuePolicy,
     //publisher and subscribers use this sender to submit statistical data
     statistic_data_sender: StatisticDataSender,
     //used for receiving statistical data from publishers and subscribers
@@ -51,16 +75,34 @@ pub struct StreamDataTransceiver {
     statistic_data: Arc<Mutex<StatisticsStream>>,
     //a hander implement by protocols, such as rtmp, webrtc, http-flv, hls
     stream_handler: Arc<dyn TStreamHandler>,
+    //normalizes the timestamps and keeps them continuous when the publisher is switched
+    continuity: Arc<Mutex<StreamContinuity>>,
+    //the time-shift buffer of the stream
+    dvr: Option<DvrBuffer>,
+    //the rtp packets of the last key frame group for the subscribers on the packet path
+    packet_cache: Arc<Mutex<RtpGopCache>>,
+    //the processors which may modify, drop or inject the data before it is sent
+    processors: Arc<Mutex<ProcessorChain>>,
+    identifier: StreamIdentifier,
+    //the memory budget of the gop caches, the subscriber count of the stream is reported to it
+    cache_budget: CacheBudget,
 }
 
 impl StreamDataTransceiver {
+    #[allow(clippy::too_many_arguments)]
     fn new(
         data_receiver: DataReceiver,
         event_receiver: UnboundedReceiver<TransceiverEvent>,
         identifier: StreamIdentifier,
         h: Arc<dyn TStreamHandler>,
+        queue_policy: SubscriberQueuePolicy,
+        timestamp_policy: TimestampPolicy,
+        dvr_policy: Option<DvrPolicy>,
+        cache_budget: CacheBudget,
+        processors: ProcessorChain,
     ) -> Self {
         let (statistic_data_sender, statistic_data_receiver) = mpsc::unbounded_channel();
+        let dvr = dvr_policy.map(|policy| DvrBuffer::new(&identifier, policy));
         Self {
             data_receiver,
             event_receiver,
@@ -69,137 +111,306 @@ impl StreamDataTransceiver {
             id_to_frame_sender: Arc::new(Mutex::new(HashMap::new())),
             id_to_packet_sender: Arc::new(Mutex::new(HashMap::new())),
             stream_handler: h,
-            statistic_data: Arc::new(Mutex::new(StatisticsStream::new(identifier))),
+            statistic_data: Arc::new(Mutex::new(StatisticsStream::new(identifier.clone()))),
+            queue_policy,
+            continuity: Arc::new(Mutex::new(StreamContinuity::new(timestamp_policy))),
+            dvr,
+            packet_cache: Arc::new(Mutex::new(RtpGopCache::new())),
+            processors: Arc::new(Mutex::new(processors)),
+            identifier,
+            cache_budget,
         }
     }
 
     async fn receive_frame_data(
         data: Option<FrameData>,
-        frame_senders: &Arc<Mutex<HashMap<Uuid, FrameDataSender>>>,
+        frame_senders: &Arc<Mutex<HashMap<Uuid, SubscriberSender<FrameData>>>>,
+        statistic_sender: &StatisticDataSender,
+        continuity: &Arc<Mutex<StreamContinuity>>,
+        packet_cache: &Arc<Mutex<RtpGopCache>>,
+        processors: &Arc<Mutex<ProcessorChain>>,
     ) {
-        if let Some(val) = data {
-            match val {
-                FrameData::MetaData {
-                    timestamp: _,
-                    data: _,
-                } => {}
-                FrameData::Audio { timestamp, data } => {
-                    let data = FrameData::Audio {
-                        timestamp,
-                        data: data.clone(),
-                    };
+        let Some(val) = data else {
+            return;
+        };
+        //the rtp packets of the publisher are parsed by the video codec of its media info
+        if let FrameData::MediaInfo { media_info } = &val {
+            packet_cache.lock().await.set_video_codec(media_info.vcodec);
+        }
+        let (discontinuity, data) = {
+            let mut continuity = continuity.lock().await;
+            let data = continuity.process(val);
+            match data {
+                Some(_) => (continuity.take_discontinuity(), data),
+                None => (None, None),
+            }
+        };
 
-                    for (_, v) in frame_senders.lock().await.iter() {
-                        if let Err(audio_err) = v.send(data.clone()).map_err(|_| StreamHubError {
-                            value: StreamHubErrorValue::SendAudioError,
-                        }) {
-                            log::error!("Transmiter send error: {}", audio_err);
-                        }
-                    }
-                }
-                FrameData::Video { timestamp, data } => {
-                    let data = FrameData::Video {
-                        timestamp,
-                        data: data.clone(),
-                    };
-                    for (_, v) in frame_senders.lock().await.iter() {
-                        if let Err(video_err) = v.send(data.clone()).map_err(|_| StreamHubError {
-                            value: StreamHubErrorValue::SendVideoError,
-                        }) {
-                            log::error!("Transmiter send error: {}", video_err);
-                        }
-                    }
-                }
-                FrameData::MediaInfo {
-                    media_info: info_value,
-                } => {
-                    let data = FrameData::MediaInfo {
-                        media_info: info_value,
-                    };
-                    for (_, v) in frame_senders.lock().await.iter() {
-                        if let Err(media_err) = v.send(data.clone()).map_err(|_| StreamHubError {
-                            value: StreamHubErrorValue::SendVideoError,
-                        }) {
-                            log::error!("Transmiter send error: {}", media_err);
+        if let Some(marker) = discontinuity {
+            Self::send_to_subscribers(marker, frame_senders, statistic_sender).await;
+        }
+        //the data messages are forwarded in order with the a/v frames.
+        if let Some(val) = data {
+            let mut processors = processors.lock().await;
+            let frames = if processors.is_empty() {
+                vec![val]
+            } else {
+                processors.process_frame(val)
+            };
+            for val in frames {
+                //the codec neutral frames are converted once for all the FLV subscribers
+                let val = match val {
+                    FrameData::Media { .. } | FrameData::CodecConfig { .. } => {
+                        match val.into_flv() {
+                            Some(val) => val,
+                            None => continue,
                         }
                     }
-                }
+                    _ => val,
+                };
+                Self::send_to_subscribers(val, frame_senders, statistic_sender).await;
             }
         }
     }
 
-    async fn receive_frame_data_loop(
+    fn receive_frame_data_loop(
         mut exit: broadcast::Receiver<()>,
         mut receiver: FrameDataReceiver,
-        frame_senders: Arc<Mutex<HashMap<Uuid, FrameDataSender>>>,
-    ) {
+        frame_senders: Arc<Mutex<HashMap<Uuid, SubscriberSender<FrameData>>>>,
+        statistic_sender: StatisticDataSender,
+        continuity: Arc<Mutex<StreamContinuity>>,
+        packet_cache: Arc<Mutex<RtpGopCache>>,
+        processors: Arc<Mutex<ProcessorChain>>,
+    ) -> JoinHandle<()> {
         tokio::spawn(async move {
             loop {
                 tokio::select! {
                     data = receiver.recv() => {
-                       Self::receive_frame_data(data, &frame_senders).await;
+                       Self::receive_frame_data(data, &frame_senders, &statistic_sender, &continuity, &packet_cache, &processors).await;
                     }
                     _ = exit.recv()=>{
                         break;
                     }
                 }
             }
-        });
+        })
     }
 
     async fn receive_packet_data(
         data: Option<PacketData>,
-        packet_senders: &Arc<Mutex<HashMap<Uuid, PacketDataSender>>>,
+        packet_senders: &Arc<Mutex<HashMap<Uuid, SubscriberSender<PacketData>>>>,
+        statistic_sender: &StatisticDataSender,
+        packet_cache: &Arc<Mutex<RtpGopCache>>,
+        processors: &Arc<Mutex<ProcessorChain>>,
     ) {
         if let Some(val) = data {
-            match val {
-                PacketData::Audio { timestamp, data } => {
-                    let data = PacketData::Audio {
-                        timestamp,
-                        data: data.clone(),
-                    };
-
-                    for (_, v) in packet_senders.lock().await.iter() {
-                        if let Err(audio_err) = v.send(data.clone()).map_err(|_| StreamHubError {
-                            value: StreamHubErrorValue::SendAudioError,
-                        }) {
-                            log::error!("Transmiter send error: {}", audio_err);
-                        }
-                    }
-                }
-                PacketData::Video { timestamp, data } => {
-                    let data = PacketData::Video {
-                        timestamp,
-                        data: data.clone(),
-                    };
-                    for (_, v) in packet_senders.lock().await.iter() {
-                        if let Err(video_err) = v.send(data.clone()).map_err(|_| StreamHubError {
-                            value: StreamHubErrorValue::SendVideoError,
-                        }) {
-                            log::error!("Transmiter send error: {}", video_err);
-                        }
-                    }
+            let mut processors = processors.lock().await;
+            let packets = if processors.is_empty() {
+                vec![val]
+            } else {
+                processors.process_packet(val)
+            };
+            for val in packets {
+                if !packet_cache.lock().await.save(&val) {
+                    continue;
                 }
+                Self::send_to_subscribers(val, packet_senders, statistic_sender).await;
             }
         }
     }
 
-    async fn receive_packet_data_loop(
+    fn receive_packet_data_loop(
         mut exit: broadcast::Receiver<()>,
         mut receiver: PacketDataReceiver,
-        packet_senders: Arc<Mutex<HashMap<Uuid, PacketDataSender>>>,
-    ) {
+        packet_senders: Arc<Mutex<HashMap<Uuid, SubscriberSender<PacketData>>>>,
+        statistic_sender: StatisticDataSender,
+        packet_cache: Arc<Mutex<RtpGopCache>>,
+        processors: Arc<Mutex<ProcessorChain>>,
+    ) -> JoinHandle<()> {
         tokio::spawn(async move {
             loop {
                 tokio::select! {
                     data = receiver.recv() => {
-                       Self::receive_packet_data(data, &packet_senders).await;
+                       Self::receive_packet_data(data, &packet_senders, &statistic_sender, &packet_cache, &processors).await;
                     }
                     _ = exit.recv()=>{
                         break;
                     }
                 }
             }
+        })
+    }
+
+    //Receive the data of a publisher until the exit signal, it is called again
+    //with the new publisher's receiver when the publisher is switched.
+    #[allow(clippy::too_many_arguments)]
+    fn receive_publisher_data(
+        data_receiver: DataReceiver,
+        exit: &broadcast::Sender<()>,
+        packet_senders: &Arc<Mutex<HashMap<Uuid, SubscriberSender<PacketData>>>>,
+        frame_senders: &Arc<Mutex<HashMap<Uuid, SubscriberSender<FrameData>>>>,
+        statistic_sender: &StatisticDataSender,
+        continuity: &Arc<Mutex<StreamContinuity>>,
+        packet_cache: &Arc<Mutex<RtpGopCache>>,
+        processors: &Arc<Mutex<ProcessorChain>>,
+    ) -> Vec<JoinHandle<()>> {
+        let mut handles = Vec::new();
+
+        if let Some(receiver) = data_receiver.frame_receiver {
+            handles.push(Self::receive_frame_data_loop(
+                exit.subscribe(),
+                receiver,
+                frame_senders.clone(),
+                statistic_sender.clone(),
+                continuity.clone(),
+                packet_cache.clone(),
+                processors.clone(),
+            ));
+        }
+
+        if let Some(receiver) = data_receiver.packet_receiver {
+            handles.push(Self::receive_packet_data_loop(
+                exit.subscribe(),
+                receiver,
+                packet_senders.clone(),
+                statistic_sender.clone(),
+                packet_cache.clone(),
+                processors.clone(),
+            ));
+        }
+
+        handles
+    }
+
+    //Send the sequence headers of a newly switched publisher to the frame subscribers,
+    //so that they can decode the frames coming from it.
+    async fn send_sequence_headers(
+        stream_handler: &Arc<dyn TStreamHandler>,
+        frame_senders: &Arc<Mutex<HashMap<Uuid, SubscriberSender<FrameData>>>>,
+        statistic_sender: &StatisticDataSender,
+        continuity: &Arc<Mutex<StreamContinuity>>,
+        queue_size: usize,
+    ) {
+        let last_timestamp = continuity.lock().await.last_timestamp();
+
+        for (id, subscriber) in frame_senders.lock().await.iter_mut() {
+            let (sender, mut receiver) = mpsc::channel(queue_size);
+            if let Err(err) = stream_handler
+                .send_prior_data(DataSender::Frame { sender }, subscriber.sub_type.clone())
+                .await
+            {
+                log::error!("send sequence headers to subscriber: {} err: {}", id, err);
+                continue;
+            }
+
+            while let Ok(mut frame) = receiver.try_recv() {
+                if !is_sequence_header(&frame)
+                    && !matches!(
+                        frame,
+                        FrameData::MetaData { .. } | FrameData::MediaInfo { .. }
+                    )
+                {
+                    continue;
+                }
+                frame.set_timestamp(last_timestamp);
+                subscriber.send(frame, statistic_sender);
+            }
+        }
+    }
+
+    //The prior data of a new subscriber(the sequence headers, metadata and gop cache)
+    //carries the timestamps of the publisher, they are mapped to the timeline of the
+    //subscribers before being queued.
+    //Returns true if the prior data is truncated by a full queue, the subscriber must
+    //then wait for the next key frame.
+    async fn send_prior_data(
+        stream_handler: &Arc<dyn TStreamHandler>,
+        sender: DataSender,
+        sub_type: SubscribeType,
+        track_filter: TrackFilter,
+        continuity: &Arc<Mutex<StreamContinuity>>,
+        packet_cache: &Arc<Mutex<RtpGopCache>>,
+        queue_size: usize,
+    ) -> Result<bool, StreamHubError> {
+        let frame_sender = match sender {
+            DataSender::Frame { sender } => sender,
+            DataSender::Packet {
+                sender: packet_sender,
+            } => {
+                stream_handler
+                    .send_prior_data(
+                        DataSender::Packet {
+                            sender: packet_sender.clone(),
+                        },
+                        sub_type,
+                    )
+                    .await?;
+                //the cache holds the video packets only
+                let packets = if track_filter.has_video() {
+                    packet_cache.lock().await.get_packets()
+                } else {
+                    Vec::new()
+                };
+                for packet in packets {
+                    if packet_sender.try_send(packet).is_err() {
+                        log::warn!("send_prior_data: the subscriber queue is full");
+                        return Ok(true);
+                    }
+                }
+                return Ok(false);
+            }
+        };
+
+        let (sender, mut receiver) = mpsc::channel(queue_size);
+        stream_handler
+            .send_prior_data(
+                DataSender::Frame {
+                    sender: sender.clone(),
+                },
+                sub_type,
+            )
+            .await?;
+        //the stream handler stops at a full queue and leaves out the rest of the gop.
+        let mut truncated = sender.capacity() == 0;
+
+        let continuity = continuity.lock().await;
+        while let Ok(mut frame) = receiver.try_recv() {
+            if !track_filter.accepts(&frame) {
+                continue;
+            }
+            continuity.translate(&mut frame);
+            match frame_sender.try_send(frame) {
+                Ok(()) => {}
+                Err(TrySendError::Full(_)) => {
+                    log::warn!("send_prior_data: the subscriber queue is full");
+                    truncated = true;
+                    break;
+                }
+                Err(TrySendError::Closed(_)) => {
+                    return Err(StreamHubError {
+                        value: StreamHubErrorValue::SendError,
+                    });
+                }
+            }
+        }
+        Ok(truncated)
+    }
+
+    //The subscribers which lag behind too long are removed, the closing of their
+    //queues makes the subscriber sessions quit.
+    async fn send_to_subscribers<T: TQueueData + Clone>(
+        data: T,
+        senders: &Arc<Mutex<HashMap<Uuid, SubscriberSender<T>>>>,
+        statistic_sender: &StatisticDataSender,
+    ) {
+        senders.lock().await.retain(|id, sender| {
+            match sender.send(data.clone(), statistic_sender) {
+                SendResult::Sent | SendResult::Dropped | SendResult::Skipped => true,
+                SendResult::Disconnect => {
+                    log::info!("Transmiter remove subscriber: {}", id);
+                    false
+                }
+            }
         });
     }
 
@@ -317,17 +528,21 @@ impl StreamDataTransceiver {
                     start_time,
                 } => {
                     let subscriber = &mut statistics_data.lock().await.subscribers;
-                    let sub = StatisticSubscriber {
-                        id,
-                        remote_address: remote_addr,
-                        sub_type,
-                        start_time,
-                        send_bitrate: 0,
-                        send_bytes: 0,
-                        total_send_bytes: 0,
-                    };
+                    let mut sub = StatisticSubscriber::new(id, remote_addr, sub_type);
+                    sub.start_time = start_time;
                     subscriber.insert(id, sub);
                 }
+                StatisticData::SubscriberDrop {
+                    id,
+                    frame_count,
+                    data_size,
+                } => {
+                    let subscriber = &mut statistics_data.lock().await.subscribers;
+                    if let Some(sub) = subscriber.get_mut(&id) {
+                        sub.dropped_frames += frame_count;
+                        sub.dropped_bytes += data_size;
+                    }
+                }
             }
         }
     }
@@ -357,16 +572,38 @@ impl StreamDataTransceiver {
         });
     }
 
+    #[allow(clippy::too_many_arguments)]
     async fn receive_event_loop(
-        stream_handler: Arc<dyn TStreamHandler>,
+        data_receiver: DataReceiver,
+        mut stream_handler: Arc<dyn TStreamHandler>,
         exit: broadcast::Sender<()>,
         mut receiver: TransceiverEventReceiver,
-        packet_senders: Arc<Mutex<HashMap<Uuid, PacketDataSender>>>,
-        frame_senders: Arc<Mutex<HashMap<Uuid, FrameDataSender>>>,
+        packet_senders: Arc<Mutex<HashMap<Uuid, SubscriberSender<PacketData>>>>,
+        frame_senders: Arc<Mutex<HashMap<Uuid, SubscriberSender<FrameData>>>>,
         statistic_sender: StatisticDataSender,
         statistics_data: Arc<Mutex<StatisticsStream>>,
+        queue_policy: SubscriberQueuePolicy,
+        continuity: Arc<Mutex<StreamContinuity>>,
+        dvr: Option<DvrBuffer>,
+        packet_cache: Arc<Mutex<RtpGopCache>>,
+        processors: Arc<Mutex<ProcessorChain>>,
+        identifier: StreamIdentifier,
+        cache_budget: CacheBudget,
     ) {
         tokio::spawn(async move {
+            //stops receiving the data of the current publisher
+            let (mut publisher_exit, _) = broadcast::channel::<()>(1);
+            let mut publisher_handles = Self::receive_publisher_data(
+                data_receiver,
+                &publisher_exit,
+                &packet_senders,
+                &frame_senders,
+                &statistic_sender,
+                &continuity,
+                &packet_cache,
+                &processors,
+            );
+
             loop {
                 if let Some(val) = receiver.recv().await {
                     match val {
@@ -375,23 +612,81 @@ impl StreamDataTransceiver {
                             info,
                             result_sender,
                         } => {
-                            if let Err(err) = stream_handler
-                                .send_prior_data(sender.clone(), info.sub_type)
-                                .await
-                            {
-                                log::error!("receive_event_loop send_prior_data err: {}", err);
-                                break;
-                            }
-                            match sender {
-                                DataSender::Frame {
-                                    sender: frame_sender,
-                                } => {
-                                    frame_senders.lock().await.insert(info.id, frame_sender);
+                            let is_shared = info.sub_type.is_shared();
+                            //a time shifted subscriber is fed from the dvr buffer instead of the live stream.
+                            let time_shift = dvr
+                                .as_ref()
+                                .zip(dvr::time_shift_ms(&info.notify_info.request_url));
+                            match (time_shift, sender) {
+                                (
+                                    Some((dvr, time_shift_ms)),
+                                    DataSender::Frame {
+                                        sender: frame_sender,
+                                    },
+                                ) => {
+                                    log::info!(
+                                        "subscriber: {} plays the stream {} ms ago",
+                                        info.id,
+                                        time_shift_ms
+                                    );
+                                    dvr.replay(frame_sender, time_shift_ms, info.track_filter);
                                 }
-                                DataSender::Packet {
-                                    sender: packet_sender,
-                                } => {
-                                    packet_senders.lock().await.insert(info.id, packet_sender);
+                                (_, sender) => {
+                                    let truncated = match Self::send_prior_data(
+                                        &stream_handler,
+                                        sender.clone(),
+                                        info.sub_type.clone(),
+                                        info.track_filter,
+                                        &continuity,
+                                        &packet_cache,
+                                        queue_policy.queue_size,
+                                    )
+                                    .await
+                                    {
+                                        Ok(truncated) => truncated,
+                                        Err(err) => {
+                                            //only this subscriber is dropped, its result
+                                            //sender is dropped so that its subscribe fails.
+                                            log::error!(
+                                                "receive_event_loop send_prior_data of subscriber: {} err: {}",
+                                                info.id,
+                                                err
+                                            );
+                                            continue;
+                                        }
+                                    };
+                                    match sender {
+                                        DataSender::Frame {
+                                            sender: frame_sender,
+                                        } => {
+                                            frame_senders.lock().await.insert(
+                                                info.id,
+                                                SubscriberSender::new(
+                                                    info.id,
+                                                    info.sub_type,
+                                                    frame_sender,
+                                                    &queue_policy,
+                                                )
+                                                .with_track_filter(info.track_filter)
+                                                .with_wait_key_frame(truncated),
+                                            );
+                                        }
+                                        DataSender::Packet {
+                                            sender: packet_sender,
+                                        } => {
+                                            packet_senders.lock().await.insert(
+                                                info.id,
+                                                SubscriberSender::new(
+                                                    info.id,
+                                                    info.sub_type,
+                                                    packet_sender,
+                                                    &queue_policy,
+                                                )
+                                                .with_track_filter(info.track_filter)
+                                                .with_wait_key_frame(truncated),
+                                            );
+                                        }
+                                    }
                                 }
                             }
 
@@ -402,8 +697,14 @@ impl StreamDataTransceiver {
                                 )
                             }
 
-                            let mut statistics_data = statistics_data.lock().await;
-                            statistics_data.subscriber_count += 1;
+                            if !is_shared {
+                                let mut statistics_data = statistics_data.lock().await;
+                                statistics_data.subscriber_count += 1;
+                                cache_budget.set_subscriber_count(
+                                    &identifier,
+                                    statistics_data.subscriber_count,
+                                );
+                            }
                         }
                         TransceiverEvent::UnSubscribe { info } => {
                             match info.sub_type {
@@ -414,26 +715,112 @@ impl StreamDataTransceiver {
                                     frame_senders.lock().await.remove(&info.id);
                                 }
                             }
+                            if info.sub_type.is_shared() {
+                                continue;
+                            }
                             let mut statistics_data = statistics_data.lock().await;
                             let subscribers = &mut statistics_data.subscribers;
                             subscribers.remove(&info.id);
 
-                            statistics_data.subscriber_count -= 1;
+                            statistics_data.subscriber_count =
+                                statistics_data.subscriber_count.saturating_sub(1);
+                            cache_budget.set_subscriber_count(
+                                &identifier,
+                                statistics_data.subscriber_count,
+                            );
+                        }
+                        TransceiverEvent::ViewerJoin { info } => {
+                            let mut statistics_data = statistics_data.lock().await;
+                            statistics_data.subscribers.insert(
+                                info.id,
+                                StatisticSubscriber::new(
+                                    info.id,
+                                    info.notify_info.remote_addr,
+                                    info.sub_type,
+                                ),
+                            );
+                            statistics_data.subscriber_count += 1;
+                            cache_budget.set_subscriber_count(
+                                &identifier,
+                                statistics_data.subscriber_count,
+                            );
+                        }
+                        TransceiverEvent::ViewerLeave { info } => {
+                            let mut statistics_data = statistics_data.lock().await;
+                            if statistics_data.subscribers.remove(&info.id).is_some() {
+                                statistics_data.subscriber_count -= 1;
+                                cache_budget.set_subscriber_count(
+                                    &identifier,
+                                    statistics_data.subscriber_count,
+                                );
+                            }
                         }
                         TransceiverEvent::UnPublish {} => {
+                            cache_budget.set_subscriber_count(&identifier, 0);
+                            if let Err(err) = publisher_exit.send(()) {
+                                log::error!("TransmitterEvent::UnPublish send error: {}", err);
+                            }
                             if let Err(err) = exit.send(()) {
                                 log::error!("TransmitterEvent::UnPublish send error: {}", err);
                             }
                             break;
                         }
+                        TransceiverEvent::Suspend {} => {
+                            if let Err(err) = publisher_exit.send(()) {
+                                log::error!("TransmitterEvent::Suspend send error: {}", err);
+                            }
+                            for handle in publisher_handles.drain(..) {
+                                if let Err(err) = handle.await {
+                                    log::error!("TransmitterEvent::Suspend join error: {}", err);
+                                }
+                            }
+                        }
+                        TransceiverEvent::Republish {
+                            data_receiver,
+                            stream_handler: new_stream_handler,
+                        } => {
+                            if let Err(err) = publisher_exit.send(()) {
+                                log::error!("TransmitterEvent::Republish send error: {}", err);
+                            }
+                            for handle in publisher_handles.drain(..) {
+                                if let Err(err) = handle.await {
+                                    log::error!("TransmitterEvent::Republish join error: {}", err);
+                                }
+                            }
+
+                            continuity.lock().await.switch_publisher();
+                            packet_cache.lock().await.switch_publisher();
+                            stream_handler = new_stream_handler;
+                            Self::send_sequence_headers(
+                                &stream_handler,
+                                &frame_senders,
+                                &statistic_sender,
+                                &continuity,
+                                queue_policy.queue_size,
+                            )
+                            .await;
+
+                            (publisher_exit, _) = broadcast::channel::<()>(1);
+                            publisher_handles = Self::receive_publisher_data(
+                                data_receiver,
+                                &publisher_exit,
+                                &packet_senders,
+                                &frame_senders,
+                                &statistic_sender,
+                                &continuity,
+                                &packet_cache,
+                                &processors,
+                            );
+                        }
                         TransceiverEvent::Api { sender, uuid } => {
                             log::info!("api:  stream identifier: {:?}", uuid);
-                            let statistic_data = if let Some(uid) = uuid {
+                            let mut statistic_data = if let Some(uid) = uuid {
                                 statistics_data.lock().await.query_by_uuid(uid)
                             } else {
                                 log::info!("api2:  stream identifier: {:?}", statistics_data);
                                 statistics_data.lock().await.clone()
                             };
+                            statistic_data.gop_cache_bytes = cache_budget.stream_bytes(&identifier);
 
                             if let Err(err) = sender.send(statistic_data) {
                                 log::info!("Transmitter send avstatistic data err: {}", err);
@@ -442,6 +829,29 @@ impl StreamDataTransceiver {
                         TransceiverEvent::Request { sender } => {
                             stream_handler.send_information(sender).await;
                         }
+                        TransceiverEvent::ExportClip {
+                            duration_ms,
+                            path,
+                            result_sender,
+                        } => match &dvr {
+                            Some(dvr) => {
+                                let dvr = dvr.clone();
+                                tokio::spawn(async move {
+                                    let result = dvr.export_clip(duration_ms, &path).await;
+                                    if result_sender.send(result).is_err() {
+                                        log::error!("TransmitterEvent::ExportClip send error");
+                                    }
+                                });
+                            }
+                            None => {
+                                let result = Err(StreamHubError {
+                                    value: StreamHubErrorValue::NoDvrBuffer,
+                                });
+                                if result_sender.send(result).is_err() {
+                                    log::error!("TransmitterEvent::ExportClip send error");
+                                }
+                            }
+                        },
                     }
                 }
             }
@@ -451,22 +861,19 @@ impl StreamDataTransceiver {
     pub async fn run(self) -> Result<(), StreamHubError> {
         let (tx, _) = broadcast::channel::<()>(1);
 
-        if let Some(receiver) = self.data_receiver.frame_receiver {
-            Self::receive_frame_data_loop(
-                tx.subscribe(),
-                receiver,
-                self.id_to_frame_sender.clone(),
-            )
-            .await;
-        }
-
-        if let Some(receiver) = self.data_receiver.packet_receiver {
-            Self::receive_packet_data_loop(
-                tx.subscribe(),
-                receiver,
-                self.id_to_packet_sender.clone(),
-            )
-            .await;
+        //the dvr buffer records the stream as an internal subscriber which is never disconnected.
+        if let Some(dvr) = &self.dvr {
+            let (sender, receiver) = mpsc::channel(self.queue_policy.queue_size);
+            let id = Uuid::new(RandomDigitCount::Four);
+            let policy = SubscriberQueuePolicy {
+                queue_size: self.queue_policy.queue_size,
+                max_lag_ms: 0,
+            };
+            self.id_to_frame_sender.lock().await.insert(
+                id,
+                SubscriberSender::new(id, SubscribeType::DvrRecord, sender, &policy),
+            );
+            dvr.record(receiver);
         }
 
         Self::receive_statistics_data_loop(
@@ -478,6 +885,7 @@ impl StreamDataTransceiver {
         .await;
 
         Self::receive_event_loop(
+            self.data_receiver,
             self.stream_handler,
             tx,
             self.event_receiver,
@@ -485,6 +893,13 @@ impl StreamDataTransceiver {
             self.id_to_frame_sender,
             self.statistic_data_sender,
             self.statistic_data.clone(),
+            self.queue_policy,
+            self.continuity,
+            self.dvr,
+            self.packet_cache,
+            self.processors,
+            self.identifier,
+            self.cache_budget,
         )
         .await;
 
@@ -496,9 +911,29 @@ impl StreamDataTransceiver {
     }
 }
 
+//A subscriber which waits for its stream to be published.
+struct PendingSubscriber {
+    info: SubscriberInfo,
+    sender: DataSender,
+    receiver: SubDataReceiver,
+    result_sender: SubEventExecuteResultSender,
+}
+
+//The current publisher of a stream.
+struct StreamPublisher {
+    id: Uuid,
+    statistic_data_sender: StatisticDataSender,
+    //the publisher has left and the stream is waiting for it to come back.
+    suspended: bool,
+}
+
 pub struct StreamsHub {
     //stream identifier to transceiver event sender
     streams: HashMap<StreamIdentifier, TransceiverEventSender>,
+    //stream identifier to its current publisher
+    publishers: HashMap<StreamIdentifier, StreamPublisher>,
+    //stream identifier to the subscribers waiting for it to be published
+    pending_subscribers: HashMap<StreamIdentifier, Vec<PendingSubscriber>>,
     //construct UnSubscribe and UnPublish event from Subscribe and Publish event to kick off client
     un_pub_sub_events: HashMap<Uuid, StreamHubEvent>,
     //event is consumed in Stream hub, produced from other protocol sessions
@@ -515,8 +950,28 @@ pub struct StreamsHub {
     rtmp_pull_enabled: bool,
     //enable hls
     hls_enabled: bool,
+    //enable dash
+    dash_enabled: bool,
     //http notifier on sub/pub event
     notifier: Option<Arc<dyn Notifier>>,
+    //the queue size and lag threshold of subscribers
+    subscriber_queue_policy: SubscriberQueuePolicy,
+    //stream identifier to its standby publisher
+    standby_publishers: HashMap<StreamIdentifier, StandbyPublisher>,
+    //the policy of the apps which are not configured separately
+    default_app_policy: AppPolicy,
+    //app name to its policy
+    app_policies: HashMap<String, AppPolicy>,
+    //where the exported clips are written
+    clip_dir: String,
+    //the memory budget shared by the gop caches of all the streams
+    cache_budget: CacheBudget,
+    //whether the rtsp/whip streams are remuxed to rtmp on demand
+    remux_policy: RemuxPolicy,
+    //the rtmp stream identifier to its on demand remuxing
+    remuxed_streams: HashMap<StreamIdentifier, RemuxedStream>,
+    //creates the processors of the published streams
+    processor_registry: ProcessorRegistry,
 }
 
 impl StreamsHub {
@@ -526,6 +981,8 @@ impl StreamsHub {
 
         Self {
             streams: HashMap::new(),
+            publishers: HashMap::new(),
+            pending_subscribers: HashMap::new(),
             un_pub_sub_events: HashMap::new(),
             hub_event_receiver: event_consumer,
             hub_event_sender: event_producer,
@@ -534,7 +991,17 @@ impl StreamsHub {
             rtmp_pull_enabled: false,
             rtmp_remuxer_enabled: false,
             hls_enabled: false,
+            dash_enabled: false,
             notifier,
+            subscriber_queue_policy: SubscriberQueuePolicy::default(),
+            standby_publishers: HashMap::new(),
+            default_app_policy: AppPolicy::default(),
+            app_policies: HashMap::new(),
+            clip_dir: String::from("./clips"),
+            cache_budget: CacheBudget::default(),
+            remux_policy: RemuxPolicy::default(),
+            remuxed_streams: HashMap::new(),
+            processor_registry: ProcessorRegistry::default(),
         }
     }
     pub async fn run(&mut self) {
@@ -553,10 +1020,64 @@ impl StreamsHub {
         self.rtmp_remuxer_enabled = enabled;
     }
 
+    pub fn set_remux_policy(&mut self, policy: RemuxPolicy) {
+        self.remux_policy = policy;
+    }
+
     pub fn set_hls_enabled(&mut self, enabled: bool) {
         self.hls_enabled = enabled;
     }
 
+    pub fn set_dash_enabled(&mut self, enabled: bool) {
+        self.dash_enabled = enabled;
+    }
+
+    pub fn set_subscriber_queue_policy(&mut self, policy: SubscriberQueuePolicy) {
+        self.subscriber_queue_policy = policy;
+    }
+
+    pub fn get_subscriber_queue_policy(&self) -> SubscriberQueuePolicy {
+        self.subscriber_queue_policy.clone()
+    }
+
+    pub fn set_default_app_policy(&mut self, policy: AppPolicy) {
+        self.default_app_policy = policy;
+    }
+
+    pub fn set_app_policy(&mut self, app_name: String, policy: AppPolicy) {
+        self.app_policies.insert(app_name, policy);
+    }
+
+    pub fn set_clip_dir(&mut self, clip_dir: String) {
+        self.clip_dir = clip_dir;
+    }
+
+    //0 means no limit
+    pub fn set_cache_budget(&mut self, max_bytes: usize) {
+        self.cache_budget.set_max_bytes(max_bytes);
+    }
+
+    pub fn get_cache_budget(&self) -> CacheBudget {
+        self.cache_budget.clone()
+    }
+
+    //Registers a processor factory for the streams of an app, or of all the apps if the
+    //app name is None. It applies to the streams published after it is registered.
+    pub fn register_processor(
+        &mut self,
+        app_name: Option<String>,
+        factory: Arc<dyn TStreamProcessorFactory>,
+    ) {
+        self.processor_registry.register(app_name, factory);
+    }
+
+    fn get_app_policy(&self, identifier: &StreamIdentifier) -> &AppPolicy {
+        identifier
+            .app_name()
+            .and_then(|app_name| self.app_policies.get(app_name))
+            .unwrap_or(&self.default_app_policy)
+    }
+
     pub fn get_hub_event_sender(&mut self) -> StreamHubEventSender {
         self.hub_event_sender.clone()
     }
@@ -616,6 +1137,95 @@ impl StreamsHub {
                         }
                     };
 
+                    if let Some(publisher) = self.publishers.get_mut(&identifier) {
+                        if publisher.suspended {
+                            let event = TransceiverEvent::Republish {
+                                data_receiver: receiver,
+                                stream_handler,
+                            };
+                            let result = match self.streams.get(&identifier) {
+                                Some(producer) if producer.send(event).is_ok() => {
+                                    log::info!(
+                                        "publish: {} reconnects in grace period",
+                                        identifier
+                                    );
+                                    //the grace period timer of the left publisher
+                                    //is ignored once its unpublish event is gone
+                                    self.un_pub_sub_events.remove(&publisher.id);
+                                    publisher.id = info.id;
+                                    publisher.suspended = false;
+                                    let statistic_data_sender =
+                                        publisher.statistic_data_sender.clone();
+                                    self.un_pub_sub_events.insert(
+                                        info.id,
+                                        StreamHubEvent::UnPublish { identifier, info },
+                                    );
+                                    Ok((frame_sender, packet_sender, Some(statistic_data_sender)))
+                                }
+                                _ => Err(StreamHubError {
+                                    value: StreamHubErrorValue::SendError,
+                                }),
+                            };
+
+                            if result_sender.send(result).is_err() {
+                                log::error!("event_loop Publish error: The receiver dropped.")
+                            }
+                            continue;
+                        }
+                    }
+
+                    let publish_policy = self.get_app_policy(&identifier).publish_policy.clone();
+                    if self.streams.contains_key(&identifier) {
+                        match publish_policy {
+                            PublishPolicy::Replace => {
+                                let result = self
+                                    .replace_publisher(&identifier, info, receiver, stream_handler)
+                                    .await
+                                    .map(|statistic_data_sender| {
+                                        (frame_sender, packet_sender, Some(statistic_data_sender))
+                                    });
+                                if result_sender.send(result).is_err() {
+                                    log::error!("event_loop Publish error: The receiver dropped.")
+                                }
+                                continue;
+                            }
+                            PublishPolicy::Standby
+                                if !self.standby_publishers.contains_key(&identifier) =>
+                            {
+                                log::info!("publish: hold {} as the standby publisher", identifier);
+                                //the standby publisher's statistics are not collected until it is promoted.
+                                let (statistic_data_sender, statistic_data_receiver) =
+                                    mpsc::unbounded_channel();
+                                self.standby_publishers.insert(
+                                    identifier.clone(),
+                                    StandbyPublisher::new(
+                                        info.clone(),
+                                        receiver,
+                                        statistic_data_receiver,
+                                        stream_handler,
+                                    ),
+                                );
+                                self.un_pub_sub_events.insert(
+                                    info.id,
+                                    StreamHubEvent::UnPublish { identifier, info },
+                                );
+
+                                if result_sender
+                                    .send(Ok((
+                                        frame_sender,
+                                        packet_sender,
+                                        Some(statistic_data_sender),
+                                    )))
+                                    .is_err()
+                                {
+                                    log::error!("event_loop Publish error: The receiver dropped.")
+                                }
+                                continue;
+                            }
+                            _ => {}
+                        }
+                    }
+
                     let result = match self
                         .publish(identifier.clone(), receiver, stream_handler)
                         .await
@@ -624,6 +1234,21 @@ impl StreamsHub {
                             if let Some(notifier) = &self.notifier {
                                 notifier.on_publish_notify(&message).await;
                             }
+                            self.publishers.insert(
+                                identifier.clone(),
+                                StreamPublisher {
+                                    id: info.id,
+                                    statistic_data_sender: statistic_data_sender.clone(),
+                                    suspended: false,
+                                },
+                            );
+                            if let Some(subscribers) = self.pending_subscribers.remove(&identifier)
+                            {
+                                for subscriber in subscribers {
+                                    self.attach_subscriber(identifier.clone(), subscriber).await;
+                                }
+                            }
+                            self.on_remux_publish(&identifier);
                             self.un_pub_sub_events
                                 .insert(info.id, StreamHubEvent::UnPublish { identifier, info });
 
@@ -640,10 +1265,63 @@ impl StreamsHub {
                     }
                 }
 
-                StreamHubEvent::UnPublish {
-                    identifier,
-                    info: _,
-                } => {
+                StreamHubEvent::UnPublish { identifier, info } => {
+                    if let Some(standby_publisher) = self.standby_publishers.get(&identifier) {
+                        if standby_publisher.info.id == info.id {
+                            log::info!("unpublish the standby publisher of {}", identifier);
+                            self.standby_publishers.remove(&identifier);
+                            self.un_pub_sub_events.remove(&info.id);
+                            continue;
+                        }
+                    }
+
+                    //the unpublish of a replaced publisher or of an ended grace period timer
+                    let Some(publisher) = self.publishers.get_mut(&identifier) else {
+                        log::warn!("unpublish: {} is not published", identifier);
+                        continue;
+                    };
+                    if publisher.id != info.id {
+                        log::info!("unpublish: {} is not the current publisher", info.id);
+                        //a replaced publisher which is kicked off
+                        if self.un_pub_sub_events.remove(&info.id).is_some() {
+                            if let Some(notifier) = &self.notifier {
+                                notifier.on_unpublish_notify(&message).await;
+                            }
+                        }
+                        continue;
+                    }
+                    self.remuxed_streams.remove(&identifier);
+
+                    if let Some(standby_publisher) = self.standby_publishers.remove(&identifier) {
+                        match self
+                            .promote_standby_publisher(&identifier, standby_publisher)
+                            .await
+                        {
+                            Ok(standby_info) => {
+                                if let Some(notifier) = &self.notifier {
+                                    notifier.on_unpublish_notify(&message).await;
+                                    notifier
+                                        .on_publish_notify(&StreamHubEventMessage::Publish {
+                                            identifier,
+                                            info: standby_info,
+                                        })
+                                        .await;
+                                }
+                                continue;
+                            }
+                            Err(err) => {
+                                log::error!("promote the standby publisher err: {}", err);
+                            }
+                        }
+                    }
+
+                    let policy = self.get_app_policy(&identifier).clone();
+                    if (policy.reconnect_grace_ms > 0 || policy.fallback_slate.is_some())
+                        && self.suspend_publisher(&identifier, info.clone(), &policy)
+                    {
+                        continue;
+                    }
+
                     if let Err(err) = self.unpublish(&identifier) {
                         log::error!(
                             "event_loop Unpublish err: {} with identifier: {}",
@@ -661,30 +1339,28 @@ impl StreamsHub {
                     info,
                     result_sender,
                 } => {
-                    let sub_id = info.id;
-                    let info_clone = info.clone();
-
                     //new chan for Frame/Packet sender and receiver
+                    let queue_size = self.subscriber_queue_policy.queue_size;
                     let (sender, receiver) = match info.sub_data_type {
                         define::SubDataType::Frame => {
-                            let (sender_chan, receiver_chan) = mpsc::unbounded_channel();
+                            let (sender_chan, receiver_chan) = mpsc::channel(queue_size);
                             (
                                 DataSender::Frame {
                                     sender: sender_chan,
                                 },
-                                DataReceiver {
+                                SubDataReceiver {
                                     frame_receiver: Some(receiver_chan),
                                     packet_receiver: None,
                                 },
                             )
                         }
                         define::SubDataType::Packet => {
-                            let (sender_chan, receiver_chan) = mpsc::unbounded_channel();
+                            let (sender_chan, receiver_chan) = mpsc::channel(queue_size);
                             (
                                 DataSender::Packet {
                                     sender: sender_chan,
                                 },
-                                DataReceiver {
+                                SubDataReceiver {
                                     frame_receiver: None,
                                     packet_receiver: Some(receiver_chan),
                                 },
@@ -692,33 +1368,99 @@ impl StreamsHub {
                         }
                     };
 
-                    let rv = match self.subscribe(&identifier, info_clone, sender).await {
-                        Ok(statistic_data_sender) => {
-                            if let Some(notifier) = &self.notifier {
-                                notifier.on_play_notify(&message).await;
-                            }
-
-                            self.un_pub_sub_events
-                                .insert(sub_id, StreamHubEvent::UnSubscribe { identifier, info });
-                            Ok((receiver, Some(statistic_data_sender)))
-                        }
-                        Err(err) => {
-                            log::error!("event_loop Subscribe error: {}", err);
-                            Err(err)
+                    let subscriber = PendingSubscriber {
+                        info,
+                        sender,
+                        receiver,
+                        result_sender,
+                    };
+                    let subscribe_wait_ms = self.get_app_policy(&identifier).subscribe_wait_ms;
+                    if self.streams.contains_key(&identifier) {
+                        self.attach_subscriber(identifier, subscriber).await;
+                    } else if matches!(subscriber.info.sub_data_type, define::SubDataType::Frame)
+                        && self.start_remux(&identifier)
+                    {
+                        let wait_ms = subscribe_wait_ms.max(REMUX_START_WAIT_MS);
+                        self.park_subscriber(identifier, subscriber, wait_ms);
+                    } else if subscribe_wait_ms > 0 {
+                        if let Err(err) = self.pull_stream(&identifier) {
+                            log::error!("subscribe: pull stream {} err: {}", identifier, err);
                         }
+                        self.park_subscriber(identifier, subscriber, subscribe_wait_ms);
+                    } else {
+                        self.attach_subscriber(identifier, subscriber).await;
+                    }
+                }
+                StreamHubEvent::SubscribeTimeout { identifier, id } => {
+                    let Some(subscribers) = self.pending_subscribers.get_mut(&identifier) else {
+                        continue;
+                    };
+                    let Some(index) = subscribers.iter().position(|sub| sub.info.id == id) else {
+                        continue;
                     };
+                    let subscriber = subscribers.remove(index);
+                    if subscribers.is_empty() {
+                        self.pending_subscribers.remove(&identifier);
+                    }
 
-                    if result_sender.send(rv).is_err() {
-                        log::error!("event_loop Subscribe error: The receiver dropped.")
+                    log::info!("subscribe: {} is not published in time", identifier);
+                    let rv = Err(StreamHubError {
+                        value: StreamHubErrorValue::NoAppOrStreamName,
+                    });
+                    if subscriber.result_sender.send(rv).is_err() {
+                        log::error!("event_loop SubscribeTimeout error: The receiver dropped.")
                     }
                 }
                 StreamHubEvent::UnSubscribe { identifier, info } => {
+                    let keeps_remux_alive = remux::keeps_remux_alive(&info.sub_type);
+                    let is_shared = info.sub_type.is_shared();
                     if self.unsubscribe(&identifier, info).is_ok() {
+                        if keeps_remux_alive {
+                            self.on_remux_unsubscribe(&identifier);
+                        }
+                        if is_shared {
+                            continue;
+                        }
                         if let Some(notifier) = &self.notifier {
                             notifier.on_stop_notify(&message).await;
                         }
                     }
                 }
+                StreamHubEvent::ViewerJoin { identifier, info } => {
+                    let Some(producer) = self.streams.get(&identifier) else {
+                        continue;
+                    };
+                    let event = TransceiverEvent::ViewerJoin { info: info.clone() };
+                    if producer.send(event).is_err() {
+                        log::error!("event_loop ViewerJoin send error");
+                        continue;
+                    }
+                    if let Some(remuxed_stream) = self.remuxed_streams.get_mut(&identifier) {
+                        remuxed_stream.on_subscribe();
+                    }
+                    if let Some(notifier) = &self.notifier {
+                        notifier.on_play_notify(&message).await;
+                    }
+                }
+                StreamHubEvent::ViewerLeave { identifier, info } => {
+                    let Some(producer) = self.streams.get(&identifier) else {
+                        continue;
+                    };
+                    if producer
+                        .send(TransceiverEvent::ViewerLeave { info })
+                        .is_err()
+                    {
+                        log::error!("event_loop ViewerLeave send error");
+                        continue;
+                    }
+                    self.on_remux_unsubscribe(&identifier);
+                    if let Some(notifier) = &self.notifier {
+                        notifier.on_stop_notify(&message).await;
+                    }
+                }
+                StreamHubEvent::RemuxIdleTimeout { identifier } => {
+                    self.stop_idle_remux(&identifier);
+                }
 
                 StreamHubEvent::ApiStatistic {
                     top_n,
@@ -758,6 +1500,13 @@ impl StreamsHub {
                         log::error!("event_loop api error: {:?}", err);
                     }
                 }
+                StreamHubEvent::ApiExportClip {
+                    identifier,
+                    duration_ms,
+                    result_sender,
+                } => {
+                    self.api_export_clip(identifier, duration_ms, result_sender);
+                }
                 StreamHubEvent::ApiStopRelayStream {
                     id,
                     relay_type,
@@ -854,6 +1603,66 @@ impl StreamsHub {
         Ok(serde_json::to_value(data)?)
     }
 
+    //The clip is exported by the transceiver of the stream, the api gets the clip
+    //when the file is finalized and then the notifier is called.
+    fn api_export_clip(
+        &self,
+        identifier: StreamIdentifier,
+        duration_ms: u64,
+        result_sender: ApiExportClipResultSender,
+    ) {
+        let Some(event_sender) = self.streams.get(&identifier) else {
+            let result = Err(StreamHubError {
+                value: StreamHubErrorValue::NoAppOrStreamName,
+            });
+            if result_sender.send(result).is_err() {
+                log::error!("api_export_clip send result error");
+            }
+            return;
+        };
+
+        let file_name = format!(
+            "{}_{}.flv",
+            identifier.to_string().replace(['/', ':', ' '], "_"),
+            chrono::Local::now().format("%Y%m%d%H%M%S%3f")
+        );
+        let path = format!("{}/{}", self.clip_dir, file_name);
+
+        let (sender, receiver) = oneshot::channel();
+        let event = TransceiverEvent::ExportClip {
+            duration_ms,
+            path,
+            result_sender: sender,
+        };
+        if event_sender.send(event).is_err() {
+            let result = Err(StreamHubError {
+                value: StreamHubErrorValue::SendError,
+            });
+            if result_sender.send(result).is_err() {
+                log::error!("api_export_clip send result error");
+            }
+            return;
+        }
+
+        let notifier = self.notifier.clone();
+        tokio::spawn(async move {
+            let result = match receiver.await {
+                Ok(result) => result,
+                Err(err) => Err(err.into()),
+            };
+            let clip = result.as_ref().ok().cloned();
+            if result_sender.send(result).is_err() {
+                log::error!("api_export_clip send result error");
+            }
+
+            if let (Some(clip), Some(notifier)) = (clip, notifier) {
+                notifier
+                    .on_clip_notify(&StreamHubEventMessage::Clip { identifier, clip })
+                    .await;
+            }
+        });
+    }
+
     fn api_kick_off_client(&mut self, uid: Uuid) -> Result<(), StreamHubError> {
         if let Some(event) = self.un_pub_sub_events.get(&uid) {
             match event {
@@ -979,12 +1788,21 @@ impl StreamsHub {
             return Ok(result_receiver.await?);
         }
 
+        self.pull_stream(identifer)?;
+
+        Err(StreamHubError {
+            value: StreamHubErrorValue::NoAppOrStreamName,
+        })
+    }
+
+    //asks the pull clients to pull the stream from the other server nodes.
+    fn pull_stream(&self, identifier: &StreamIdentifier) -> Result<(), StreamHubError> {
         if self.rtmp_pull_enabled {
-            log::info!("subscribe: try to pull stream, identifier: {}", identifer);
+            log::info!("subscribe: try to pull stream, identifier: {}", identifier);
 
             let client_event = BroadcastEvent::Subscribe {
                 id: String::from("rtmp_relay"),
-                identifier: identifer.clone(),
+                identifier: identifier.clone(),
                 server_address: None,
                 result_sender: None,
             };
@@ -996,10 +1814,7 @@ impl StreamsHub {
                     value: StreamHubErrorValue::SendError,
                 })?;
         }
-
-        Err(StreamHubError {
-            value: StreamHubErrorValue::NoAppOrStreamName,
-        })
+        Ok(())
     }
 
     pub fn unsubscribe(
@@ -1040,8 +1855,17 @@ impl StreamsHub {
         }
 
         let (event_sender, event_receiver) = mpsc::unbounded_channel();
-        let transceiver =
-            StreamDataTransceiver::new(receiver, event_receiver, identifier.clone(), handler);
+        let transceiver = StreamDataTransceiver::new(
+            receiver,
+            event_receiver,
+            identifier.clone(),
+            handler,
+            self.subscriber_queue_policy.clone(),
+            self.get_app_policy(&identifier).timestamp_policy.clone(),
+            self.get_app_policy(&identifier).dvr_policy.clone(),
+            self.cache_budget.clone(),
+            self.processor_registry.create_chain(&identifier),
+        );
 
         let statistic_data_sender = transceiver.get_statistics_data_sender();
         let identifier_clone = identifier.clone();
@@ -1058,8 +1882,16 @@ impl StreamsHub {
 
         self.streams.insert(identifier.clone(), event_sender);
 
-        if self.rtmp_push_enabled || self.hls_enabled || self.rtmp_remuxer_enabled {
-            let client_event = BroadcastEvent::Publish { identifier };
+        if self.rtmp_push_enabled
+            || self.hls_enabled
+            || self.dash_enabled
+            || self.rtmp_remuxer_enabled
+        {
+            let timestamp_normalized = self.get_app_policy(&identifier).timestamp_policy.enabled;
+            let client_event = BroadcastEvent::Publish {
+                identifier,
+                timestamp_normalized,
+            };
 
             //send publish info to push clients
             self.client_event_sender
@@ -1072,6 +1904,352 @@ impl StreamsHub {
         Ok(statistic_data_sender)
     }
 
+    //Subscribes to a published stream and answers the subscriber with the receiver.
+    async fn attach_subscriber(
+        &mut self,
+        identifier: StreamIdentifier,
+        subscriber: PendingSubscriber,
+    ) {
+        let PendingSubscriber {
+            info,
+            sender,
+            receiver,
+            result_sender,
+        } = subscriber;
+        let sub_id = info.id;
+
+        let rv = match self.subscribe(&identifier, info.clone(), sender).await {
+            Ok(statistic_data_sender) => {
+                if let Some(notifier) = self
+                    .notifier
+                    .as_ref()
+                    .filter(|_| !info.sub_type.is_shared())
+                {
+                    let message = StreamHubEventMessage::Subscribe {
+                        identifier: identifier.clone(),
+                        info: info.clone(),
+                    };
+                    notifier.on_play_notify(&message).await;
+                }
+
+                if remux::keeps_remux_alive(&info.sub_type) {
+                    if let Some(remuxed_stream) = self.remuxed_streams.get_mut(&identifier) {
+                        remuxed_stream.on_subscribe();
+                    }
+                }
+                self.un_pub_sub_events
+                    .insert(sub_id, StreamHubEvent::UnSubscribe { identifier, info });
+                Ok((receiver, Some(statistic_data_sender)))
+            }
+            Err(err) => {
+                log::error!("event_loop Subscribe error: {}", err);
+                Err(err)
+            }
+        };
+
+        if result_sender.send(rv).is_err() {
+            log::error!("event_loop Subscribe error: The receiver dropped.")
+        }
+    }
+
+    //The subscriber is attached when the stream is published, or is answered with
+    //NoAppOrStreamName when the wait times out.
+    fn park_subscriber(
+        &mut self,
+        identifier: StreamIdentifier,
+        subscriber: PendingSubscriber,
+        subscribe_wait_ms: u64,
+    ) {
+        log::info!(
+            "subscribe: wait {} ms for {} to be published",
+            subscribe_wait_ms,
+            identifier
+        );
+
+        let event = StreamHubEvent::SubscribeTimeout {
+            identifier: identifier.clone(),
+            id: subscriber.info.id,
+        };
+        self.pending_subscribers
+            .entry(identifier)
+            .or_default()
+            .push(subscriber);
+
+        let hub_event_sender = self.hub_event_sender.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(subscribe_wait_ms)).await;
+            if let Err(err) = hub_event_sender.send(event) {
+                log::error!("send subscribe timeout err: {}", err);
+            }
+        });
+    }
+
+    //Asks the rtmp remuxer to remux the rtsp/whip source of the subscribed rtmp stream
+    //if the remuxing is on demand. Returns false if there is no source to remux.
+    fn start_remux(&mut self, identifier: &StreamIdentifier) -> bool {
+        if !self.remux_policy.on_demand {
+            return false;
+        }
+        let start_wait = Duration::from_millis(REMUX_START_WAIT_MS);
+        if let Some(remuxed_stream) = self.remuxed_streams.get(identifier) {
+            if remuxed_stream.is_starting_for(start_wait) {
+                return true;
+            }
+        }
+        let Some(source) = remux::remux_sources(identifier)
+            .into_iter()
+            .find(|source| self.streams.contains_key(source))
+        else {
+            return false;
+        };
+
+        log::info!("start remuxing {} to {} on demand", source, identifier);
+        let client_event = BroadcastEvent::Remux {
+            identifier: source.clone(),
+        };
+        if let Err(err) = self.client_event_sender.send(client_event) {
+            log::error!("start remuxing {} err: {}", source, err);
+            return false;
+        }
+        self.remuxed_streams
+            .insert(identifier.clone(), RemuxedStream::new(source));
+        true
+    }
+
+    //A source is published while its rtmp stream has waiting subscribers, or the rtmp
+    //stream is published by the remuxer.
+    fn on_remux_publish(&mut self, identifier: &StreamIdentifier) {
+        if let Some(target) = remux::remux_target(identifier) {
+            if self.pending_subscribers.contains_key(&target) {
+                self.start_remux(&target);
+            }
+        } else if self
+            .remuxed_streams
+            .get_mut(identifier)
+            .is_some_and(|remuxed_stream| remuxed_stream.mark_idle())
+        {
+            //all the waiting subscribers have timed out
+            self.schedule_remux_idle_timeout(identifier.clone());
+        }
+    }
+
+    fn on_remux_unsubscribe(&mut self, identifier: &StreamIdentifier) {
+        if self
+            .remuxed_streams
+            .get_mut(identifier)
+            .is_some_and(|remuxed_stream| remuxed_stream.on_unsubscribe())
+        {
+            self.schedule_remux_idle_timeout(identifier.clone());
+        }
+    }
+
+    fn schedule_remux_idle_timeout(&self, identifier: StreamIdentifier) {
+        let idle_timeout_ms = self.remux_policy.idle_timeout_ms;
+        let hub_event_sender = self.hub_event_sender.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(idle_timeout_ms)).await;
+            let event = StreamHubEvent::RemuxIdleTimeout { identifier };
+            if let Err(err) = hub_event_sender.send(event) {
+                log::error!("send remux idle timeout err: {}", err);
+            }
+        });
+    }
+
+    //Stops the remuxing by removing its subscription of the source, the remuxer session
+    //ends and unpublishes the rtmp stream.
+    fn stop_idle_remux(&mut self, identifier: &StreamIdentifier) {
+        let idle_timeout = Duration::from_millis(self.remux_policy.idle_timeout_ms);
+        match self.remuxed_streams.get(identifier) {
+            Some(remuxed_stream) if remuxed_stream.is_idle_for(idle_timeout) => {}
+            _ => return,
+        }
+        let Some(remuxed_stream) = self.remuxed_streams.remove(identifier) else {
+            return;
+        };
+        log::info!(
+            "{} has no subscribers for {} ms, stop remuxing {}",
+            identifier,
+            idle_timeout.as_millis(),
+            remuxed_stream.source
+        );
+
+        let remuxer_ids: Vec<Uuid> = self
+            .un_pub_sub_events
+            .iter()
+            .filter_map(|(id, event)| match event {
+                StreamHubEvent::UnSubscribe { identifier, info }
+                    if identifier == &remuxed_stream.source
+                        && matches!(
+                            info.sub_type,
+                            SubscribeType::RtspRemux2Rtmp | SubscribeType::WebRTCRemux2Rtmp
+                        ) =>
+                {
+                    Some(*id)
+                }
+                _ => None,
+            })
+            .collect();
+        for id in remuxer_ids {
+            if let Some(StreamHubEvent::UnSubscribe { identifier, info }) =
+                self.un_pub_sub_events.remove(&id)
+            {
+                if let Err(err) = self.unsubscribe(&identifier, info) {
+                    log::error!("stop remuxing {} err: {}", identifier, err);
+                }
+            }
+        }
+    }
+
+    //Keeps the stream and its subscribers after its publisher leaves, the fallback
+    //slate is played meanwhile if there is one. The stream is unpublished when the
+    //grace period ends(the timer sends the same unpublish event again).
+    //Returns false if the stream should be unpublished now.
+    fn suspend_publisher(
+        &mut self,
+        identifier: &StreamIdentifier,
+        info: PublisherInfo,
+        policy: &AppPolicy,
+    ) -> bool {
+        let (Some(publisher), Some(producer)) = (
+            self.publishers.get_mut(identifier),
+            self.streams.get(identifier),
+        ) else {
+            return false;
+        };
+        if publisher.suspended {
+            return false;
+        }
+
+        let event = match &policy.fallback_slate {
+            Some(slate) => {
+                let (data_receiver, stream_handler) = slate.play();
+                TransceiverEvent::Republish {
+                    data_receiver,
+                    stream_handler,
+                }
+            }
+            None => TransceiverEvent::Suspend {},
+        };
+        if producer.send(event).is_err() {
+            return false;
+        }
+        publisher.suspended = true;
+        log::info!("unpublish: keep {} to wait for reconnecting", identifier);
+
+        if policy.reconnect_grace_ms > 0 {
+            let hub_event_sender = self.hub_event_sender.clone();
+            let event = StreamHubEvent::UnPublish {
+                identifier: identifier.clone(),
+                info,
+            };
+            let reconnect_grace_ms = policy.reconnect_grace_ms;
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(reconnect_grace_ms)).await;
+                if let Err(err) = hub_event_sender.send(event) {
+                    log::error!("send grace period end err: {}", err);
+                }
+            });
+        }
+        true
+    }
+
+    //The new publisher takes over the stream and the subscribers are kept. The replaced
+    //publisher is kicked off, its data receiver is dropped so that its session quits.
+    async fn replace_publisher(
+        &mut self,
+        identifier: &StreamIdentifier,
+        info: PublisherInfo,
+        data_receiver: DataReceiver,
+        stream_handler: Arc<dyn TStreamHandler>,
+    ) -> Result<StatisticDataSender, StreamHubError> {
+        let (Some(publisher), Some(producer)) = (
+            self.publishers.get_mut(identifier),
+            self.streams.get(identifier),
+        ) else {
+            return Err(StreamHubError {
+                value: StreamHubErrorValue::NoAppOrStreamName,
+            });
+        };
+
+        let event = TransceiverEvent::Republish {
+            data_receiver,
+            stream_handler,
+        };
+        producer.send(event).map_err(|_| StreamHubError {
+            value: StreamHubErrorValue::SendError,
+        })?;
+        log::info!("publish: replace the publisher of {}", identifier);
+
+        let replaced_id = std::mem::replace(&mut publisher.id, info.id);
+        publisher.suspended = false;
+        let statistic_data_sender = publisher.statistic_data_sender.clone();
+
+        if let Err(err) = self.api_kick_off_client(replaced_id) {
+            log::error!("kick off the replaced publisher err: {}", err);
+        }
+        if let Some(notifier) = &self.notifier {
+            notifier
+                .on_publish_notify(&StreamHubEventMessage::Publish {
+                    identifier: identifier.clone(),
+                    info: info.clone(),
+                })
+                .await;
+        }
+        self.un_pub_sub_events.insert(
+            info.id,
+            StreamHubEvent::UnPublish {
+                identifier: identifier.clone(),
+                info,
+            },
+        );
+
+        Ok(statistic_data_sender)
+    }
+
+    //switch the stream to its standby publisher, the subscribers are kept.
+    async fn promote_standby_publisher(
+        &mut self,
+        identifier: &StreamIdentifier,
+        standby_publisher: StandbyPublisher,
+    ) -> Result<PublisherInfo, StreamHubError> {
+        let Some(publisher) = self.publishers.get_mut(identifier) else {
+            return Err(StreamHubError {
+                value: StreamHubErrorValue::NoAppName,
+            });
+        };
+        let Some((info, data_receiver, stream_handler)) = standby_publisher
+            .promote(publisher.statistic_data_sender.clone())
+            .await
+        else {
+            return Err(StreamHubError {
+                value: StreamHubErrorValue::SendError,
+            });
+        };
+
+        match self.streams.get_mut(identifier) {
+            Some(producer) => {
+                let event = TransceiverEvent::Republish {
+                    data_receiver,
+                    stream_handler,
+                };
+                producer.send(event).map_err(|_| StreamHubError {
+                    value: StreamHubErrorValue::SendError,
+                })?;
+                //the old primary has left, the standby's own unpublish event takes over.
+                self.un_pub_sub_events.remove(&publisher.id);
+                publisher.id = info.id;
+                log::info!("promote the standby publisher of {}", identifier);
+            }
+            None => {
+                return Err(StreamHubError {
+                    value: StreamHubErrorValue::NoAppName,
+                });
+            }
+        }
+
+        Ok(info)
+    }
+
     fn unpublish(&mut self, identifier: &StreamIdentifier) -> Result<(), StreamHubError> {
         match self.streams.get_mut(identifier) {
             Some(producer) => {
@@ -1080,6 +2258,7 @@ impl StreamsHub {
                     value: StreamHubErrorValue::SendError,
                 })?;
                 self.streams.remove(identifier);
+                self.publishers.remove(identifier);
                 log::info!("unpublish remove stream, stream identifier: {}", identifier);
             }
             None => {
@@ -1092,3 +2271,379 @@ impl StreamsHub {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::StreamsHub;
+    use crate::{
+        define::{
+            AppPolicy, DataSender, FrameData, FrameDataSender, InformationSender, NotifyInfo,
+            PubDataType, PublishPolicy, PublishType, PublisherInfo, StatisticData, StreamHubEvent,
+            StreamHubEventMessage, StreamHubEventSender, SubDataReceiver, SubDataType,
+            SubscribeType, SubscriberInfo, TStreamHandler, TrackFilter,
+        },
+        errors::{StreamHubError, StreamHubErrorValue},
+        notify::Notifier,
+        statistics::StatisticsStream,
+        stream::StreamIdentifier,
+        utils::{RandomDigitCount, Uuid},
+    };
+    use async_trait::async_trait;
+    use bytes::Bytes;
+    use commonlib::media::{MediaCodec, MediaFrame, PayloadFormat};
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+    use tokio::{sync::oneshot, time::timeout};
+
+    struct TestStreamHandler;
+
+    #[async_trait]
+    impl TStreamHandler for TestStreamHandler {
+        async fn send_prior_data(
+            &self,
+            sender: DataSender,
+            _sub_type: SubscribeType,
+        ) -> Result<(), StreamHubError> {
+            //like the rtmp stream handler, it only serves frames
+            match sender {
+                DataSender::Frame { .. } => Ok(()),
+                DataSender::Packet { .. } => Err(StreamHubError {
+                    value: StreamHubErrorValue::NotCorrectDataSenderType,
+                }),
+            }
+        }
+
+        async fn get_statistic_data(&self) -> Option<StatisticsStream> {
+            None
+        }
+
+        async fn send_information(&self, _sender: InformationSender) {}
+    }
+
+    #[derive(Default)]
+    struct TestNotifier {
+        publish_count: AtomicUsize,
+        unpublish_count: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl Notifier for TestNotifier {
+        async fn on_publish_notify(&self, _event: &StreamHubEventMessage) {
+            self.publish_count.fetch_add(1, Ordering::SeqCst);
+        }
+        async fn on_unpublish_notify(&self, _event: &StreamHubEventMessage) {
+            self.unpublish_count.fetch_add(1, Ordering::SeqCst);
+        }
+        async fn on_play_notify(&self, _event: &StreamHubEventMessage) {}
+        async fn on_stop_notify(&self, _event: &StreamHubEventMessage) {}
+    }
+
+    fn identifier() -> StreamIdentifier {
+        StreamIdentifier::Rtmp {
+            app_name: String::from("live"),
+            stream_name: String::from("test"),
+        }
+    }
+
+    fn subscriber_info(sub_type: SubscribeType) -> SubscriberInfo {
+        SubscriberInfo {
+            id: Uuid::new(RandomDigitCount::Four),
+            sub_type,
+            notify_info: NotifyInfo::default(),
+            sub_data_type: SubDataType::Frame,
+            track_filter: TrackFilter::All,
+        }
+    }
+
+    fn publisher_info() -> PublisherInfo {
+        PublisherInfo {
+            id: Uuid::new(RandomDigitCount::Four),
+            pub_type: PublishType::RtmpPush,
+            pub_data_type: PubDataType::Frame,
+            notify_info: NotifyInfo::default(),
+        }
+    }
+
+    async fn publish(sender: &StreamHubEventSender, info: PublisherInfo) -> FrameDataSender {
+        let (result_sender, result_receiver) = oneshot::channel();
+        sender
+            .send(StreamHubEvent::Publish {
+                identifier: identifier(),
+                info,
+                result_sender,
+                stream_handler: Arc::new(TestStreamHandler),
+            })
+            .unwrap();
+        let (frame_sender, _, _) = result_receiver.await.unwrap().unwrap();
+        frame_sender.unwrap()
+    }
+
+    async fn start_hub_with_policy(policy: AppPolicy) -> StreamHubEventSender {
+        let mut hub = StreamsHub::new(None);
+        hub.set_default_app_policy(policy);
+        let sender = hub.get_hub_event_sender();
+        tokio::spawn(async move { hub.run().await });
+        sender
+    }
+
+    async fn start_hub() -> StreamHubEventSender {
+        let sender = start_hub_with_policy(AppPolicy::default()).await;
+        publish(&sender, publisher_info()).await;
+        sender
+    }
+
+    async fn subscribe(sender: &StreamHubEventSender, info: SubscriberInfo) -> SubDataReceiver {
+        let (result_sender, result_receiver) = oneshot::channel();
+        sender
+            .send(StreamHubEvent::Subscribe {
+                identifier: identifier(),
+                info,
+                result_sender,
+            })
+            .unwrap();
+        result_receiver.await.unwrap().unwrap().0
+    }
+
+    async fn subscriber_count(sender: &StreamHubEventSender) -> u64 {
+        let (result_sender, result_receiver) = oneshot::channel();
+        sender
+            .send(StreamHubEvent::ApiStatistic {
+                top_n: None,
+                identifier: Some(identifier()),
+                uuid: None,
+                result_sender,
+            })
+            .unwrap();
+        result_receiver.await.unwrap()[0]["subscriber_count"]
+            .as_u64()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_shared_stage_viewers() {
+        let sender = start_hub().await;
+
+        let stage = subscriber_info(SubscribeType::RtmpRemux2HttpFlvStage);
+        subscribe(&sender, stage.clone()).await;
+        assert_eq!(subscriber_count(&sender).await, 0);
+
+        let viewers = [
+            subscriber_info(SubscribeType::RtmpRemux2HttpFlv),
+            subscriber_info(SubscribeType::RtmpRemux2HttpFlv),
+        ];
+        for viewer in &viewers {
+            let event = StreamHubEvent::ViewerJoin {
+                identifier: identifier(),
+                info: viewer.clone(),
+            };
+            sender.send(event).unwrap();
+        }
+        assert_eq!(subscriber_count(&sender).await, 2);
+
+        //a viewer leaving twice is only counted once
+        for _ in 0..2 {
+            let event = StreamHubEvent::ViewerLeave {
+                identifier: identifier(),
+                info: viewers[0].clone(),
+            };
+            sender.send(event).unwrap();
+        }
+        assert_eq!(subscriber_count(&sender).await, 1);
+
+        let event = StreamHubEvent::UnSubscribe {
+            identifier: identifier(),
+            info: stage,
+        };
+        sender.send(event).unwrap();
+        assert_eq!(subscriber_count(&sender).await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_convert_media_frames() {
+        let sender = start_hub_with_policy(AppPolicy::default()).await;
+        let frame_sender = publish(&sender, publisher_info()).await;
+        let mut receiver = subscribe(&sender, subscriber_info(SubscribeType::RtmpPull))
+            .await
+            .frame_receiver
+            .unwrap();
+
+        let frame = MediaFrame {
+            codec: MediaCodec::H264,
+            pts: 40,
+            dts: 40,
+            key_frame: true,
+            format: PayloadFormat::AnnexB,
+            data: Bytes::from_static(&[0x00, 0x00, 0x00, 0x01, 0x65, 0x88]),
+        };
+        frame_sender.send(FrameData::Media { frame }).unwrap();
+
+        //the subscriber gets the flv tag body with the length prefixed nalu
+        match timeout(Duration::from_secs(1), receiver.recv())
+            .await
+            .unwrap()
+        {
+            Some(FrameData::Video { timestamp: _, data }) => {
+                assert_eq!(&data[..], &[0x17, 0x01, 0, 0, 0, 0, 0, 0, 2, 0x65, 0x88]);
+            }
+            _ => panic!("not a video frame"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_replace_publisher() {
+        let sender = start_hub_with_policy(AppPolicy {
+            publish_policy: PublishPolicy::Replace,
+            ..Default::default()
+        })
+        .await;
+
+        let replaced_info = publisher_info();
+        let replaced_sender = publish(&sender, replaced_info.clone()).await;
+        subscribe(&sender, subscriber_info(SubscribeType::RtmpPull)).await;
+        publish(&sender, publisher_info()).await;
+
+        //the replaced publisher is kicked off and its session sees the data sender closed
+        timeout(Duration::from_secs(1), replaced_sender.closed())
+            .await
+            .unwrap();
+
+        //its own unpublish does not end the stream of the new publisher
+        let event = StreamHubEvent::UnPublish {
+            identifier: identifier(),
+            info: replaced_info,
+        };
+        sender.send(event).unwrap();
+        assert_eq!(subscriber_count(&sender).await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_promote_standby_publisher() {
+        let sender = start_hub_with_policy(AppPolicy {
+            publish_policy: PublishPolicy::Standby,
+            ..Default::default()
+        })
+        .await;
+
+        let primary_info = publisher_info();
+        publish(&sender, primary_info.clone()).await;
+        subscribe(&sender, subscriber_info(SubscribeType::RtmpPull)).await;
+
+        let standby_info = publisher_info();
+        let (result_sender, result_receiver) = oneshot::channel();
+        sender
+            .send(StreamHubEvent::Publish {
+                identifier: identifier(),
+                info: standby_info.clone(),
+                result_sender,
+                stream_handler: Arc::new(TestStreamHandler),
+            })
+            .unwrap();
+        let (_, _, statistic_sender) = result_receiver.await.unwrap().unwrap();
+        statistic_sender
+            .unwrap()
+            .send(StatisticData::Publisher {
+                id: standby_info.id,
+                remote_addr: String::from("127.0.0.1:1935"),
+                start_time: chrono::Local::now(),
+            })
+            .unwrap();
+
+        let event = StreamHubEvent::UnPublish {
+            identifier: identifier(),
+            info: primary_info,
+        };
+        sender.send(event).unwrap();
+
+        //the statistics of the promoted publisher are collected for the stream
+        let standby_id = serde_json::to_value(standby_info.id).unwrap();
+        let mut publisher_id = serde_json::Value::Null;
+        for _ in 0..100 {
+            let (result_sender, result_receiver) = oneshot::channel();
+            sender
+                .send(StreamHubEvent::ApiStatistic {
+                    top_n: None,
+                    identifier: Some(identifier()),
+                    uuid: None,
+                    result_sender,
+                })
+                .unwrap();
+            publisher_id = result_receiver.await.unwrap()[0]["publisher"]["id"].clone();
+            if publisher_id == standby_id {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(publisher_id, standby_id);
+        assert_eq!(subscriber_count(&sender).await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_in_grace_period() {
+        let notifier = Arc::new(TestNotifier::default());
+        let mut hub = StreamsHub::new(Some(notifier.clone()));
+        hub.set_default_app_policy(AppPolicy {
+            reconnect_grace_ms: 50,
+            ..Default::default()
+        });
+        let sender = hub.get_hub_event_sender();
+        tokio::spawn(async move { hub.run().await });
+
+        let left_info = publisher_info();
+        publish(&sender, left_info.clone()).await;
+        subscribe(&sender, subscriber_info(SubscribeType::RtmpPull)).await;
+        let event = StreamHubEvent::UnPublish {
+            identifier: identifier(),
+            info: left_info,
+        };
+        sender.send(event).unwrap();
+        publish(&sender, publisher_info()).await;
+
+        //the grace period timer of the left publisher expires after the reconnect
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(subscriber_count(&sender).await, 1);
+        assert_eq!(notifier.publish_count.load(Ordering::SeqCst), 1);
+        assert_eq!(notifier.unpublish_count.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_failed_subscriber_keeps_stream() {
+        let sender = start_hub_with_policy(AppPolicy::default()).await;
+        let frame_sender = publish(&sender, publisher_info()).await;
+        let mut receiver = subscribe(&sender, subscriber_info(SubscribeType::RtmpPull))
+            .await
+            .frame_receiver
+            .unwrap();
+
+        //the prior data of a packet subscriber cannot be sent by the stream handler
+        let (result_sender, result_receiver) = oneshot::channel();
+        let info = SubscriberInfo {
+            sub_data_type: SubDataType::Packet,
+            ..subscriber_info(SubscribeType::WhepPull)
+        };
+        sender
+            .send(StreamHubEvent::Subscribe {
+                identifier: identifier(),
+                info,
+                result_sender,
+            })
+            .unwrap();
+        assert!(result_receiver.await.unwrap().is_err());
+
+        //the stream goes on for the other subscribers
+        assert_eq!(subscriber_count(&sender).await, 1);
+        let frame = FrameData::Video {
+            timestamp: 0,
+            data: Bytes::from_static(&[0x17, 0x01]),
+        };
+        frame_sender.send(frame).unwrap();
+        assert!(matches!(
+            timeout(Duration::from_secs(1), receiver.recv()).await,
+            Ok(Some(FrameData::Video { .. }))
+        ));
+    }
+}
@@ -0,0 +1,222 @@
+use crate::{
+    continuity::is_sequence_header,
+    define::{FrameData, TimestampPolicy},
+};
+
+#[derive(Clone, Copy, PartialEq)]
+enum Track {
+    Video,
+    Audio,
+    //the sequence headers, codec configurations and data messages, they follow the
+    //a/v tracks and do not move the stream time.
+    Other,
+}
+
+fn track_of(frame: &FrameData) -> Track {
+    if is_sequence_header(frame) {
+        return Track::Other;
+    }
+    match frame {
+        FrameData::Video { .. } => Track::Video,
+        FrameData::Audio { .. } => Track::Audio,
+        FrameData::Media { frame } if frame.codec.is_video() => Track::Video,
+        FrameData::Media { .. } => Track::Audio,
+        _ => Track::Other,
+    }
+}
+
+//Normalizes the timestamps of a publisher before they reach the subscribers:
+//  - the stream is rebased to start from 0;
+//  - the 32 bits timestamps are extended to 64 bits, so a rollover is not taken as a jump;
+//  - a small step backwards(jitter) is clamped to keep the DTS of each track monotonic;
+//  - a jump forwards or backwards larger than the policy allows is removed by continuing
+//    one frame interval after the last timestamp, and a discontinuity is flagged for the
+//    downstream muxers.
+//The frames still carry the low 32 bits of the normalized timestamps, the muxers which
+//keep a timeline(HLS, DASH) extend them back to 64 bits with a TimestampExtender.
+pub struct TimestampNormalizer {
+    policy: TimestampPolicy,
+    //the last input a/v timestamp, extended to 64 bits
+    last_input: Option<i64>,
+    //added to the extended input timestamps
+    offset: i64,
+    //the last output timestamp of each track, keeps their DTS monotonic
+    last_video: i64,
+    last_audio: i64,
+    //the largest output timestamp of the a/v tracks
+    last_output: i64,
+    //the last interval between two frames of a track
+    frame_interval: i64,
+    discontinuity: bool,
+}
+
+impl TimestampNormalizer {
+    pub fn new(policy: TimestampPolicy) -> Self {
+        Self {
+            policy,
+            last_input: None,
+            offset: 0,
+            last_video: i64::MIN,
+            last_audio: i64::MIN,
+            last_output: 0,
+            frame_interval: 0,
+            discontinuity: false,
+        }
+    }
+
+    //The publisher is switched, the new one starts from 0 again. The switch is a
+    //discontinuity if the previous publisher has sent any frame.
+    pub fn reset(&mut self) {
+        let policy = self.policy.clone();
+        let discontinuity = self.discontinuity || self.last_input.is_some();
+        *self = Self::new(policy);
+        self.discontinuity = discontinuity && self.policy.enabled;
+    }
+
+    //Returns true once for the first frame after a discontinuity.
+    pub fn take_discontinuity(&mut self) -> bool {
+        std::mem::take(&mut self.discontinuity)
+    }
+
+    pub fn process(&mut self, frame: &mut FrameData) {
+        if !self.policy.enabled {
+            return;
+        }
+        let Some(timestamp) = frame.timestamp() else {
+            return;
+        };
+
+        let track = track_of(frame);
+        if track == Track::Other {
+            frame.set_timestamp(self.translate(timestamp));
+            return;
+        }
+
+        let extended = self.extend(timestamp);
+        let started = self.last_input.is_some();
+        if !started {
+            self.offset = -extended;
+        }
+        self.last_input = Some(extended);
+
+        let mut output = extended + self.offset;
+        let max_jump = self.policy.max_jump_ms as i64;
+        if !started {
+            self.last_output = output;
+        } else if max_jump > 0 && (output - self.last_output).abs() > max_jump {
+            let next_output = self.last_output + self.frame_interval.max(1);
+            log::warn!(
+                "timestamp jumps from {} to {}, continue from {}",
+                self.last_output,
+                output,
+                next_output
+            );
+            self.offset = next_output - extended;
+            output = next_output;
+            self.discontinuity = true;
+        }
+
+        let last_track = match track {
+            Track::Video => &mut self.last_video,
+            _ => &mut self.last_audio,
+        };
+        //the other track may start a little earlier than the first frame.
+        output = output.max(*last_track).max(0);
+        if *last_track != i64::MIN && output > *last_track {
+            self.frame_interval = output - *last_track;
+        }
+        *last_track = output;
+        self.last_output = self.last_output.max(output);
+
+        frame.set_timestamp(output as u32);
+    }
+
+    //Maps a timestamp of the current publisher without changing the state, it is
+    //used for the sequence headers, data messages and the cached frames.
+    pub fn translate(&self, timestamp: u32) -> u32 {
+        if !self.policy.enabled {
+            return timestamp;
+        }
+        match self.last_input {
+            Some(_) => (self.extend(timestamp) + self.offset).max(0) as u32,
+            //the stream has not started yet
+            None => 0,
+        }
+    }
+
+    fn extend(&self, timestamp: u32) -> i64 {
+        extend_timestamp(self.last_input, timestamp)
+    }
+}
+
+//The timestamp is taken as the one nearest to the last extended timestamp, so a
+//rollover of the 32 bits timestamps continues the 64 bits timeline.
+fn extend_timestamp(last: Option<i64>, timestamp: u32) -> i64 {
+    match last {
+        Some(last) => {
+            let delta = timestamp.wrapping_sub(last as u32) as i32;
+            last + delta as i64
+        }
+        None => timestamp as i64,
+    }
+}
+
+//Extends the 32 bits timestamps of the frames a subscriber receives to 64 bits, so
+//the timeline of a muxer goes on across the rollover instead of falling back to 0.
+#[derive(Default)]
+pub struct TimestampExtender {
+    last: Option<i64>,
+}
+
+impl TimestampExtender {
+    pub fn extend(&mut self, timestamp: u32) -> i64 {
+        let extended = extend_timestamp(self.last, timestamp);
+        self.last = Some(extended);
+        extended
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TimestampExtender, TimestampNormalizer};
+    use crate::define::{FrameData, TimestampPolicy};
+    use bytes::Bytes;
+
+    fn normalize(normalizer: &mut TimestampNormalizer, timestamp: u32) -> (u32, bool) {
+        let mut frame = FrameData::Video {
+            timestamp,
+            data: Bytes::from_static(&[0x27, 0x01]),
+        };
+        normalizer.process(&mut frame);
+        (frame.timestamp().unwrap(), normalizer.take_discontinuity())
+    }
+
+    #[test]
+    fn test_normalize_timestamps() {
+        let mut normalizer = TimestampNormalizer::new(TimestampPolicy {
+            enabled: true,
+            max_jump_ms: 10000,
+        });
+
+        //rebased to 0 and extended across the rollover
+        assert_eq!(normalize(&mut normalizer, u32::MAX - 39), (0, false));
+        assert_eq!(normalize(&mut normalizer, 0), (40, false));
+        assert_eq!(normalize(&mut normalizer, 40), (80, false));
+        //the jitter is clamped
+        assert_eq!(normalize(&mut normalizer, 30), (80, false));
+        //the jump is removed and flagged, the stream continues one frame interval later
+        assert_eq!(normalize(&mut normalizer, 60000), (120, true));
+        assert_eq!(normalize(&mut normalizer, 60040), (160, false));
+    }
+
+    #[test]
+    fn test_extend_timestamps() {
+        let mut extender = TimestampExtender::default();
+
+        assert_eq!(extender.extend(u32::MAX - 39), u32::MAX as i64 - 39);
+        assert_eq!(extender.extend(0), u32::MAX as i64 + 1);
+        //a frame of the other track a little earlier
+        assert_eq!(extender.extend(u32::MAX - 19), u32::MAX as i64 - 19);
+        assert_eq!(extender.extend(40), u32::MAX as i64 + 41);
+    }
+}
@@ -23,6 +23,7 @@ pub struct HttpNotifier {
     on_unpublish_url: Option<String>,
     on_play_url: Option<String>,
     on_stop_url: Option<String>,
+    on_clip_url: Option<String>,
 }
 
 impl HttpNotifier {
@@ -31,6 +32,7 @@ impl HttpNotifier {
         on_unpublish_url: Option<String>,
         on_play_url: Option<String>,
         on_stop_url: Option<String>,
+        on_clip_url: Option<String>,
     ) -> Self {
         Self {
             request_client: reqwest::Client::new(),
@@ -38,6 +40,7 @@ impl HttpNotifier {
             on_unpublish_url,
             on_play_url,
             on_stop_url,
+            on_clip_url,
         }
     }
 }
@@ -119,4 +122,23 @@ impl Notifier for HttpNotifier {
             }
         }
     }
+
+    async fn on_clip_notify(&self, event: &StreamHubEventMessage) {
+        if let Some(on_clip_url) = &self.on_clip_url {
+            match self
+                .request_client
+                .post(on_clip_url)
+                .body(serialize_event!(event))
+                .send()
+                .await
+            {
+                Err(err) => {
+                    log::error!("on_clip error: {}", err);
+                }
+                Ok(response) => {
+                    log::info!("on_clip success: {:?}", response);
+                }
+            }
+        }
+    }
 }
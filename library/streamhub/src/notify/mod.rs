@@ -9,4 +9,6 @@ pub trait Notifier: Sync + Send {
     async fn on_unpublish_notify(&self, event: &StreamHubEventMessage);
     async fn on_play_notify(&self, event: &StreamHubEventMessage);
     async fn on_stop_notify(&self, event: &StreamHubEventMessage);
+    //a clip of a stream has been written to a file
+    async fn on_clip_notify(&self, _event: &StreamHubEventMessage) {}
 }
\ No newline at end of file
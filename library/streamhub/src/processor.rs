@@ -0,0 +1,166 @@
+use {
+    crate::{
+        define::{FrameData, PacketData},
+        stream::StreamIdentifier,
+    },
+    std::{fmt, sync::Arc},
+};
+
+//A processor of one stream, it is called for each frame/packet between the publisher
+//and the subscribers. The data pushed to the output is sent on in place of the input:
+//push it(modified or not) to keep it, push nothing to drop it, or push more to inject
+//new data before or after it.
+//The processors do not see the prior data(the gop caches of the protocols), and the
+//packet path is only processed for the publishers which send rtp packets(WHIP/RTSP).
+pub trait TStreamProcessor: Send {
+    fn process_frame(&mut self, frame: FrameData, output: &mut Vec<FrameData>) {
+        output.push(frame);
+    }
+
+    fn process_packet(&mut self, packet: PacketData, output: &mut Vec<PacketData>) {
+        output.push(packet);
+    }
+}
+
+//Registered on the StreamsHub for all the apps or for one app, it creates the processor
+//of each newly published stream.
+pub trait TStreamProcessorFactory: Send + Sync {
+    //None if the stream is not processed by this factory
+    fn create(&self, identifier: &StreamIdentifier) -> Option<Box<dyn TStreamProcessor>>;
+}
+
+impl fmt::Debug for dyn TStreamProcessorFactory {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "TStreamProcessorFactory")
+    }
+}
+
+//The registered factories, the processors of a stream are called in the order their
+//factories are registered.
+#[derive(Debug, Clone, Default)]
+pub struct ProcessorRegistry {
+    //the app name(None for all the apps) to the factory
+    factories: Vec<(Option<String>, Arc<dyn TStreamProcessorFactory>)>,
+}
+
+impl ProcessorRegistry {
+    pub fn register(
+        &mut self,
+        app_name: Option<String>,
+        factory: Arc<dyn TStreamProcessorFactory>,
+    ) {
+        self.factories.push((app_name, factory));
+    }
+
+    pub fn create_chain(&self, identifier: &StreamIdentifier) -> ProcessorChain {
+        let processors = self
+            .factories
+            .iter()
+            .filter(|(app_name, _)| {
+                app_name.is_none() || app_name.as_deref() == identifier.app_name()
+            })
+            .filter_map(|(_, factory)| factory.create(identifier))
+            .collect();
+        ProcessorChain { processors }
+    }
+}
+
+//The processors of a stream, each one processes the output of the previous one.
+#[derive(Default)]
+pub struct ProcessorChain {
+    processors: Vec<Box<dyn TStreamProcessor>>,
+}
+
+impl ProcessorChain {
+    pub fn is_empty(&self) -> bool {
+        self.processors.is_empty()
+    }
+
+    pub fn process_frame(&mut self, frame: FrameData) -> Vec<FrameData> {
+        let mut frames = vec![frame];
+        for processor in &mut self.processors {
+            let mut output = Vec::with_capacity(frames.len());
+            for frame in frames {
+                processor.process_frame(frame, &mut output);
+            }
+            frames = output;
+        }
+        frames
+    }
+
+    pub fn process_packet(&mut self, packet: PacketData) -> Vec<PacketData> {
+        let mut packets = vec![packet];
+        for processor in &mut self.processors {
+            let mut output = Vec::with_capacity(packets.len());
+            for packet in packets {
+                processor.process_packet(packet, &mut output);
+            }
+            packets = output;
+        }
+        packets
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ProcessorRegistry, TStreamProcessor, TStreamProcessorFactory};
+    use crate::{define::FrameData, stream::StreamIdentifier};
+    use bytes::Bytes;
+    use std::sync::Arc;
+
+    //drops the audio and sends a data message after each video frame
+    struct Tagger;
+
+    impl TStreamProcessor for Tagger {
+        fn process_frame(&mut self, frame: FrameData, output: &mut Vec<FrameData>) {
+            if let FrameData::Video { timestamp, data: _ } = &frame {
+                let timestamp = *timestamp;
+                output.push(frame);
+                output.push(FrameData::MetaData {
+                    timestamp,
+                    data: Bytes::from_static(b"tag"),
+                });
+            }
+        }
+    }
+
+    struct TaggerFactory;
+
+    impl TStreamProcessorFactory for TaggerFactory {
+        fn create(&self, _identifier: &StreamIdentifier) -> Option<Box<dyn TStreamProcessor>> {
+            Some(Box::new(Tagger))
+        }
+    }
+
+    fn identifier(app_name: &str) -> StreamIdentifier {
+        StreamIdentifier::Rtmp {
+            app_name: String::from(app_name),
+            stream_name: String::from("test"),
+        }
+    }
+
+    #[test]
+    fn test_processor_chain() {
+        let mut registry = ProcessorRegistry::default();
+        registry.register(Some(String::from("live")), Arc::new(TaggerFactory));
+        assert!(registry.create_chain(&identifier("vod")).is_empty());
+
+        let mut chain = registry.create_chain(&identifier("live"));
+        let audio = FrameData::Audio {
+            timestamp: 40,
+            data: Bytes::from_static(&[0xaf, 0x01]),
+        };
+        assert!(chain.process_frame(audio).is_empty());
+
+        let video = FrameData::Video {
+            timestamp: 40,
+            data: Bytes::from_static(&[0x27, 0x01]),
+        };
+        let frames = chain.process_frame(video);
+        assert_eq!(frames.len(), 2);
+        assert!(matches!(
+            frames[1],
+            FrameData::MetaData { timestamp: 40, .. }
+        ));
+    }
+}
@@ -0,0 +1,160 @@
+use {
+    crate::{define::SubscribeType, stream::StreamIdentifier},
+    std::time::{Duration, Instant},
+};
+
+//how long the subscribers wait for the rtmp stream to be remuxed and published
+pub const REMUX_START_WAIT_MS: u64 = 5000;
+//the app of the rtmp stream remuxed from a rtsp path without an app name
+const RTSP_DEFAULT_APP: &str = "rtsp";
+
+//The rtsp/whip streams which may be remuxed to the rtmp stream, in the order they are
+//looked up. The rtsp path "app/stream" is remuxed to the rtmp stream "app/stream".
+pub fn remux_sources(identifier: &StreamIdentifier) -> Vec<StreamIdentifier> {
+    let StreamIdentifier::Rtmp {
+        app_name,
+        stream_name,
+    } = identifier
+    else {
+        return Vec::new();
+    };
+
+    let mut sources = vec![
+        StreamIdentifier::Rtsp {
+            stream_path: format!("{app_name}/{stream_name}"),
+        },
+        StreamIdentifier::WebRTC {
+            app_name: app_name.clone(),
+            stream_name: stream_name.clone(),
+        },
+    ];
+    if app_name == RTSP_DEFAULT_APP {
+        sources.push(StreamIdentifier::Rtsp {
+            stream_path: stream_name.clone(),
+        });
+    }
+    sources
+}
+
+//The rtmp stream a rtsp/whip stream is remuxed to.
+pub fn remux_target(source: &StreamIdentifier) -> Option<StreamIdentifier> {
+    match source {
+        StreamIdentifier::Rtsp { stream_path } => {
+            let (app_name, stream_name) = stream_path
+                .split_once('/')
+                .unwrap_or((RTSP_DEFAULT_APP, stream_path));
+            Some(StreamIdentifier::Rtmp {
+                app_name: String::from(app_name),
+                stream_name: String::from(stream_name),
+            })
+        }
+        StreamIdentifier::WebRTC {
+            app_name,
+            stream_name,
+        } => Some(StreamIdentifier::Rtmp {
+            app_name: app_name.clone(),
+            stream_name: stream_name.clone(),
+        }),
+        _ => None,
+    }
+}
+
+//The subscribers started by the publishing of the rtmp stream itself(hls, dash, push
+//relay) do not keep an on demand remuxing alive. Neither does a shared stage, its
+//viewers do.
+pub fn keeps_remux_alive(sub_type: &SubscribeType) -> bool {
+    !matches!(
+        sub_type,
+        SubscribeType::RtmpRemux2Hls
+            | SubscribeType::RtmpRemux2Dash
+            | SubscribeType::RtmpRelay
+            | SubscribeType::RtmpRemux2HttpFlvStage
+    )
+}
+
+//A rtmp stream which is remuxed from its rtsp/whip source on demand.
+pub struct RemuxedStream {
+    pub source: StreamIdentifier,
+    //when the remuxing is asked for, it is asked again if the rtmp stream is not
+    //published in time.
+    started_at: Instant,
+    subscriber_count: usize,
+    //the remuxing is stopped if there is no subscriber for the idle timeout
+    idle_since: Option<Instant>,
+}
+
+impl RemuxedStream {
+    pub fn new(source: StreamIdentifier) -> Self {
+        Self {
+            source,
+            started_at: Instant::now(),
+            subscriber_count: 0,
+            idle_since: None,
+        }
+    }
+
+    pub fn is_starting_for(&self, wait: Duration) -> bool {
+        self.started_at.elapsed() < wait
+    }
+
+    pub fn on_subscribe(&mut self) {
+        self.subscriber_count += 1;
+        self.idle_since = None;
+    }
+
+    //returns true if the last subscriber leaves
+    pub fn on_unsubscribe(&mut self) -> bool {
+        self.subscriber_count = self.subscriber_count.saturating_sub(1);
+        self.mark_idle()
+    }
+
+    //returns true if the stream becomes idle
+    pub fn mark_idle(&mut self) -> bool {
+        if self.subscriber_count > 0 || self.idle_since.is_some() {
+            return false;
+        }
+        self.idle_since = Some(Instant::now());
+        true
+    }
+
+    //an idle timer may be from an earlier idle period which has ended
+    pub fn is_idle_for(&self, timeout: Duration) -> bool {
+        self.idle_since
+            .is_some_and(|idle_since| idle_since.elapsed() >= timeout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{remux_sources, remux_target, RemuxedStream};
+    use crate::stream::StreamIdentifier;
+    use std::time::Duration;
+
+    #[test]
+    fn test_remux_identifiers() {
+        let rtmp = StreamIdentifier::Rtmp {
+            app_name: String::from("live"),
+            stream_name: String::from("camera1"),
+        };
+        let sources = remux_sources(&rtmp);
+        assert_eq!(sources.len(), 2);
+        for source in &sources {
+            assert_eq!(remux_target(source), Some(rtmp.clone()));
+        }
+
+        let rtsp = StreamIdentifier::Rtsp {
+            stream_path: String::from("camera2"),
+        };
+        let target = remux_target(&rtsp).unwrap();
+        assert!(remux_sources(&target).contains(&rtsp));
+
+        let mut remuxed = RemuxedStream::new(rtsp);
+        remuxed.on_subscribe();
+        remuxed.on_subscribe();
+        assert!(!remuxed.on_unsubscribe());
+        assert!(remuxed.on_unsubscribe());
+        assert!(remuxed.is_idle_for(Duration::ZERO));
+        remuxed.on_subscribe();
+        assert!(!remuxed.is_idle_for(Duration::ZERO));
+    }
+}
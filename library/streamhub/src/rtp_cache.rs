@@ -0,0 +1,464 @@
+use {
+    crate::define::{PacketData, VideoCodecType},
+    byteorder::{BigEndian, ByteOrder},
+    bytes::{Bytes, BytesMut},
+};
+
+const RTP_HEADER_LEN: usize = 12;
+//a key frame group larger than this is not cached
+const MAX_CACHED_PACKETS: usize = 8192;
+
+const H264_NAL_SLICE: u8 = 1;
+const H264_NAL_IDR: u8 = 5;
+const H264_NAL_SPS: u8 = 7;
+const H264_NAL_STAP_A: u8 = 24;
+const H264_NAL_FU_A: u8 = 28;
+
+//BLA, IDR and CRA
+const H265_NAL_BLA_W_LP: u8 = 16;
+const H265_NAL_CRA: u8 = 21;
+const H265_NAL_VPS: u8 = 32;
+const H265_NAL_PPS: u8 = 34;
+const H265_NAL_AP: u8 = 48;
+const H265_NAL_FU: u8 = 49;
+
+fn payload_offset(packet: &[u8]) -> Option<usize> {
+    if packet.len() < RTP_HEADER_LEN {
+        return None;
+    }
+    let csrc_count = (packet[0] & 0x0F) as usize;
+    let mut offset = RTP_HEADER_LEN + csrc_count * 4;
+    //the header extension
+    if packet[0] & 0x10 != 0 {
+        if packet.len() < offset + 4 {
+            return None;
+        }
+        let extension_len = BigEndian::read_u16(&packet[offset + 2..]) as usize;
+        offset += 4 + extension_len * 4;
+    }
+    (offset < packet.len()).then_some(offset)
+}
+
+//A key frame group starts from a packet carrying the parameter sets or the first part
+//of a key frame. The codec of an unknown stream is taken as h264.
+fn is_key_packet(codec: Option<VideoCodecType>, payload: &[u8]) -> bool {
+    match codec {
+        Some(VideoCodecType::H265) => is_h265_key_packet(payload),
+        _ => is_h264_key_packet(payload),
+    }
+}
+
+fn is_h264_key_packet(payload: &[u8]) -> bool {
+    //the forbidden bit of a h264 NAL unit header
+    if payload[0] & 0x80 != 0 {
+        return false;
+    }
+    match payload[0] & 0x1F {
+        H264_NAL_IDR | H264_NAL_SPS => true,
+        H264_NAL_STAP_A => payload
+            .get(3)
+            .is_some_and(|nal| matches!(nal & 0x1F, H264_NAL_IDR | H264_NAL_SPS)),
+        H264_NAL_FU_A => payload
+            .get(1)
+            .is_some_and(|fu_header| fu_header & 0x80 != 0 && fu_header & 0x1F == H264_NAL_IDR),
+        _ => false,
+    }
+}
+
+fn h265_nal_type(header: u8) -> u8 {
+    (header >> 1) & 0x3F
+}
+
+fn is_h265_key_nal(nal_type: u8) -> bool {
+    matches!(
+        nal_type,
+        H265_NAL_BLA_W_LP..=H265_NAL_CRA | H265_NAL_VPS..=H265_NAL_PPS
+    )
+}
+
+fn is_h265_key_packet(payload: &[u8]) -> bool {
+    //the forbidden bit of a h265 NAL unit header
+    if payload[0] & 0x80 != 0 {
+        return false;
+    }
+    match h265_nal_type(payload[0]) {
+        //the first NAL unit of an aggregation packet follows the payload header and its size
+        H265_NAL_AP => payload
+            .get(4)
+            .is_some_and(|nal| is_h265_key_nal(h265_nal_type(*nal))),
+        H265_NAL_FU => payload.get(2).is_some_and(|fu_header| {
+            fu_header & 0x80 != 0 && matches!(fu_header & 0x3F, H265_NAL_BLA_W_LP..=H265_NAL_CRA)
+        }),
+        nal_type => is_h265_key_nal(nal_type),
+    }
+}
+
+//A packet which depends on the packets before it: a non key frame slice or a fragment
+//which does not start a NAL unit.
+fn is_h264_inter_packet(payload: &[u8]) -> bool {
+    if payload[0] & 0x80 != 0 {
+        return false;
+    }
+    match payload[0] & 0x1F {
+        H264_NAL_SLICE => true,
+        H264_NAL_STAP_A => payload
+            .get(3)
+            .is_some_and(|nal| nal & 0x1F == H264_NAL_SLICE),
+        H264_NAL_FU_A => payload
+            .get(1)
+            .is_some_and(|fu_header| fu_header & 0x80 == 0 || fu_header & 0x1F == H264_NAL_SLICE),
+        _ => false,
+    }
+}
+
+//The h264 video packets are classified by their NAL units, the packets which cannot be
+//parsed are neither key nor inter frame packets.
+pub fn is_key_frame_packet(packet: &[u8]) -> bool {
+    payload_offset(packet).is_some_and(|offset| is_h264_key_packet(&packet[offset..]))
+}
+
+pub fn is_inter_frame_packet(packet: &[u8]) -> bool {
+    payload_offset(packet).is_some_and(|offset| is_h264_inter_packet(&packet[offset..]))
+}
+
+fn rtp_sequence_number(packet: &[u8]) -> u16 {
+    BigEndian::read_u16(&packet[2..4])
+}
+
+//the last packet of a frame has the marker bit set
+fn rtp_marker(packet: &[u8]) -> bool {
+    packet[1] & 0x80 != 0
+}
+
+fn rtp_timestamp(packet: &[u8]) -> u32 {
+    BigEndian::read_u32(&packet[4..8])
+}
+
+//Caches the h264/h265 rtp packets of the last key frame group for the subscribers on the
+//packet path(WHEP, RTSP etc.), so that they start rendering without waiting for the next
+//key frame. The audio packets are not cached.
+#[derive(Default)]
+pub struct RtpGopCache {
+    packets: Vec<Bytes>,
+    //the rtp timestamp of the key frame which starts the cached group
+    key_timestamp: Option<u32>,
+    //the publisher is switched, the video packets of the new publisher are held back
+    //until its first key frame, which carries the parameter sets in band.
+    wait_key_frame: bool,
+    //the video codec told by the publisher's media info
+    codec: Option<VideoCodecType>,
+    //a key frame has been found in the stream, the video of the other codecs
+    //cannot be parsed and is never held back.
+    key_frame_found: bool,
+    //the video packets held back since the publisher is switched
+    held_packets: usize,
+    //the sequence number of the last video packet sent to the subscribers
+    last_sequence_number: Option<u16>,
+    //a packet of the frame with this timestamp is lost, its other packets are not cached
+    incomplete_timestamp: Option<u32>,
+}
+
+impl RtpGopCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    //The NAL units of h264 and h265 are parsed differently, the cache is restarted
+    //when the codec changes.
+    pub fn set_video_codec(&mut self, codec: VideoCodecType) {
+        if self.codec != Some(codec) {
+            self.codec = Some(codec);
+            self.clear();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.packets.clear();
+        self.key_timestamp = None;
+        self.incomplete_timestamp = None;
+    }
+
+    //the publisher is switched, its packets cannot be continued
+    pub fn switch_publisher(&mut self) {
+        self.clear();
+        self.wait_key_frame = self.key_frame_found;
+        self.held_packets = 0;
+        self.last_sequence_number = None;
+    }
+
+    //A packet is lost, the frames it belongs to are dropped from the cache. The rest of
+    //the group is still cached, the player conceals the missing references until the
+    //next key frame.
+    fn drop_incomplete_frames(&mut self, timestamp: u32) {
+        while self
+            .packets
+            .last()
+            .is_some_and(|packet| !rtp_marker(packet))
+        {
+            self.packets.pop();
+        }
+        if self.packets.is_empty() {
+            //the key frame itself is incomplete
+            self.clear();
+            return;
+        }
+        self.incomplete_timestamp = Some(timestamp);
+    }
+
+    //Returns false if the packet cannot be sent to the subscribers.
+    pub fn save(&mut self, packet: &PacketData) -> bool {
+        let PacketData::Video { timestamp: _, data } = packet else {
+            return true;
+        };
+        let Some(offset) = payload_offset(data) else {
+            return !self.wait_key_frame;
+        };
+
+        let timestamp = rtp_timestamp(data);
+        let sequence_number = rtp_sequence_number(data);
+        let lost = self
+            .last_sequence_number
+            .is_some_and(|last| sequence_number != last.wrapping_add(1));
+
+        if is_key_packet(self.codec, &data[offset..]) && self.key_timestamp != Some(timestamp) {
+            self.clear();
+            self.key_timestamp = Some(timestamp);
+            self.wait_key_frame = false;
+            self.key_frame_found = true;
+        }
+        if self.wait_key_frame {
+            self.held_packets += 1;
+            if self.held_packets < MAX_CACHED_PACKETS {
+                return false;
+            }
+            //the new publisher sends a codec which cannot be parsed
+            log::warn!("no key frame is found in the video of the new publisher");
+            self.wait_key_frame = false;
+            self.key_frame_found = false;
+        }
+        self.last_sequence_number = Some(sequence_number);
+        if self.key_timestamp.is_none() {
+            return true;
+        }
+        if lost && self.packets.last().is_some() {
+            self.drop_incomplete_frames(timestamp);
+        }
+        if self.key_timestamp.is_none() || self.incomplete_timestamp == Some(timestamp) {
+            return true;
+        }
+
+        if self.packets.len() == MAX_CACHED_PACKETS {
+            log::warn!("the key frame group is too large to be cached");
+            self.clear();
+            return true;
+        }
+        self.packets.push(data.clone());
+        true
+    }
+
+    //The cached packets for a new subscriber. They are renumbered contiguously up to the
+    //sequence number of the last live packet, so the live packets which follow continue
+    //the numbering without a gap, and the frames are squeezed right before the last
+    //timestamp, so the player decodes them at once and continues with the live packets.
+    pub fn get_packets(&self) -> Vec<PacketData> {
+        let (Some(last), Some(last_sequence_number)) =
+            (self.packets.last(), self.last_sequence_number)
+        else {
+            return Vec::new();
+        };
+        let packet_count = self.packets.len() as u16;
+        let last_timestamp = rtp_timestamp(last);
+
+        let mut frame_count: u32 = 0;
+        let mut previous_timestamp = None;
+        for packet in &self.packets {
+            let timestamp = rtp_timestamp(packet);
+            if previous_timestamp != Some(timestamp) {
+                frame_count += 1;
+                previous_timestamp = Some(timestamp);
+            }
+        }
+
+        let mut frame_index: u32 = 0;
+        let mut previous_timestamp = None;
+        self.packets
+            .iter()
+            .enumerate()
+            .map(|(packet_index, packet)| {
+                let timestamp = rtp_timestamp(packet);
+                if previous_timestamp.is_some_and(|previous| previous != timestamp) {
+                    frame_index += 1;
+                }
+                previous_timestamp = Some(timestamp);
+
+                let timestamp = last_timestamp.wrapping_sub(frame_count - 1 - frame_index);
+                let sequence_number =
+                    last_sequence_number.wrapping_sub(packet_count - 1 - packet_index as u16);
+
+                let mut data = BytesMut::from(&packet[..]);
+                BigEndian::write_u16(&mut data[2..4], sequence_number);
+                BigEndian::write_u32(&mut data[4..8], timestamp);
+                PacketData::Video {
+                    timestamp,
+                    data: data.freeze(),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RtpGopCache;
+    use crate::define::{PacketData, VideoCodecType};
+    use bytes::Bytes;
+
+    fn video_packet(sequence_number: u16, timestamp: u32, payload: &[u8]) -> PacketData {
+        let mut data = vec![0x80, 96];
+        data.extend_from_slice(&sequence_number.to_be_bytes());
+        data.extend_from_slice(&timestamp.to_be_bytes());
+        data.extend_from_slice(&[0, 0, 0, 1]);
+        data.extend_from_slice(payload);
+        PacketData::Video {
+            timestamp,
+            data: Bytes::from(data),
+        }
+    }
+
+    fn video_packet_end(sequence_number: u16, timestamp: u32, payload: &[u8]) -> PacketData {
+        let PacketData::Video { timestamp, data } =
+            video_packet(sequence_number, timestamp, payload)
+        else {
+            unreachable!();
+        };
+        let mut data = data.to_vec();
+        data[1] |= 0x80;
+        PacketData::Video {
+            timestamp,
+            data: Bytes::from(data),
+        }
+    }
+
+    fn sequence_and_timestamp(packet: &PacketData) -> (u16, u32) {
+        let PacketData::Video { timestamp, data } = packet else {
+            panic!("not a video packet");
+        };
+        (u16::from_be_bytes([data[2], data[3]]), *timestamp)
+    }
+
+    #[test]
+    fn test_rtp_gop_cache() {
+        let mut cache = RtpGopCache::new();
+        //an inter frame before any key frame
+        cache.save(&video_packet_end(9, 0, &[0x41, 0x9a]));
+        //the sps/pps and an idr split into two fragments
+        cache.save(&video_packet(10, 3000, &[0x78, 0x00, 0x02, 0x67, 0x42]));
+        cache.save(&video_packet(11, 3000, &[0x7c, 0x85, 0x88]));
+        cache.save(&video_packet_end(12, 3000, &[0x7c, 0x45, 0x88]));
+        //the packet 13 is lost, the frame 6000 may miss its first packet
+        cache.save(&video_packet_end(14, 6000, &[0x41, 0x9a]));
+        cache.save(&video_packet_end(15, 9000, &[0x41, 0x9a]));
+
+        let packets: Vec<(u16, u32)> = cache
+            .get_packets()
+            .iter()
+            .map(sequence_and_timestamp)
+            .collect();
+        //the incomplete frame is dropped and the rest is renumbered up to the last packet
+        assert_eq!(
+            packets,
+            vec![(12, 8999), (13, 8999), (14, 8999), (15, 9000)]
+        );
+
+        //the packet 17 which ends the frame 12000 is lost
+        cache.save(&video_packet(16, 12000, &[0x5c, 0x81, 0x9a]));
+        cache.save(&video_packet_end(18, 15000, &[0x41, 0x9a]));
+        let packets: Vec<(u16, u32)> = cache
+            .get_packets()
+            .iter()
+            .map(sequence_and_timestamp)
+            .collect();
+        //the live packet 19 follows without a gap
+        assert_eq!(
+            packets,
+            vec![(15, 8999), (16, 8999), (17, 8999), (18, 9000)]
+        );
+
+        //the next key frame starts a new group
+        cache.save(&video_packet_end(19, 18000, &[0x65, 0x88]));
+        assert_eq!(cache.get_packets().len(), 1);
+
+        //the key frame itself is incomplete, nothing is cached until the next one
+        cache.save(&video_packet(20, 21000, &[0x7c, 0x85, 0x88]));
+        cache.save(&video_packet_end(22, 21000, &[0x7c, 0x45, 0x88]));
+        cache.save(&video_packet_end(23, 24000, &[0x41, 0x9a]));
+        assert!(cache.get_packets().is_empty());
+    }
+
+    #[test]
+    fn test_h265_rtp_gop_cache() {
+        let mut cache = RtpGopCache::new();
+        cache.set_video_codec(VideoCodecType::H265);
+        //a trailing picture before any key frame
+        cache.save(&video_packet(9, 0, &[0x02, 0x01, 0xd0]));
+        //the vps/sps/pps in an aggregation packet and an idr split into two fragments
+        cache.save(&video_packet(
+            10,
+            3000,
+            &[0x60, 0x01, 0x00, 0x02, 0x40, 0x01],
+        ));
+        cache.save(&video_packet(11, 3000, &[0x62, 0x01, 0x93, 0xaf]));
+        cache.save(&video_packet(12, 3000, &[0x62, 0x01, 0x53, 0xaf]));
+        cache.save(&video_packet(13, 6000, &[0x02, 0x01, 0xd0]));
+        assert_eq!(cache.get_packets().len(), 4);
+
+        //a cra starts a new group
+        cache.save(&video_packet(14, 9000, &[0x2a, 0x01, 0xaf]));
+        assert_eq!(cache.get_packets().len(), 1);
+
+        //the h264 key frames are not taken from a h265 stream
+        cache.save(&video_packet(15, 12000, &[0x65, 0x88]));
+        assert_eq!(cache.get_packets().len(), 2);
+    }
+
+    #[test]
+    fn test_switch_publisher() {
+        let mut cache = RtpGopCache::new();
+        assert!(cache.save(&video_packet(1, 0, &[0x65, 0x88])));
+        assert!(cache.save(&video_packet(2, 3000, &[0x41, 0x9a])));
+
+        cache.switch_publisher();
+        assert!(cache.get_packets().is_empty());
+        //the new publisher is sent from its first key frame, the audio is not held back
+        assert!(!cache.save(&video_packet(100, 3000, &[0x41, 0x9a])));
+        assert!(cache.save(&PacketData::Audio {
+            timestamp: 3000,
+            data: Bytes::from_static(&[0x80, 111, 0, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0]),
+        }));
+        assert!(cache.save(&video_packet(101, 6000, &[0x78, 0x00, 0x02, 0x67, 0x42])));
+        assert!(cache.save(&video_packet(102, 9000, &[0x41, 0x9a])));
+    }
+
+    #[test]
+    fn test_switch_publisher_without_h264() {
+        let mut cache = RtpGopCache::new();
+        //the vp8 payload descriptors are not h264 NAL units
+        assert!(cache.save(&video_packet(1, 0, &[0x90, 0x80, 0x00])));
+        assert!(cache.save(&video_packet(2, 3000, &[0x10, 0x01])));
+        assert!(cache.get_packets().is_empty());
+
+        cache.switch_publisher();
+        assert!(cache.save(&video_packet(100, 6000, &[0x90, 0x80, 0x01])));
+
+        //a h264 stream is switched to a publisher which sends vp8
+        let mut cache = RtpGopCache::new();
+        assert!(cache.save(&video_packet(1, 0, &[0x65, 0x88])));
+        cache.switch_publisher();
+        let mut held = 0;
+        while !cache.save(&video_packet(held as u16, 3000, &[0x90, 0x80, 0x01])) {
+            held += 1;
+        }
+        assert_eq!(held, super::MAX_CACHED_PACKETS - 1);
+        assert!(cache.save(&video_packet(0, 6000, &[0x90, 0x80, 0x01])));
+    }
+}
@@ -0,0 +1,223 @@
+use {
+    crate::{
+        continuity::is_sequence_header,
+        define::{
+            DataReceiver, DataSender, FrameData, FrameDataSender, InformationSender, SubscribeType,
+            TStreamHandler,
+        },
+        errors::{StreamHubError, StreamHubErrorValue},
+        statistics::StatisticsStream,
+        subscriber::TQueueData,
+    },
+    async_trait::async_trait,
+    bytes::BytesMut,
+    std::{fmt, sync::Arc},
+    tokio::{
+        sync::mpsc,
+        time::{sleep_until, Duration, Instant},
+    },
+    xflv::{define::FlvData, demuxer::FlvDemuxer},
+};
+
+//A pre-recorded FLV clip which is looped to the subscribers of a stream while its
+//publisher is gone. The timestamps keep increasing across the loops and the
+//sequence headers are sent by its stream handler when the stream switches to it.
+pub struct FlvSlate {
+    path: String,
+    sequence_headers: Vec<FrameData>,
+    //starts with a video key frame, the timestamps start from 0.
+    frames: Vec<FrameData>,
+    //the duration of one loop in milliseconds
+    duration: u32,
+}
+
+impl fmt::Debug for FlvSlate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "FlvSlate({})", self.path)
+    }
+}
+
+impl FlvSlate {
+    pub fn load(path: &str) -> Result<Self, StreamHubError> {
+        let data = std::fs::read(path)?;
+        Self::parse(path.to_string(), BytesMut::from(&data[..]))
+    }
+
+    fn parse(path: String, data: BytesMut) -> Result<Self, StreamHubError> {
+        let mut demuxer = FlvDemuxer::new(data);
+        demuxer.read_flv_header()?;
+
+        let mut sequence_headers = Vec::new();
+        let mut frames: Vec<FrameData> = Vec::new();
+        //a truncated last tag ends the clip
+        while let Ok(flv_data) = demuxer.read_flv_tag() {
+            let frame = match flv_data {
+                Some(FlvData::Video { timestamp, data }) => FrameData::Video {
+                    timestamp,
+                    data: data.freeze(),
+                },
+                Some(FlvData::Audio { timestamp, data }) => FrameData::Audio {
+                    timestamp,
+                    data: data.freeze(),
+                },
+                _ => continue,
+            };
+
+            if is_sequence_header(&frame) {
+                sequence_headers.push(frame);
+            } else if !frames.is_empty() || frame.is_key_frame() {
+                frames.push(frame);
+            }
+        }
+
+        let (Some(first), Some(last)) = (frames.first(), frames.last()) else {
+            return Err(StreamHubError {
+                value: StreamHubErrorValue::NoKeyFrameInSlate,
+            });
+        };
+        let first_timestamp = timestamp_of(first);
+        let last_timestamp = timestamp_of(last);
+
+        for frame in &mut frames {
+            match frame {
+                FrameData::Video { timestamp, data: _ }
+                | FrameData::Audio { timestamp, data: _ } => {
+                    *timestamp = timestamp.wrapping_sub(first_timestamp)
+                }
+                _ => {}
+            }
+        }
+        //the next loop starts one frame interval after the last frame.
+        let frame_count = frames.len() as u32;
+        let span = last_timestamp.wrapping_sub(first_timestamp);
+        let duration = if frame_count > 1 {
+            span + span / (frame_count - 1)
+        } else {
+            40
+        };
+
+        Ok(Self {
+            path,
+            sequence_headers,
+            frames,
+            duration: duration.max(1),
+        })
+    }
+
+    //Starts looping the clip in real time, the returned receiver and stream handler
+    //are published into the stream in place of its publisher. The loop stops when
+    //the receiver is dropped.
+    pub fn play(self: &Arc<Self>) -> (DataReceiver, Arc<dyn TStreamHandler>) {
+        let (frame_sender, frame_receiver) = mpsc::unbounded_channel();
+        tokio::spawn(Self::run(self.clone(), frame_sender));
+
+        let receiver = DataReceiver {
+            frame_receiver: Some(frame_receiver),
+            packet_receiver: None,
+        };
+        let stream_handler: Arc<dyn TStreamHandler> = Arc::new(SlateStreamHandler {
+            slate: self.clone(),
+        });
+        (receiver, stream_handler)
+    }
+
+    async fn run(slate: Arc<Self>, frame_sender: FrameDataSender) {
+        let start = Instant::now();
+        let mut loop_timestamp: u32 = 0;
+
+        loop {
+            for frame in &slate.frames {
+                let mut frame = frame.clone();
+                if let FrameData::Video { timestamp, data: _ }
+                | FrameData::Audio { timestamp, data: _ } = &mut frame
+                {
+                    *timestamp = timestamp.wrapping_add(loop_timestamp);
+                    sleep_until(start + Duration::from_millis(*timestamp as u64)).await;
+                }
+                if frame_sender.send(frame).is_err() {
+                    log::info!("fallback slate {} stopped", slate.path);
+                    return;
+                }
+            }
+            loop_timestamp = loop_timestamp.wrapping_add(slate.duration);
+        }
+    }
+}
+
+fn timestamp_of(frame: &FrameData) -> u32 {
+    frame.timestamp().unwrap_or(0)
+}
+
+struct SlateStreamHandler {
+    slate: Arc<FlvSlate>,
+}
+
+#[async_trait]
+impl TStreamHandler for SlateStreamHandler {
+    async fn send_prior_data(
+        &self,
+        data_sender: DataSender,
+        _sub_type: SubscribeType,
+    ) -> Result<(), StreamHubError> {
+        let sender = match data_sender {
+            DataSender::Frame { sender } => sender,
+            DataSender::Packet { sender: _ } => {
+                return Err(StreamHubError {
+                    value: StreamHubErrorValue::NotCorrectDataSenderType,
+                });
+            }
+        };
+
+        for frame in &self.slate.sequence_headers {
+            if let Err(err) = sender.try_send(frame.clone()) {
+                log::error!("send slate sequence header err: {}", err);
+                return Err(StreamHubError {
+                    value: StreamHubErrorValue::SendError,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    async fn get_statistic_data(&self) -> Option<StatisticsStream> {
+        None
+    }
+
+    async fn send_information(&self, _sender: InformationSender) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{timestamp_of, FlvSlate};
+    use bytes::{BufMut, BytesMut};
+
+    fn write_tag(flv: &mut BytesMut, tag_type: u8, timestamp: u32, body: &[u8]) {
+        flv.put_u8(tag_type);
+        flv.put_uint(body.len() as u64, 3);
+        flv.put_uint((timestamp & 0xffffff) as u64, 3);
+        flv.put_u8((timestamp >> 24) as u8);
+        flv.put_uint(0, 3);
+        flv.put_slice(body);
+        flv.put_u32(11 + body.len() as u32);
+    }
+
+    #[test]
+    fn test_parse_slate() {
+        let mut flv = BytesMut::new();
+        flv.put_slice(b"FLV\x01\x05\x00\x00\x00\x09");
+        flv.put_u32(0);
+        write_tag(&mut flv, 9, 500, &[0x17, 0x00]);
+        write_tag(&mut flv, 8, 500, &[0xaf, 0x00]);
+        //the inter frames before the first key frame are skipped
+        write_tag(&mut flv, 9, 500, &[0x27, 0x01]);
+        write_tag(&mut flv, 9, 540, &[0x17, 0x01]);
+        write_tag(&mut flv, 9, 580, &[0x27, 0x01]);
+        write_tag(&mut flv, 9, 620, &[0x27, 0x01]);
+
+        let slate = FlvSlate::parse(String::from("test.flv"), flv).unwrap();
+        assert_eq!(slate.sequence_headers.len(), 2);
+        let timestamps: Vec<u32> = slate.frames.iter().map(timestamp_of).collect();
+        assert_eq!(timestamps, vec![0, 40, 80]);
+        assert_eq!(slate.duration, 120);
+    }
+}
@@ -0,0 +1,111 @@
+use {
+    crate::define::{
+        DataReceiver, PublisherInfo, StatisticData, StatisticDataReceiver, StatisticDataSender,
+        TStreamHandler,
+    },
+    std::sync::Arc,
+    tokio::{sync::oneshot, task::JoinHandle},
+};
+
+//A second publisher of a stream which is held as a hot standby. Its data is
+//discarded until the primary publisher leaves and it is promoted, meanwhile its
+//stream handler keeps caching the sequence headers and the latest gop.
+pub struct StandbyPublisher {
+    pub info: PublisherInfo,
+    stream_handler: Arc<dyn TStreamHandler>,
+    stop_sender: oneshot::Sender<()>,
+    drain_handle: JoinHandle<(DataReceiver, StatisticDataReceiver, Option<StatisticData>)>,
+}
+
+impl StandbyPublisher {
+    //The statistic receiver belongs to the statistic sender given to the standby publisher's
+    //session, its data is discarded too except the publisher information.
+    pub fn new(
+        info: PublisherInfo,
+        receiver: DataReceiver,
+        statistic_receiver: StatisticDataReceiver,
+        stream_handler: Arc<dyn TStreamHandler>,
+    ) -> Self {
+        let (stop_sender, stop_receiver) = oneshot::channel();
+        let drain_handle = tokio::spawn(Self::drain(receiver, statistic_receiver, stop_receiver));
+
+        Self {
+            info,
+            stream_handler,
+            stop_sender,
+            drain_handle,
+        }
+    }
+
+    //the publisher channels are unbounded, so the data must be consumed.
+    async fn drain(
+        mut receiver: DataReceiver,
+        mut statistic_receiver: StatisticDataReceiver,
+        mut stop_receiver: oneshot::Receiver<()>,
+    ) -> (DataReceiver, StatisticDataReceiver, Option<StatisticData>) {
+        let mut publisher_statistic = None;
+        loop {
+            tokio::select! {
+                _ = &mut stop_receiver => {
+                    break;
+                }
+                Some(_) = async {
+                    match &mut receiver.frame_receiver {
+                        Some(frame_receiver) => frame_receiver.recv().await,
+                        None => std::future::pending().await,
+                    }
+                } => {}
+                Some(_) = async {
+                    match &mut receiver.packet_receiver {
+                        Some(packet_receiver) => packet_receiver.recv().await,
+                        None => std::future::pending().await,
+                    }
+                } => {}
+                Some(statistic_data) = statistic_receiver.recv() => {
+                    if let StatisticData::Publisher { .. } = statistic_data {
+                        publisher_statistic = Some(statistic_data);
+                    }
+                }
+                //the publisher has gone, wait for its unpublish.
+                else => {
+                    let _ = stop_receiver.await;
+                    break;
+                }
+            }
+        }
+        (receiver, statistic_receiver, publisher_statistic)
+    }
+
+    //Stops discarding the data and returns what the stream needs to switch to this publisher.
+    //From now on the statistics of the publisher are forwarded to the stream's statistic sender.
+    pub async fn promote(
+        self,
+        statistic_data_sender: StatisticDataSender,
+    ) -> Option<(PublisherInfo, DataReceiver, Arc<dyn TStreamHandler>)> {
+        if self.stop_sender.send(()).is_err() {
+            log::error!("standby publisher: the drain task has exited");
+        }
+
+        match self.drain_handle.await {
+            Ok((receiver, mut statistic_receiver, publisher_statistic)) => {
+                tokio::spawn(async move {
+                    if let Some(statistic_data) = publisher_statistic {
+                        if statistic_data_sender.send(statistic_data).is_err() {
+                            return;
+                        }
+                    }
+                    while let Some(statistic_data) = statistic_receiver.recv().await {
+                        if statistic_data_sender.send(statistic_data).is_err() {
+                            break;
+                        }
+                    }
+                });
+                Some((self.info, receiver, self.stream_handler))
+            }
+            Err(err) => {
+                log::error!("standby publisher: join the drain task err: {}", err);
+                None
+            }
+        }
+    }
+}
@@ -56,6 +56,8 @@ pub struct StatisticsStream {
     pub total_recv_bytes: usize,
     /*calculate downstream traffic, now equals audio and video traffic sent to all subscribers*/
     pub total_send_bytes: usize,
+    /*the bytes of media cached for this stream's new subscribers(the gop cache)*/
+    pub gop_cache_bytes: usize,
 }
 #[derive(Debug, Clone, Serialize, Default)]
 pub struct StatisticPublisher {
@@ -95,6 +97,25 @@ pub struct StatisticSubscriber {
     pub send_bitrate: usize,
     #[serde(rename = "total_send_bytes(kbits/s)")]
     pub total_send_bytes: usize,
+    /*the frames(packets) dropped because the subscriber cannot keep up with the stream*/
+    pub dropped_frames: usize,
+    pub dropped_bytes: usize,
+}
+
+impl StatisticSubscriber {
+    pub fn new(id: Uuid, remote_address: String, sub_type: SubscribeType) -> Self {
+        Self {
+            id,
+            start_time: Local::now(),
+            remote_address,
+            sub_type,
+            send_bytes: 0,
+            send_bitrate: 0,
+            total_send_bytes: 0,
+            dropped_frames: 0,
+            dropped_bytes: 0,
+        }
+    }
 }
 
 impl StatisticsStream {
@@ -18,6 +18,23 @@ pub enum StreamIdentifier {
         stream_name: String,
     },
 }
+impl StreamIdentifier {
+    //the rtsp streams have no app name.
+    pub fn app_name(&self) -> Option<&str> {
+        match self {
+            StreamIdentifier::Rtmp {
+                app_name,
+                stream_name: _,
+            }
+            | StreamIdentifier::WebRTC {
+                app_name,
+                stream_name: _,
+            } => Some(app_name),
+            _ => None,
+        }
+    }
+}
+
 impl fmt::Display for StreamIdentifier {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
@@ -0,0 +1,452 @@
+use {
+    crate::{
+        define::{
+            FrameData, PacketData, StatisticData, StatisticDataSender, SubscribeType,
+            SubscriberQueuePolicy, TrackFilter,
+        },
+        rtp_cache,
+        utils::Uuid,
+    },
+    std::time::{Duration, Instant},
+    tokio::sync::mpsc::{error::TrySendError, Sender},
+    xflv::define::frame_type,
+};
+
+pub trait TQueueData {
+    //Video inter frames depend on the previous frames, once some data of a subscriber
+    //is dropped, they are dropped as well until the next key frame.
+    fn is_inter_frame(&self) -> bool;
+    fn is_key_frame(&self) -> bool;
+    fn data_size(&self) -> usize;
+    fn is_audio(&self) -> bool;
+    fn is_video(&self) -> bool;
+}
+
+impl TQueueData for FrameData {
+    //The first 4 bits of a FLV video tag are the frame type. Frames which are not
+    //FLV encoded (annexb frames from rtsp/whip) are treated as key frames.
+    fn is_inter_frame(&self) -> bool {
+        match self {
+            FrameData::Video { timestamp: _, data } => {
+                !data.is_empty() && (data[0] >> 4) == frame_type::INTER_FRAME
+            }
+            FrameData::Media { frame } => frame.codec.is_video() && !frame.key_frame,
+            _ => false,
+        }
+    }
+
+    fn is_key_frame(&self) -> bool {
+        match self {
+            FrameData::Video {
+                timestamp: _,
+                data: _,
+            } => !self.is_inter_frame(),
+            FrameData::Media { frame } => frame.codec.is_video() && frame.key_frame,
+            _ => false,
+        }
+    }
+
+    fn data_size(&self) -> usize {
+        match self {
+            FrameData::Video { timestamp: _, data }
+            | FrameData::Audio { timestamp: _, data }
+            | FrameData::MetaData { timestamp: _, data } => data.len(),
+            FrameData::Media { frame } => frame.data.len(),
+            FrameData::CodecConfig { config } => config.data.len(),
+            FrameData::MediaInfo { media_info: _ } | FrameData::Discontinuity { timestamp: _ } => 0,
+        }
+    }
+
+    fn is_audio(&self) -> bool {
+        match self {
+            FrameData::Audio { .. } => true,
+            FrameData::Media { frame } => !frame.codec.is_video(),
+            FrameData::CodecConfig { config } => !config.codec.is_video(),
+            _ => false,
+        }
+    }
+
+    fn is_video(&self) -> bool {
+        match self {
+            FrameData::Video { .. } => true,
+            FrameData::Media { frame } => frame.codec.is_video(),
+            FrameData::CodecConfig { config } => config.codec.is_video(),
+            _ => false,
+        }
+    }
+}
+
+impl TQueueData for PacketData {
+    //The h264 RTP packets are classified by their NAL units, the packets of the other
+    //codecs cannot be parsed and are never dropped selectively.
+    fn is_inter_frame(&self) -> bool {
+        match self {
+            PacketData::Video { timestamp: _, data } => rtp_cache::is_inter_frame_packet(data),
+            PacketData::Audio { .. } => false,
+        }
+    }
+
+    fn is_key_frame(&self) -> bool {
+        match self {
+            PacketData::Video { timestamp: _, data } => rtp_cache::is_key_frame_packet(data),
+            PacketData::Audio { .. } => false,
+        }
+    }
+
+    fn data_size(&self) -> usize {
+        match self {
+            PacketData::Video { timestamp: _, data } | PacketData::Audio { timestamp: _, data } => {
+                data.len()
+            }
+        }
+    }
+
+    fn is_audio(&self) -> bool {
+        matches!(self, PacketData::Audio { .. })
+    }
+
+    fn is_video(&self) -> bool {
+        matches!(self, PacketData::Video { .. })
+    }
+}
+
+pub enum SendResult {
+    Sent,
+    Dropped,
+    //the data belongs to a track the subscriber does not receive
+    Skipped,
+    //the subscriber lags behind too long or has been closed, remove it.
+    Disconnect,
+}
+
+//The stream hub side of a subscriber's bounded queue.
+pub struct SubscriberSender<T: TQueueData> {
+    id: Uuid,
+    pub sub_type: SubscribeType,
+    pub sender: Sender<T>,
+    max_lag: Duration,
+    //some data has been dropped, the inter frames are dropped until the next key frame.
+    wait_key_frame: bool,
+    //the time since when the queue is full and nothing can be sent to the subscriber.
+    lag_start: Option<Instant>,
+    track_filter: TrackFilter,
+}
+
+impl<T: TQueueData> SubscriberSender<T> {
+    pub fn new(
+        id: Uuid,
+        sub_type: SubscribeType,
+        sender: Sender<T>,
+        policy: &SubscriberQueuePolicy,
+    ) -> Self {
+        //the prior data(gop cache) may have already filled the queue.
+        let is_full = sender.capacity() == 0;
+
+        Self {
+            id,
+            sub_type,
+            sender,
+            max_lag: Duration::from_millis(policy.max_lag_ms),
+            wait_key_frame: is_full,
+            lag_start: if is_full { Some(Instant::now()) } else { None },
+            track_filter: TrackFilter::All,
+        }
+    }
+
+    pub fn with_track_filter(mut self, track_filter: TrackFilter) -> Self {
+        self.track_filter = track_filter;
+        self
+    }
+
+    //The prior data(gop cache) sent to the subscriber is truncated, the live inter frames
+    //are dropped until the next key frame so that they are not decoded without references.
+    pub fn with_wait_key_frame(mut self, truncated: bool) -> Self {
+        if truncated {
+            self.wait_key_frame = true;
+        }
+        self
+    }
+
+    pub fn send(&mut self, data: T, statistic_sender: &StatisticDataSender) -> SendResult {
+        if !self.track_filter.accepts(&data) {
+            return SendResult::Skipped;
+        }
+        if self.wait_key_frame && data.is_inter_frame() {
+            self.statistic_drop(&data, statistic_sender);
+            return SendResult::Dropped;
+        }
+
+        let is_key_frame = data.is_key_frame();
+        match self.sender.try_send(data) {
+            Ok(()) => {
+                self.lag_start = None;
+                if is_key_frame && self.wait_key_frame {
+                    self.wait_key_frame = false;
+                    log::info!("subscriber: {} catches up with the stream", self.id);
+                }
+                SendResult::Sent
+            }
+            Err(TrySendError::Full(data)) => {
+                self.statistic_drop(&data, statistic_sender);
+                if !self.wait_key_frame {
+                    log::warn!(
+                        "subscriber: {} queue is full, drop frames until the next key frame",
+                        self.id
+                    );
+                    self.wait_key_frame = true;
+                }
+
+                let lag_start = self.lag_start.get_or_insert_with(Instant::now);
+                if !self.max_lag.is_zero() && lag_start.elapsed() > self.max_lag {
+                    log::warn!(
+                        "subscriber: {} lags behind more than {} ms, disconnect it",
+                        self.id,
+                        self.max_lag.as_millis()
+                    );
+                    return SendResult::Disconnect;
+                }
+                SendResult::Dropped
+            }
+            Err(TrySendError::Closed(_)) => SendResult::Disconnect,
+        }
+    }
+
+    fn statistic_drop(&self, data: &T, statistic_sender: &StatisticDataSender) {
+        let statistic_drop_data = StatisticData::SubscriberDrop {
+            id: self.id,
+            frame_count: 1,
+            data_size: data.data_size(),
+        };
+        if let Err(err) = statistic_sender.send(statistic_drop_data) {
+            log::error!("send statistic_data err: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SendResult, SubscriberSender};
+    use crate::define::{FrameData, PacketData, SubscribeType, SubscriberQueuePolicy, TrackFilter};
+    use crate::utils::{RandomDigitCount, Uuid};
+    use bytes::Bytes;
+    use tokio::sync::mpsc;
+
+    fn video_frame(first_byte: u8) -> FrameData {
+        FrameData::Video {
+            timestamp: 0,
+            data: Bytes::copy_from_slice(&[first_byte, 0x01]),
+        }
+    }
+
+    #[test]
+    fn test_drop_until_key_frame() {
+        let (sender, mut receiver) = mpsc::channel(2);
+        let (statistic_sender, mut statistic_receiver) = mpsc::unbounded_channel();
+        let policy = SubscriberQueuePolicy {
+            queue_size: 2,
+            max_lag_ms: 0,
+        };
+        let mut subscriber = SubscriberSender::new(
+            Uuid::new(RandomDigitCount::Four),
+            SubscribeType::RtmpPull,
+            sender,
+            &policy,
+        );
+
+        assert!(matches!(
+            subscriber.send(video_frame(0x17), &statistic_sender),
+            SendResult::Sent
+        ));
+        assert!(matches!(
+            subscriber.send(video_frame(0x27), &statistic_sender),
+            SendResult::Sent
+        ));
+        //the queue is full
+        assert!(matches!(
+            subscriber.send(video_frame(0x27), &statistic_sender),
+            SendResult::Dropped
+        ));
+
+        receiver.try_recv().unwrap();
+        receiver.try_recv().unwrap();
+        //there is room now, but the inter frames are still dropped until the next key frame
+        assert!(matches!(
+            subscriber.send(video_frame(0x27), &statistic_sender),
+            SendResult::Dropped
+        ));
+        assert!(matches!(
+            subscriber.send(video_frame(0x17), &statistic_sender),
+            SendResult::Sent
+        ));
+        assert!(matches!(
+            subscriber.send(video_frame(0x27), &statistic_sender),
+            SendResult::Sent
+        ));
+
+        let mut drop_count = 0;
+        while statistic_receiver.try_recv().is_ok() {
+            drop_count += 1;
+        }
+        assert_eq!(drop_count, 2);
+    }
+
+    fn video_packet(sequence_number: u16, payload: &[u8]) -> PacketData {
+        let mut data = vec![0x80, 96];
+        data.extend_from_slice(&sequence_number.to_be_bytes());
+        data.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 1]);
+        data.extend_from_slice(payload);
+        PacketData::Video {
+            timestamp: 0,
+            data: Bytes::from(data),
+        }
+    }
+
+    #[test]
+    fn test_drop_packets_until_key_frame() {
+        let (sender, mut receiver) = mpsc::channel(2);
+        let (statistic_sender, _statistic_receiver) = mpsc::unbounded_channel();
+        let policy = SubscriberQueuePolicy {
+            queue_size: 2,
+            max_lag_ms: 0,
+        };
+        let mut subscriber = SubscriberSender::new(
+            Uuid::new(RandomDigitCount::Four),
+            SubscribeType::WhepPull,
+            sender,
+            &policy,
+        );
+
+        //an idr split into two fragments
+        assert!(matches!(
+            subscriber.send(video_packet(1, &[0x7c, 0x85, 0x88]), &statistic_sender),
+            SendResult::Sent
+        ));
+        assert!(matches!(
+            subscriber.send(video_packet(2, &[0x7c, 0x45, 0x88]), &statistic_sender),
+            SendResult::Sent
+        ));
+        //the queue is full, the first fragment of an inter frame is dropped
+        assert!(matches!(
+            subscriber.send(video_packet(3, &[0x7c, 0x81, 0x9a]), &statistic_sender),
+            SendResult::Dropped
+        ));
+
+        receiver.try_recv().unwrap();
+        receiver.try_recv().unwrap();
+        //the rest of the frame and the next inter frame are dropped
+        assert!(matches!(
+            subscriber.send(video_packet(4, &[0x7c, 0x41, 0x9a]), &statistic_sender),
+            SendResult::Dropped
+        ));
+        assert!(matches!(
+            subscriber.send(video_packet(5, &[0x41, 0x9a]), &statistic_sender),
+            SendResult::Dropped
+        ));
+        //a packet which cannot be parsed is not held back
+        assert!(matches!(
+            subscriber.send(video_packet(6, &[0x90, 0x80]), &statistic_sender),
+            SendResult::Sent
+        ));
+        //the sps of the next key frame
+        assert!(matches!(
+            subscriber.send(video_packet(7, &[0x67, 0x42]), &statistic_sender),
+            SendResult::Sent
+        ));
+        receiver.try_recv().unwrap();
+        assert!(matches!(
+            subscriber.send(video_packet(8, &[0x41, 0x9a]), &statistic_sender),
+            SendResult::Sent
+        ));
+    }
+
+    #[test]
+    fn test_truncated_prior_data() {
+        let (sender, mut receiver) = mpsc::channel(4);
+        let (statistic_sender, _statistic_receiver) = mpsc::unbounded_channel();
+        let mut subscriber = SubscriberSender::new(
+            Uuid::new(RandomDigitCount::Four),
+            SubscribeType::RtmpPull,
+            sender,
+            &SubscriberQueuePolicy::default(),
+        )
+        .with_wait_key_frame(true);
+
+        assert!(matches!(
+            subscriber.send(video_frame(0x27), &statistic_sender),
+            SendResult::Dropped
+        ));
+        assert!(matches!(
+            subscriber.send(video_frame(0x17), &statistic_sender),
+            SendResult::Sent
+        ));
+        assert!(matches!(
+            subscriber.send(video_frame(0x27), &statistic_sender),
+            SendResult::Sent
+        ));
+        assert!(
+            matches!(receiver.try_recv(), Ok(FrameData::Video { data, .. }) if data[0] == 0x17)
+        );
+    }
+
+    #[test]
+    fn test_disconnect_lagging_subscriber() {
+        let (sender, _receiver) = mpsc::channel(1);
+        let (statistic_sender, _statistic_receiver) = mpsc::unbounded_channel();
+        let policy = SubscriberQueuePolicy {
+            queue_size: 1,
+            max_lag_ms: 1,
+        };
+        let mut subscriber = SubscriberSender::new(
+            Uuid::new(RandomDigitCount::Four),
+            SubscribeType::RtmpPull,
+            sender,
+            &policy,
+        );
+
+        assert!(matches!(
+            subscriber.send(video_frame(0x17), &statistic_sender),
+            SendResult::Sent
+        ));
+        assert!(matches!(
+            subscriber.send(video_frame(0x17), &statistic_sender),
+            SendResult::Dropped
+        ));
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        assert!(matches!(
+            subscriber.send(video_frame(0x17), &statistic_sender),
+            SendResult::Disconnect
+        ));
+    }
+
+    #[test]
+    fn test_audio_only_subscriber() {
+        let (sender, mut receiver) = mpsc::channel(4);
+        let (statistic_sender, _statistic_receiver) = mpsc::unbounded_channel();
+        let policy = SubscriberQueuePolicy::default();
+        let track_filter = TrackFilter::from_request_url("/live/test.flv?only_audio=1");
+        assert_eq!(track_filter, TrackFilter::AudioOnly);
+
+        let mut subscriber = SubscriberSender::new(
+            Uuid::new(RandomDigitCount::Four),
+            SubscribeType::RtmpRemux2HttpFlv,
+            sender,
+            &policy,
+        )
+        .with_track_filter(track_filter);
+
+        assert!(matches!(
+            subscriber.send(video_frame(0x17), &statistic_sender),
+            SendResult::Skipped
+        ));
+        let audio_frame = FrameData::Audio {
+            timestamp: 0,
+            data: Bytes::from_static(&[0xaf, 0x01]),
+        };
+        assert!(matches!(
+            subscriber.send(audio_frame, &statistic_sender),
+            SendResult::Sent
+        ));
+        assert!(matches!(receiver.try_recv(), Ok(FrameData::Audio { .. })));
+        assert!(receiver.try_recv().is_err());
+    }
+}
@@ -0,0 +1,19 @@
+//the target duration(s) of the segments
+pub const DASH_DURATION: i64 = 5;
+//the segments which are kept in the MPD(the time-shift buffer)
+pub const DASH_SEGMENT_COUNT: usize = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrackType {
+    Video,
+    Audio,
+}
+
+impl TrackType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Video => "video",
+            Self::Audio => "audio",
+        }
+    }
+}
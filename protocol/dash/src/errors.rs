@@ -0,0 +1,122 @@
+use {
+    failure::{Backtrace, Fail},
+    std::fmt,
+    streamhub::errors::StreamHubError,
+    tokio::sync::broadcast::error::RecvError,
+    tokio::sync::oneshot::error::RecvError as OneshotRecvError,
+    xflv::errors::FlvDemuxerError,
+    xmp4::errors::Mp4Error,
+};
+
+#[derive(Debug)]
+pub struct MediaError {
+    pub value: MediaErrorValue,
+}
+
+#[derive(Debug, Fail)]
+pub enum MediaErrorValue {
+    #[fail(display = "flv demuxer error:{}", _0)]
+    FlvDemuxerError(#[cause] FlvDemuxerError),
+    #[fail(display = "mp4 error:{}", _0)]
+    Mp4Error(#[cause] Mp4Error),
+    #[fail(display = "write file error:{}", _0)]
+    IOError(#[cause] std::io::Error),
+}
+
+impl From<FlvDemuxerError> for MediaError {
+    fn from(error: FlvDemuxerError) -> Self {
+        MediaError {
+            value: MediaErrorValue::FlvDemuxerError(error),
+        }
+    }
+}
+
+impl From<Mp4Error> for MediaError {
+    fn from(error: Mp4Error) -> Self {
+        MediaError {
+            value: MediaErrorValue::Mp4Error(error),
+        }
+    }
+}
+
+impl From<std::io::Error> for MediaError {
+    fn from(error: std::io::Error) -> Self {
+        MediaError {
+            value: MediaErrorValue::IOError(error),
+        }
+    }
+}
+
+impl fmt::Display for MediaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.value, f)
+    }
+}
+
+impl Fail for MediaError {
+    fn cause(&self) -> Option<&dyn Fail> {
+        self.value.cause()
+    }
+
+    fn backtrace(&self) -> Option<&Backtrace> {
+        self.value.backtrace()
+    }
+}
+
+pub struct DashError {
+    pub value: DashErrorValue,
+}
+
+#[derive(Debug, Fail)]
+pub enum DashErrorValue {
+    #[fail(display = "channel error:{}", _0)]
+    ChannelError(#[cause] StreamHubError),
+    #[fail(display = "media error:{}", _0)]
+    MediaError(#[cause] MediaError),
+    #[fail(display = "receive error:{}", _0)]
+    RecvError(#[cause] RecvError),
+    #[fail(display = "tokio: oneshot receiver err: {}", _0)]
+    OneshotRecvError(#[cause] OneshotRecvError),
+    #[fail(display = "stream hub event send error")]
+    StreamHubEventSendErr,
+    #[fail(display = "channel recv error")]
+    ChannelRecvError,
+}
+
+impl From<RecvError> for DashError {
+    fn from(error: RecvError) -> Self {
+        DashError {
+            value: DashErrorValue::RecvError(error),
+        }
+    }
+}
+
+impl From<MediaError> for DashError {
+    fn from(error: MediaError) -> Self {
+        DashError {
+            value: DashErrorValue::MediaError(error),
+        }
+    }
+}
+
+impl From<StreamHubError> for DashError {
+    fn from(error: StreamHubError) -> Self {
+        DashError {
+            value: DashErrorValue::ChannelError(error),
+        }
+    }
+}
+
+impl From<OneshotRecvError> for DashError {
+    fn from(error: OneshotRecvError) -> Self {
+        DashError {
+            value: DashErrorValue::OneshotRecvError(error),
+        }
+    }
+}
+
+impl fmt::Display for DashError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.value, f)
+    }
+}
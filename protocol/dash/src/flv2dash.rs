@@ -0,0 +1,255 @@
+use {
+    super::{
+        define::TrackType,
+        errors::MediaError,
+        mpd::{Mpd, Representation},
+    },
+    bytes::BytesMut,
+    streamhub::normalizer::TimestampExtender,
+    xflv::{
+        define::{frame_type, AvcCodecId, FlvData},
+        demuxer::{FlvAudioTagDemuxer, FlvVideoTagDemuxer},
+    },
+    xmp4::{
+        define::{AudioConfig, Mp4Id3Event, Mp4Sample, VideoCodec, VideoConfig},
+        fmp4::Fmp4Muxer,
+    },
+};
+
+//Remuxes the FLV tags of a stream into the fMP4 segments of DASH, the video and the
+//audio are written as separate tracks.
+pub struct Flv2DashRemuxer {
+    video_demuxer: FlvVideoTagDemuxer,
+    audio_demuxer: FlvAudioTagDemuxer,
+    //the segments follow the 64 bits timeline of the 32 bits FLV timestamps
+    timestamp_extender: TimestampExtender,
+
+    video_muxer: Fmp4Muxer,
+    audio_muxer: Fmp4Muxer,
+    video_config: Option<VideoConfig>,
+    audio_config: Option<AudioConfig>,
+    //the codec configs changed, a new period starts with the next segment
+    init_pending: bool,
+    //nothing is muxed into the current segment yet
+    segment_empty: bool,
+    //a discontinuity is flagged by the stream hub, the segment is cut at the next key frame
+    discontinuity_pending: bool,
+
+    //the target duration(s) of the segments
+    duration: i64,
+    segment_start: i64,
+    last_dts: i64,
+
+    mpd_handler: Mpd,
+}
+
+impl Flv2DashRemuxer {
+    pub fn new(
+        duration: i64,
+        segment_count: usize,
+        app_name: String,
+        stream_name: String,
+    ) -> Result<Self, MediaError> {
+        Ok(Self {
+            video_demuxer: FlvVideoTagDemuxer::new_length_prefixed(),
+            audio_demuxer: FlvAudioTagDemuxer::new_raw(),
+            timestamp_extender: TimestampExtender::default(),
+
+            video_muxer: Fmp4Muxer::new(),
+            audio_muxer: Fmp4Muxer::new(),
+            video_config: None,
+            audio_config: None,
+            init_pending: false,
+            segment_empty: true,
+            discontinuity_pending: false,
+
+            duration,
+            segment_start: 0,
+            last_dts: 0,
+
+            mpd_handler: Mpd::new(duration * 1000, segment_count, app_name, stream_name)?,
+        })
+    }
+
+    pub fn process_flv_data(&mut self, data: FlvData) -> Result<(), MediaError> {
+        match data {
+            FlvData::Audio { timestamp, data } => {
+                let timestamp = self.timestamp_extender.extend(timestamp);
+                let audio_data = self.audio_demuxer.demux(timestamp, data)?;
+                if !audio_data.has_data {
+                    self.update_audio_config();
+                    return Ok(());
+                }
+                self.process_sample(
+                    TrackType::Audio,
+                    Mp4Sample {
+                        dts: audio_data.dts,
+                        pts: audio_data.pts,
+                        is_key: true,
+                        data: audio_data.data,
+                    },
+                )
+            }
+            FlvData::Video { timestamp, data } => {
+                let timestamp = self.timestamp_extender.extend(timestamp);
+                match self.video_demuxer.demux(timestamp, data)? {
+                    Some(video_data) => self.process_sample(
+                        TrackType::Video,
+                        Mp4Sample {
+                            dts: video_data.dts,
+                            pts: video_data.pts,
+                            is_key: video_data.frame_type == frame_type::KEY_FRAME,
+                            data: video_data.data,
+                        },
+                    ),
+                    None => {
+                        self.update_video_config();
+                        Ok(())
+                    }
+                }
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn update_video_config(&mut self) {
+        let record = self.video_demuxer.decoder_configuration_record();
+        if record.is_empty() {
+            return;
+        }
+        let (width, height) = self.video_demuxer.resolution();
+        let config = VideoConfig {
+            codec: if self.video_demuxer.codec_id() == AvcCodecId::HEVC as u8 {
+                VideoCodec::H265
+            } else {
+                VideoCodec::H264
+            },
+            width,
+            height,
+            decoder_configuration_record: record.clone(),
+        };
+        if self.video_muxer.set_video_config(config.clone()) {
+            self.video_config = Some(config);
+            self.init_pending = true;
+        }
+    }
+
+    fn update_audio_config(&mut self) {
+        let audio_specific_config = self.audio_demuxer.audio_specific_config();
+        if audio_specific_config.is_empty() {
+            return;
+        }
+        let mpeg4_aac = self.audio_demuxer.mpeg4_aac();
+        let config = AudioConfig {
+            sample_rate: mpeg4_aac.sampling_frequency,
+            channels: mpeg4_aac.channels,
+            audio_specific_config: audio_specific_config.clone(),
+        };
+        if self.audio_muxer.set_audio_config(config.clone()) {
+            self.audio_config = Some(config);
+            self.init_pending = true;
+        }
+    }
+
+    fn process_sample(
+        &mut self,
+        track_type: TrackType,
+        sample: Mp4Sample,
+    ) -> Result<(), MediaError> {
+        let dts = sample.dts;
+        //the segments start with a video key frame, or with any audio frame if there is
+        //no video
+        let can_cut = match track_type {
+            TrackType::Video => sample.is_key,
+            TrackType::Audio => self.video_config.is_none(),
+        };
+        if can_cut
+            && !self.segment_empty
+            && (self.discontinuity_pending || dts - self.segment_start >= self.duration * 1000)
+        {
+            self.flush_segment(dts)?;
+            self.discontinuity_pending = false;
+        }
+
+        if self.segment_empty {
+            self.segment_start = dts;
+            //the tracks only change between the segments
+            if self.init_pending {
+                self.start_period()?;
+            }
+        }
+        self.segment_empty = false;
+        self.last_dts = dts;
+
+        match track_type {
+            TrackType::Video => self.video_muxer.push_video(sample),
+            TrackType::Audio => self.audio_muxer.push_audio(sample),
+        }
+
+        Ok(())
+    }
+
+    //Writes an ID3 tag as an emsg box of the first track, which the mpd describes with
+    //an InbandEventStream.
+    pub fn process_id3(&mut self, timestamp: u32, data: BytesMut) {
+        let dts = self.timestamp_extender.extend(timestamp);
+        let event = Mp4Id3Event { dts, data };
+        if self.video_config.is_some() {
+            self.video_muxer.push_id3_event(event);
+        } else {
+            self.audio_muxer.push_id3_event(event);
+        }
+    }
+
+    pub fn mark_discontinuity(&mut self) {
+        self.discontinuity_pending = true;
+    }
+
+    fn start_period(&mut self) -> Result<(), MediaError> {
+        let mut tracks = Vec::new();
+        if let Some(config) = &self.video_config {
+            tracks.push((
+                Representation::video(config),
+                self.video_muxer.init_segment()?,
+            ));
+        }
+        if let Some(config) = &self.audio_config {
+            tracks.push((
+                Representation::audio(config),
+                self.audio_muxer.init_segment()?,
+            ));
+        }
+        self.mpd_handler.add_period(self.segment_start, tracks)?;
+        self.init_pending = false;
+
+        Ok(())
+    }
+
+    //Writes the segment which ends at end_dts.
+    fn flush_segment(&mut self, end_dts: i64) -> Result<(), MediaError> {
+        let fragments = vec![
+            (TrackType::Video, self.video_muxer.flush_fragment(end_dts)?),
+            (TrackType::Audio, self.audio_muxer.flush_fragment(end_dts)?),
+        ];
+        self.mpd_handler.add_segment(
+            self.segment_start,
+            end_dts - self.segment_start,
+            fragments,
+        )?;
+        self.mpd_handler.refresh_mpd()?;
+        self.segment_empty = true;
+
+        Ok(())
+    }
+
+    pub fn flush_remaining_data(&mut self) -> Result<(), MediaError> {
+        if self.segment_empty || self.last_dts <= self.segment_start {
+            return Ok(());
+        }
+        self.flush_segment(self.last_dts)
+    }
+
+    pub fn clear_files(&mut self) -> Result<(), MediaError> {
+        self.mpd_handler.clear()
+    }
+}
@@ -0,0 +1,197 @@
+use tokio::sync::oneshot;
+
+use {
+    super::{
+        errors::{DashError, DashErrorValue},
+        flv2dash::Flv2DashRemuxer,
+    },
+    bytes::BytesMut,
+    std::time::Duration,
+    streamhub::{
+        define::{
+            FrameData, NotifyInfo, StreamHubEvent, StreamHubEventSender, SubFrameDataReceiver,
+            SubscribeType, SubscriberInfo, TimedMetadata,
+        },
+        stream::StreamIdentifier,
+        utils::{RandomDigitCount, Uuid},
+    },
+    tokio::{sync::mpsc, time::sleep},
+    xflv::define::FlvData,
+};
+
+pub struct FlvDataReceiver {
+    app_name: String,
+    stream_name: String,
+    event_producer: StreamHubEventSender,
+    data_consumer: SubFrameDataReceiver,
+    media_processor: Flv2DashRemuxer,
+    subscriber_id: Uuid,
+}
+
+impl FlvDataReceiver {
+    pub fn new(
+        app_name: String,
+        stream_name: String,
+        event_producer: StreamHubEventSender,
+        duration: i64,
+        segment_count: usize,
+    ) -> Result<Self, DashError> {
+        let (_, data_consumer) = mpsc::channel(1);
+        let subscriber_id = Uuid::new(RandomDigitCount::Four);
+        let media_processor = Flv2DashRemuxer::new(
+            duration,
+            segment_count,
+            app_name.clone(),
+            stream_name.clone(),
+        )?;
+
+        Ok(Self {
+            app_name,
+            stream_name,
+            data_consumer,
+            event_producer,
+            media_processor,
+            subscriber_id,
+        })
+    }
+
+    pub async fn run(&mut self) -> Result<(), DashError> {
+        self.subscribe_from_stream_hub(self.app_name.clone(), self.stream_name.clone())
+            .await?;
+        self.receive_flv_data().await?;
+
+        Ok(())
+    }
+
+    pub async fn receive_flv_data(&mut self) -> Result<(), DashError> {
+        let mut retry_count = 0;
+
+        loop {
+            if let Some(data) = self.data_consumer.recv().await {
+                if let FrameData::Discontinuity { timestamp } = data {
+                    log::info!("dash: discontinuity at {}", timestamp);
+                    self.media_processor.mark_discontinuity();
+                    continue;
+                }
+                //the flv demuxer reads the tag in place, copy the shared payload out once here.
+                let flv_data: FlvData = match data {
+                    FrameData::Audio { timestamp, data } => FlvData::Audio {
+                        timestamp,
+                        data: BytesMut::from(&data[..]),
+                    },
+                    FrameData::Video { timestamp, data } => FlvData::Video {
+                        timestamp,
+                        data: BytesMut::from(&data[..]),
+                    },
+                    FrameData::MetaData { .. } => {
+                        //the stream metadata is carried by the codec configs
+                        if let Some(metadata) = TimedMetadata::from_frame(&data)
+                            .filter(|metadata| !metadata.is_stream_metadata())
+                        {
+                            self.media_processor
+                                .process_id3(metadata.timestamp, metadata.to_id3());
+                        }
+                        continue;
+                    }
+                    _ => continue,
+                };
+                retry_count = 0;
+                self.media_processor.process_flv_data(flv_data)?;
+            } else {
+                sleep(Duration::from_millis(100)).await;
+                retry_count += 1;
+            }
+            //When rtmp stream is interupted here we retry 10 times.
+            //maybe have a better way to judge the stream status.
+            //will do an optimization in the future.
+            //todo
+            if retry_count > 10 {
+                self.media_processor.flush_remaining_data()?;
+                break;
+            }
+        }
+
+        self.media_processor.clear_files()?;
+        self.unsubscribe_from_stream_hub().await
+    }
+
+    pub fn flush_response_data(&mut self) -> Result<(), DashError> {
+        Ok(())
+    }
+
+    pub async fn subscribe_from_stream_hub(
+        &mut self,
+        app_name: String,
+        stream_name: String,
+    ) -> Result<(), DashError> {
+        /*the sub info is only used to transfer from RTMP to DASH, but not for client player */
+        let sub_info = SubscriberInfo {
+            id: self.subscriber_id,
+            sub_type: SubscribeType::RtmpRemux2Dash,
+            sub_data_type: streamhub::define::SubDataType::Frame,
+            track_filter: streamhub::define::TrackFilter::All,
+            notify_info: NotifyInfo {
+                request_url: String::from(""),
+                remote_addr: String::from(""),
+            },
+        };
+
+        let identifier = StreamIdentifier::Rtmp {
+            app_name,
+            stream_name,
+        };
+
+        let (event_result_sender, event_result_receiver) = oneshot::channel();
+
+        let subscribe_event = StreamHubEvent::Subscribe {
+            identifier,
+            info: sub_info,
+            result_sender: event_result_sender,
+        };
+
+        let rv = self.event_producer.send(subscribe_event);
+        if rv.is_err() {
+            return Err(DashError {
+                value: DashErrorValue::StreamHubEventSendErr,
+            });
+        }
+
+        let Some(receiver) = event_result_receiver.await??.0.frame_receiver else {
+            return Err(DashError {
+                value: DashErrorValue::ChannelRecvError,
+            });
+        };
+
+        self.data_consumer = receiver;
+
+        Ok(())
+    }
+
+    pub async fn unsubscribe_from_stream_hub(&mut self) -> Result<(), DashError> {
+        let sub_info = SubscriberInfo {
+            id: self.subscriber_id,
+            sub_type: SubscribeType::RtmpRemux2Dash,
+            sub_data_type: streamhub::define::SubDataType::Frame,
+            track_filter: streamhub::define::TrackFilter::All,
+            notify_info: NotifyInfo {
+                request_url: String::from(""),
+                remote_addr: String::from(""),
+            },
+        };
+
+        let identifier = StreamIdentifier::Rtmp {
+            app_name: self.app_name.clone(),
+            stream_name: self.stream_name.clone(),
+        };
+
+        let subscribe_event = StreamHubEvent::UnSubscribe {
+            identifier,
+            info: sub_info,
+        };
+        if let Err(err) = self.event_producer.send(subscribe_event) {
+            log::error!("unsubscribe_from_stream_hub err {}", err);
+        }
+
+        Ok(())
+    }
+}
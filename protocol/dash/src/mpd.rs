@@ -0,0 +1,454 @@
+use {
+    super::{define::TrackType, errors::MediaError},
+    bytes::BytesMut,
+    chrono::{DateTime, Duration, SecondsFormat, Utc},
+    std::{collections::VecDeque, fs, fs::File, io::Write, path::Path},
+    xmp4::define::{AudioConfig, VideoConfig, ID3_SCHEME_ID_URI},
+};
+
+//the timescale of the segment timelines, the segments are timed in milliseconds
+const TIMELINE_TIMESCALE: i64 = 1000;
+
+//A media segment of a track.
+struct Segment {
+    //ms
+    start: i64,
+    duration: i64,
+    size: usize,
+    path: String,
+}
+
+//A track of a period, written as an AdaptationSet with a single Representation.
+pub struct Representation {
+    track_type: TrackType,
+    codecs: String,
+    //the Representation attributes besides id, codecs and bandwidth
+    attributes: String,
+    channels: Option<u8>,
+    init_name: String,
+    init_path: String,
+    segments: VecDeque<Segment>,
+}
+
+impl Representation {
+    fn new(
+        track_type: TrackType,
+        codecs: String,
+        attributes: String,
+        channels: Option<u8>,
+    ) -> Self {
+        Self {
+            track_type,
+            codecs,
+            attributes,
+            channels,
+            init_name: String::default(),
+            init_path: String::default(),
+            segments: VecDeque::new(),
+        }
+    }
+
+    pub fn video(config: &VideoConfig) -> Self {
+        Self::new(
+            TrackType::Video,
+            config.codec_string(),
+            format!(r#"width="{}" height="{}""#, config.width, config.height),
+            None,
+        )
+    }
+
+    pub fn audio(config: &AudioConfig) -> Self {
+        Self::new(
+            TrackType::Audio,
+            config.codec_string(),
+            format!(r#"audioSamplingRate="{}""#, config.sample_rate),
+            Some(config.channels),
+        )
+    }
+
+    //bits per second of the segments in the MPD
+    fn bandwidth(&self) -> u64 {
+        let size: usize = self.segments.iter().map(|segment| segment.size).sum();
+        let duration: i64 = self.segments.iter().map(|segment| segment.duration).sum();
+        if duration <= 0 {
+            return 1;
+        }
+        std::cmp::max(size as u64 * 8 * 1000 / duration as u64, 1)
+    }
+}
+
+//The tracks do not change in a period, a new period starts when they do.
+struct Period {
+    id: u32,
+    //ms
+    start: i64,
+    representations: Vec<Representation>,
+}
+
+impl Period {
+    fn is_empty(&self) -> bool {
+        self.representations
+            .iter()
+            .all(|representation| representation.segments.is_empty())
+    }
+}
+
+pub struct Mpd {
+    //the max segment duration(ms)
+    duration: i64,
+    //how many segments are listed in the MPD
+    segment_count: usize,
+
+    mpd_folder: String,
+    mpd_name: String,
+
+    //the wall clock time of the media time 0
+    availability_start_time: Option<DateTime<Utc>>,
+    periods: VecDeque<Period>,
+    next_period_id: u32,
+    //the start(ms) of the segments in the MPD
+    segment_starts: VecDeque<i64>,
+    //the end(ms) of the last segment
+    segment_end: i64,
+}
+
+impl Mpd {
+    pub fn new(
+        duration: i64,
+        segment_count: usize,
+        app_name: String,
+        stream_name: String,
+    ) -> Result<Self, MediaError> {
+        let mpd_folder = format!("./{app_name}/{stream_name}");
+        fs::create_dir_all(mpd_folder.clone())?;
+
+        Ok(Self {
+            duration,
+            segment_count,
+            mpd_folder,
+            mpd_name: format!("{stream_name}.mpd"),
+            availability_start_time: None,
+            periods: VecDeque::new(),
+            next_period_id: 0,
+            segment_starts: VecDeque::new(),
+            segment_end: 0,
+        })
+    }
+
+    fn write_file(&self, file_name: &str, data: &[u8]) -> Result<String, MediaError> {
+        let file_path = format!("{}/{}", self.mpd_folder, file_name);
+        let mut file_handler = File::create(file_path.clone())?;
+        file_handler.write_all(data)?;
+        Ok(file_path)
+    }
+
+    fn delete(file_path: &str) {
+        if let Err(err) = fs::remove_file(file_path) {
+            log::error!("dash: delete {} error: {}", file_path, err);
+        }
+    }
+
+    //Starts a new period at start(ms), each track comes with its init segment.
+    pub fn add_period(
+        &mut self,
+        start: i64,
+        tracks: Vec<(Representation, BytesMut)>,
+    ) -> Result<(), MediaError> {
+        if self.availability_start_time.is_none() {
+            self.availability_start_time = Some(Utc::now() - Duration::milliseconds(start));
+        }
+
+        let id = self.next_period_id;
+        self.next_period_id += 1;
+
+        let mut representations = Vec::with_capacity(tracks.len());
+        for (mut representation, init_data) in tracks {
+            let init_name = format!("{}_init_{}.mp4", representation.track_type.name(), id);
+            representation.init_path = self.write_file(&init_name, &init_data[..])?;
+            representation.init_name = init_name;
+            representations.push(representation);
+        }
+        self.periods.push_back(Period {
+            id,
+            start,
+            representations,
+        });
+
+        Ok(())
+    }
+
+    //Adds the segment which starts at start(ms) with the fragments of the tracks of the
+    //current period, a track without data is left out of the segment.
+    pub fn add_segment(
+        &mut self,
+        start: i64,
+        duration: i64,
+        fragments: Vec<(TrackType, BytesMut)>,
+    ) -> Result<(), MediaError> {
+        let Some(period) = self.periods.back() else {
+            return Ok(());
+        };
+        let mut written = Vec::new();
+        for (track_type, data) in fragments {
+            if data.is_empty()
+                || !period
+                    .representations
+                    .iter()
+                    .any(|representation| representation.track_type == track_type)
+            {
+                continue;
+            }
+            let file_name = format!("{}_{}.m4s", track_type.name(), start);
+            let path = self.write_file(&file_name, &data[..])?;
+            written.push((track_type, data.len(), path));
+        }
+
+        let period = self.periods.back_mut().unwrap();
+        for (track_type, size, path) in written {
+            if let Some(representation) = period
+                .representations
+                .iter_mut()
+                .find(|representation| representation.track_type == track_type)
+            {
+                representation.segments.push_back(Segment {
+                    start,
+                    duration,
+                    size,
+                    path,
+                });
+            }
+        }
+
+        self.duration = std::cmp::max(duration, self.duration);
+        self.segment_starts.push_back(start);
+        self.segment_end = start + duration;
+        while self.segment_starts.len() > self.segment_count {
+            let oldest = self.segment_starts.pop_front().unwrap();
+            self.remove_segments(oldest);
+        }
+
+        Ok(())
+    }
+
+    //removes the segments which start at start and the periods left empty
+    fn remove_segments(&mut self, start: i64) {
+        for period in &mut self.periods {
+            for representation in &mut period.representations {
+                while representation
+                    .segments
+                    .front()
+                    .is_some_and(|segment| segment.start <= start)
+                {
+                    let segment = representation.segments.pop_front().unwrap();
+                    Self::delete(&segment.path);
+                }
+            }
+        }
+
+        while self.periods.len() > 1 && self.periods[0].is_empty() {
+            let period = self.periods.pop_front().unwrap();
+            for representation in &period.representations {
+                Self::delete(&representation.init_path);
+            }
+        }
+    }
+
+    pub fn refresh_mpd(&mut self) -> Result<String, MediaError> {
+        let mpd_content = self.generate_mpd(Utc::now());
+        self.write_file(&self.mpd_name, mpd_content.as_bytes())?;
+
+        Ok(mpd_content)
+    }
+
+    fn generate_mpd(&self, publish_time: DateTime<Utc>) -> String {
+        let availability_start_time = self.availability_start_time.unwrap_or(publish_time);
+        let time_shift_buffer_depth = self
+            .segment_starts
+            .front()
+            .map_or(0, |first| self.segment_end - first);
+        let target = (self.duration + 999) / 1000 * 1000;
+
+        let mut mpd_content = String::from(r#"<?xml version="1.0" encoding="utf-8"?>"#);
+        mpd_content += "\n";
+        mpd_content += format!(
+            concat!(
+                r#"<MPD xmlns="urn:mpeg:dash:schema:mpd:2011" profiles="urn:mpeg:dash:profile:isoff-live:2011" type="dynamic""#,
+                r#" availabilityStartTime="{}" publishTime="{}" minimumUpdatePeriod="{}" minBufferTime="{}""#,
+                r#" timeShiftBufferDepth="{}" suggestedPresentationDelay="{}">"#,
+                "\n"
+            ),
+            availability_start_time.to_rfc3339_opts(SecondsFormat::Millis, true),
+            publish_time.to_rfc3339_opts(SecondsFormat::Millis, true),
+            Self::duration_string(target),
+            Self::duration_string(target),
+            Self::duration_string(time_shift_buffer_depth),
+            Self::duration_string(target * 3),
+        )
+        .as_str();
+
+        for period in self.periods.iter().filter(|period| !period.is_empty()) {
+            mpd_content += format!(
+                "  <Period id=\"{}\" start=\"{}\">\n",
+                period.id,
+                Self::duration_string(period.start)
+            )
+            .as_str();
+            for (index, representation) in period
+                .representations
+                .iter()
+                .enumerate()
+                .filter(|(_, representation)| !representation.segments.is_empty())
+            {
+                Self::append_adaptation_set(&mut mpd_content, index, period, representation);
+            }
+            mpd_content += "  </Period>\n";
+        }
+        mpd_content += "</MPD>\n";
+
+        mpd_content
+    }
+
+    fn append_adaptation_set(
+        mpd_content: &mut String,
+        index: usize,
+        period: &Period,
+        representation: &Representation,
+    ) {
+        let name = representation.track_type.name();
+        *mpd_content += format!(
+            "    <AdaptationSet id=\"{index}\" contentType=\"{name}\" mimeType=\"{name}/mp4\" segmentAlignment=\"true\" startWithSAP=\"1\">\n"
+        )
+        .as_str();
+        //the first track of a period carries the timed metadata as emsg boxes
+        if index == 0 {
+            *mpd_content += format!(
+                "      <InbandEventStream schemeIdUri=\"{ID3_SCHEME_ID_URI}\" value=\"\"/>\n"
+            )
+            .as_str();
+        }
+        *mpd_content += format!(
+            "      <SegmentTemplate timescale=\"{}\" presentationTimeOffset=\"{}\" initialization=\"{}\" media=\"{}_$Time$.m4s\">\n",
+            TIMELINE_TIMESCALE, period.start, representation.init_name, name
+        )
+        .as_str();
+        *mpd_content += "        <SegmentTimeline>\n";
+        for segment in &representation.segments {
+            *mpd_content += format!(
+                "          <S t=\"{}\" d=\"{}\"/>\n",
+                segment.start, segment.duration
+            )
+            .as_str();
+        }
+        *mpd_content += "        </SegmentTimeline>\n";
+        *mpd_content += "      </SegmentTemplate>\n";
+
+        *mpd_content += format!(
+            "      <Representation id=\"{}\" codecs=\"{}\" bandwidth=\"{}\" {}",
+            name,
+            representation.codecs,
+            representation.bandwidth(),
+            representation.attributes
+        )
+        .as_str();
+        match representation.channels {
+            Some(channels) => {
+                *mpd_content += ">\n";
+                *mpd_content += format!(
+                    "        <AudioChannelConfiguration schemeIdUri=\"urn:mpeg:dash:23003:3:audio_channel_configuration:2011\" value=\"{channels}\"/>\n"
+                )
+                .as_str();
+                *mpd_content += "      </Representation>\n";
+            }
+            None => *mpd_content += "/>\n",
+        }
+        *mpd_content += "    </AdaptationSet>\n";
+    }
+
+    //the xs:duration of ms
+    fn duration_string(ms: i64) -> String {
+        format!("PT{:.3}S", ms as f64 / 1000.0)
+    }
+
+    pub fn clear(&mut self) -> Result<(), MediaError> {
+        for period in &self.periods {
+            for representation in &period.representations {
+                for segment in &representation.segments {
+                    Self::delete(&segment.path);
+                }
+                Self::delete(&representation.init_path);
+            }
+        }
+        self.periods.clear();
+        self.segment_starts.clear();
+
+        //the MPD is written with the first segment
+        let mpd_path = format!("{}/{}", self.mpd_folder, self.mpd_name);
+        if Path::new(&mpd_path).exists() {
+            fs::remove_file(mpd_path)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Mpd, Representation};
+    use crate::define::TrackType;
+    use bytes::BytesMut;
+    use chrono::Utc;
+    use xmp4::define::{AudioConfig, VideoCodec, VideoConfig};
+
+    #[test]
+    fn test_generate_mpd() {
+        let mut mpd = Mpd::new(5000, 2, String::from("dash_test"), String::from("test")).unwrap();
+        let video = VideoConfig {
+            codec: VideoCodec::H264,
+            width: 1280,
+            height: 720,
+            decoder_configuration_record: BytesMut::from(&[0x01, 0x64, 0x00, 0x1f][..]),
+        };
+        let audio = AudioConfig {
+            sample_rate: 44100,
+            channels: 2,
+            audio_specific_config: BytesMut::from(&[0x12, 0x10][..]),
+        };
+        mpd.add_period(
+            0,
+            vec![
+                (Representation::video(&video), BytesMut::from(&b"init"[..])),
+                (Representation::audio(&audio), BytesMut::from(&b"init"[..])),
+            ],
+        )
+        .unwrap();
+        for start in [0, 5000, 10000] {
+            let fragment = BytesMut::from(&[0u8; 625][..]);
+            mpd.add_segment(
+                start,
+                5000,
+                vec![
+                    (TrackType::Video, fragment.clone()),
+                    (TrackType::Audio, fragment),
+                ],
+            )
+            .unwrap();
+        }
+
+        let content = mpd.generate_mpd(Utc::now());
+        assert!(content.contains(r#"type="dynamic""#));
+        assert!(content.contains(r#"timeShiftBufferDepth="PT10.000S""#));
+        assert!(content.contains(r#"initialization="video_init_0.mp4" media="video_$Time$.m4s""#));
+        //the first segment is out of the window
+        assert!(!content.contains(r#"<S t="0""#));
+        assert!(content.contains(r#"<S t="5000" d="5000"/>"#));
+        assert!(content.contains(
+            r#"<Representation id="video" codecs="avc1.64001f" bandwidth="1000" width="1280" height="720"/>"#
+        ));
+        assert!(content.contains(r#"codecs="mp4a.40.2""#));
+        assert_eq!(content.matches("<InbandEventStream").count(), 1);
+
+        mpd.refresh_mpd().unwrap();
+        mpd.clear().unwrap();
+        std::fs::remove_dir_all("./dash_test").unwrap();
+    }
+}
@@ -0,0 +1,63 @@
+use {
+    super::{
+        define::{DASH_DURATION, DASH_SEGMENT_COUNT},
+        errors::DashError,
+        flv_data_receiver::FlvDataReceiver,
+    },
+    streamhub::{
+        define::{BroadcastEvent, BroadcastEventReceiver, StreamHubEventSender},
+        stream::StreamIdentifier,
+    },
+};
+
+pub struct DashRemuxer {
+    client_event_consumer: BroadcastEventReceiver,
+    event_producer: StreamHubEventSender,
+}
+
+impl DashRemuxer {
+    pub fn new(consumer: BroadcastEventReceiver, event_producer: StreamHubEventSender) -> Self {
+        Self {
+            client_event_consumer: consumer,
+            event_producer,
+        }
+    }
+
+    pub async fn run(&mut self) -> Result<(), DashError> {
+        loop {
+            let val = self.client_event_consumer.recv().await?;
+            match val {
+                BroadcastEvent::Publish { identifier, .. } => {
+                    if let StreamIdentifier::Rtmp {
+                        app_name,
+                        stream_name,
+                    } = identifier
+                    {
+                        let mut rtmp_subscriber = match FlvDataReceiver::new(
+                            app_name,
+                            stream_name,
+                            self.event_producer.clone(),
+                            DASH_DURATION,
+                            DASH_SEGMENT_COUNT,
+                        ) {
+                            Ok(rtmp_subscriber) => rtmp_subscriber,
+                            Err(err) => {
+                                log::error!("dash handler new error {err}");
+                                continue;
+                            }
+                        };
+
+                        tokio::spawn(async move {
+                            if let Err(err) = rtmp_subscriber.run().await {
+                                log::error!("dash handler run error {err}");
+                            }
+                        });
+                    }
+                }
+                _ => {
+                    log::trace!("other infos...");
+                }
+            }
+        }
+    }
+}
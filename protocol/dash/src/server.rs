@@ -0,0 +1,194 @@
+use {
+    axum::{
+        body::Body,
+        extract::{Request, State},
+        handler::Handler,
+        http::StatusCode,
+        response::Response,
+    },
+    commonlib::auth::{Auth, SecretCarrier},
+    std::net::SocketAddr,
+    tokio::{fs::File, net::TcpListener},
+    tokio_util::codec::{BytesCodec, FramedRead},
+};
+
+type GenericError = Box<dyn std::error::Error + Send + Sync>;
+type Result<T> = std::result::Result<T, GenericError>;
+
+static NOTFOUND: &[u8] = b"Not Found";
+static UNAUTHORIZED: &[u8] = b"Unauthorized";
+
+#[derive(Debug)]
+enum DashFileType {
+    Manifest,
+    Segment,
+    InitSegment,
+}
+
+impl DashFileType {
+    fn content_type(&self) -> &str {
+        match self {
+            Self::Manifest => "application/dash+xml",
+            Self::Segment => "video/iso.segment",
+            Self::InitSegment => "video/mp4",
+        }
+    }
+}
+
+#[derive(Debug)]
+struct DashPath {
+    app_name: String,
+    stream_name: String,
+    file_name: String,
+    file_type: DashFileType,
+}
+
+impl DashPath {
+    const MPD_EXT: &'static str = "mpd";
+    const M4S_EXT: &'static str = "m4s";
+    const MP4_EXT: &'static str = "mp4";
+
+    fn parse(path: &str) -> Option<Self> {
+        if path.is_empty() || path.contains("..") {
+            return None;
+        }
+
+        let mut parts = path[1..].split('/');
+        let app_name = parts.next()?;
+        let stream_name = parts.next()?;
+        let file_part = parts.next()?;
+        if parts.next().is_some() {
+            return None;
+        }
+
+        let (file_name, ext) = file_part.rsplit_once('.')?;
+        if file_name.is_empty() {
+            return None;
+        }
+
+        let file_type = match ext {
+            Self::MPD_EXT => DashFileType::Manifest,
+            Self::M4S_EXT => DashFileType::Segment,
+            Self::MP4_EXT => DashFileType::InitSegment,
+            _ => return None,
+        };
+
+        Some(Self {
+            app_name: app_name.into(),
+            stream_name: stream_name.into(),
+            file_name: file_name.into(),
+            file_type,
+        })
+    }
+
+    fn to_file_path(&self) -> String {
+        let ext = match self.file_type {
+            DashFileType::Manifest => Self::MPD_EXT,
+            DashFileType::Segment => Self::M4S_EXT,
+            DashFileType::InitSegment => Self::MP4_EXT,
+        };
+        format!(
+            "./{}/{}/{}.{}",
+            self.app_name, self.stream_name, self.file_name, ext
+        )
+    }
+}
+
+fn response_unauthorized() -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::UNAUTHORIZED)
+        .body(UNAUTHORIZED.into())
+        .unwrap()
+}
+
+fn response_not_found() -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::NOT_FOUND)
+        .body(NOTFOUND.into())
+        .unwrap()
+}
+
+async fn response_file(dash_path: &DashPath) -> Response<Body> {
+    let file_path = dash_path.to_file_path();
+
+    if let Ok(file) = File::open(&file_path).await {
+        let builder =
+            Response::builder().header("Content-Type", dash_path.file_type.content_type());
+
+        // Serve a file by asynchronously reading it by chunks using tokio-util crate.
+        let stream = FramedRead::new(file, BytesCodec::new());
+        return builder.body(Body::from_stream(stream)).unwrap();
+    }
+
+    response_not_found()
+}
+
+//the MPD is authenticated like the HLS playlist, the segments are not
+async fn handle_connection(State(auth): State<Option<Auth>>, req: Request<Body>) -> Response<Body> {
+    let path = req.uri().path();
+    let query_string = req.uri().query().map(|s| s.to_string());
+
+    let dash_path = match DashPath::parse(path) {
+        Some(p) => p,
+        None => return response_not_found(),
+    };
+
+    if let (Some(auth_val), DashFileType::Manifest) = (auth.as_ref(), &dash_path.file_type) {
+        if auth_val
+            .authenticate(
+                &dash_path.stream_name,
+                &query_string.map(SecretCarrier::Query),
+                true,
+            )
+            .is_err()
+        {
+            return response_unauthorized();
+        }
+    }
+
+    response_file(&dash_path).await
+}
+
+pub async fn run(port: usize, auth: Option<Auth>) -> Result<()> {
+    let listen_address = format!("0.0.0.0:{port}");
+    let sock_addr: SocketAddr = listen_address.parse().unwrap();
+
+    let listener = TcpListener::bind(sock_addr).await?;
+
+    log::info!("Dash server listening on http://{}", sock_addr);
+
+    let handle_connection = handle_connection.with_state(auth);
+
+    axum::serve(listener, handle_connection.into_make_service()).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DashFileType, DashPath};
+
+    #[test]
+    fn test_dash_path_parse() {
+        let manifest = DashPath::parse("/live/stream/stream.mpd").unwrap();
+        assert_eq!(manifest.app_name, "live");
+        assert_eq!(manifest.stream_name, "stream");
+        assert!(matches!(manifest.file_type, DashFileType::Manifest));
+        assert_eq!(manifest.to_file_path(), "./live/stream/stream.mpd");
+        assert_eq!(manifest.file_type.content_type(), "application/dash+xml");
+
+        let segment = DashPath::parse("/live/stream/video_5000.m4s").unwrap();
+        assert_eq!(segment.file_name, "video_5000");
+        assert!(matches!(segment.file_type, DashFileType::Segment));
+        assert_eq!(segment.to_file_path(), "./live/stream/video_5000.m4s");
+
+        let init = DashPath::parse("/live/stream/audio_init_0.mp4").unwrap();
+        assert!(matches!(init.file_type, DashFileType::InitSegment));
+        assert_eq!(init.file_type.content_type(), "video/mp4");
+
+        assert!(DashPath::parse("/live/stream/stream.m3u8").is_none());
+        assert!(DashPath::parse("/live/stream/../../etc/passwd").is_none());
+        assert!(DashPath::parse("/live/stream.mpd").is_none());
+        assert!(DashPath::parse("/live/stream/.mpd").is_none());
+    }
+}
@@ -1,4 +1,7 @@
-use xflv::demuxer::{FlvDemuxerAudioData, FlvDemuxerVideoData};
+use {
+    serde::Deserialize,
+    xflv::demuxer::{FlvDemuxerAudioData, FlvDemuxerVideoData},
+};
 
 pub const HLS_DURATION: u8 = 10;
 
@@ -7,3 +10,24 @@ pub enum FlvDemuxerData {
     Audio { data: FlvDemuxerAudioData },
     None,
 }
+
+//The container of the media segments.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+pub enum SegmentFormat {
+    //MPEG-TS segments(.ts)
+    #[default]
+    #[serde(rename = "ts")]
+    Ts,
+    //fragmented MP4 segments(.m4s) with an init segment(EXT-X-MAP)
+    #[serde(rename = "fmp4")]
+    Fmp4,
+}
+
+impl SegmentFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Ts => "ts",
+            Self::Fmp4 => "m4s",
+        }
+    }
+}
@@ -1,10 +1,21 @@
 use {
-    super::{define::FlvDemuxerData, errors::MediaError, m3u8::M3u8},
+    super::{
+        define::{FlvDemuxerData, SegmentFormat},
+        errors::MediaError,
+        ll_hls::LowLatencyHls,
+        m3u8::M3u8,
+        storage::SegmentStore,
+    },
     bytes::BytesMut,
+    streamhub::normalizer::TimestampExtender,
     xflv::{
-        define::{frame_type, FlvData},
+        define::{frame_type, AvcCodecId, FlvData},
         demuxer::{FlvAudioTagDemuxer, FlvVideoTagDemuxer},
     },
+    xmp4::{
+        define::{AudioConfig, Mp4Id3Event, Mp4Sample, VideoCodec, VideoConfig},
+        fmp4::Fmp4Muxer,
+    },
     xmpegts::{
         define::{epsi_stream_type, MPEG_FLAG_IDR_FRAME},
         ts::TsMuxer,
@@ -14,8 +25,16 @@ use {
 pub struct Flv2HlsRemuxer {
     video_demuxer: FlvVideoTagDemuxer,
     audio_demuxer: FlvAudioTagDemuxer,
+    //the segments follow the 64 bits timeline of the 32 bits FLV timestamps
+    timestamp_extender: TimestampExtender,
 
     ts_muxer: TsMuxer,
+    segment_format: SegmentFormat,
+    fmp4_muxer: Fmp4Muxer,
+    //the codec configs changed, a new init segment is written when the next segment starts
+    init_pending: bool,
+    //nothing is muxed into the current segment yet
+    segment_empty: bool,
 
     last_ts_dts: i64,
     last_ts_pts: i64,
@@ -25,15 +44,39 @@ pub struct Flv2HlsRemuxer {
 
     duration: i64,
     need_new_segment: bool,
+    //a discontinuity is flagged by the stream hub, the next segment starts after it.
+    discontinuity_pending: bool,
+    //the current segment starts after a discontinuity
+    segment_discontinuity: bool,
+    //the stream hub does not flag the discontinuities if it does not normalize the
+    //timestamps, a segment longer than 15s is then taken as one.
+    timestamp_normalized: bool,
 
     video_pid: u16,
     audio_pid: u16,
+    id3_pid: u16,
+
+    //LL-HLS: cut a partial segment after this duration(ms)
+    part_duration: Option<i64>,
+    last_part_dts: i64,
+    //the current partial segment has a key frame
+    part_independent: bool,
+    //the data of the partial segments of the current segment
+    segment_data: BytesMut,
 
     m3u8_handler: M3u8,
 }
 
 impl Flv2HlsRemuxer {
-    pub fn new(duration: i64, app_name: String, stream_name: String, need_record: bool) -> Self {
+    pub fn new(
+        duration: i64,
+        app_name: String,
+        stream_name: String,
+        need_record: bool,
+        segment_format: SegmentFormat,
+        low_latency: Option<LowLatencyHls>,
+        store: SegmentStore,
+    ) -> Self {
         let mut ts_muxer = TsMuxer::new();
         let audio_pid = ts_muxer
             .add_stream(epsi_stream_type::PSI_STREAM_AAC, BytesMut::new())
@@ -41,12 +84,27 @@ impl Flv2HlsRemuxer {
         let video_pid = ts_muxer
             .add_stream(epsi_stream_type::PSI_STREAM_H264, BytesMut::new())
             .unwrap();
+        let id3_pid = ts_muxer.add_id3_stream().unwrap();
+
+        //fMP4 keeps the NALUs length prefixed and the AAC frames raw
+        let (video_demuxer, audio_demuxer) = match segment_format {
+            SegmentFormat::Ts => (FlvVideoTagDemuxer::new(), FlvAudioTagDemuxer::new()),
+            SegmentFormat::Fmp4 => (
+                FlvVideoTagDemuxer::new_length_prefixed(),
+                FlvAudioTagDemuxer::new_raw(),
+            ),
+        };
 
         Self {
-            video_demuxer: FlvVideoTagDemuxer::new(),
-            audio_demuxer: FlvAudioTagDemuxer::new(),
+            video_demuxer,
+            audio_demuxer,
+            timestamp_extender: TimestampExtender::default(),
 
             ts_muxer,
+            segment_format,
+            fmp4_muxer: Fmp4Muxer::new(),
+            init_pending: false,
+            segment_empty: true,
 
             last_ts_dts: 0,
             last_ts_pts: 0,
@@ -56,24 +114,50 @@ impl Flv2HlsRemuxer {
 
             duration,
             need_new_segment: false,
+            discontinuity_pending: false,
+            segment_discontinuity: false,
+            timestamp_normalized: false,
 
             video_pid,
             audio_pid,
-
-            m3u8_handler: M3u8::new(duration, 6, app_name, stream_name, need_record),
+            id3_pid,
+
+            part_duration: low_latency
+                .as_ref()
+                .map(|low_latency| low_latency.part_duration),
+            last_part_dts: 0,
+            part_independent: false,
+            segment_data: BytesMut::new(),
+
+            m3u8_handler: M3u8::new(
+                duration,
+                6,
+                app_name,
+                stream_name,
+                need_record,
+                segment_format,
+                low_latency,
+                store,
+            ),
         }
     }
 
     pub fn process_flv_data(&mut self, data: FlvData) -> Result<(), MediaError> {
         let flv_demux_data: FlvDemuxerData = match data {
             FlvData::Audio { timestamp, data } => {
+                let timestamp = self.timestamp_extender.extend(timestamp);
                 let audio_data = self.audio_demuxer.demux(timestamp, data)?;
+                if !audio_data.has_data {
+                    self.update_audio_config();
+                }
                 FlvDemuxerData::Audio { data: audio_data }
             }
             FlvData::Video { timestamp, data } => {
+                let timestamp = self.timestamp_extender.extend(timestamp);
                 if let Some(video_data) = self.video_demuxer.demux(timestamp, data)? {
                     FlvDemuxerData::Video { data: video_data }
                 } else {
+                    self.update_video_codec()?;
                     return Ok(());
                 }
             }
@@ -85,15 +169,124 @@ impl Flv2HlsRemuxer {
         Ok(())
     }
 
-    pub fn flush_remaining_data(&mut self) -> Result<(), MediaError> {
-        let data = self.ts_muxer.get_data();
-        let mut discontinuity: bool = false;
-        if self.last_dts > self.last_ts_dts + 15 * 1000 {
-            discontinuity = true;
+    //Muxes an ID3 tag as timed metadata, into the ID3 stream of the ts segments or
+    //as an emsg box of the fmp4 fragments.
+    pub fn process_id3(&mut self, timestamp: u32, data: BytesMut) -> Result<(), MediaError> {
+        let dts = self.timestamp_extender.extend(timestamp);
+        match self.segment_format {
+            SegmentFormat::Ts => {
+                self.ts_muxer
+                    .write(self.id3_pid, dts * 90, dts * 90, 0, data)?;
+            }
+            SegmentFormat::Fmp4 => {
+                self.fmp4_muxer.push_id3_event(Mp4Id3Event { dts, data });
+            }
+        }
+        Ok(())
+    }
+
+    //The video stream is added as H.264, it is switched to the codec of the sequence
+    //header before the first frame is muxed.
+    //With fMP4 the sequence header is saved for the next init segment.
+    fn update_video_codec(&mut self) -> Result<(), MediaError> {
+        let is_hevc = self.video_demuxer.codec_id() == AvcCodecId::HEVC as u8;
+        if self.segment_format == SegmentFormat::Fmp4 {
+            let record = self.video_demuxer.decoder_configuration_record();
+            if !record.is_empty() {
+                let (width, height) = self.video_demuxer.resolution();
+                let config = VideoConfig {
+                    codec: if is_hevc {
+                        VideoCodec::H265
+                    } else {
+                        VideoCodec::H264
+                    },
+                    width,
+                    height,
+                    decoder_configuration_record: record.clone(),
+                };
+                self.init_pending |= self.fmp4_muxer.set_video_config(config);
+            }
+            return Ok(());
         }
+
+        let codec_id = if is_hevc {
+            epsi_stream_type::PSI_STREAM_H265
+        } else {
+            epsi_stream_type::PSI_STREAM_H264
+        };
+        self.ts_muxer.set_stream_codec(self.video_pid, codec_id)?;
+
+        Ok(())
+    }
+
+    fn update_audio_config(&mut self) {
+        let audio_specific_config = self.audio_demuxer.audio_specific_config();
+        if self.segment_format != SegmentFormat::Fmp4 || audio_specific_config.is_empty() {
+            return;
+        }
+        let mpeg4_aac = self.audio_demuxer.mpeg4_aac();
+        let config = AudioConfig {
+            sample_rate: mpeg4_aac.sampling_frequency,
+            channels: mpeg4_aac.channels,
+            audio_specific_config: audio_specific_config.clone(),
+        };
+        self.init_pending |= self.fmp4_muxer.set_audio_config(config);
+    }
+
+    //the data muxed since the last call, which ends at dts
+    fn muxed_data(&mut self, dts: i64) -> Result<BytesMut, MediaError> {
+        match self.segment_format {
+            SegmentFormat::Ts => Ok(self.ts_muxer.get_data()),
+            SegmentFormat::Fmp4 => Ok(self.fmp4_muxer.flush_fragment(dts)?),
+        }
+    }
+
+    pub fn set_timestamp_normalized(&mut self, timestamp_normalized: bool) {
+        self.timestamp_normalized = timestamp_normalized;
+    }
+
+    //whether the segment which ends at dts is marked with EXT-X-DISCONTINUITY
+    fn is_discontinuity(&self, dts: i64) -> bool {
+        self.segment_discontinuity
+            || (!self.timestamp_normalized && dts > self.last_ts_dts + 15 * 1000)
+    }
+
+    //The next segment is cut at the next key frame and marked with EXT-X-DISCONTINUITY.
+    pub fn mark_discontinuity(&mut self) {
+        self.discontinuity_pending = true;
+    }
+
+    //Cuts the data muxed since the last partial segment as a new one.
+    fn cut_part(&mut self, dts: i64) -> Result<(), MediaError> {
+        let data = self.muxed_data(dts)?;
+        if data.is_empty() {
+            return Ok(());
+        }
+        self.segment_data.extend_from_slice(&data[..]);
+        self.m3u8_handler.add_part(
+            dts - self.last_part_dts,
+            std::mem::take(&mut self.part_independent),
+            data,
+        )?;
+        self.last_part_dts = dts;
+
+        Ok(())
+    }
+
+    //the data of the segment which ends at dts
+    fn take_segment_data(&mut self, dts: i64) -> Result<BytesMut, MediaError> {
+        if self.part_duration.is_none() {
+            return self.muxed_data(dts);
+        }
+        self.cut_part(dts)?;
+        Ok(std::mem::take(&mut self.segment_data))
+    }
+
+    pub fn flush_remaining_data(&mut self) -> Result<(), MediaError> {
+        let data = self.take_segment_data(self.last_dts)?;
         self.m3u8_handler.add_segment(
             self.last_dts - self.last_ts_dts,
-            discontinuity,
+            self.is_discontinuity(self.last_dts),
             true,
             data,
         )?;
@@ -123,7 +316,8 @@ impl Flv2HlsRemuxer {
 
                 if data.frame_type == frame_type::KEY_FRAME {
                     flags = MPEG_FLAG_IDR_FRAME;
-                    if dts - self.last_ts_dts >= self.duration * 1000 {
+                    if self.discontinuity_pending || dts - self.last_ts_dts >= self.duration * 1000
+                    {
                         self.need_new_segment = true;
                     }
                 }
@@ -146,27 +340,62 @@ impl Flv2HlsRemuxer {
         }
 
         if self.need_new_segment {
-            let mut discontinuity: bool = false;
-            if dts > self.last_ts_dts + 15 * 1000 {
-                discontinuity = true;
-            }
-            let data = self.ts_muxer.get_data();
-
-            self.m3u8_handler
-                .add_segment(dts - self.last_ts_dts, discontinuity, false, data)?;
+            let data = self.take_segment_data(dts)?;
+
+            self.m3u8_handler.add_segment(
+                dts - self.last_ts_dts,
+                self.is_discontinuity(dts),
+                false,
+                data,
+            )?;
             self.m3u8_handler.refresh_playlist()?;
+            self.segment_discontinuity = std::mem::take(&mut self.discontinuity_pending);
 
             self.ts_muxer.reset();
+            self.segment_empty = true;
             self.last_ts_dts = dts;
             self.last_ts_pts = pts;
             self.need_new_segment = false;
+        } else if self
+            .part_duration
+            .is_some_and(|part_duration| dts - self.last_part_dts >= part_duration)
+        {
+            self.cut_part(dts)?;
+            self.m3u8_handler.refresh_playlist()?;
+        }
+
+        if flags & MPEG_FLAG_IDR_FRAME > 0 {
+            self.part_independent = true;
         }
 
         self.last_dts = dts;
         self.last_pts = pts;
 
-        self.ts_muxer
-            .write(pid, pts * 90, dts * 90, flags, payload)?;
+        if self.segment_format == SegmentFormat::Ts {
+            self.ts_muxer
+                .write(pid, pts * 90, dts * 90, flags, payload)?;
+            return Ok(());
+        }
+
+        //the init segment only changes between the segments
+        if self.init_pending && self.segment_empty {
+            let init_data = self.fmp4_muxer.init_segment()?;
+            self.m3u8_handler.set_init_segment(init_data)?;
+            self.init_pending = false;
+        }
+        self.segment_empty = false;
+
+        let sample = Mp4Sample {
+            dts,
+            pts,
+            is_key: flags & MPEG_FLAG_IDR_FRAME > 0,
+            data: payload,
+        };
+        if pid == self.video_pid {
+            self.fmp4_muxer.push_video(sample);
+        } else {
+            self.fmp4_muxer.push_audio(sample);
+        }
 
         Ok(())
     }
@@ -2,14 +2,18 @@ use tokio::sync::oneshot;
 
 use {
     super::{
+        define::SegmentFormat,
         errors::{HlsError, HlsErrorValue},
         flv2hls::Flv2HlsRemuxer,
+        ll_hls::LowLatencyHls,
+        storage::SegmentStore,
     },
+    bytes::BytesMut,
     std::time::Duration,
     streamhub::{
         define::{
-            FrameData, FrameDataReceiver, NotifyInfo, StreamHubEvent, StreamHubEventSender,
-            SubscribeType, SubscriberInfo,
+            FrameData, NotifyInfo, StreamHubEvent, StreamHubEventSender, SubFrameDataReceiver,
+            SubscribeType, SubscriberInfo, TimedMetadata,
         },
         stream::StreamIdentifier,
         utils::{RandomDigitCount, Uuid},
@@ -24,28 +28,44 @@ pub struct FlvDataReceiver {
     app_name: String,
     stream_name: String,
     event_producer: StreamHubEventSender,
-    data_consumer: FrameDataReceiver,
+    data_consumer: SubFrameDataReceiver,
     media_processor: Flv2HlsRemuxer,
     subscriber_id: Uuid,
 }
 
 impl FlvDataReceiver {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         app_name: String,
         stream_name: String,
         event_producer: StreamHubEventSender,
         duration: i64,
         need_record: bool,
+        segment_format: SegmentFormat,
+        low_latency: Option<LowLatencyHls>,
+        store: SegmentStore,
+        timestamp_normalized: bool,
     ) -> Self {
-        let (_, data_consumer) = mpsc::unbounded_channel();
+        let (_, data_consumer) = mpsc::channel(1);
         let subscriber_id = Uuid::new(RandomDigitCount::Four);
 
+        let mut media_processor = Flv2HlsRemuxer::new(
+            duration,
+            app_name.clone(),
+            stream_name.clone(),
+            need_record,
+            segment_format,
+            low_latency,
+            store,
+        );
+        media_processor.set_timestamp_normalized(timestamp_normalized);
+
         Self {
-            app_name: app_name.clone(),
-            stream_name: stream_name.clone(),
+            app_name,
+            stream_name,
             data_consumer,
             event_producer,
-            media_processor: Flv2HlsRemuxer::new(duration, app_name, stream_name, need_record),
+            media_processor,
             subscriber_id,
         }
     }
@@ -63,9 +83,31 @@ impl FlvDataReceiver {
 
         loop {
             if let Some(data) = self.data_consumer.recv().await {
+                if let FrameData::Discontinuity { timestamp } = data {
+                    log::info!("hls: discontinuity at {}", timestamp);
+                    self.media_processor.mark_discontinuity();
+                    continue;
+                }
+                //the flv demuxer reads the tag in place, copy the shared payload out once here.
                 let flv_data: FlvData = match data {
-                    FrameData::Audio { timestamp, data } => FlvData::Audio { timestamp, data },
-                    FrameData::Video { timestamp, data } => FlvData::Video { timestamp, data },
+                    FrameData::Audio { timestamp, data } => FlvData::Audio {
+                        timestamp,
+                        data: BytesMut::from(&data[..]),
+                    },
+                    FrameData::Video { timestamp, data } => FlvData::Video {
+                        timestamp,
+                        data: BytesMut::from(&data[..]),
+                    },
+                    FrameData::MetaData { .. } => {
+                        //the stream metadata is carried by the codec configs
+                        if let Some(metadata) = TimedMetadata::from_frame(&data)
+                            .filter(|metadata| !metadata.is_stream_metadata())
+                        {
+                            self.media_processor
+                                .process_id3(metadata.timestamp, metadata.to_id3())?;
+                        }
+                        continue;
+                    }
                     _ => continue,
                 };
                 retry_count = 0;
@@ -102,6 +144,7 @@ impl FlvDataReceiver {
             id: self.subscriber_id,
             sub_type: SubscribeType::RtmpRemux2Hls,
             sub_data_type: streamhub::define::SubDataType::Frame,
+            track_filter: streamhub::define::TrackFilter::All,
             notify_info: NotifyInfo {
                 request_url: String::from(""),
                 remote_addr: String::from(""),
@@ -140,6 +183,7 @@ impl FlvDataReceiver {
             id: self.subscriber_id,
             sub_type: SubscribeType::RtmpRemux2Hls,
             sub_data_type: streamhub::define::SubDataType::Frame,
+            track_filter: streamhub::define::TrackFilter::All,
             notify_info: NotifyInfo {
                 request_url: String::from(""),
                 remote_addr: String::from(""),
@@ -2,8 +2,10 @@ pub mod define;
 pub mod errors;
 pub mod flv2hls;
 pub mod flv_data_receiver;
+pub mod ll_hls;
 pub mod m3u8;
 pub mod remuxer;
 pub mod server;
+pub mod storage;
 mod test_flv2hls;
 pub mod ts;
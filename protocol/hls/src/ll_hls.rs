@@ -0,0 +1,181 @@
+use {
+    std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+    },
+    tokio::sync::watch,
+};
+
+pub const DEFAULT_PART_DURATION_MS: i64 = 500;
+
+//The options of the Low-Latency HLS output.
+#[derive(Clone, Default)]
+pub struct LowLatencyHls {
+    //the target duration(ms) of the partial segments
+    pub part_duration: i64,
+    //shared with the hls server, which answers the blocking playlist requests
+    pub playlists: LivePlaylists,
+}
+
+impl LowLatencyHls {
+    pub fn new(part_duration: i64, playlists: LivePlaylists) -> Self {
+        Self {
+            part_duration,
+            playlists,
+        }
+    }
+}
+
+//The live playlist of a stream as it is after its last update.
+#[derive(Debug, Default)]
+pub struct PlaylistSnapshot {
+    pub target_duration_ms: i64,
+    //the media sequence number of the segment which is being written
+    pub next_msn: u64,
+    //the count of the partial segments of the segment which is being written
+    pub next_part: u64,
+    pub playlist: String,
+    //the playlist with the older segments replaced by EXT-X-SKIP, None if nothing
+    //can be skipped
+    pub delta_playlist: Option<String>,
+}
+
+impl PlaylistSnapshot {
+    //Whether the playlist contains the media segment msn, or its partial segment
+    //part if it is asked for.
+    pub fn is_ready(&self, msn: u64, part: Option<u64>) -> bool {
+        match part {
+            Some(part) => msn < self.next_msn || (msn == self.next_msn && part < self.next_part),
+            None => msn < self.next_msn,
+        }
+    }
+}
+
+//The live playlists of the streams, keyed by "app_name/stream_name".
+#[derive(Clone, Default)]
+pub struct LivePlaylists {
+    senders: Arc<Mutex<HashMap<String, watch::Sender<Arc<PlaylistSnapshot>>>>>,
+}
+
+impl LivePlaylists {
+    pub fn publish(&self, key: &str, snapshot: PlaylistSnapshot) {
+        let mut senders = self.senders.lock().unwrap();
+        let snapshot = Arc::new(snapshot);
+        match senders.get(key) {
+            Some(sender) => {
+                sender.send_replace(snapshot);
+            }
+            None => {
+                senders.insert(key.to_string(), watch::channel(snapshot).0);
+            }
+        }
+    }
+
+    //the waiting requests are woken up and fail
+    pub fn remove(&self, key: &str) {
+        self.senders.lock().unwrap().remove(key);
+    }
+
+    pub fn subscribe(&self, key: &str) -> Option<watch::Receiver<Arc<PlaylistSnapshot>>> {
+        self.senders
+            .lock()
+            .unwrap()
+            .get(key)
+            .map(|sender| sender.subscribe())
+    }
+}
+
+//The delivery directives of a playlist request.
+#[derive(Debug, Default, PartialEq)]
+pub struct PlaylistRequest {
+    //_HLS_msn, block until the playlist contains this media segment
+    pub msn: Option<u64>,
+    //_HLS_part, block until the playlist contains this partial segment of msn
+    pub part: Option<u64>,
+    //_HLS_skip=YES or v2, ask for a delta update
+    pub skip: bool,
+}
+
+impl PlaylistRequest {
+    //None if the directives are not valid
+    pub fn parse(query: Option<&str>) -> Option<Self> {
+        let mut request = Self::default();
+        for (key, value) in query
+            .unwrap_or_default()
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+        {
+            match key {
+                "_HLS_msn" => request.msn = Some(value.parse().ok()?),
+                "_HLS_part" => request.part = Some(value.parse().ok()?),
+                "_HLS_skip" => request.skip = value == "YES" || value == "v2",
+                _ => {}
+            }
+        }
+        if request.part.is_some() && request.msn.is_none() {
+            return None;
+        }
+        Some(request)
+    }
+}
+
+//The partial segments are named "{msn}.{part}", the media segments "{msn}".
+pub fn part_name(msn: u64, part: u64) -> String {
+    format!("{msn}.{part}")
+}
+
+pub fn parse_part_name(file_name: &str) -> Option<(u64, u64)> {
+    let (msn, part) = file_name.split_once('.')?;
+    Some((msn.parse().ok()?, part.parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_part_name, part_name, LivePlaylists, PlaylistRequest, PlaylistSnapshot};
+
+    #[test]
+    fn test_playlist_request() {
+        let request = PlaylistRequest::parse(Some("token=abc&_HLS_msn=12&_HLS_part=3")).unwrap();
+        assert_eq!(request.msn, Some(12));
+        assert_eq!(request.part, Some(3));
+        assert!(!request.skip);
+
+        assert!(PlaylistRequest::parse(Some("_HLS_skip=YES")).unwrap().skip);
+        assert_eq!(
+            PlaylistRequest::parse(None),
+            Some(PlaylistRequest::default())
+        );
+        assert!(PlaylistRequest::parse(Some("_HLS_part=3")).is_none());
+        assert!(PlaylistRequest::parse(Some("_HLS_msn=abc")).is_none());
+
+        assert_eq!(parse_part_name(&part_name(12, 3)), Some((12, 3)));
+        assert_eq!(parse_part_name("12"), None);
+    }
+
+    #[tokio::test]
+    async fn test_blocking_reload() {
+        let playlists = LivePlaylists::default();
+        playlists.publish("live/test", PlaylistSnapshot::default());
+
+        let mut receiver = playlists.subscribe("live/test").unwrap();
+        assert!(!receiver.borrow().is_ready(0, Some(0)));
+
+        let waiter = tokio::spawn(async move {
+            receiver
+                .wait_for(|snapshot| snapshot.is_ready(0, Some(0)))
+                .await
+                .is_ok()
+        });
+        playlists.publish(
+            "live/test",
+            PlaylistSnapshot {
+                next_part: 1,
+                ..Default::default()
+            },
+        );
+        assert!(waiter.await.unwrap());
+
+        playlists.remove("live/test");
+        assert!(playlists.subscribe("live/test").is_none());
+    }
+}
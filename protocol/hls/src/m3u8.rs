@@ -1,33 +1,53 @@
 use {
-    super::{errors::MediaError, ts::Ts},
+    super::{
+        define::SegmentFormat,
+        errors::MediaError,
+        ll_hls::{LowLatencyHls, PlaylistSnapshot},
+        storage::{DiskStorage, HlsStorage, MemoryStorage, SegmentStore},
+        ts::Ts,
+    },
     bytes::BytesMut,
-    std::{collections::VecDeque, fs, fs::File, io::Write},
+    std::collections::VecDeque,
 };
 
+//the partial segments are listed for the segments in the last 3 target durations
+const PART_LIST_TARGET_DURATIONS: i64 = 3;
+//the client may ask to skip the segments older than 6 target durations
+const SKIP_UNTIL_TARGET_DURATIONS: i64 = 6;
+
+//A partial segment of LL-HLS.
+pub struct Part {
+    pub duration: i64,
+    pub independent: bool,
+    pub name: String,
+}
+
+//The init segment(EXT-X-MAP) of the fMP4 segments.
+#[derive(Clone, PartialEq)]
+pub struct InitSegment {
+    pub name: String,
+}
+
 pub struct Segment {
     /*ts duration*/
     pub duration: i64,
     pub discontinuity: bool,
     /*ts name*/
     pub name: String,
-    path: String,
     pub is_eof: bool,
+    pub parts: Vec<Part>,
+    pub init: Option<InitSegment>,
 }
 
 impl Segment {
-    pub fn new(
-        duration: i64,
-        discontinuity: bool,
-        name: String,
-        path: String,
-        is_eof: bool,
-    ) -> Self {
+    pub fn new(duration: i64, discontinuity: bool, name: String, is_eof: bool) -> Self {
         Self {
             duration,
             discontinuity,
             name,
-            path,
             is_eof,
+            parts: Vec::new(),
+            init: None,
         }
     }
 }
@@ -45,7 +65,8 @@ pub struct M3u8 {
 
     segments: VecDeque<Segment>,
 
-    m3u8_folder: String,
+    //app_name/stream_name
+    playlist_key: String,
     live_m3u8_name: String,
 
     ts_handler: Ts,
@@ -53,18 +74,36 @@ pub struct M3u8 {
     need_record: bool,
     vod_m3u8_content: String,
     vod_m3u8_name: String,
+    //the init segment of the last segment in the vod playlist
+    vod_init_name: Option<String>,
+
+    //the init segment of the segment which is being written
+    init: Option<InitSegment>,
+
+    low_latency: Option<LowLatencyHls>,
+    //the partial segments of the segment which is being written
+    parts: Vec<Part>,
 }
 
 impl M3u8 {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         duration: i64,
         live_ts_count: usize,
         app_name: String,
         stream_name: String,
         need_record: bool,
+        segment_format: SegmentFormat,
+        low_latency: Option<LowLatencyHls>,
+        store: SegmentStore,
     ) -> Self {
-        let m3u8_folder = format!("./{app_name}/{stream_name}");
-        fs::create_dir_all(m3u8_folder.clone()).unwrap();
+        //the files are only written to the disk for recording
+        let storage: Box<dyn HlsStorage> = if need_record {
+            Box::new(DiskStorage::new(&app_name, &stream_name))
+        } else {
+            Box::new(MemoryStorage::new(store, &app_name, &stream_name))
+        };
+        let playlist_key = format!("{app_name}/{stream_name}");
 
         let live_m3u8_name = format!("{stream_name}.m3u8");
         let vod_m3u8_name = if need_record {
@@ -74,18 +113,27 @@ impl M3u8 {
         };
 
         let mut m3u8 = Self {
-            version: 3,
+            //EXT-X-SKIP needs version 9, EXT-X-MAP of the media segments needs 6
+            version: match (&low_latency, segment_format) {
+                (Some(_), _) => 9,
+                (None, SegmentFormat::Fmp4) => 7,
+                (None, SegmentFormat::Ts) => 3,
+            },
             sequence_no: 0,
             duration,
             live_ts_count,
             segments: VecDeque::new(),
-            m3u8_folder,
+            playlist_key,
             live_m3u8_name,
-            ts_handler: Ts::new(app_name, stream_name),
+            ts_handler: Ts::new(storage, segment_format),
             // record,
             need_record,
             vod_m3u8_content: String::default(),
             vod_m3u8_name,
+            vod_init_name: None,
+            init: None,
+            low_latency,
+            parts: Vec::new(),
         };
 
         if need_record {
@@ -105,15 +153,28 @@ impl M3u8 {
 
         if segment_count >= self.live_ts_count {
             let segment = self.segments.pop_front().unwrap();
+            for part in segment.parts {
+                self.ts_handler.delete(&part.name);
+            }
             if !self.need_record {
-                self.ts_handler.delete(segment.path);
+                self.ts_handler.delete(&segment.name);
+                //the init segment is deleted with the last segment which uses it
+                let next_init = self
+                    .segments
+                    .front()
+                    .map_or(self.init.as_ref(), |next| next.init.as_ref());
+                if let Some(init) = segment.init.filter(|init| Some(init) != next_init) {
+                    self.ts_handler.delete(&init.name);
+                }
             }
 
             self.sequence_no += 1;
         }
         self.duration = std::cmp::max(duration, self.duration);
-        let (ts_name, ts_path) = self.ts_handler.write(ts_data)?;
-        let segment = Segment::new(duration, discontinuity, ts_name, ts_path, is_eof);
+        let ts_name = self.ts_handler.write(ts_data)?;
+        let mut segment = Segment::new(duration, discontinuity, ts_name, is_eof);
+        segment.parts = std::mem::take(&mut self.parts);
+        segment.init = self.init.clone();
 
         if self.need_record {
             self.update_vod_m3u8(&segment);
@@ -124,21 +185,70 @@ impl M3u8 {
         Ok(())
     }
 
+    //Writes a partial segment of the segment which is being written, the segment
+    //itself is added with all its data when it is complete.
+    pub fn add_part(
+        &mut self,
+        duration: i64,
+        independent: bool,
+        ts_data: BytesMut,
+    ) -> Result<(), MediaError> {
+        let name = self
+            .ts_handler
+            .write_part(self.parts.len() as u64, ts_data)?;
+        self.parts.push(Part {
+            duration,
+            independent,
+            name,
+        });
+
+        Ok(())
+    }
+
+    //Writes the init segment of the segment which is being written and the next ones.
+    pub fn set_init_segment(&mut self, data: BytesMut) -> Result<(), MediaError> {
+        let name = self.ts_handler.write_init(data)?;
+        self.init = Some(InitSegment { name });
+
+        Ok(())
+    }
+
     pub fn clear(&mut self) -> Result<(), MediaError> {
+        if let Some(low_latency) = &self.low_latency {
+            low_latency.playlists.remove(&self.playlist_key);
+        }
+        for part in &self.parts {
+            self.ts_handler.delete(&part.name);
+        }
+        for segment in &self.segments {
+            for part in &segment.parts {
+                self.ts_handler.delete(&part.name);
+            }
+        }
+
         if self.need_record {
-            let vod_m3u8_path = format!("{}/{}", self.m3u8_folder, self.vod_m3u8_name);
-            let mut file_handler = File::create(vod_m3u8_path).unwrap();
             self.vod_m3u8_content += "#EXT-X-ENDLIST\n";
-            file_handler.write_all(self.vod_m3u8_content.as_bytes())?;
+            self.ts_handler
+                .write_playlist(&self.vod_m3u8_name, &self.vod_m3u8_content)?;
         } else {
             for segment in &self.segments {
-                self.ts_handler.delete(segment.path.clone());
+                self.ts_handler.delete(&segment.name);
+            }
+            let mut init_names: Vec<&String> = self
+                .segments
+                .iter()
+                .filter_map(|segment| segment.init.as_ref())
+                .chain(self.init.as_ref())
+                .map(|init| &init.name)
+                .collect();
+            init_names.dedup();
+            for init_name in init_names {
+                self.ts_handler.delete(init_name);
             }
         }
 
         //clear live m3u8
-        let live_m3u8_path = format!("{}/{}", self.m3u8_folder, self.live_m3u8_name);
-        fs::remove_file(live_m3u8_path)?;
+        self.ts_handler.delete(&self.live_m3u8_name);
 
         Ok(())
     }
@@ -148,6 +258,17 @@ impl M3u8 {
         m3u8_header += format!("#EXT-X-VERSION:{}\n", self.version).as_str();
         m3u8_header += format!("#EXT-X-TARGETDURATION:{}\n", (self.duration + 999) / 1000).as_str();
 
+        if let (false, Some(low_latency)) = (is_vod, &self.low_latency) {
+            let part_target = low_latency.part_duration as f64 / 1000.0;
+            m3u8_header += format!(
+                "#EXT-X-SERVER-CONTROL:CAN-BLOCK-RELOAD=YES,PART-HOLD-BACK={:.3},CAN-SKIP-UNTIL={:.1}\n",
+                part_target * 3.0,
+                self.skip_until() as f64 / 1000.0
+            )
+            .as_str();
+            m3u8_header += format!("#EXT-X-PART-INF:PART-TARGET={part_target:.3}\n").as_str();
+        }
+
         if is_vod {
             m3u8_header += "#EXT-X-MEDIA-SEQUENCE:0\n";
             m3u8_header += "#EXT-X-PLAYLIST-TYPE:VOD\n";
@@ -160,12 +281,43 @@ impl M3u8 {
     }
 
     pub fn refresh_playlist(&mut self) -> Result<String, MediaError> {
+        let m3u8_content = self.generate_playlist(0);
+
+        self.ts_handler
+            .write_playlist(&self.live_m3u8_name, &m3u8_content)?;
+
+        if let Some(low_latency) = &self.low_latency {
+            let skip_count = self.skip_count();
+            let snapshot = PlaylistSnapshot {
+                target_duration_ms: self.target_duration_ms(),
+                next_msn: self.next_msn(),
+                next_part: self.parts.len() as u64,
+                playlist: m3u8_content.clone(),
+                delta_playlist: (skip_count > 0).then(|| self.generate_playlist(skip_count)),
+            };
+            low_latency.playlists.publish(&self.playlist_key, snapshot);
+        }
+
+        Ok(m3u8_content)
+    }
+
+    //the live playlist with the first skip_count segments replaced by EXT-X-SKIP
+    fn generate_playlist(&self, skip_count: usize) -> String {
         let mut m3u8_content = self.generate_m3u8_header(false);
+        if skip_count > 0 {
+            m3u8_content += format!("#EXT-X-SKIP:SKIPPED-SEGMENTS={skip_count}\n").as_str();
+        }
 
-        for segment in &self.segments {
+        let part_list_start = self.part_list_start();
+        let mut last_init = None;
+        for (index, segment) in self.segments.iter().enumerate().skip(skip_count) {
             if segment.discontinuity {
                 m3u8_content += "#EXT-X-DISCONTINUITY\n";
             }
+            Self::append_map(&mut m3u8_content, &mut last_init, segment.init.as_ref());
+            if index >= part_list_start {
+                Self::append_parts(&mut m3u8_content, &segment.parts);
+            }
             m3u8_content += format!(
                 "#EXTINF:{:.3}\n{}\n",
                 segment.duration as f64 / 1000.0,
@@ -175,22 +327,101 @@ impl M3u8 {
 
             if segment.is_eof {
                 m3u8_content += "#EXT-X-ENDLIST\n";
-                break;
+                return m3u8_content;
             }
         }
 
-        let m3u8_path = format!("{}/{}", self.m3u8_folder, self.live_m3u8_name);
+        if self.low_latency.is_some() {
+            Self::append_map(&mut m3u8_content, &mut last_init, self.init.as_ref());
+            Self::append_parts(&mut m3u8_content, &self.parts);
+            m3u8_content += format!(
+                "#EXT-X-PRELOAD-HINT:TYPE=PART,URI=\"{}\"\n",
+                self.ts_handler
+                    .part_file_name(self.next_msn(), self.parts.len() as u64)
+            )
+            .as_str();
+        }
 
-        let mut file_handler = File::create(m3u8_path).unwrap();
-        file_handler.write_all(m3u8_content.as_bytes())?;
+        m3u8_content
+    }
 
-        Ok(m3u8_content)
+    //EXT-X-MAP is written when the init segment changes
+    fn append_map<'a>(
+        m3u8_content: &mut String,
+        last_init: &mut Option<&'a InitSegment>,
+        init: Option<&'a InitSegment>,
+    ) {
+        if let Some(init) = init {
+            if *last_init != Some(init) {
+                *m3u8_content += format!("#EXT-X-MAP:URI=\"{}\"\n", init.name).as_str();
+                *last_init = Some(init);
+            }
+        }
+    }
+
+    fn append_parts(m3u8_content: &mut String, parts: &[Part]) {
+        for part in parts {
+            *m3u8_content += format!(
+                "#EXT-X-PART:DURATION={:.3},URI=\"{}\"{}\n",
+                part.duration as f64 / 1000.0,
+                part.name,
+                if part.independent {
+                    ",INDEPENDENT=YES"
+                } else {
+                    ""
+                }
+            )
+            .as_str();
+        }
+    }
+
+    //the media sequence number of the segment which is being written
+    fn next_msn(&self) -> u64 {
+        self.sequence_no + self.segments.len() as u64
+    }
+
+    fn target_duration_ms(&self) -> i64 {
+        (self.duration + 999) / 1000 * 1000
+    }
+
+    fn skip_until(&self) -> i64 {
+        self.target_duration_ms() * SKIP_UNTIL_TARGET_DURATIONS
+    }
+
+    //the count of the segments from the end of the playlist which last for duration
+    fn last_segments_for(&self, duration: i64) -> usize {
+        let mut total = 0;
+        let mut count = 0;
+        for segment in self.segments.iter().rev() {
+            if total >= duration {
+                break;
+            }
+            total += segment.duration;
+            count += 1;
+        }
+        count
+    }
+
+    fn part_list_start(&self) -> usize {
+        self.segments.len()
+            - self.last_segments_for(self.target_duration_ms() * PART_LIST_TARGET_DURATIONS)
+    }
+
+    //the segments which may be skipped by a delta update
+    fn skip_count(&self) -> usize {
+        self.segments.len() - self.last_segments_for(self.skip_until())
     }
 
     pub fn update_vod_m3u8(&mut self, segment: &Segment) {
         if segment.discontinuity {
             self.vod_m3u8_content += "#EXT-X-DISCONTINUITY\n";
         }
+        if let Some(init) = &segment.init {
+            if self.vod_init_name.as_ref() != Some(&init.name) {
+                self.vod_m3u8_content += format!("#EXT-X-MAP:URI=\"{}\"\n", init.name).as_str();
+                self.vod_init_name = Some(init.name.clone());
+            }
+        }
         self.vod_m3u8_content += format!(
             "#EXTINF:{:.3}\n{}\n",
             segment.duration as f64 / 1000.0,
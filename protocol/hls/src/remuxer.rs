@@ -1,5 +1,8 @@
 use {
-    super::{errors::HlsError, flv_data_receiver::FlvDataReceiver},
+    super::{
+        define::SegmentFormat, errors::HlsError, flv_data_receiver::FlvDataReceiver,
+        ll_hls::LowLatencyHls, storage::SegmentStore,
+    },
     streamhub::{
         define::{BroadcastEvent, BroadcastEventReceiver, StreamHubEventSender},
         stream::StreamIdentifier,
@@ -10,6 +13,10 @@ pub struct HlsRemuxer {
     client_event_consumer: BroadcastEventReceiver,
     event_producer: StreamHubEventSender,
     need_record: bool,
+    segment_format: SegmentFormat,
+    low_latency: Option<LowLatencyHls>,
+    //the files of the live streams which are not recorded
+    store: SegmentStore,
 }
 
 impl HlsRemuxer {
@@ -17,11 +24,17 @@ impl HlsRemuxer {
         consumer: BroadcastEventReceiver,
         event_producer: StreamHubEventSender,
         need_record: bool,
+        segment_format: SegmentFormat,
+        low_latency: Option<LowLatencyHls>,
+        store: SegmentStore,
     ) -> Self {
         Self {
             client_event_consumer: consumer,
             event_producer,
             need_record,
+            segment_format,
+            low_latency,
+            store,
         }
     }
 
@@ -29,7 +42,10 @@ impl HlsRemuxer {
         loop {
             let val = self.client_event_consumer.recv().await?;
             match val {
-                BroadcastEvent::Publish { identifier } => {
+                BroadcastEvent::Publish {
+                    identifier,
+                    timestamp_normalized,
+                } => {
                     if let StreamIdentifier::Rtmp {
                         app_name,
                         stream_name,
@@ -41,6 +57,10 @@ impl HlsRemuxer {
                             self.event_producer.clone(),
                             5,
                             self.need_record,
+                            self.segment_format,
+                            self.low_latency.clone(),
+                            self.store.clone(),
+                            timestamp_normalized,
                         );
 
                         tokio::spawn(async move {
@@ -1,4 +1,8 @@
 use {
+    super::{
+        ll_hls::{parse_part_name, LivePlaylists, PlaylistRequest, PlaylistSnapshot},
+        storage::SegmentStore,
+    },
     axum::{
         body::Body,
         extract::{Request, State},
@@ -7,8 +11,8 @@ use {
         response::Response,
     },
     commonlib::auth::{Auth, SecretCarrier},
-    std::net::SocketAddr,
-    tokio::{fs::File, net::TcpListener},
+    std::{net::SocketAddr, sync::Arc, time::Duration},
+    tokio::{fs::File, net::TcpListener, sync::watch, time::timeout},
     tokio_util::codec::{BytesCodec, FramedRead},
 };
 
@@ -18,20 +22,37 @@ type Result<T> = std::result::Result<T, GenericError>;
 static NOTFOUND: &[u8] = b"Not Found";
 static UNAUTHORIZED: &[u8] = b"Unauthorized";
 
+#[derive(Clone)]
+struct HlsState {
+    auth: Option<Auth>,
+    //the live playlists of LL-HLS, served from memory
+    playlists: LivePlaylists,
+    //the files of the live streams which are not recorded, the recorded ones are on disk
+    store: SegmentStore,
+}
+
 #[derive(Debug)]
 enum HlsFileType {
     Playlist,
     Segment,
+    //the fMP4 media segments
+    Fmp4Segment,
+    //the fMP4 init segments(EXT-X-MAP)
+    InitSegment,
 }
 
 impl HlsFileType {
     const CONTENT_TYPE_PLAYLIST: &'static str = "application/vnd.apple.mpegurl";
     const CONTENT_TYPE_SEGMENT: &'static str = "video/mp2t";
+    const CONTENT_TYPE_FMP4_SEGMENT: &'static str = "video/iso.segment";
+    const CONTENT_TYPE_INIT_SEGMENT: &'static str = "video/mp4";
 
     fn content_type(&self) -> &str {
         match self {
             Self::Playlist => Self::CONTENT_TYPE_PLAYLIST,
             Self::Segment => Self::CONTENT_TYPE_SEGMENT,
+            Self::Fmp4Segment => Self::CONTENT_TYPE_FMP4_SEGMENT,
+            Self::InitSegment => Self::CONTENT_TYPE_INIT_SEGMENT,
         }
     }
 }
@@ -47,6 +68,8 @@ struct HlsPath {
 impl HlsPath {
     const M3U8_EXT: &'static str = "m3u8";
     const TS_EXT: &'static str = "ts";
+    const M4S_EXT: &'static str = "m4s";
+    const MP4_EXT: &'static str = "mp4";
 
     fn parse(path: &str) -> Option<Self> {
         if path.is_empty() || path.contains("..") {
@@ -69,6 +92,8 @@ impl HlsPath {
         let file_type = match ext {
             Self::M3U8_EXT => HlsFileType::Playlist,
             Self::TS_EXT => HlsFileType::Segment,
+            Self::M4S_EXT => HlsFileType::Fmp4Segment,
+            Self::MP4_EXT => HlsFileType::InitSegment,
             _ => return None,
         };
 
@@ -80,14 +105,23 @@ impl HlsPath {
         })
     }
 
-    fn to_file_path(&self) -> String {
+    //the file name with its extension
+    fn full_file_name(&self) -> String {
         let ext = match self.file_type {
             HlsFileType::Playlist => Self::M3U8_EXT,
             HlsFileType::Segment => Self::TS_EXT,
+            HlsFileType::Fmp4Segment => Self::M4S_EXT,
+            HlsFileType::InitSegment => Self::MP4_EXT,
         };
+        format!("{}.{}", self.file_name, ext)
+    }
+
+    fn to_file_path(&self) -> String {
         format!(
-            "./{}/{}/{}.{}",
-            self.app_name, self.stream_name, self.file_name, ext
+            "./{}/{}/{}",
+            self.app_name,
+            self.stream_name,
+            self.full_file_name()
         )
     }
 }
@@ -106,6 +140,67 @@ fn response_not_found() -> Response<Body> {
         .unwrap()
 }
 
+fn response_status(status: StatusCode) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(status.canonical_reason().unwrap_or_default().into())
+        .unwrap()
+}
+
+//Holds the request until the playlist contains the media segment msn, or its partial
+//segment part, for at most 3 target durations.
+async fn block_until_ready(
+    receiver: &mut watch::Receiver<Arc<PlaylistSnapshot>>,
+    msn: u64,
+    part: Option<u64>,
+) -> std::result::Result<(), StatusCode> {
+    let hold = {
+        let snapshot = receiver.borrow();
+        //a segment too far in the future will not be ready in time
+        if msn > snapshot.next_msn + 2 {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+        Duration::from_millis((snapshot.target_duration_ms * 3) as u64)
+    };
+
+    let ready = async {
+        receiver
+            .wait_for(|snapshot| snapshot.is_ready(msn, part))
+            .await
+            .map(|_| ())
+    };
+    match timeout(hold, ready).await {
+        Ok(Ok(())) => Ok(()),
+        //the stream is unpublished
+        Ok(Err(_)) => Err(StatusCode::NOT_FOUND),
+        Err(_) => Err(StatusCode::SERVICE_UNAVAILABLE),
+    }
+}
+
+async fn response_live_playlist(
+    mut receiver: watch::Receiver<Arc<PlaylistSnapshot>>,
+    query_string: Option<&str>,
+) -> Response<Body> {
+    let Some(request) = PlaylistRequest::parse(query_string) else {
+        return response_status(StatusCode::BAD_REQUEST);
+    };
+    if let Some(msn) = request.msn {
+        if let Err(status) = block_until_ready(&mut receiver, msn, request.part).await {
+            return response_status(status);
+        }
+    }
+
+    let snapshot = Arc::clone(&receiver.borrow());
+    let playlist = match (&snapshot.delta_playlist, request.skip) {
+        (Some(delta_playlist), true) => delta_playlist,
+        _ => &snapshot.playlist,
+    };
+    Response::builder()
+        .header("Content-Type", HlsFileType::CONTENT_TYPE_PLAYLIST)
+        .body(playlist.clone().into())
+        .unwrap()
+}
+
 async fn response_file(hls_path: &HlsPath) -> Response<Body> {
     let file_path = hls_path.to_file_path();
 
@@ -120,7 +215,7 @@ async fn response_file(hls_path: &HlsPath) -> Response<Body> {
     response_not_found()
 }
 
-async fn handle_connection(State(auth): State<Option<Auth>>, req: Request<Body>) -> Response<Body> {
+async fn handle_connection(State(state): State<HlsState>, req: Request<Body>) -> Response<Body> {
     let path = req.uri().path();
     let query_string = req.uri().query().map(|s| s.to_string());
 
@@ -129,11 +224,11 @@ async fn handle_connection(State(auth): State<Option<Auth>>, req: Request<Body>)
         None => return response_not_found(),
     };
 
-    if let (Some(auth_val), HlsFileType::Playlist) = (auth.as_ref(), &hls_path.file_type) {
+    if let (Some(auth_val), HlsFileType::Playlist) = (state.auth.as_ref(), &hls_path.file_type) {
         if auth_val
             .authenticate(
                 &hls_path.stream_name,
-                &query_string.map(SecretCarrier::Query),
+                &query_string.clone().map(SecretCarrier::Query),
                 true,
             )
             .is_err()
@@ -142,10 +237,40 @@ async fn handle_connection(State(auth): State<Option<Auth>>, req: Request<Body>)
         }
     }
 
+    let playlist_key = format!("{}/{}", hls_path.app_name, hls_path.stream_name);
+    if let Some(mut receiver) = state.playlists.subscribe(&playlist_key) {
+        match hls_path.file_type {
+            HlsFileType::Playlist if hls_path.file_name == hls_path.stream_name => {
+                return response_live_playlist(receiver, query_string.as_deref()).await;
+            }
+            HlsFileType::Segment | HlsFileType::Fmp4Segment => {
+                //a partial segment may be asked for by its preload hint before it is ready
+                if let Some((msn, part)) = parse_part_name(&hls_path.file_name) {
+                    if let Err(status) = block_until_ready(&mut receiver, msn, Some(part)).await {
+                        return response_status(status);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if let Some(data) = state.store.read(&playlist_key, &hls_path.full_file_name()) {
+        return Response::builder()
+            .header("Content-Type", hls_path.file_type.content_type())
+            .body(Body::from(data))
+            .unwrap();
+    }
+
     response_file(&hls_path).await
 }
 
-pub async fn run(port: usize, auth: Option<Auth>) -> Result<()> {
+pub async fn run(
+    port: usize,
+    auth: Option<Auth>,
+    playlists: LivePlaylists,
+    store: SegmentStore,
+) -> Result<()> {
     let listen_address = format!("0.0.0.0:{port}");
     let sock_addr: SocketAddr = listen_address.parse().unwrap();
 
@@ -153,7 +278,11 @@ pub async fn run(port: usize, auth: Option<Auth>) -> Result<()> {
 
     log::info!("Hls server listening on http://{}", sock_addr);
 
-    let handle_connection = handle_connection.with_state(auth);
+    let handle_connection = handle_connection.with_state(HlsState {
+        auth,
+        playlists,
+        store,
+    });
 
     axum::serve(listener, handle_connection.into_make_service()).await?;
 
@@ -187,11 +316,22 @@ mod tests {
         assert_eq!(segment.to_file_path(), "./live/stream/123.ts");
         assert_eq!(segment.file_type.content_type(), "video/mp2t");
 
+        // fMP4 segment and init segment
+        let segment = HlsPath::parse("/live/stream/123.4.m4s").unwrap();
+        assert_eq!(segment.file_name, "123.4");
+        assert!(matches!(segment.file_type, HlsFileType::Fmp4Segment));
+        assert_eq!(segment.to_file_path(), "./live/stream/123.4.m4s");
+        assert_eq!(segment.file_type.content_type(), "video/iso.segment");
+        let init = HlsPath::parse("/live/stream/init0.mp4").unwrap();
+        assert!(matches!(init.file_type, HlsFileType::InitSegment));
+        assert_eq!(init.to_file_path(), "./live/stream/init0.mp4");
+        assert_eq!(init.file_type.content_type(), "video/mp4");
+
         // Negative
         assert!(HlsPath::parse("").is_none());
         assert!(HlsPath::parse("/invalid").is_none());
         assert!(HlsPath::parse("/too/many/parts/of/path.m3u8").is_none());
-        assert!(HlsPath::parse("/live/stream/invalid.mp4").is_none());
+        assert!(HlsPath::parse("/live/stream/invalid.flv").is_none());
         assert!(HlsPath::parse("/live/stream/../../etc/passwd").is_none());
         assert!(HlsPath::parse("/live/stream/...").is_none());
         assert!(HlsPath::parse("/live/stream.m3u8").is_none());
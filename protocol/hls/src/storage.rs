@@ -0,0 +1,181 @@
+use {
+    super::errors::MediaError,
+    bytes::Bytes,
+    std::{
+        collections::{HashMap, VecDeque},
+        fs,
+        fs::File,
+        io::Write,
+        sync::{Arc, Mutex},
+    },
+};
+
+//the bytes kept in memory for a stream, the oldest files are dropped beyond it
+pub const DEFAULT_STREAM_MAX_BYTES: usize = 256 * 1024 * 1024;
+
+//Where the playlists and segments of a stream are written.
+pub trait HlsStorage: Send {
+    //writes the file, or replaces it if it exists
+    fn write(&mut self, file_name: &str, data: Bytes) -> Result<(), MediaError>;
+    fn delete(&mut self, file_name: &str);
+}
+
+//The files are written to ./{app_name}/{stream_name}, it is used for recording.
+pub struct DiskStorage {
+    folder: String,
+}
+
+impl DiskStorage {
+    pub fn new(app_name: &str, stream_name: &str) -> Self {
+        let folder = format!("./{app_name}/{stream_name}");
+        fs::create_dir_all(folder.clone()).unwrap();
+        Self { folder }
+    }
+}
+
+impl HlsStorage for DiskStorage {
+    fn write(&mut self, file_name: &str, data: Bytes) -> Result<(), MediaError> {
+        let mut file_handler = File::create(format!("{}/{}", self.folder, file_name))?;
+        file_handler.write_all(&data[..])?;
+        Ok(())
+    }
+
+    fn delete(&mut self, file_name: &str) {
+        let file_path = format!("{}/{}", self.folder, file_name);
+        if let Err(err) = fs::remove_file(&file_path) {
+            log::error!("hls: delete {} error: {}", file_path, err);
+        }
+    }
+}
+
+//The files of a stream in write order.
+#[derive(Default)]
+struct StreamFiles {
+    files: VecDeque<(String, Bytes)>,
+    size: usize,
+}
+
+impl StreamFiles {
+    fn remove(&mut self, file_name: &str) {
+        if let Some(index) = self.files.iter().position(|(name, _)| name == file_name) {
+            let (_, data) = self.files.remove(index).unwrap();
+            self.size -= data.len();
+        }
+    }
+}
+
+//The in-memory files of the live streams, keyed by "app_name/stream_name". The hls
+//server serves them directly.
+#[derive(Clone)]
+pub struct SegmentStore {
+    streams: Arc<Mutex<HashMap<String, StreamFiles>>>,
+    stream_max_bytes: usize,
+}
+
+impl Default for SegmentStore {
+    fn default() -> Self {
+        Self::new(DEFAULT_STREAM_MAX_BYTES)
+    }
+}
+
+impl SegmentStore {
+    pub fn new(stream_max_bytes: usize) -> Self {
+        Self {
+            streams: Arc::new(Mutex::new(HashMap::new())),
+            stream_max_bytes,
+        }
+    }
+
+    pub fn read(&self, key: &str, file_name: &str) -> Option<Bytes> {
+        let streams = self.streams.lock().unwrap();
+        streams
+            .get(key)?
+            .files
+            .iter()
+            .find(|(name, _)| name == file_name)
+            .map(|(_, data)| data.clone())
+    }
+
+    //The files are kept as a ring buffer: a rewritten file moves to the end and the
+    //oldest ones are dropped when the stream holds more than stream_max_bytes.
+    fn write(&self, key: &str, file_name: &str, data: Bytes) {
+        let mut streams = self.streams.lock().unwrap();
+        let stream = streams.entry(key.to_string()).or_default();
+        stream.remove(file_name);
+        stream.size += data.len();
+        stream.files.push_back((file_name.to_string(), data));
+
+        while stream.size > self.stream_max_bytes && stream.files.len() > 1 {
+            let (name, data) = stream.files.pop_front().unwrap();
+            stream.size -= data.len();
+            log::warn!("hls: {} of {} is dropped from memory", name, key);
+        }
+    }
+
+    fn delete(&self, key: &str, file_name: &str) {
+        let mut streams = self.streams.lock().unwrap();
+        if let Some(stream) = streams.get_mut(key) {
+            stream.remove(file_name);
+            if stream.files.is_empty() {
+                streams.remove(key);
+            }
+        }
+    }
+}
+
+//The files of a stream kept in a SegmentStore.
+pub struct MemoryStorage {
+    store: SegmentStore,
+    key: String,
+}
+
+impl MemoryStorage {
+    pub fn new(store: SegmentStore, app_name: &str, stream_name: &str) -> Self {
+        Self {
+            store,
+            key: format!("{app_name}/{stream_name}"),
+        }
+    }
+}
+
+impl HlsStorage for MemoryStorage {
+    fn write(&mut self, file_name: &str, data: Bytes) -> Result<(), MediaError> {
+        self.store.write(&self.key, file_name, data);
+        Ok(())
+    }
+
+    fn delete(&mut self, file_name: &str) {
+        self.store.delete(&self.key, file_name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HlsStorage, MemoryStorage, SegmentStore};
+    use bytes::Bytes;
+
+    #[test]
+    fn test_memory_storage() {
+        let store = SegmentStore::new(10);
+        let mut storage = MemoryStorage::new(store.clone(), "live", "test");
+
+        storage
+            .write("test.m3u8", Bytes::from_static(b"abc"))
+            .unwrap();
+        storage.write("0.ts", Bytes::from_static(b"0000")).unwrap();
+        assert_eq!(store.read("live/test", "0.ts").unwrap(), &b"0000"[..]);
+
+        //the rewritten playlist is the newest file, the oldest segment is dropped
+        storage
+            .write("test.m3u8", Bytes::from_static(b"abcd"))
+            .unwrap();
+        storage.write("1.ts", Bytes::from_static(b"1111")).unwrap();
+        assert!(store.read("live/test", "0.ts").is_none());
+        assert_eq!(store.read("live/test", "test.m3u8").unwrap(), &b"abcd"[..]);
+
+        storage.delete("test.m3u8");
+        storage.delete("1.ts");
+        assert!(store.read("live/test", "1.ts").is_none());
+        assert!(store.streams.lock().unwrap().is_empty());
+    }
+}
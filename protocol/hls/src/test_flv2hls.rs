@@ -1,7 +1,9 @@
 #[cfg(test)]
 mod tests {
+    use crate::define::SegmentFormat;
     use crate::errors::MediaError;
     use crate::flv2hls::Flv2HlsRemuxer;
+    use crate::storage::SegmentStore;
     use bytes::BytesMut;
     use xflv::define::FlvData;
 
@@ -64,8 +66,15 @@ mod tests {
         demuxer.read_flv_header()?;
 
         let start = Instant::now();
-        let mut media_demuxer =
-            Flv2HlsRemuxer::new(5, String::from("live"), String::from("test"), false);
+        let mut media_demuxer = Flv2HlsRemuxer::new(
+            5,
+            String::from("live"),
+            String::from("test"),
+            false,
+            SegmentFormat::Ts,
+            None,
+            SegmentStore::default(),
+        );
 
         loop {
             let data_ = demuxer.read_flv_tag();
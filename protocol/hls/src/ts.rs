@@ -1,35 +1,63 @@
 use {
-    super::errors::MediaError,
+    super::{define::SegmentFormat, errors::MediaError, ll_hls, storage::HlsStorage},
     bytes::BytesMut,
-    std::{fs, fs::File, io::Write},
 };
 
 pub struct Ts {
     ts_number: u32,
-    live_path: String,
+    init_number: u32,
+    storage: Box<dyn HlsStorage>,
+    segment_format: SegmentFormat,
 }
 
 impl Ts {
-    pub fn new(app_name: String, stream_name: String) -> Self {
-        let live_path = format!("./{app_name}/{stream_name}");
-        fs::create_dir_all(live_path.clone()).unwrap();
-
+    pub fn new(storage: Box<dyn HlsStorage>, segment_format: SegmentFormat) -> Self {
         Self {
             ts_number: 0,
-            live_path,
+            init_number: 0,
+            storage,
+            segment_format,
         }
     }
-    pub fn write(&mut self, data: BytesMut) -> Result<(String, String), MediaError> {
-        let ts_file_name = format!("{}.ts", self.ts_number);
-        let ts_file_path = format!("{}/{}", self.live_path, ts_file_name);
+    pub fn write(&mut self, data: BytesMut) -> Result<String, MediaError> {
+        let ts_file_name = format!("{}.{}", self.ts_number, self.segment_format.extension());
         self.ts_number += 1;
 
-        let mut ts_file_handler = File::create(ts_file_path.clone())?;
-        ts_file_handler.write_all(&data[..])?;
+        self.storage.write(&ts_file_name, data.freeze())?;
+
+        Ok(ts_file_name)
+    }
+    //Writes a partial segment of the next segment.
+    pub fn write_part(&mut self, part: u64, data: BytesMut) -> Result<String, MediaError> {
+        let part_file_name = self.part_file_name(self.ts_number as u64, part);
+        self.storage.write(&part_file_name, data.freeze())?;
+
+        Ok(part_file_name)
+    }
+    //Writes the init segment(fMP4) of the next segments.
+    pub fn write_init(&mut self, data: BytesMut) -> Result<String, MediaError> {
+        let init_file_name = format!("init{}.mp4", self.init_number);
+        self.init_number += 1;
+
+        self.storage.write(&init_file_name, data.freeze())?;
 
-        Ok((ts_file_name, ts_file_path))
+        Ok(init_file_name)
+    }
+    //Writes a playlist, or replaces it.
+    pub fn write_playlist(&mut self, name: &str, content: &str) -> Result<(), MediaError> {
+        self.storage
+            .write(name, content.to_string().into_bytes().into())
     }
-    pub fn delete(&mut self, ts_file_name: String) {
-        fs::remove_file(ts_file_name).unwrap();
+
+    pub fn part_file_name(&self, msn: u64, part: u64) -> String {
+        format!(
+            "{}.{}",
+            ll_hls::part_name(msn, part),
+            self.segment_format.extension()
+        )
+    }
+
+    pub fn delete(&mut self, file_name: &str) {
+        self.storage.delete(file_name);
     }
 }
@@ -0,0 +1,126 @@
+use {
+    bytes::Bytes,
+    streamhub::define::{FrameData, TimedMetadata},
+    xflv::define::{aac_packet_type, avc_packet_type, frame_type, AvcCodecId, SoundFormat},
+};
+
+//Caches the serialized FLV tags which a late joiner needs before the live tags:
+//the metadata, the sequence headers and the tags of the current GOP.
+#[derive(Default)]
+pub struct FlvTagCache {
+    metadata: Option<Bytes>,
+    video_seq: Option<Bytes>,
+    audio_seq: Option<Bytes>,
+    //starts with a video key frame tag.
+    gop: Vec<Bytes>,
+}
+
+impl FlvTagCache {
+    pub fn save(&mut self, frame: &FrameData, tag: &Bytes) {
+        match frame {
+            //the timed data messages are only sent live.
+            FrameData::MetaData {
+                timestamp: _,
+                data: _,
+            } if TimedMetadata::from_frame(frame)
+                .is_some_and(|metadata| metadata.is_stream_metadata()) =>
+            {
+                self.metadata = Some(tag.clone());
+            }
+            FrameData::Video { timestamp: _, data } => {
+                if data.len() < 2 {
+                    return;
+                }
+                let codec_id = data[0] & 0x0F;
+                let is_avc =
+                    codec_id == AvcCodecId::H264 as u8 || codec_id == AvcCodecId::HEVC as u8;
+                if is_avc && data[1] == avc_packet_type::AVC_SEQHDR {
+                    self.video_seq = Some(tag.clone());
+                    return;
+                }
+                if (data[0] >> 4) == frame_type::KEY_FRAME {
+                    self.gop.clear();
+                    self.gop.push(tag.clone());
+                } else if !self.gop.is_empty() {
+                    self.gop.push(tag.clone());
+                }
+            }
+            FrameData::Audio { timestamp: _, data } => {
+                if data.len() >= 2
+                    && (data[0] >> 4) == SoundFormat::AAC as u8
+                    && data[1] == aac_packet_type::AAC_SEQHDR
+                {
+                    self.audio_seq = Some(tag.clone());
+                } else if !self.gop.is_empty() {
+                    self.gop.push(tag.clone());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub fn prefix(&self) -> Vec<Bytes> {
+        let mut tags: Vec<Bytes> = Vec::new();
+        for tag in [&self.metadata, &self.video_seq, &self.audio_seq]
+            .iter()
+            .copied()
+            .flatten()
+        {
+            tags.push(tag.clone());
+        }
+        tags.extend(self.gop.iter().cloned());
+        tags
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FlvTagCache;
+    use bytes::Bytes;
+    use streamhub::define::FrameData;
+
+    fn video(first_byte: u8, packet_type: u8) -> FrameData {
+        FrameData::Video {
+            timestamp: 0,
+            data: Bytes::copy_from_slice(&[first_byte, packet_type]),
+        }
+    }
+
+    fn save(cache: &mut FlvTagCache, frame: FrameData, tag: &'static [u8]) {
+        cache.save(&frame, &Bytes::from_static(tag));
+    }
+
+    #[test]
+    fn test_prefix_starts_from_last_key_frame() {
+        let mut cache = FlvTagCache::default();
+
+        save(&mut cache, video(0x27, 0x01), b"inter0");
+        save(&mut cache, video(0x17, 0x00), b"vseq");
+        save(
+            &mut cache,
+            FrameData::Audio {
+                timestamp: 0,
+                data: Bytes::from_static(&[0xaf, 0x00]),
+            },
+            b"aseq",
+        );
+        save(&mut cache, video(0x17, 0x01), b"key1");
+        save(&mut cache, video(0x27, 0x01), b"inter1");
+        save(&mut cache, video(0x17, 0x01), b"key2");
+        save(
+            &mut cache,
+            FrameData::Audio {
+                timestamp: 0,
+                data: Bytes::from_static(&[0xaf, 0x01]),
+            },
+            b"audio",
+        );
+
+        let prefix = cache.prefix();
+        let tags: Vec<&[u8]> = prefix.iter().map(|tag| &tag[..]).collect();
+        assert_eq!(
+            tags,
+            vec![&b"vseq"[..], &b"aseq"[..], &b"key2"[..], &b"audio"[..]]
+        );
+    }
+}
@@ -1,11 +1,133 @@
 use {
-    futures::channel::mpsc::{UnboundedReceiver, UnboundedSender},
-    {bytes::BytesMut, std::io},
+    bytes::Bytes,
+    std::net::SocketAddr,
+    streamhub::{
+        define::{SubscribeType, SubscriberQueuePolicy},
+        subscriber::{SubscriberSender, TQueueData},
+        utils::{RandomDigitCount, Uuid},
+    },
+    tokio::sync::mpsc,
+    xflv::{define::frame_type, muxer::HEADER_LENGTH},
 };
 pub mod tag_type {
     pub const AUDIO: u8 = 8;
     pub const VIDEO: u8 = 9;
     pub const SCRIPT_DATA_AMF: u8 = 18;
 }
-pub type HttpResponseDataProducer = UnboundedSender<io::Result<BytesMut>>;
-pub type HttpResponseDataConsumer = UnboundedReceiver<io::Result<BytesMut>>;
+
+//A piece of the http-flv response: the FLV header or a FLV tag with its previous tag size.
+pub struct FlvTag(pub Bytes);
+
+//The tags are classified by the tag type and the frame type of the video tag body,
+//the FLV header is neither audio nor video and is never dropped selectively.
+impl TQueueData for FlvTag {
+    fn is_inter_frame(&self) -> bool {
+        self.is_video()
+            && self.0.len() > HEADER_LENGTH as usize
+            && (self.0[HEADER_LENGTH as usize] >> 4) == frame_type::INTER_FRAME
+    }
+
+    fn is_key_frame(&self) -> bool {
+        self.is_video() && !self.is_inter_frame()
+    }
+
+    fn data_size(&self) -> usize {
+        self.0.len()
+    }
+
+    fn is_audio(&self) -> bool {
+        self.0.first() == Some(&tag_type::AUDIO)
+    }
+
+    fn is_video(&self) -> bool {
+        self.0.first() == Some(&tag_type::VIDEO)
+    }
+}
+
+pub type HttpResponseDataProducer = mpsc::Sender<FlvTag>;
+pub type HttpResponseDataConsumer = mpsc::Receiver<FlvTag>;
+
+//A http-flv client waiting for the tags of a stream, it is registered in the stream hub
+//with its own id. Its bounded queue follows the subscriber queue policy of the stream hub.
+pub struct FlvViewer {
+    pub id: Uuid,
+    pub remote_addr: SocketAddr,
+    pub request_url: String,
+    pub response_producer: SubscriberSender<FlvTag>,
+}
+
+impl FlvViewer {
+    pub fn new(
+        remote_addr: SocketAddr,
+        request_url: String,
+        response_producer: HttpResponseDataProducer,
+        policy: &SubscriberQueuePolicy,
+    ) -> Self {
+        let id = Uuid::new(RandomDigitCount::Four);
+        Self {
+            id,
+            remote_addr,
+            request_url,
+            response_producer: SubscriberSender::new(
+                id,
+                SubscribeType::RtmpRemux2HttpFlv,
+                response_producer,
+                policy,
+            ),
+        }
+    }
+}
+
+pub type FlvViewerSender = mpsc::UnboundedSender<FlvViewer>;
+pub type FlvViewerReceiver = mpsc::UnboundedReceiver<FlvViewer>;
+
+#[cfg(test)]
+mod tests {
+    use super::{tag_type, FlvTag, FlvViewer};
+    use bytes::Bytes;
+    use streamhub::{define::SubscriberQueuePolicy, subscriber::SendResult};
+    use tokio::sync::mpsc;
+
+    fn video_tag(frame_type_and_codec: u8) -> FlvTag {
+        let mut tag = vec![tag_type::VIDEO];
+        tag.extend_from_slice(&[0; 10]);
+        tag.extend_from_slice(&[frame_type_and_codec, 0x01]);
+        FlvTag(Bytes::from(tag))
+    }
+
+    #[test]
+    fn test_viewer_drops_tags_until_key_frame() {
+        let policy = SubscriberQueuePolicy {
+            queue_size: 2,
+            max_lag_ms: 0,
+        };
+        let (sender, mut receiver) = mpsc::channel(policy.queue_size);
+        let (statistic_sender, mut statistic_receiver) = mpsc::unbounded_channel();
+        let mut viewer = FlvViewer::new(
+            "127.0.0.1:8080".parse().unwrap(),
+            String::from("/live/test.flv"),
+            sender,
+            &policy,
+        );
+        let mut send = |tag| viewer.response_producer.send(tag, &statistic_sender);
+
+        assert!(matches!(
+            send(FlvTag(Bytes::from_static(b"FLV"))),
+            SendResult::Sent
+        ));
+        assert!(matches!(send(video_tag(0x17)), SendResult::Sent));
+        assert!(matches!(send(video_tag(0x27)), SendResult::Dropped));
+
+        receiver.try_recv().unwrap();
+        receiver.try_recv().unwrap();
+        //the inter tags are dropped until the next key frame even if the queue has room.
+        assert!(matches!(send(video_tag(0x27)), SendResult::Dropped));
+        assert!(matches!(send(video_tag(0x17)), SendResult::Sent));
+
+        let mut drop_count = 0;
+        while statistic_receiver.try_recv().is_ok() {
+            drop_count += 1;
+        }
+        assert_eq!(drop_count, 2);
+    }
+}
@@ -1,15 +1,17 @@
 use streamhub::define::{StatisticData, StatisticDataSender};
+use streamhub::subscriber::SendResult;
 use tokio::sync::oneshot;
 use {
     super::{
-        define::{tag_type, HttpResponseDataProducer},
+        cache::FlvTagCache,
+        define::{tag_type, FlvTag, FlvViewer, FlvViewerReceiver},
         errors::{HttpFLvError, HttpFLvErrorValue},
     },
-    bytes::BytesMut,
+    bytes::Bytes,
     std::net::SocketAddr,
     streamhub::define::{
-        FrameData, FrameDataReceiver, NotifyInfo, StreamHubEvent, StreamHubEventSender,
-        SubDataType, SubscribeType, SubscriberInfo,
+        FrameData, NotifyInfo, StreamHubEvent, StreamHubEventSender, SubDataType,
+        SubFrameDataReceiver, SubscribeType, SubscriberInfo, TrackFilter,
     },
     streamhub::{
         stream::StreamIdentifier,
@@ -20,22 +22,33 @@ use {
     xflv::muxer::{FlvMuxer, HEADER_LENGTH},
 };
 
+//The shared muxing stage of one stream: it subscribes the stream from the hub once,
+//serializes every frame into a FLV tag once and sends the same buffers to all the
+//http-flv viewers of the stream.
 pub struct HttpFlv {
     app_name: String,
     stream_name: String,
 
     muxer: FlvMuxer,
+    cache: FlvTagCache,
+    //the FLV header and the first previous tag size, built once the av types are known.
+    header: Option<Bytes>,
 
     has_audio: bool,
     has_video: bool,
-    has_send_header: bool,
+    //the tracks selected by the query of the url, the header flags follow it.
+    track_filter: TrackFilter,
+
+    viewers: Vec<FlvViewer>,
+    viewer_receiver: FlvViewerReceiver,
 
     event_producer: StreamHubEventSender,
-    data_receiver: FrameDataReceiver,
+    data_receiver: SubFrameDataReceiver,
     /* now used for subscriber session */
-    statistic_data_sender: Option<StatisticDataSender>,
-    http_response_data_producer: HttpResponseDataProducer,
+    statistic_data_sender: StatisticDataSender,
+    //the id of the stage's own subscription, the viewers join the stream with their ids.
     subscriber_id: Uuid,
+    //the url and address of the viewer which starts the stage
     request_url: String,
     remote_addr: SocketAddr,
 }
@@ -45,24 +58,28 @@ impl HttpFlv {
         app_name: String,
         stream_name: String,
         event_producer: StreamHubEventSender,
-        http_response_data_producer: HttpResponseDataProducer,
+        viewer_receiver: FlvViewerReceiver,
         request_url: String,
         remote_addr: SocketAddr,
     ) -> Self {
-        let (_, data_receiver) = mpsc::unbounded_channel();
+        let (_, data_receiver) = mpsc::channel(1);
+        let (statistic_data_sender, _) = mpsc::unbounded_channel();
         let subscriber_id = Uuid::new(RandomDigitCount::Four);
 
         Self {
             app_name,
             stream_name,
             muxer: FlvMuxer::new(),
+            cache: FlvTagCache::default(),
+            header: None,
             has_audio: false,
             has_video: false,
-            has_send_header: false,
+            track_filter: TrackFilter::from_request_url(&request_url),
+            viewers: Vec::new(),
+            viewer_receiver,
             data_receiver,
-            statistic_data_sender: None,
+            statistic_data_sender,
             event_producer,
-            http_response_data_producer,
             subscriber_id,
             request_url,
             remote_addr,
@@ -71,130 +88,250 @@ impl HttpFlv {
 
     pub async fn run(&mut self) -> Result<(), HttpFLvError> {
         self.subscribe_from_stream_hub().await?;
-        self.send_media_stream().await?;
+        let rv = self.send_media_stream().await;
 
-        Ok(())
+        for viewer in std::mem::take(&mut self.viewers) {
+            self.leave(&viewer);
+        }
+        self.unsubscribe_from_stream_hub().await?;
+
+        rv
     }
 
     pub async fn send_media_stream(&mut self) -> Result<(), HttpFLvError> {
-        let mut retry_count = 0;
-
         let mut max_av_frame_num_to_guess_av = 0;
-        // the first av frames are sequence configs, must be cached;
-        let mut cached_frames = Vec::new();
-        //write flv body
+        //once closed, the new viewers of this stream go to a new muxing stage.
+        let mut viewer_receiver_closed = false;
+
         loop {
-            if let Some(data) = self.data_receiver.recv().await {
-                if !self.has_send_header {
-                    max_av_frame_num_to_guess_av += 1;
-
-                    match data {
-                        FrameData::Audio {
-                            timestamp: _,
-                            data: _,
-                        } => {
-                            self.has_audio = true;
-                            cached_frames.push(data);
-                        }
-                        FrameData::Video {
-                            timestamp: _,
-                            data: _,
-                        } => {
-                            self.has_video = true;
-                            cached_frames.push(data);
+            tokio::select! {
+                viewer = self.viewer_receiver.recv(), if !viewer_receiver_closed => {
+                    if let Some(viewer) = viewer {
+                        self.add_viewer(viewer);
+                    }
+                }
+                data = self.data_receiver.recv() => {
+                    let Some(data) = data else {
+                        log::info!("httpflv: the stream is finished");
+                        break;
+                    };
+                    //flv has no discontinuity, the viewers just continue.
+                    if let FrameData::Discontinuity { .. } = data {
+                        continue;
+                    }
+
+                    if self.header.is_none() {
+                        max_av_frame_num_to_guess_av += 1;
+                        match data {
+                            FrameData::Audio { .. } => self.has_audio = true,
+                            FrameData::Video { .. } => self.has_video = true,
+                            _ => {}
                         }
-                        FrameData::MetaData {
-                            timestamp: _,
-                            data: _,
-                        } => cached_frames.push(data),
-                        _ => {}
                     }
 
-                    if (self.has_audio && self.has_video) || max_av_frame_num_to_guess_av > 10 {
-                        self.has_send_header = true;
-                        self.muxer
-                            .write_flv_header(self.has_audio, self.has_video)?;
-                        self.muxer.write_previous_tag_size(0)?;
+                    let tag = self.write_flv_tag(&data)?;
+                    self.cache.save(&data, &tag);
 
-                        self.flush_response_data()?;
+                    if self.header.is_some() {
+                        self.send_to_viewers(&data, &tag);
+                    } else if self.has_selected_tracks() || max_av_frame_num_to_guess_av > 10 {
+                        self.write_flv_header()?;
+                    }
 
-                        for frame in &cached_frames {
-                            self.write_flv_tag(frame.clone())?;
+                    if self.viewers.is_empty() && !viewer_receiver_closed {
+                        //stop accepting viewers, but serve those already queued.
+                        self.viewer_receiver.close();
+                        viewer_receiver_closed = true;
+                        while let Ok(viewer) = self.viewer_receiver.try_recv() {
+                            self.add_viewer(viewer);
                         }
-                        cached_frames.clear();
                     }
 
-                    continue;
-                }
-
-                if let Err(err) = self.write_flv_tag(data) {
-                    if let HttpFLvErrorValue::MpscSendError(err_in) = &err.value {
-                        if err_in.is_disconnected() {
-                            log::info!("write_flv_tag: {}", err_in);
-                            break;
-                        }
+                    if self.viewers.is_empty() {
+                        log::info!("httpflv: all the viewers are gone");
+                        break;
                     }
-                    log::error!("write_flv_tag err: {}", err);
-                    retry_count += 1;
-                } else {
-                    retry_count = 0;
                 }
-            } else {
-                retry_count += 1;
-            }
-            if retry_count > 10 {
-                break;
             }
         }
-        self.unsubscribe_from_stream_hub().await
+        Ok(())
     }
 
-    //used for the http-flv protocol
+    //all the tracks the viewers receive have come
+    fn has_selected_tracks(&self) -> bool {
+        (self.has_audio || !self.track_filter.has_audio())
+            && (self.has_video || !self.track_filter.has_video())
+    }
 
-    pub fn write_flv_tag(&mut self, channel_data: FrameData) -> Result<(), HttpFLvError> {
-        let (common_data, common_timestamp, tag_type) = match channel_data {
-            FrameData::Audio { timestamp, data } => {
-                if let Some(sender) = &self.statistic_data_sender {
-                    let statistic_audio_data = StatisticData::Audio {
-                        uuid: Some(self.subscriber_id),
-                        aac_packet_type: 1,
-                        data_size: data.len(),
-                        duration: 0,
-                    };
-                    if let Err(err) = sender.send(statistic_audio_data) {
-                        log::error!("send statistic data err: {}", err);
-                    }
+    fn add_viewer(&mut self, mut viewer: FlvViewer) {
+        log::info!(
+            "httpflv: new viewer {} of stream {}/{}",
+            viewer.remote_addr,
+            self.app_name,
+            self.stream_name
+        );
+        self.join(&viewer);
+        //the viewers coming before the header is built are served once it is built.
+        if let Some(header) = &self.header {
+            if !Self::send_prefix(
+                header,
+                &self.cache,
+                &mut viewer,
+                &self.statistic_data_sender,
+            ) {
+                self.leave(&viewer);
+                return;
+            }
+        }
+        self.viewers.push(viewer);
+    }
+
+    //the viewers which are gone or lag behind too long leave the stream.
+    fn send_to_viewers(&mut self, data: &FrameData, tag: &Bytes) {
+        for mut viewer in std::mem::take(&mut self.viewers) {
+            match viewer
+                .response_producer
+                .send(FlvTag(tag.clone()), &self.statistic_data_sender)
+            {
+                SendResult::Sent => self.statistic_send(&viewer, data),
+                SendResult::Dropped | SendResult::Skipped => {}
+                SendResult::Disconnect => {
+                    self.leave(&viewer);
+                    continue;
                 }
+            }
+            self.viewers.push(viewer);
+        }
+    }
+
+    fn identifier(&self) -> StreamIdentifier {
+        StreamIdentifier::Rtmp {
+            app_name: self.app_name.clone(),
+            stream_name: self.stream_name.clone(),
+        }
+    }
+
+    fn viewer_info(&self, viewer: &FlvViewer) -> SubscriberInfo {
+        SubscriberInfo {
+            id: viewer.id,
+            sub_type: SubscribeType::RtmpRemux2HttpFlv,
+            sub_data_type: SubDataType::Frame,
+            track_filter: self.track_filter,
+            notify_info: NotifyInfo {
+                request_url: viewer.request_url.clone(),
+                remote_addr: viewer.remote_addr.to_string(),
+            },
+        }
+    }
+
+    //The viewers share the data of the stage's subscription, but each of them is counted
+    //and notified in the stream hub.
+    fn join(&self, viewer: &FlvViewer) {
+        let event = StreamHubEvent::ViewerJoin {
+            identifier: self.identifier(),
+            info: self.viewer_info(viewer),
+        };
+        if let Err(err) = self.event_producer.send(event) {
+            log::error!("httpflv: viewer join err {}", err);
+        }
+    }
+
+    fn leave(&self, viewer: &FlvViewer) {
+        let event = StreamHubEvent::ViewerLeave {
+            identifier: self.identifier(),
+            info: self.viewer_info(viewer),
+        };
+        if let Err(err) = self.event_producer.send(event) {
+            log::error!("httpflv: viewer leave err {}", err);
+        }
+    }
+
+    fn statistic_send(&self, viewer: &FlvViewer, data: &FrameData) {
+        let statistic_data = match data {
+            FrameData::Audio { timestamp: _, data } => StatisticData::Audio {
+                uuid: Some(viewer.id),
+                aac_packet_type: 1,
+                data_size: data.len(),
+                duration: 0,
+            },
+            FrameData::Video { timestamp: _, data } => StatisticData::Video {
+                uuid: Some(viewer.id),
+                frame_count: 1,
+                is_key_frame: None,
+                data_size: data.len(),
+                duration: 0,
+            },
+            _ => return,
+        };
+        if let Err(err) = self.statistic_data_sender.send(statistic_data) {
+            log::error!("send statistic data err: {}", err);
+        }
+    }
 
-                (data, timestamp, tag_type::AUDIO)
+    //a late joiner gets the header, the metadata, the sequence headers and the current gop first.
+    //A prefix which does not fit in the queue is truncated and the viewer waits for the next key frame.
+    fn send_prefix(
+        header: &Bytes,
+        cache: &FlvTagCache,
+        viewer: &mut FlvViewer,
+        statistic_sender: &StatisticDataSender,
+    ) -> bool {
+        for data in std::iter::once(header.clone()).chain(cache.prefix()) {
+            if let SendResult::Disconnect = viewer
+                .response_producer
+                .send(FlvTag(data), statistic_sender)
+            {
+                return false;
             }
-            FrameData::Video { timestamp, data } => {
-                if let Some(sender) = &self.statistic_data_sender {
-                    let statistic_video_data = StatisticData::Video {
-                        uuid: Some(self.subscriber_id),
-                        frame_count: 1,
-                        is_key_frame: None,
-                        data_size: data.len(),
-                        duration: 0,
-                    };
-                    if let Err(err) = sender.send(statistic_video_data) {
-                        log::error!("send statistic data err: {}", err);
-                    }
-                }
+        }
+        true
+    }
 
-                (data, timestamp, tag_type::VIDEO)
+    fn write_flv_header(&mut self) -> Result<(), HttpFLvError> {
+        self.muxer
+            .write_flv_header(self.has_audio, self.has_video)?;
+        self.muxer.write_previous_tag_size(0)?;
+        let header = self.muxer.writer.extract_current_bytes().freeze();
+
+        for mut viewer in std::mem::take(&mut self.viewers) {
+            if Self::send_prefix(
+                &header,
+                &self.cache,
+                &mut viewer,
+                &self.statistic_data_sender,
+            ) {
+                self.viewers.push(viewer);
+            } else {
+                self.leave(&viewer);
             }
+        }
+        self.header = Some(header);
+
+        Ok(())
+    }
+
+    //used for the http-flv protocol
+    pub fn write_flv_tag(&mut self, channel_data: &FrameData) -> Result<Bytes, HttpFLvError> {
+        let (common_data, common_timestamp, tag_type) = match channel_data {
+            FrameData::Audio { timestamp, data } => (data.clone(), *timestamp, tag_type::AUDIO),
+            FrameData::Video { timestamp, data } => (data.clone(), *timestamp, tag_type::VIDEO),
             FrameData::MetaData { timestamp, data } => {
-                //remove @setDataFrame from RTMP's metadata
+                //remove @setDataFrame from RTMP's metadata, the other data messages
+                //(onTextData, onCuePoint...) are written as they are.
                 let mut amf_writer: Amf0Writer = Amf0Writer::new();
                 amf_writer.write_string(&String::from("@setDataFrame"))?;
-                let (_, right) = data.split_at(amf_writer.len());
+                let set_data_frame = amf_writer.extract_current_bytes();
 
-                (BytesMut::from(right), timestamp, tag_type::SCRIPT_DATA_AMF)
+                let body = if data.starts_with(&set_data_frame[..]) {
+                    data.slice(set_data_frame.len()..)
+                } else {
+                    data.clone()
+                };
+                (body, *timestamp, tag_type::SCRIPT_DATA_AMF)
             }
             _ => {
                 log::error!("should not be here!!!");
-                (BytesMut::new(), 0, 0)
+                (Bytes::new(), 0, 0)
             }
         };
 
@@ -202,40 +339,27 @@ impl HttpFlv {
 
         self.muxer
             .write_flv_tag_header(tag_type, common_data_len, common_timestamp)?;
-        self.muxer.write_flv_tag_body(common_data)?;
+        self.muxer.write_flv_tag_body(&common_data)?;
         self.muxer
             .write_previous_tag_size(common_data_len + HEADER_LENGTH)?;
 
-        self.flush_response_data()?;
-
-        Ok(())
-    }
-
-    pub fn flush_response_data(&mut self) -> Result<(), HttpFLvError> {
-        let data = self.muxer.writer.extract_current_bytes();
-        self.http_response_data_producer.start_send(Ok(data))?;
-
-        Ok(())
+        Ok(self.muxer.writer.extract_current_bytes().freeze())
     }
 
     pub async fn unsubscribe_from_stream_hub(&mut self) -> Result<(), HttpFLvError> {
         let sub_info = SubscriberInfo {
             id: self.subscriber_id,
-            sub_type: SubscribeType::RtmpRemux2HttpFlv,
+            sub_type: SubscribeType::RtmpRemux2HttpFlvStage,
             sub_data_type: SubDataType::Frame,
+            track_filter: self.track_filter,
             notify_info: NotifyInfo {
                 request_url: self.request_url.clone(),
                 remote_addr: self.remote_addr.to_string(),
             },
         };
 
-        let identifier = StreamIdentifier::Rtmp {
-            app_name: self.app_name.clone(),
-            stream_name: self.stream_name.clone(),
-        };
-
         let subscribe_event = StreamHubEvent::UnSubscribe {
-            identifier,
+            identifier: self.identifier(),
             info: sub_info,
         };
         if let Err(err) = self.event_producer.send(subscribe_event) {
@@ -248,23 +372,19 @@ impl HttpFlv {
     pub async fn subscribe_from_stream_hub(&mut self) -> Result<(), HttpFLvError> {
         let sub_info = SubscriberInfo {
             id: self.subscriber_id,
-            sub_type: SubscribeType::RtmpRemux2HttpFlv,
+            sub_type: SubscribeType::RtmpRemux2HttpFlvStage,
             sub_data_type: SubDataType::Frame,
+            track_filter: self.track_filter,
             notify_info: NotifyInfo {
                 request_url: self.request_url.clone(),
                 remote_addr: self.remote_addr.to_string(),
             },
         };
 
-        let identifier = StreamIdentifier::Rtmp {
-            app_name: self.app_name.clone(),
-            stream_name: self.stream_name.clone(),
-        };
-
         let (event_result_sender, event_result_receiver) = oneshot::channel();
 
         let subscribe_event = StreamHubEvent::Subscribe {
-            identifier,
+            identifier: self.identifier(),
             info: sub_info,
             result_sender: event_result_sender,
         };
@@ -279,18 +399,8 @@ impl HttpFlv {
         let result_receiver = event_result_receiver.await??;
         let receiver = result_receiver.0.frame_receiver.unwrap();
         self.data_receiver = receiver;
-        self.statistic_data_sender = result_receiver.1;
-
-        if let Some(sender) = &self.statistic_data_sender {
-            let statistic_subscriber = StatisticData::Subscriber {
-                id: self.subscriber_id,
-                remote_addr: self.remote_addr.to_string(),
-                start_time: chrono::Local::now(),
-                sub_type: SubscribeType::RtmpRemux2HttpFlv,
-            };
-            if let Err(err) = sender.send(statistic_subscriber) {
-                log::error!("send statistic_subscriber err: {}", err);
-            }
+        if let Some(statistic_data_sender) = result_receiver.1 {
+            self.statistic_data_sender = statistic_data_sender;
         }
 
         Ok(())
@@ -1,5 +1,7 @@
+pub mod cache;
 pub mod define;
 pub mod errors;
 pub mod httpflv;
+pub mod manager;
 pub mod server;
 pub mod server_test;
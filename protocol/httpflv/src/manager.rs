@@ -0,0 +1,72 @@
+use {
+    super::{
+        define::{FlvViewer, FlvViewerSender},
+        httpflv::HttpFlv,
+    },
+    std::{collections::HashMap, net::SocketAddr},
+    streamhub::{
+        define::{StreamHubEventSender, TrackFilter},
+        dvr,
+    },
+    tokio::sync::{mpsc, Mutex},
+};
+
+//Dispatches the http-flv viewers to the shared muxing stage of their stream,
+//a stage is started when the first viewer of a stream comes.
+pub struct HttpFlvManager {
+    event_producer: StreamHubEventSender,
+    //app_name/stream_name/track filter -> the viewer sender of the muxing stage
+    stages: Mutex<HashMap<String, FlvViewerSender>>,
+}
+
+impl HttpFlvManager {
+    pub fn new(event_producer: StreamHubEventSender) -> Self {
+        Self {
+            event_producer,
+            stages: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub async fn add_viewer(&self, app_name: String, stream_name: String, mut viewer: FlvViewer) {
+        let request_url = viewer.request_url.clone();
+        let mut stages = self.stages.lock().await;
+        //the finished stages close their viewer receivers.
+        stages.retain(|_, sender| !sender.is_closed());
+
+        //a time shifted viewer does not share the live stage of the stream.
+        let time_shifted = dvr::time_shift_ms(&request_url).is_some();
+        //the viewers selecting different tracks do not share a stage either.
+        let track_filter = TrackFilter::from_request_url(&request_url);
+        let key = format!("{app_name}/{stream_name}/{track_filter:?}");
+        if let Some(sender) = stages.get(&key).filter(|_| !time_shifted) {
+            match sender.send(viewer) {
+                Ok(()) => return,
+                Err(err) => viewer = err.0,
+            }
+        }
+
+        let (viewer_sender, viewer_receiver) = mpsc::unbounded_channel();
+        let remote_addr: SocketAddr = viewer.remote_addr;
+        if viewer_sender.send(viewer).is_err() {
+            return;
+        }
+
+        let mut stage = HttpFlv::new(
+            app_name,
+            stream_name,
+            self.event_producer.clone(),
+            viewer_receiver,
+            request_url,
+            remote_addr,
+        );
+        tokio::spawn(async move {
+            if let Err(err) = stage.run().await {
+                log::error!("flv handler run error {}", err);
+            }
+        });
+
+        if !time_shifted {
+            stages.insert(key, viewer_sender);
+        }
+    }
+}
@@ -1,5 +1,5 @@
 use {
-    super::httpflv::HttpFlv,
+    super::{define::FlvViewer, manager::HttpFlvManager},
     axum::{
         body::Body,
         extract::{ConnectInfo, Request, State},
@@ -8,10 +8,9 @@ use {
         response::Response,
     },
     commonlib::auth::{Auth, SecretCarrier},
-    futures::channel::mpsc::unbounded,
-    std::net::SocketAddr,
-    streamhub::define::StreamHubEventSender,
-    tokio::net::TcpListener,
+    std::{io, net::SocketAddr, sync::Arc},
+    streamhub::define::{StreamHubEventSender, SubscriberQueuePolicy},
+    tokio::{net::TcpListener, sync::mpsc},
 };
 
 type GenericError = Box<dyn std::error::Error + Send + Sync>;
@@ -20,7 +19,11 @@ static NOTFOUND: &[u8] = b"Not Found";
 static UNAUTHORIZED: &[u8] = b"Unauthorized";
 
 async fn handle_connection(
-    State((event_producer, auth)): State<(StreamHubEventSender, Option<Auth>)>, // event_producer: ChannelEventProducer
+    State((manager, auth, queue_policy)): State<(
+        Arc<HttpFlvManager>,
+        Option<Auth>,
+        SubscriberQueuePolicy,
+    )>,
     ConnectInfo(remote_addr): ConnectInfo<SocketAddr>,
     req: Request<Body>,
 ) -> Response<Body> {
@@ -51,24 +54,22 @@ async fn handle_connection(
                 }
             }
 
-            let (http_response_data_producer, http_response_data_consumer) = unbounded();
+            let (http_response_data_producer, http_response_data_consumer) =
+                mpsc::channel(queue_policy.queue_size);
 
-            let mut flv_hanlder = HttpFlv::new(
-                app_name,
-                stream_name,
-                event_producer,
-                http_response_data_producer,
-                req.uri().to_string(),
+            let viewer = FlvViewer::new(
                 remote_addr,
+                req.uri().to_string(),
+                http_response_data_producer,
+                &queue_policy,
             );
+            manager.add_viewer(app_name, stream_name, viewer).await;
 
-            tokio::spawn(async move {
-                if let Err(err) = flv_hanlder.run().await {
-                    log::error!("flv handler run error {}", err);
-                }
+            let body = futures::stream::unfold(http_response_data_consumer, |mut consumer| async {
+                let tag = consumer.recv().await?;
+                Some((Ok::<_, io::Error>(tag.0), consumer))
             });
-
-            let mut resp = Response::new(Body::from_stream(http_response_data_consumer));
+            let mut resp = Response::new(Body::from_stream(body));
             resp.headers_mut()
                 .insert("Access-Control-Allow-Origin", "*".parse().unwrap());
 
@@ -86,6 +87,7 @@ pub async fn run(
     event_producer: StreamHubEventSender,
     port: usize,
     auth: Option<Auth>,
+    queue_policy: SubscriberQueuePolicy,
 ) -> Result<()> {
     let listen_address = format!("0.0.0.0:{port}");
     let sock_addr: SocketAddr = listen_address.parse().unwrap();
@@ -94,7 +96,8 @@ pub async fn run(
 
     log::info!("Httpflv server listening on http://{}", sock_addr);
 
-    let handle_connection = handle_connection.with_state((event_producer.clone(), auth));
+    let manager = Arc::new(HttpFlvManager::new(event_producer));
+    let handle_connection = handle_connection.with_state((manager, auth, queue_policy));
 
     axum::serve(
         listener,
@@ -1,7 +1,48 @@
-use {std::collections::VecDeque, streamhub::define::FrameData};
+use {
+    std::collections::VecDeque,
+    streamhub::{cache_budget::CacheBudget, define::FrameData},
+};
+
+//How many gops the cache keeps for the new subscribers and how they are sent.
+#[derive(Debug, Clone)]
+pub struct GopCachePolicy {
+    //the max number of gops, 0 disables the cache
+    pub gop_num: usize,
+    //the older gops are evicted once the cached gops span longer than this, the latest
+    //gop is always kept. 0 means no limit.
+    pub max_duration_ms: u32,
+    //the older gops are evicted once the cached gops take more bytes than this, the
+    //cache is emptied if the latest gop alone exceeds it. 0 means no limit.
+    pub max_bytes: usize,
+    //send only the latest gop to a new subscriber and fast-forward it, so that the
+    //player starts near the live edge instead of playing the backlog in real time.
+    pub low_latency_join: bool,
+    //the process wide memory budget the cache is accounted to
+    pub budget: Option<CacheBudget>,
+}
+
+impl GopCachePolicy {
+    pub fn new(gop_num: usize) -> Self {
+        Self {
+            gop_num,
+            max_duration_ms: 0,
+            max_bytes: 0,
+            low_latency_join: false,
+            budget: None,
+        }
+    }
+}
+
+impl Default for GopCachePolicy {
+    fn default() -> Self {
+        Self::new(1)
+    }
+}
+
 #[derive(Clone)]
 pub struct Gop {
     datas: Vec<FrameData>,
+    bytes: usize,
 }
 
 impl Default for Gop {
@@ -12,10 +53,18 @@ impl Default for Gop {
 
 impl Gop {
     pub fn new() -> Self {
-        Self { datas: Vec::new() }
+        Self {
+            datas: Vec::new(),
+            bytes: 0,
+        }
     }
 
     fn save_frame_data(&mut self, data: FrameData) {
+        if let FrameData::Video { timestamp: _, data } | FrameData::Audio { timestamp: _, data } =
+            &data
+        {
+            self.bytes += data.len();
+        }
         self.datas.push(data);
     }
 
@@ -30,12 +79,20 @@ impl Gop {
     pub fn is_empty(&self) -> bool {
         self.len() == 0
     }
+
+    fn first_timestamp(&self) -> Option<u32> {
+        self.datas.first().and_then(|data| data.timestamp())
+    }
+
+    fn last_timestamp(&self) -> Option<u32> {
+        self.datas.last().and_then(|data| data.timestamp())
+    }
 }
 
 #[derive(Clone)]
 pub struct Gops {
     gops: VecDeque<Gop>,
-    size: usize,
+    policy: GopCachePolicy,
 }
 
 impl Default for Gops {
@@ -46,20 +103,25 @@ impl Default for Gops {
 
 impl Gops {
     pub fn new(size: usize) -> Self {
+        Self::with_policy(GopCachePolicy::new(size))
+    }
+
+    pub fn with_policy(policy: GopCachePolicy) -> Self {
         Self {
             gops: VecDeque::from([Gop::new()]),
-            size,
+            policy,
         }
     }
 
     pub fn save_frame_data(&mut self, data: FrameData, is_key_frame: bool) {
-        if self.size == 0 {
+        //the cache is emptied by an oversized gop, wait for the next key frame.
+        if self.policy.gop_num == 0 || (self.gops.is_empty() && !is_key_frame) {
             return;
         }
 
         if is_key_frame {
             //todo It may be possible to optimize here
-            if self.gops.len() == self.size {
+            if self.gops.len() == self.policy.gop_num {
                 self.gops.pop_front();
             }
             self.gops.push_back(Gop::new());
@@ -70,13 +132,121 @@ impl Gops {
         } else {
             log::error!("should not be here!");
         }
+        self.evict();
+    }
+
+    fn evict(&mut self) {
+        let max_bytes = self.policy.max_bytes;
+        if max_bytes > 0 {
+            while self.gops.len() > 1 && self.bytes() > max_bytes {
+                self.gops.pop_front();
+            }
+            //the inter frames are useless without their key frame, drop the whole gop.
+            if self.bytes() > max_bytes {
+                log::warn!("the gop exceeds the cache size {}, drop it", max_bytes);
+                self.gops.clear();
+            }
+        }
+
+        let max_duration = self.policy.max_duration_ms;
+        if max_duration > 0 {
+            while self.gops.len() > 1 && self.duration() > max_duration {
+                self.gops.pop_front();
+            }
+        }
+    }
+
+    pub fn bytes(&self) -> usize {
+        self.gops.iter().map(|gop| gop.bytes).sum()
+    }
+
+    fn duration(&self) -> u32 {
+        let first = self.gops.iter().find_map(|gop| gop.first_timestamp());
+        let last = self.gops.back().and_then(|gop| gop.last_timestamp());
+        match (first, last) {
+            (Some(first), Some(last)) => last.wrapping_sub(first),
+            _ => 0,
+        }
+    }
+
+    //the cache is emptied by the memory budget, it starts again from the next key frame.
+    pub fn clear(&mut self) {
+        self.gops.clear();
     }
 
     pub fn setted(&self) -> bool {
-        self.size != 0
+        self.policy.gop_num != 0
     }
 
     pub fn get_gops(&self) -> VecDeque<Gop> {
         self.gops.clone()
     }
+
+    pub fn low_latency_join(&self) -> bool {
+        self.policy.low_latency_join
+    }
+
+    //The latest gop whose video frames are squeezed right before the last timestamp,
+    //the player decodes them at once and starts from the live edge. The audio frames
+    //of the backlog are dropped.
+    pub fn get_fast_forwarded_gop(&self) -> Vec<FrameData> {
+        let Some(gop) = self.gops.back() else {
+            return Vec::new();
+        };
+        let Some(last_timestamp) = gop.last_timestamp() else {
+            return Vec::new();
+        };
+
+        let mut frames: Vec<FrameData> = gop
+            .datas
+            .iter()
+            .filter(|data| !matches!(data, FrameData::Audio { .. }))
+            .cloned()
+            .collect();
+        let count = frames.len() as u32;
+        for (index, frame) in frames.iter_mut().enumerate() {
+            frame.set_timestamp(last_timestamp.wrapping_sub(count - 1 - index as u32));
+        }
+        frames
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{GopCachePolicy, Gops};
+    use bytes::Bytes;
+    use streamhub::define::FrameData;
+
+    fn video(timestamp: u32) -> FrameData {
+        FrameData::Video {
+            timestamp,
+            data: Bytes::from_static(&[0x27, 0x01, 0x00, 0x00, 0x00]),
+        }
+    }
+
+    #[test]
+    fn test_duration_bounded_cache() {
+        let mut gops = Gops::with_policy(GopCachePolicy {
+            gop_num: 10,
+            max_duration_ms: 2500,
+            max_bytes: 0,
+            low_latency_join: true,
+            budget: None,
+        });
+
+        //a key frame every second
+        for timestamp in (0..5000).step_by(500) {
+            gops.save_frame_data(video(timestamp), timestamp % 1000 == 0);
+        }
+        let cached = gops.get_gops();
+        assert_eq!(cached.len(), 3);
+        assert_eq!(cached.front().unwrap().first_timestamp(), Some(2000));
+
+        let timestamps: Vec<Option<u32>> = gops
+            .get_fast_forwarded_gop()
+            .iter()
+            .map(|frame| frame.timestamp())
+            .collect();
+        assert_eq!(timestamps, vec![Some(4499), Some(4500)]);
+    }
 }
@@ -1,11 +1,11 @@
 use {
-    bytes::BytesMut,
+    bytes::{Bytes, BytesMut},
     bytesio::bytes_reader::BytesReader,
     xflv::amf0::{amf0_reader::Amf0Reader, Amf0ValueType},
 };
 #[derive(Clone)]
 pub struct MetaData {
-    chunk_body: BytesMut,
+    chunk_body: Bytes,
     // values: Vec<Amf0ValueType>,
 }
 
@@ -18,19 +18,22 @@ impl Default for MetaData {
 impl MetaData {
     pub fn new() -> Self {
         Self {
-            chunk_body: BytesMut::new(),
+            chunk_body: Bytes::new(),
             //values: Vec::new(),
         }
     }
     //, values: Vec<Amf0ValueType>
-    pub fn save(&mut self, body: &BytesMut) {
+    //the other data messages(onTextData, onCuePoint...) are not saved, returns if the body is saved.
+    pub fn save(&mut self, body: &Bytes) -> bool {
         if self.is_metadata(body.clone()) {
             self.chunk_body = body.clone();
+            return true;
         }
+        false
     }
 
-    pub fn is_metadata(&mut self, body: BytesMut) -> bool {
-        let reader = BytesReader::new(body);
+    pub fn is_metadata(&mut self, body: Bytes) -> bool {
+        let reader = BytesReader::new(BytesMut::from(&body[..]));
         let result = Amf0Reader::new(reader).read_all();
 
         let mut values: Vec<Amf0ValueType> = Vec::new();
@@ -80,7 +83,7 @@ impl MetaData {
         is_metadata
     }
 
-    pub fn get_chunk_body(&self) -> BytesMut {
+    pub fn get_chunk_body(&self) -> Bytes {
         self.chunk_body.clone()
     }
 }
@@ -3,13 +3,16 @@ pub mod gop;
 pub mod metadata;
 
 use {
-    self::gop::Gops,
-    bytes::BytesMut,
+    self::gop::{GopCachePolicy, Gops},
+    bytes::{Bytes, BytesMut},
     bytesio::bytes_reader::BytesReader,
     errors::CacheError,
     gop::Gop,
     std::collections::VecDeque,
-    streamhub::define::{FrameData, StatisticData, StatisticDataSender},
+    streamhub::{
+        cache_budget::CacheUsage,
+        define::{FrameData, StatisticData, StatisticDataSender},
+    },
     xflv::{
         define,
         flv_tag_header::{AudioTagHeader, VideoTagHeader},
@@ -23,32 +26,40 @@ use {
 pub struct Cache {
     metadata: metadata::MetaData,
     metadata_timestamp: u32,
-    video_seq: BytesMut,
+    video_seq: Bytes,
     video_timestamp: u32,
-    audio_seq: BytesMut,
+    audio_seq: Bytes,
     audio_timestamp: u32,
     gops: Gops,
+    //the usage of the gop cache in the memory budget
+    cache_usage: Option<CacheUsage>,
     statistic_data_sender: Option<StatisticDataSender>,
 }
 
 impl Cache {
-    pub fn new(gop_num: usize, statistic_data_sender: Option<StatisticDataSender>) -> Self {
+    pub fn new(
+        gop_policy: GopCachePolicy,
+        cache_usage: Option<CacheUsage>,
+        statistic_data_sender: Option<StatisticDataSender>,
+    ) -> Self {
         Cache {
             metadata: metadata::MetaData::new(),
             metadata_timestamp: 0,
-            video_seq: BytesMut::new(),
+            video_seq: Bytes::new(),
             video_timestamp: 0,
-            audio_seq: BytesMut::new(),
+            audio_seq: Bytes::new(),
             audio_timestamp: 0,
-            gops: Gops::new(gop_num),
+            gops: Gops::with_policy(gop_policy),
+            cache_usage,
             statistic_data_sender,
         }
     }
 
     //, values: Vec<Amf0ValueType>
-    pub fn save_metadata(&mut self, chunk_body: &BytesMut, timestamp: u32) {
-        self.metadata.save(chunk_body);
-        self.metadata_timestamp = timestamp;
+    pub fn save_metadata(&mut self, chunk_body: &Bytes, timestamp: u32) {
+        if self.metadata.save(chunk_body) {
+            self.metadata_timestamp = timestamp;
+        }
     }
 
     pub fn get_metadata(&self) -> Option<FrameData> {
@@ -65,7 +76,7 @@ impl Cache {
     //save audio gops and sequence header information
     pub async fn save_audio_data(
         &mut self,
-        chunk_body: &BytesMut,
+        chunk_body: &Bytes,
         timestamp: u32,
     ) -> Result<(), CacheError> {
         let channel_data = FrameData::Audio {
@@ -73,8 +84,9 @@ impl Cache {
             data: chunk_body.clone(),
         };
         self.gops.save_frame_data(channel_data, false);
+        self.update_cache_usage();
 
-        let mut reader = BytesReader::new(chunk_body.clone());
+        let mut reader = BytesReader::new(BytesMut::from(&chunk_body[..]));
         let tag_header = AudioTagHeader::unmarshal(&mut reader)?;
 
         let remain_bytes = reader.extract_remaining_bytes();
@@ -120,6 +132,14 @@ impl Cache {
         Ok(())
     }
 
+    fn update_cache_usage(&mut self) {
+        if let Some(cache_usage) = &self.cache_usage {
+            if cache_usage.update(self.gops.bytes()) {
+                self.gops.clear();
+            }
+        }
+    }
+
     pub fn get_audio_seq(&self) -> Option<FrameData> {
         if !self.audio_seq.is_empty() {
             return Some(FrameData::Audio {
@@ -142,7 +162,7 @@ impl Cache {
     //save video gops and sequence header information
     pub async fn save_video_data(
         &mut self,
-        chunk_body: &BytesMut,
+        chunk_body: &Bytes,
         timestamp: u32,
     ) -> Result<(), CacheError> {
         let channel_data = FrameData::Video {
@@ -150,11 +170,12 @@ impl Cache {
             data: chunk_body.clone(),
         };
 
-        let mut reader = BytesReader::new(chunk_body.clone());
+        let mut reader = BytesReader::new(BytesMut::from(&chunk_body[..]));
         let tag_header = VideoTagHeader::unmarshal(&mut reader)?;
 
         let is_key_frame = tag_header.frame_type == define::frame_type::KEY_FRAME;
         self.gops.save_frame_data(channel_data, is_key_frame);
+        self.update_cache_usage();
 
         if is_key_frame && tag_header.avc_packet_type == define::avc_packet_type::AVC_SEQHDR {
             self.video_seq = chunk_body.clone();
@@ -200,4 +221,20 @@ impl Cache {
             None
         }
     }
+
+    //the cached frames sent to a new subscriber, the latest gop is fast-forwarded
+    //if the low latency join is enabled.
+    pub fn get_prior_gops_data(&self) -> Vec<FrameData> {
+        if !self.gops.setted() {
+            return Vec::new();
+        }
+        if self.gops.low_latency_join() {
+            return self.gops.get_fast_forwarded_gop();
+        }
+        self.gops
+            .get_gops()
+            .into_iter()
+            .flat_map(|gop| gop.get_frame_data())
+            .collect()
+    }
 }
@@ -4,6 +4,7 @@ use {
         ChunkMessageHeader, ExtendTimestampType,
     },
     byteorder::{BigEndian, LittleEndian},
+    bytes::Bytes,
     bytesio::{bytes_writer::AsyncBytesWriter, bytesio::TNetIO},
     std::{collections::HashMap, sync::Arc},
     tokio::sync::Mutex,
@@ -167,6 +168,25 @@ impl ChunkPacketizer {
     }
 
     pub async fn write_chunk(&mut self, chunk_info: &mut ChunkInfo) -> Result<(), PackError> {
+        let payload = chunk_info.payload.split().freeze();
+        self.write_payload(chunk_info, &payload).await
+    }
+
+    //Writes a message whose payload is shared by the subscriber sessions of a stream,
+    //the payload of the chunk info is ignored and the data is not copied before it.
+    pub async fn write_shared_chunk(
+        &mut self,
+        chunk_info: &mut ChunkInfo,
+        payload: &Bytes,
+    ) -> Result<(), PackError> {
+        self.write_payload(chunk_info, payload).await
+    }
+
+    async fn write_payload(
+        &mut self,
+        chunk_info: &mut ChunkInfo,
+        payload: &[u8],
+    ) -> Result<(), PackError> {
         self.zip_chunk_header(chunk_info)?;
 
         log::trace!(
@@ -174,8 +194,6 @@ impl ChunkPacketizer {
             chunk_info.message_header.timestamp,
         );
 
-        let mut whole_payload_size = chunk_info.payload.len();
-
         self.write_basic_header(
             chunk_info.basic_header.format,
             chunk_info.basic_header.chunk_stream_id,
@@ -187,18 +205,11 @@ impl ChunkPacketizer {
             self.write_extened_timestamp(extended_timestamp)?;
         }
 
-        let mut cur_payload_size: usize;
-        while whole_payload_size > 0 {
-            cur_payload_size = if whole_payload_size > self.max_chunk_size {
-                self.max_chunk_size
-            } else {
-                whole_payload_size
-            };
-
-            let payload_bytes = chunk_info.payload.split_to(cur_payload_size);
-            self.writer.write(&payload_bytes[0..])?;
+        let mut whole_payload_size = payload.len();
+        for payload_bytes in payload.chunks(self.max_chunk_size) {
+            self.writer.write(payload_bytes)?;
 
-            whole_payload_size -= cur_payload_size;
+            whole_payload_size -= payload_bytes.len();
 
             if whole_payload_size > 0 {
                 self.write_basic_header(3, chunk_info.basic_header.chunk_stream_id)?;
@@ -18,13 +18,21 @@ use self::{errors::RtmpRemuxerError, rtsp2rtmp::Rtsp2RtmpRemuxerSession};
 pub struct RtmpRemuxer {
     receiver: BroadcastEventReceiver,
     event_producer: StreamHubEventSender,
+    //remux a stream only when the stream hub asks for it(its rtmp stream is subscribed),
+    //instead of when it is published.
+    on_demand: bool,
 }
 
 impl RtmpRemuxer {
-    pub fn new(receiver: BroadcastEventReceiver, event_producer: StreamHubEventSender) -> Self {
+    pub fn new(
+        receiver: BroadcastEventReceiver,
+        event_producer: StreamHubEventSender,
+        on_demand: bool,
+    ) -> Self {
         Self {
             receiver,
             event_producer,
+            on_demand,
         }
     }
     pub async fn run(&mut self) -> Result<(), RtmpRemuxerError> {
@@ -34,38 +42,47 @@ impl RtmpRemuxer {
             let val = self.receiver.recv().await?;
             log::info!("{:?}", val);
             match val {
-                BroadcastEvent::Publish { identifier } => match identifier {
-                    StreamIdentifier::Rtsp { stream_path } => {
-                        let mut session =
-                            Rtsp2RtmpRemuxerSession::new(stream_path, self.event_producer.clone());
-                        tokio::spawn(async move {
-                            if let Err(err) = session.run().await {
-                                log::error!("rtsp2rtmp session error: {}", err);
-                            }
-                        });
-                    }
-                    StreamIdentifier::WebRTC {
-                        app_name,
-                        stream_name,
-                    } => {
-                        let mut session = Whip2RtmpRemuxerSession::new(
-                            app_name,
-                            stream_name,
-                            self.event_producer.clone(),
-                        );
-                        tokio::spawn(async move {
-                            if let Err(err) = session.run().await {
-                                log::error!("whip2rtmp session error: {}", err);
-                            }
-                        });
-                    }
-
-                    _ => {}
-                },
+                BroadcastEvent::Publish { identifier, .. } if !self.on_demand => {
+                    self.remux(identifier);
+                }
+                BroadcastEvent::Remux { identifier } => {
+                    self.remux(identifier);
+                }
                 _ => {
                     log::trace!("other infos...");
                 }
             }
         }
     }
+
+    fn remux(&self, identifier: StreamIdentifier) {
+        match identifier {
+            StreamIdentifier::Rtsp { stream_path } => {
+                let mut session =
+                    Rtsp2RtmpRemuxerSession::new(stream_path, self.event_producer.clone());
+                tokio::spawn(async move {
+                    if let Err(err) = session.run().await {
+                        log::error!("rtsp2rtmp session error: {}", err);
+                    }
+                });
+            }
+            StreamIdentifier::WebRTC {
+                app_name,
+                stream_name,
+            } => {
+                let mut session = Whip2RtmpRemuxerSession::new(
+                    app_name,
+                    stream_name,
+                    self.event_producer.clone(),
+                );
+                tokio::spawn(async move {
+                    if let Err(err) = session.run().await {
+                        log::error!("whip2rtmp session error: {}", err);
+                    }
+                });
+            }
+
+            _ => {}
+        }
+    }
 }
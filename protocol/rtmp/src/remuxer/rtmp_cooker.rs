@@ -1,4 +1,4 @@
-use bytes::BytesMut;
+use bytes::{Bytes, BytesMut};
 use bytesio::bytes_writer::BytesWriter;
 use indexmap::IndexMap;
 
@@ -43,7 +43,23 @@ impl RtmpCooker {
             composition_time: 0,
         };
         let tag_header_data = video_tag_header.marshal()?;
+        let mpegavc_data = self.gen_avc_decoder_configuration_record(sps, pps, profile, level)?;
 
+        let mut writer = BytesWriter::new();
+        writer.write(&tag_header_data)?;
+        writer.write(&mpegavc_data)?;
+
+        Ok(writer.extract_current_bytes())
+    }
+
+    //the AVCDecoderConfigurationRecord of the sps and pps
+    pub fn gen_avc_decoder_configuration_record(
+        &self,
+        sps: BytesMut,
+        pps: BytesMut,
+        profile: u8,
+        level: u8,
+    ) -> Result<BytesMut, RtmpRemuxerError> {
         let mut processor = Mpeg4AvcProcessor {
             mpeg4_avc: Mpeg4Avc {
                 profile,
@@ -57,13 +73,7 @@ impl RtmpCooker {
                 ..Default::default()
             },
         };
-        let mpegavc_data = processor.decoder_configuration_record_save()?;
-
-        let mut writer = BytesWriter::new();
-        writer.write(&tag_header_data)?;
-        writer.write(&mpegavc_data)?;
-
-        Ok(writer.extract_current_bytes())
+        Ok(processor.decoder_configuration_record_save()?)
     }
 
     pub fn gen_video_frame_data(
@@ -95,10 +105,7 @@ impl RtmpCooker {
         Ok(writer.extract_current_bytes())
     }
     //generate audio rtmp frame (including seq header and common frame)
-    pub fn gen_audio_frame_data(
-        &self,
-        audio_data: &BytesMut,
-    ) -> Result<BytesMut, RtmpRemuxerError> {
+    pub fn gen_audio_frame_data(&self, audio_data: &Bytes) -> Result<BytesMut, RtmpRemuxerError> {
         let mut aac_packet_type: u8 = 0;
 
         if audio_data.len() > 5 {
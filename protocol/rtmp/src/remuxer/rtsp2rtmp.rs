@@ -1,10 +1,12 @@
-use bytes::BytesMut;
+use bytes::{Bytes, BytesMut};
 use bytesio::bytes_reader::BytesReader;
+use commonlib::media::{CodecConfig, MediaCodec, MediaFrame, PayloadFormat};
 use h264_decoder::sps::SpsParser;
 use streamhub::define::VideoCodecType;
 use tokio::sync::oneshot;
 use xflv::define::h264_nal_type::{H264_NAL_IDR, H264_NAL_PPS, H264_NAL_SPS};
 
+use crate::cache::gop::GopCachePolicy;
 use crate::session::define::SessionType;
 
 use super::{
@@ -17,7 +19,7 @@ use {
     std::time::Duration,
     streamhub::{
         define::{
-            FrameData, FrameDataReceiver, NotifyInfo, StreamHubEvent, StreamHubEventSender,
+            FrameData, NotifyInfo, StreamHubEvent, StreamHubEventSender, SubFrameDataReceiver,
             SubscribeType, SubscriberInfo,
         },
         stream::StreamIdentifier,
@@ -32,7 +34,7 @@ pub struct Rtsp2RtmpRemuxerSession {
     stream_name: String,
 
     //RTSP
-    data_receiver: FrameDataReceiver,
+    data_receiver: SubFrameDataReceiver,
     stream_path: String,
     subscribe_id: Uuid,
     video_clock_rate: u32,
@@ -51,7 +53,7 @@ pub fn find_start_code(nalus: &[u8]) -> Option<usize> {
 
 impl Rtsp2RtmpRemuxerSession {
     pub fn new(stream_path: String, event_producer: StreamHubEventSender) -> Self {
-        let (_, data_consumer) = mpsc::unbounded_channel();
+        let (_, data_consumer) = mpsc::channel(1);
 
         let eles: Vec<&str> = stream_path.splitn(2, '/').collect();
         let (app_name, stream_name) = if eles.len() < 2 {
@@ -92,7 +94,11 @@ impl Rtsp2RtmpRemuxerSession {
 
     pub async fn publish_rtmp(&mut self) -> Result<(), RtmpRemuxerError> {
         self.rtmp_handler
-            .publish_to_stream_hub(self.app_name.clone(), self.stream_name.clone(), 0)
+            .publish_to_stream_hub(
+                self.app_name.clone(),
+                self.stream_name.clone(),
+                GopCachePolicy::new(1),
+            )
             .await?;
         Ok(())
     }
@@ -110,6 +116,7 @@ impl Rtsp2RtmpRemuxerSession {
             id: self.subscribe_id,
             sub_type: SubscribeType::RtspRemux2Rtmp,
             sub_data_type: streamhub::define::SubDataType::Frame,
+            track_filter: streamhub::define::TrackFilter::All,
             notify_info: NotifyInfo {
                 request_url: String::from(""),
                 remote_addr: String::from(""),
@@ -140,6 +147,7 @@ impl Rtsp2RtmpRemuxerSession {
             id: self.subscribe_id,
             sub_type: SubscribeType::RtspRemux2Rtmp,
             sub_data_type: streamhub::define::SubDataType::Frame,
+            track_filter: streamhub::define::TrackFilter::All,
             notify_info: NotifyInfo {
                 request_url: String::from(""),
                 remote_addr: String::from(""),
@@ -168,11 +176,8 @@ impl Rtsp2RtmpRemuxerSession {
                     FrameData::Audio { timestamp, data } => {
                         self.on_rtsp_audio(&data, timestamp).await?
                     }
-                    FrameData::Video {
-                        timestamp,
-                        mut data,
-                    } => {
-                        self.on_rtsp_video(&mut data, timestamp).await?;
+                    FrameData::Video { timestamp, data } => {
+                        self.on_rtsp_video(data, timestamp).await?;
                     }
                     FrameData::MediaInfo { media_info } => {
                         self.video_clock_rate = media_info.video_clock_rate;
@@ -209,32 +214,51 @@ impl Rtsp2RtmpRemuxerSession {
 
     async fn on_rtsp_audio(
         &mut self,
-        audio_data: &BytesMut,
+        audio_data: &Bytes,
         timestamp: u32,
     ) -> Result<(), RtmpRemuxerError> {
         if self.base_audio_timestamp == 0 {
             self.base_audio_timestamp = timestamp;
         }
 
-        let mut audio_frame = self.rtmp_cooker.gen_audio_frame_data(audio_data)?;
-
         let timestamp_adjust =
             (timestamp - self.base_audio_timestamp) / (self.audio_clock_rate / 1000);
-        self.rtmp_handler
-            .on_audio_data(&mut audio_frame, &timestamp_adjust)
-            .await?;
+        //the AudioSpecificConfig is sent before the raw AAC frames
+        let frame = if audio_data.len() <= 5 {
+            FrameData::CodecConfig {
+                config: CodecConfig {
+                    codec: MediaCodec::Aac,
+                    timestamp: timestamp_adjust,
+                    data: audio_data.clone(),
+                },
+            }
+        } else {
+            FrameData::Media {
+                frame: MediaFrame {
+                    codec: MediaCodec::Aac,
+                    pts: timestamp_adjust,
+                    dts: timestamp_adjust,
+                    key_frame: true,
+                    format: PayloadFormat::Raw,
+                    data: audio_data.clone(),
+                },
+            }
+        };
+        self.rtmp_handler.on_frame_data(frame).await?;
 
         Ok(())
     }
 
     async fn on_rtsp_video(
         &mut self,
-        nalus: &mut BytesMut,
+        frame_data: Bytes,
         timestamp: u32,
     ) -> Result<(), RtmpRemuxerError> {
         if self.base_video_timestamp == 0 {
             self.base_video_timestamp = timestamp;
         }
+        //the nalus are split in place, so work on a private copy.
+        let nalus = &mut BytesMut::from(&frame_data[..]);
         let mut nalu_vec = Vec::new();
         while !nalus.is_empty() {
             if let Some(first_pos) = find_start_code(&nalus[..]) {
@@ -291,26 +315,34 @@ impl Rtsp2RtmpRemuxerSession {
             }
         }
 
-        if sps.is_some() && pps.is_some() {
+        if let (Some(sps), Some(pps)) = (sps, pps) {
             let mut meta_data = self.rtmp_cooker.gen_meta_data(width, height)?;
             self.rtmp_handler.on_meta_data(&mut meta_data, &0).await?;
 
-            let mut seq_header = self.rtmp_cooker.gen_video_seq_header(
-                sps.unwrap(),
-                pps.unwrap(),
-                profile,
-                level,
-            )?;
-            self.rtmp_handler.on_video_data(&mut seq_header, &0).await?;
-        } else {
-            let mut frame_data = self
+            let record = self
                 .rtmp_cooker
-                .gen_video_frame_data(nalu_vec, contains_idr)?;
-
+                .gen_avc_decoder_configuration_record(sps, pps, profile, level)?;
+            let config = CodecConfig {
+                codec: MediaCodec::H264,
+                timestamp: 0,
+                data: record.freeze(),
+            };
+            self.rtmp_handler
+                .on_frame_data(FrameData::CodecConfig { config })
+                .await?;
+        } else {
             let timestamp_adjust =
                 (timestamp - self.base_video_timestamp) / (self.video_clock_rate / 1000);
+            let frame = MediaFrame {
+                codec: MediaCodec::H264,
+                pts: timestamp_adjust,
+                dts: timestamp_adjust,
+                key_frame: contains_idr,
+                format: PayloadFormat::AnnexB,
+                data: frame_data,
+            };
             self.rtmp_handler
-                .on_video_data(&mut frame_data, &timestamp_adjust)
+                .on_frame_data(FrameData::Media { frame })
                 .await?;
         }
 
@@ -1,10 +1,12 @@
-use bytes::BytesMut;
+use bytes::{BufMut, Bytes, BytesMut};
 use bytesio::bytes_reader::BytesReader;
+use commonlib::media::{CodecConfig, MediaCodec, MediaFrame, PayloadFormat};
 use h264_decoder::sps::SpsParser;
 use streamhub::define::VideoCodecType;
 use tokio::sync::oneshot;
 use xflv::define::h264_nal_type::{H264_NAL_IDR, H264_NAL_PPS, H264_NAL_SPS};
 
+use crate::cache::gop::GopCachePolicy;
 use crate::session::define::SessionType;
 
 use super::{
@@ -17,7 +19,7 @@ use {
     std::time::Duration,
     streamhub::{
         define::{
-            FrameData, FrameDataReceiver, NotifyInfo, StreamHubEvent, StreamHubEventSender,
+            FrameData, NotifyInfo, StreamHubEvent, StreamHubEventSender, SubFrameDataReceiver,
             SubscribeType, SubscriberInfo,
         },
         stream::StreamIdentifier,
@@ -32,7 +34,7 @@ pub struct Whip2RtmpRemuxerSession {
     stream_name: String,
 
     //WHIP
-    data_receiver: FrameDataReceiver,
+    data_receiver: SubFrameDataReceiver,
 
     subscribe_id: Uuid,
     video_clock_rate: u32,
@@ -74,7 +76,7 @@ impl Whip2RtmpRemuxerSession {
         stream_name: String,
         event_producer: StreamHubEventSender,
     ) -> Self {
-        let (_, data_consumer) = mpsc::unbounded_channel();
+        let (_, data_consumer) = mpsc::channel(1);
 
         Self {
             app_name,
@@ -105,7 +107,11 @@ impl Whip2RtmpRemuxerSession {
 
     pub async fn publish_rtmp(&mut self) -> Result<(), RtmpRemuxerError> {
         self.rtmp_handler
-            .publish_to_stream_hub(self.app_name.clone(), self.stream_name.clone(), 1)
+            .publish_to_stream_hub(
+                self.app_name.clone(),
+                self.stream_name.clone(),
+                GopCachePolicy::new(1),
+            )
             .await?;
         Ok(())
     }
@@ -124,6 +130,7 @@ impl Whip2RtmpRemuxerSession {
             id: self.subscribe_id,
             sub_type: SubscribeType::WebRTCRemux2Rtmp,
             sub_data_type: streamhub::define::SubDataType::Frame,
+            track_filter: streamhub::define::TrackFilter::All,
             notify_info: NotifyInfo {
                 request_url: String::from(""),
                 remote_addr: String::from(""),
@@ -155,6 +162,7 @@ impl Whip2RtmpRemuxerSession {
             id: self.subscribe_id,
             sub_type: SubscribeType::WebRTCRemux2Rtmp,
             sub_data_type: streamhub::define::SubDataType::Frame,
+            track_filter: streamhub::define::TrackFilter::All,
             notify_info: NotifyInfo {
                 request_url: String::from(""),
                 remote_addr: String::from(""),
@@ -184,11 +192,10 @@ impl Whip2RtmpRemuxerSession {
                     FrameData::Audio { timestamp, data } => {
                         self.on_whip_audio(&data, timestamp).await?
                     }
-                    FrameData::Video {
-                        timestamp,
-                        mut data,
-                    } => {
-                        self.on_whip_video(&mut data, timestamp).await?;
+                    FrameData::Video { timestamp, data } => {
+                        //the nalus are split in place, so work on a private copy.
+                        self.on_whip_video(&mut BytesMut::from(&data[..]), timestamp)
+                            .await?;
                     }
                     FrameData::MediaInfo { media_info } => {
                         self.video_clock_rate = media_info.video_clock_rate;
@@ -225,21 +232,37 @@ impl Whip2RtmpRemuxerSession {
 
     async fn on_whip_audio(
         &mut self,
-        audio_data: &BytesMut,
+        audio_data: &Bytes,
         timestamp: u32,
     ) -> Result<(), RtmpRemuxerError> {
         if self.base_audio_timestamp == 0 {
             self.base_audio_timestamp = timestamp;
         }
 
-        let mut audio_frame = self.rtmp_cooker.gen_audio_frame_data(audio_data)?;
-
         let timestamp_adjust =
             (timestamp - self.base_audio_timestamp) / (self.audio_clock_rate / 1000);
-
-        self.rtmp_handler
-            .on_audio_data(&mut audio_frame, &timestamp_adjust)
-            .await?;
+        //the AudioSpecificConfig is sent before the raw AAC frames
+        let frame = if audio_data.len() <= 5 {
+            FrameData::CodecConfig {
+                config: CodecConfig {
+                    codec: MediaCodec::Aac,
+                    timestamp: timestamp_adjust,
+                    data: audio_data.clone(),
+                },
+            }
+        } else {
+            FrameData::Media {
+                frame: MediaFrame {
+                    codec: MediaCodec::Aac,
+                    pts: timestamp_adjust,
+                    dts: timestamp_adjust,
+                    key_frame: true,
+                    format: PayloadFormat::Raw,
+                    data: audio_data.clone(),
+                },
+            }
+        };
+        self.rtmp_handler.on_frame_data(frame).await?;
 
         Ok(())
     }
@@ -316,25 +339,42 @@ impl Whip2RtmpRemuxerSession {
                 let mut meta_data = self.rtmp_cooker.gen_meta_data(width, height)?;
                 self.rtmp_handler.on_meta_data(&mut meta_data, &0).await?;
 
-                let mut seq_header = self.rtmp_cooker.gen_video_seq_header(
+                let record = self.rtmp_cooker.gen_avc_decoder_configuration_record(
                     self.sps.clone().unwrap(),
                     self.pps.clone().unwrap(),
                     profile,
                     level,
                 )?;
-                self.rtmp_handler.on_video_data(&mut seq_header, &0).await?;
+                let config = CodecConfig {
+                    codec: MediaCodec::H264,
+                    timestamp: 0,
+                    data: record.freeze(),
+                };
+                self.rtmp_handler
+                    .on_frame_data(FrameData::CodecConfig { config })
+                    .await?;
                 self.video_seq_header_generated = true;
             }
         } else if !nalu_vec.is_empty() {
-            let mut frame_data = self
-                .rtmp_cooker
-                .gen_video_frame_data(nalu_vec, contains_idr)?;
+            //the sps and pps are left out, they are sent in the codec config
+            let mut data = BytesMut::new();
+            for nalu in &nalu_vec {
+                data.put_u32(nalu.len() as u32);
+                data.put_slice(nalu);
+            }
 
             let timestamp_adjust =
                 (timestamp - self.base_video_timestamp) / (self.video_clock_rate / 1000);
-
+            let frame = MediaFrame {
+                codec: MediaCodec::H264,
+                pts: timestamp_adjust,
+                dts: timestamp_adjust,
+                key_frame: contains_idr,
+                format: PayloadFormat::LengthPrefixed,
+                data: data.freeze(),
+            };
             self.rtmp_handler
-                .on_video_data(&mut frame_data, &timestamp_adjust)
+                .on_frame_data(FrameData::Media { frame })
                 .await?;
         }
 
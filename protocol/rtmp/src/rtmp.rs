@@ -1,5 +1,6 @@
 use streamhub::define::StreamHubEventSender;
 
+use super::cache::gop::GopCachePolicy;
 use super::session::server_session;
 use commonlib::auth::Auth;
 use std::net::SocketAddr;
@@ -9,7 +10,7 @@ use tokio::net::TcpListener;
 pub struct RtmpServer {
     address: String,
     event_producer: StreamHubEventSender,
-    gop_num: usize,
+    gop_policy: GopCachePolicy,
     auth: Option<Auth>,
 }
 
@@ -17,13 +18,13 @@ impl RtmpServer {
     pub fn new(
         address: String,
         event_producer: StreamHubEventSender,
-        gop_num: usize,
+        gop_policy: GopCachePolicy,
         auth: Option<Auth>,
     ) -> Self {
         Self {
             address,
             event_producer,
-            gop_num,
+            gop_policy,
             auth,
         }
     }
@@ -40,7 +41,7 @@ impl RtmpServer {
             let mut session = server_session::ServerSession::new(
                 tcp_stream,
                 self.event_producer.clone(),
-                self.gop_num,
+                self.gop_policy.clone(),
                 self.auth.clone(),
             );
             tokio::spawn(async move {
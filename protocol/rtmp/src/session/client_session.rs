@@ -8,6 +8,7 @@ use {
         errors::{SessionError, SessionErrorValue},
     },
     crate::{
+        cache::gop::GopCachePolicy,
         chunk::{
             define::CHUNK_SIZE,
             unpacketizer::{ChunkUnpacketizer, UnpackResult},
@@ -546,7 +547,7 @@ impl ClientSession {
                         .publish_to_stream_hub(
                             self.app_name.clone(),
                             self.stream_name.clone(),
-                            self.gop_num,
+                            GopCachePolicy::new(self.gop_num),
                         )
                         .await?
                 }
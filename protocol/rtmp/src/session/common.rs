@@ -8,7 +8,7 @@ use {
     },
     crate::{
         cache::errors::CacheError,
-        cache::Cache,
+        cache::{gop::GopCachePolicy, Cache},
         chunk::{
             define::{chunk_type, csid_type},
             packetizer::ChunkPacketizer,
@@ -17,21 +17,21 @@ use {
         messages::define::msg_type_id,
     },
     async_trait::async_trait,
-    bytes::BytesMut,
+    bytes::{Bytes, BytesMut},
     std::fmt,
     std::{net::SocketAddr, sync::Arc},
     streamhub::{
         define::{
-            FrameData, FrameDataReceiver, FrameDataSender, InformationSender, NotifyInfo,
-            PublishType, PublisherInfo, StreamHubEvent, StreamHubEventSender, SubscribeType,
-            SubscriberInfo, TStreamHandler,
+            FrameData, FrameDataSender, InformationSender, NotifyInfo, PublishType, PublisherInfo,
+            StreamHubEvent, StreamHubEventSender, SubFrameDataReceiver, SubscribeType,
+            SubscriberInfo, TStreamHandler, TrackFilter,
         },
         errors::{StreamHubError, StreamHubErrorValue},
         statistics::StatisticsStream,
         stream::StreamIdentifier,
         utils::Uuid,
     },
-    tokio::sync::{mpsc, Mutex},
+    tokio::sync::{mpsc, mpsc::error::TrySendError, Mutex},
 };
 
 pub struct Common {
@@ -42,7 +42,7 @@ pub struct Common {
     //only Server Subscriber or Client Publisher needs to send out trunck data.
     packetizer: Option<ChunkPacketizer>,
 
-    data_receiver: FrameDataReceiver,
+    data_receiver: SubFrameDataReceiver,
     data_sender: FrameDataSender,
 
     event_producer: StreamHubEventSender,
@@ -65,7 +65,8 @@ impl Common {
         remote_addr: Option<SocketAddr>,
     ) -> Self {
         //only used for init,since I don't found a better way to deal with this.
-        let (init_producer, init_consumer) = mpsc::unbounded_channel();
+        let (init_producer, _) = mpsc::unbounded_channel();
+        let (_, init_consumer) = mpsc::channel(1);
 
         Self {
             session_id: Uuid::new(streamhub::utils::RandomDigitCount::Four),
@@ -142,7 +143,7 @@ impl Common {
         }
     }
 
-    pub async fn send_audio(&mut self, data: BytesMut, timestamp: u32) -> Result<(), SessionError> {
+    pub async fn send_audio(&mut self, data: Bytes, timestamp: u32) -> Result<(), SessionError> {
         let mut chunk_info = ChunkInfo::new(
             csid_type::AUDIO,
             chunk_type::TYPE_0,
@@ -150,17 +151,19 @@ impl Common {
             data.len() as u32,
             msg_type_id::AUDIO,
             0,
-            data,
+            BytesMut::new(),
         );
 
         if let Some(packetizer) = &mut self.packetizer {
-            packetizer.write_chunk(&mut chunk_info).await?;
+            packetizer
+                .write_shared_chunk(&mut chunk_info, &data)
+                .await?;
         }
 
         Ok(())
     }
 
-    pub async fn send_video(&mut self, data: BytesMut, timestamp: u32) -> Result<(), SessionError> {
+    pub async fn send_video(&mut self, data: Bytes, timestamp: u32) -> Result<(), SessionError> {
         let mut chunk_info = ChunkInfo::new(
             csid_type::VIDEO,
             chunk_type::TYPE_0,
@@ -168,21 +171,19 @@ impl Common {
             data.len() as u32,
             msg_type_id::VIDEO,
             0,
-            data,
+            BytesMut::new(),
         );
 
         if let Some(packetizer) = &mut self.packetizer {
-            packetizer.write_chunk(&mut chunk_info).await?;
+            packetizer
+                .write_shared_chunk(&mut chunk_info, &data)
+                .await?;
         }
 
         Ok(())
     }
 
-    pub async fn send_metadata(
-        &mut self,
-        data: BytesMut,
-        timestamp: u32,
-    ) -> Result<(), SessionError> {
+    pub async fn send_metadata(&mut self, data: Bytes, timestamp: u32) -> Result<(), SessionError> {
         let mut chunk_info = ChunkInfo::new(
             csid_type::DATA_AMF0_AMF3,
             chunk_type::TYPE_0,
@@ -190,11 +191,13 @@ impl Common {
             data.len() as u32,
             msg_type_id::DATA_AMF0,
             0,
-            data,
+            BytesMut::new(),
         );
 
         if let Some(packetizer) = &mut self.packetizer {
-            packetizer.write_chunk(&mut chunk_info).await?;
+            packetizer
+                .write_shared_chunk(&mut chunk_info, &data)
+                .await?;
         }
 
         Ok(())
@@ -205,6 +208,7 @@ impl Common {
         data: &mut BytesMut,
         timestamp: &u32,
     ) -> Result<(), SessionError> {
+        let data = data.split().freeze();
         let channel_data = FrameData::Video {
             timestamp: *timestamp,
             data: data.clone(),
@@ -221,7 +225,7 @@ impl Common {
         }
 
         self.stream_handler
-            .save_video_data(data, *timestamp)
+            .save_video_data(&data, *timestamp)
             .await?;
 
         Ok(())
@@ -232,6 +236,7 @@ impl Common {
         data: &mut BytesMut,
         timestamp: &u32,
     ) -> Result<(), SessionError> {
+        let data = data.split().freeze();
         let channel_data = FrameData::Audio {
             timestamp: *timestamp,
             data: data.clone(),
@@ -248,17 +253,46 @@ impl Common {
         }
 
         self.stream_handler
-            .save_audio_data(data, *timestamp)
+            .save_audio_data(&data, *timestamp)
             .await?;
 
         Ok(())
     }
 
+    //Publishes a codec neutral frame or codec config, the stream hub converts it to FLV
+    //once for all the subscribers. The FLV tags are kept in the gop cache as well, so
+    //the late subscribers start from the last key frame.
+    pub async fn on_frame_data(&mut self, frame: FrameData) -> Result<(), SessionError> {
+        match frame.clone().into_flv() {
+            Some(FrameData::Video { timestamp, data }) => {
+                self.stream_handler
+                    .save_video_data(&data, timestamp)
+                    .await?
+            }
+            Some(FrameData::Audio { timestamp, data }) => {
+                self.stream_handler
+                    .save_audio_data(&data, timestamp)
+                    .await?
+            }
+            _ => {}
+        }
+
+        if let Err(err) = self.data_sender.send(frame) {
+            log::error!("send frame err: {}", err);
+            return Err(SessionError {
+                value: SessionErrorValue::SendFrameDataErr,
+            });
+        }
+
+        Ok(())
+    }
+
     pub async fn on_meta_data(
         &mut self,
         data: &mut BytesMut,
         timestamp: &u32,
     ) -> Result<(), SessionError> {
+        let data = data.split().freeze();
         let channel_data = FrameData::MetaData {
             timestamp: *timestamp,
             data: data.clone(),
@@ -273,7 +307,7 @@ impl Common {
             }
         }
 
-        self.stream_handler.save_metadata(data, *timestamp).await;
+        self.stream_handler.save_metadata(&data, *timestamp).await;
 
         Ok(())
     }
@@ -296,6 +330,8 @@ impl Common {
             and publish(relay) the rtmp steam to remote RTMP server*/
             sub_type,
             sub_data_type: streamhub::define::SubDataType::Frame,
+            //e.g. rtmp://host/live/stream?only_audio=1
+            track_filter: TrackFilter::from_request_url(&self.request_url),
             notify_info: NotifyInfo {
                 request_url: self.request_url.clone(),
                 remote_addr,
@@ -407,7 +443,7 @@ impl Common {
         &mut self,
         app_name: String,
         stream_name: String,
-        gop_num: usize,
+        gop_policy: GopCachePolicy,
     ) -> Result<(), SessionError> {
         let (event_result_sender, event_result_receiver) = oneshot::channel();
         let info = self.get_publisher_info();
@@ -445,8 +481,14 @@ impl Common {
             }
         }
 
+        let cache_usage = gop_policy.budget.as_ref().map(|budget| {
+            budget.register(StreamIdentifier::Rtmp {
+                app_name,
+                stream_name,
+            })
+        });
         self.stream_handler
-            .set_cache(Cache::new(gop_num, statistic_data_sender))
+            .set_cache(Cache::new(gop_policy, cache_usage, statistic_data_sender))
             .await;
         Ok(())
     }
@@ -515,7 +557,7 @@ impl RtmpStreamHandler {
 
     pub async fn save_video_data(
         &self,
-        chunk_body: &BytesMut,
+        chunk_body: &Bytes,
         timestamp: u32,
     ) -> Result<(), CacheError> {
         if let Some(cache) = &mut *self.cache.lock().await {
@@ -526,7 +568,7 @@ impl RtmpStreamHandler {
 
     pub async fn save_audio_data(
         &self,
-        chunk_body: &BytesMut,
+        chunk_body: &Bytes,
         timestamp: u32,
     ) -> Result<(), CacheError> {
         if let Some(cache) = &mut *self.cache.lock().await {
@@ -535,7 +577,7 @@ impl RtmpStreamHandler {
         Ok(())
     }
 
-    pub async fn save_metadata(&self, chunk_body: &BytesMut, timestamp: u32) {
+    pub async fn save_metadata(&self, chunk_body: &Bytes, timestamp: u32) {
         if let Some(cache) = &mut *self.cache.lock().await {
             cache.save_metadata(chunk_body, timestamp);
         }
@@ -558,40 +600,46 @@ impl TStreamHandler for RtmpStreamHandler {
             }
         };
         if let Some(cache) = &mut *self.cache.lock().await {
+            let mut prior_data = Vec::new();
             if let Some(meta_body_data) = cache.get_metadata() {
                 log::info!("send_prior_data: meta_body_data: ");
-                sender.send(meta_body_data).map_err(|_| StreamHubError {
-                    value: StreamHubErrorValue::SendError,
-                })?;
+                prior_data.push(meta_body_data);
             }
             if let Some(audio_seq_data) = cache.get_audio_seq() {
                 log::info!("send_prior_data: audio_seq_data: ",);
-                sender.send(audio_seq_data).map_err(|_| StreamHubError {
-                    value: StreamHubErrorValue::SendError,
-                })?;
+                prior_data.push(audio_seq_data);
             }
             if let Some(video_seq_data) = cache.get_video_seq() {
                 log::info!("send_prior_data: video_seq_data:");
-                sender.send(video_seq_data).map_err(|_| StreamHubError {
-                    value: StreamHubErrorValue::SendError,
-                })?;
+                prior_data.push(video_seq_data);
             }
             match sub_type {
                 SubscribeType::RtmpPull
                 | SubscribeType::RtmpRemux2HttpFlv
-                | SubscribeType::RtmpRemux2Hls => {
-                    if let Some(gops_data) = cache.get_gops_data() {
-                        for gop in gops_data {
-                            for channel_data in gop.get_frame_data() {
-                                sender.send(channel_data).map_err(|_| StreamHubError {
-                                    value: StreamHubErrorValue::SendError,
-                                })?;
-                            }
-                        }
-                    }
+                | SubscribeType::RtmpRemux2HttpFlvStage
+                | SubscribeType::RtmpRemux2Hls
+                | SubscribeType::RtmpRemux2Dash => {
+                    prior_data.extend(cache.get_prior_gops_data());
                 }
                 _ => {}
             }
+
+            for channel_data in prior_data {
+                match sender.try_send(channel_data) {
+                    Ok(()) => {}
+                    //the subscriber's queue is smaller than the gop cache, the left frames
+                    //are skipped and the stream hub continues from the next key frame.
+                    Err(TrySendError::Full(_)) => {
+                        log::warn!("send_prior_data: the subscriber queue is full");
+                        break;
+                    }
+                    Err(TrySendError::Closed(_)) => {
+                        return Err(StreamHubError {
+                            value: StreamHubErrorValue::SendError,
+                        });
+                    }
+                }
+            }
         }
 
         Ok(())
@@ -10,6 +10,7 @@ use {
         errors::{SessionError, SessionErrorValue},
     },
     crate::{
+        cache::gop::GopCachePolicy,
         chunk::{
             define::CHUNK_SIZE,
             unpacketizer::{ChunkUnpacketizer, UnpackResult},
@@ -31,7 +32,10 @@ use {
     commonlib::auth::Auth,
     indexmap::IndexMap,
     std::{sync::Arc, time::Duration},
-    streamhub::define::StreamHubEventSender,
+    streamhub::{
+        define::StreamHubEventSender,
+        errors::{StreamHubError, StreamHubErrorValue},
+    },
     tokio::{net::TcpStream, sync::Mutex},
     xflv::amf0::Amf0ValueType,
 };
@@ -58,8 +62,8 @@ pub struct ServerSession {
     has_remaing_data: bool,
     connect_properties: ConnectProperties,
     pub common: Common,
-    /*configure how many gops will be cached.*/
-    gop_num: usize,
+    /*configure how many gops will be cached and how they are sent.*/
+    gop_policy: GopCachePolicy,
     auth: Option<Auth>,
 }
 
@@ -67,7 +71,7 @@ impl ServerSession {
     pub fn new(
         stream: TcpStream,
         event_producer: StreamHubEventSender,
-        gop_num: usize,
+        gop_policy: GopCachePolicy,
         auth: Option<Auth>,
     ) -> Self {
         let remote_addr = if let Ok(addr) = stream.peer_addr() {
@@ -98,7 +102,7 @@ impl ServerSession {
             bytesio_data: BytesMut::new(),
             has_remaing_data: false,
             connect_properties: ConnectProperties::default(),
-            gop_num,
+            gop_policy,
             auth,
         }
     }
@@ -575,6 +579,46 @@ impl ServerSession {
             break;
         }
 
+        let raw_stream_name = stream_name.unwrap();
+
+        (self.stream_name, self.query) =
+            RtmpUrlParser::parse_stream_name_with_query(&raw_stream_name);
+        if let Some(auth) = &self.auth {
+            auth.authenticate(
+                &self.stream_name,
+                &self
+                    .query
+                    .as_ref()
+                    .map(|q| SecretCarrier::Query(q.to_string())),
+                true,
+            )?
+        }
+
+        /*Now it can update the request url*/
+        self.common.request_url = self.get_request_url(raw_stream_name);
+        //the hub may hold the subscription until the stream is published or the wait times out.
+        if let Err(err) = self
+            .common
+            .subscribe_from_stream_hub(self.app_name.clone(), self.stream_name.clone())
+            .await
+        {
+            if let SessionErrorValue::ChannelError(StreamHubError {
+                value: StreamHubErrorValue::NoAppOrStreamName,
+            }) = &err.value
+            {
+                let mut netstream = NetStreamWriter::new(Arc::clone(&self.io));
+                netstream
+                    .write_on_status(
+                        transaction_id,
+                        "error",
+                        "NetStream.Play.StreamNotFound",
+                        "stream not found.",
+                    )
+                    .await?;
+            }
+            return Err(err);
+        }
+
         let mut event_messages = EventMessagesWriter::new(AsyncBytesWriter::new(self.io.clone()));
         event_messages.write_stream_begin(*stream_id).await?;
         log::info!(
@@ -623,21 +667,6 @@ impl ServerSession {
 
         event_messages.write_stream_is_record(*stream_id).await?;
 
-        let raw_stream_name = stream_name.unwrap();
-
-        (self.stream_name, self.query) =
-            RtmpUrlParser::parse_stream_name_with_query(&raw_stream_name);
-        if let Some(auth) = &self.auth {
-            auth.authenticate(
-                &self.stream_name,
-                &self
-                    .query
-                    .as_ref()
-                    .map(|q| SecretCarrier::Query(q.to_string())),
-                true,
-            )?
-        }
-
         let query = if let Some(query_val) = &self.query {
             query_val.clone()
         } else {
@@ -651,12 +680,6 @@ impl ServerSession {
             query
         );
 
-        /*Now it can update the request url*/
-        self.common.request_url = self.get_request_url(raw_stream_name);
-        self.common
-            .subscribe_from_stream_hub(self.app_name.clone(), self.stream_name.clone())
-            .await?;
-
         self.state = ServerSessionState::Play;
 
         Ok(())
@@ -767,7 +790,7 @@ impl ServerSession {
             .publish_to_stream_hub(
                 self.app_name.clone(),
                 self.stream_name.clone(),
-                self.gop_num,
+                self.gop_policy.clone(),
             )
             .await?;
 
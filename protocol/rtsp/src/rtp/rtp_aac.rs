@@ -12,7 +12,7 @@ use super::RtpHeader;
 use super::RtpPacket;
 use async_trait::async_trait;
 use byteorder::BigEndian;
-use bytes::{BufMut, BytesMut};
+use bytes::{BufMut, Bytes};
 
 use bytesio::bytes_reader::BytesReader;
 use bytesio::bytesio::TNetIO;
@@ -53,7 +53,7 @@ impl RtpAacPacker {
 }
 #[async_trait]
 impl TPacker for RtpAacPacker {
-    async fn pack(&mut self, data: &mut BytesMut, timestamp: u32) -> Result<(), PackerError> {
+    async fn pack(&mut self, data: Bytes, timestamp: u32) -> Result<(), PackerError> {
         self.header.timestamp = timestamp;
 
         let data_len = data.len();
@@ -148,7 +148,7 @@ impl TUnPacker for RtpAacUnPacker {
             if let Some(f) = &self.on_frame_handler {
                 f(FrameData::Audio {
                     timestamp: rtp_packet.header.timestamp + i as u32 * 1024,
-                    data: au_data,
+                    data: au_data.freeze(),
                 })?;
             }
         }
@@ -14,7 +14,7 @@ use super::RtpHeader;
 use super::RtpPacket;
 use async_trait::async_trait;
 use byteorder::BigEndian;
-use bytes::{BufMut, BytesMut};
+use bytes::{BufMut, Bytes, BytesMut};
 use bytesio::bytes_reader::BytesReader;
 use bytesio::bytesio::TNetIO;
 use std::sync::Arc;
@@ -52,14 +52,14 @@ impl RtpH264Packer {
         }
     }
 
-    pub async fn pack_fu_a(&mut self, nalu: BytesMut) -> Result<(), PackerError> {
-        let mut nalu_reader = BytesReader::new(nalu);
-        let byte_1st = nalu_reader.read_u8()?;
+    pub async fn pack_fu_a(&mut self, nalu: Bytes) -> Result<(), PackerError> {
+        let byte_1st = nalu[0];
+        let mut left_nalu = nalu.slice(1..);
 
         let fu_indicator: u8 = (byte_1st & 0xE0) | define::FU_A;
         let mut fu_header: u8 = (byte_1st & 0x1F) | define::FU_START;
 
-        let mut left_nalu_bytes: usize = nalu_reader.len();
+        let mut left_nalu_bytes: usize = left_nalu.len();
         let mut fu_payload_len: usize;
 
         while left_nalu_bytes > 0 {
@@ -70,7 +70,7 @@ impl RtpH264Packer {
                 fu_payload_len = self.mtu - define::RTP_FIXED_HEADER_LEN - 2;
             }
 
-            let fu_payload = nalu_reader.read_bytes(fu_payload_len)?;
+            let fu_payload = left_nalu.split_to(fu_payload_len);
 
             let mut packet = RtpPacket::new(self.header.clone());
             packet.payload.put_u8(fu_indicator);
@@ -92,13 +92,13 @@ impl RtpH264Packer {
                 f(self.io.clone(), packet).await?;
             }
 
-            left_nalu_bytes = nalu_reader.len();
+            left_nalu_bytes = left_nalu.len();
             self.header.seq_number += 1;
         }
 
         Ok(())
     }
-    pub async fn pack_single(&mut self, nalu: BytesMut) -> Result<(), PackerError> {
+    pub async fn pack_single(&mut self, nalu: Bytes) -> Result<(), PackerError> {
         let mut packet = RtpPacket::new(self.header.clone());
         packet.header.marker = 1;
         packet.payload.put(nalu);
@@ -121,7 +121,7 @@ impl RtpH264Packer {
 #[async_trait]
 impl TPacker for RtpH264Packer {
     //pack annexb h264 data
-    async fn pack(&mut self, nalus: &mut BytesMut, timestamp: u32) -> Result<(), PackerError> {
+    async fn pack(&mut self, nalus: Bytes, timestamp: u32) -> Result<(), PackerError> {
         self.header.timestamp = timestamp; // ((timestamp as u64 * self.clock_rate as u64) / 1000) as u32;
         utils::split_annexb_and_process(nalus, self).await?;
         Ok(())
@@ -140,7 +140,7 @@ impl TRtpReceiverForRtcp for RtpH264Packer {
 
 #[async_trait]
 impl TVideoPacker for RtpH264Packer {
-    async fn pack_nalu(&mut self, nalu: BytesMut) -> Result<(), PackerError> {
+    async fn pack_nalu(&mut self, nalu: Bytes) -> Result<(), PackerError> {
         if nalu.len() + define::RTP_FIXED_HEADER_LEN <= self.mtu {
             self.pack_single(nalu).await?;
         } else {
@@ -216,7 +216,7 @@ impl RtpH264UnPacker {
 
             f(FrameData::Video {
                 timestamp: self.timestamp,
-                data: annexb_payload,
+                data: annexb_payload.freeze(),
             })?;
         }
         Ok(())
@@ -291,7 +291,7 @@ impl RtpH264UnPacker {
             if let Some(f) = &self.on_frame_handler {
                 f(FrameData::Video {
                     timestamp: self.timestamp,
-                    data: payload,
+                    data: payload.freeze(),
                 })?;
             }
         }
@@ -366,7 +366,7 @@ impl RtpH264UnPacker {
             if let Some(f) = &self.on_frame_handler {
                 f(FrameData::Video {
                     timestamp: self.timestamp,
-                    data: payload,
+                    data: payload.freeze(),
                 })?;
             }
         }
@@ -460,7 +460,7 @@ impl RtpH264UnPacker {
             if let Some(f) = &self.on_frame_handler {
                 f(FrameData::Video {
                     timestamp: self.timestamp,
-                    data: payload,
+                    data: payload.freeze(),
                 })?;
             }
         }
@@ -14,7 +14,7 @@ use super::RtpHeader;
 use super::RtpPacket;
 use async_trait::async_trait;
 use byteorder::BigEndian;
-use bytes::{BufMut, BytesMut};
+use bytes::{BufMut, Bytes, BytesMut};
 use bytesio::bytes_reader::BytesReader;
 use bytesio::bytesio::TNetIO;
 use std::sync::Arc;
@@ -52,8 +52,7 @@ impl RtpH265Packer {
         }
     }
 
-    pub async fn pack_fu(&mut self, nalu: BytesMut) -> Result<(), PackerError> {
-        let mut nalu_reader = BytesReader::new(nalu);
+    pub async fn pack_fu(&mut self, nalu: Bytes) -> Result<(), PackerError> {
         /* NALU header
         0               1
         0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5
@@ -66,8 +65,9 @@ impl RtpH265Packer {
         NUH layer ID(LayerId) : 6 bits
         NUH temporal ID plus 1 (TID) : 3 bits
         */
-        let nalu_header_1st_byte = nalu_reader.read_u8()?;
-        let nalu_header_2nd_byte = nalu_reader.read_u8()?;
+        let nalu_header_1st_byte = nalu[0];
+        let nalu_header_2nd_byte = nalu[1];
+        let mut left_nalu = nalu.slice(2..);
 
         /* The PayloadHdr needs replace Type with the FU type value(49) */
         let payload_hdr: u16 = ((nalu_header_1st_byte as u16 & 0x81) | ((define::FU as u16) << 1))
@@ -83,7 +83,7 @@ impl RtpH265Packer {
         /*set FuType from NALU header's Type */
         let mut fu_header = (nalu_header_1st_byte >> 1) & 0x3F | define::FU_START;
 
-        let mut left_nalu_bytes: usize = nalu_reader.len();
+        let mut left_nalu_bytes: usize = left_nalu.len();
         let mut fu_payload_len: usize;
 
         while left_nalu_bytes > 0 {
@@ -95,7 +95,7 @@ impl RtpH265Packer {
                 fu_payload_len = self.mtu - define::RTP_FIXED_HEADER_LEN - 3;
             }
 
-            let fu_payload = nalu_reader.read_bytes(fu_payload_len)?;
+            let fu_payload = left_nalu.split_to(fu_payload_len);
 
             let mut packet = RtpPacket::new(self.header.clone());
             packet.payload.put_u16(payload_hdr);
@@ -114,13 +114,13 @@ impl RtpH265Packer {
             if let Some(f) = &self.on_packet_handler {
                 f(self.io.clone(), packet).await?;
             }
-            left_nalu_bytes = nalu_reader.len();
+            left_nalu_bytes = left_nalu.len();
             self.header.seq_number += 1;
         }
 
         Ok(())
     }
-    pub async fn pack_single(&mut self, nalu: BytesMut) -> Result<(), PackerError> {
+    pub async fn pack_single(&mut self, nalu: Bytes) -> Result<(), PackerError> {
         let mut packet = RtpPacket::new(self.header.clone());
         packet.header.marker = 1;
         packet.payload.put(nalu);
@@ -140,7 +140,7 @@ impl RtpH265Packer {
 
 #[async_trait]
 impl TPacker for RtpH265Packer {
-    async fn pack(&mut self, nalus: &mut BytesMut, timestamp: u32) -> Result<(), PackerError> {
+    async fn pack(&mut self, nalus: Bytes, timestamp: u32) -> Result<(), PackerError> {
         self.header.timestamp = timestamp;
         utils::split_annexb_and_process(nalus, self).await?;
         Ok(())
@@ -158,7 +158,7 @@ impl TRtpReceiverForRtcp for RtpH265Packer {
 
 #[async_trait]
 impl TVideoPacker for RtpH265Packer {
-    async fn pack_nalu(&mut self, nalu: BytesMut) -> Result<(), PackerError> {
+    async fn pack_nalu(&mut self, nalu: Bytes) -> Result<(), PackerError> {
         if nalu.len() + define::RTP_FIXED_HEADER_LEN <= self.mtu {
             self.pack_single(nalu).await?;
         } else {
@@ -227,7 +227,7 @@ impl RtpH265UnPacker {
         if let Some(f) = &self.on_frame_handler {
             f(FrameData::Video {
                 timestamp: self.timestamp,
-                data: annexb_payload,
+                data: annexb_payload.freeze(),
             })?;
         }
         Ok(())
@@ -279,7 +279,7 @@ impl RtpH265UnPacker {
             if let Some(f) = &self.on_frame_handler {
                 f(FrameData::Video {
                     timestamp: self.timestamp,
-                    data: payload,
+                    data: payload.freeze(),
                 })?;
             }
         }
@@ -348,7 +348,7 @@ impl RtpH265UnPacker {
             if let Some(f) = &self.on_frame_handler {
                 f(FrameData::Video {
                     timestamp: self.timestamp,
-                    data: payload,
+                    data: payload.freeze(),
                 })?;
             }
         }
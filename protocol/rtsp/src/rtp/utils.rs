@@ -3,7 +3,7 @@ use super::errors::PackerError;
 use super::errors::UnPackerError;
 use super::RtpPacket;
 use async_trait::async_trait;
-use bytes::BytesMut;
+use bytes::Bytes;
 use bytesio::bytes_reader::BytesReader;
 use bytesio::bytesio::TNetIO;
 use std::future::Future;
@@ -47,7 +47,7 @@ pub trait TRtpReceiverForRtcp {
 #[async_trait]
 pub trait TPacker: TRtpReceiverForRtcp + Send + Sync {
     /*Split frame to rtp packets and send out*/
-    async fn pack(&mut self, nalus: &mut BytesMut, timestamp: u32) -> Result<(), PackerError>;
+    async fn pack(&mut self, nalus: Bytes, timestamp: u32) -> Result<(), PackerError>;
     /*Call back function used for processing a rtp packet.*/
     fn on_packet_handler(&mut self, f: OnRtpPacketFn);
 }
@@ -55,7 +55,7 @@ pub trait TPacker: TRtpReceiverForRtcp + Send + Sync {
 #[async_trait]
 pub trait TVideoPacker: TPacker {
     /*pack one nalu to rtp packets*/
-    async fn pack_nalu(&mut self, nalu: BytesMut) -> Result<(), PackerError>;
+    async fn pack_nalu(&mut self, nalu: Bytes) -> Result<(), PackerError>;
 }
 
 #[async_trait]
@@ -80,7 +80,7 @@ pub fn find_start_code(nalus: &[u8]) -> Option<usize> {
 }
 
 pub async fn split_annexb_and_process<T: TVideoPacker>(
-    nalus: &mut BytesMut,
+    mut nalus: Bytes,
     packer: &mut T,
 ) -> Result<(), PackerError> {
     while !nalus.is_empty() {
@@ -25,7 +25,7 @@ use super::rtsp_codec::RtspCodecInfo;
 use crate::rtp::utils::Marshal;
 use crate::rtp::utils::Unmarshal;
 use byteorder::BigEndian;
-use bytes::BytesMut;
+use bytes::Bytes;
 use bytesio::bytes_errors::BytesWriteError;
 use bytesio::bytes_reader::BytesReader;
 use bytesio::bytes_writer::AsyncBytesWriter;
@@ -77,11 +77,7 @@ impl RtpChannel {
     }
 
     //Receive av frame from stream hub -> pack -> send out
-    pub async fn on_frame(
-        &mut self,
-        nalus: &mut BytesMut,
-        timestamp: u32,
-    ) -> Result<(), PackerError> {
+    pub async fn on_frame(&mut self, nalus: Bytes, timestamp: u32) -> Result<(), PackerError> {
         if let Some(packer) = &mut self.rtp_packer {
             return packer.pack(nalus, timestamp).await;
         }
@@ -105,8 +101,8 @@ impl RtpChannel {
 
     //Set handler for processing received AV rtp packet from network
     pub fn on_packet_for_rtcp_handler(&mut self, f: OnRtpPacketFn2) {
-        if let Some(packer) = &mut self.rtp_packer {
-            packer.on_packet_for_rtcp_handler(f);
+        if let Some(unpacker) = &mut self.rtp_unpacker {
+            unpacker.on_packet_for_rtcp_handler(f);
         }
     }
 }
@@ -1,11 +1,15 @@
 use super::rtsp_channel::RtcpChannel;
 use super::rtsp_channel::RtpChannel;
 use super::rtsp_codec::RtspCodecInfo;
+use super::rtsp_transport::ProtocolType;
 use super::rtsp_transport::RtspTransport;
 use crate::rtp::errors::UnPackerError;
 use crate::rtsp_channel::TRtpFunc;
+use byteorder::BigEndian;
 use bytes::BytesMut;
+use bytesio::bytes_errors::BytesWriteError;
 use bytesio::bytes_reader::BytesReader;
+use bytesio::bytes_writer::AsyncBytesWriter;
 use bytesio::bytesio::TNetIO;
 use std::sync::Arc;
 use tokio::sync::Mutex;
@@ -38,6 +42,8 @@ pub struct RtspTrack {
 
     pub rtp_channel: Arc<Mutex<RtpChannel>>,
     pub rtcp_channel: Arc<Mutex<RtcpChannel>>,
+    //the network connection which the rtp packets of a player session are sent over
+    rtp_io: Option<Arc<Mutex<Box<dyn TNetIO + Send + Sync>>>>,
 }
 
 impl RtspTrack {
@@ -51,6 +57,7 @@ impl RtspTrack {
             uri: String::default(),
             rtp_channel: Arc::new(Mutex::new(rtp_channel)),
             rtcp_channel: Arc::new(Mutex::default()),
+            rtp_io: None,
         }
     }
 
@@ -124,6 +131,30 @@ impl RtspTrack {
     }
 
     pub async fn create_packer(&mut self, io: Arc<Mutex<Box<dyn TNetIO + Send + Sync>>>) {
+        self.rtp_io = Some(io.clone());
         self.rtp_channel.lock().await.create_packer(io);
     }
+
+    //Send a rtp packet received from the stream hub as it is, over the TCP it is
+    //interleaved with the rtsp signaling data.
+    pub async fn send_rtp_packet(&self, packet: &[u8]) -> Result<(), BytesWriteError> {
+        let Some(io) = &self.rtp_io else {
+            return Ok(());
+        };
+        let mut bytes_writer = AsyncBytesWriter::new(io.clone());
+        if let ProtocolType::TCP = self.transport.protocol_type {
+            let channel_identifier = match self.transport.interleaved {
+                Some(interleaveds) => interleaveds[0],
+                None => {
+                    log::error!("send_rtp_packet: no interleaved channel");
+                    0
+                }
+            };
+            bytes_writer.write_u8(0x24)?;
+            bytes_writer.write_u8(channel_identifier)?;
+            bytes_writer.write_u16::<BigEndian>(packet.len() as u16)?;
+        }
+        bytes_writer.write(packet)?;
+        bytes_writer.flush().await
+    }
 }
@@ -15,6 +15,7 @@ use commonlib::http::Unmarshal as RtspUnmarshal;
 use commonlib::http::Uri;
 use streamhub::define::SubscriberInfo;
 
+use crate::rtp::utils::Marshal as RtpMarshal;
 use crate::rtp::RtpPacket;
 
 use crate::rtsp_codec::RtspCodecInfo;
@@ -49,9 +50,10 @@ use super::define::USER_AGENT;
 
 use streamhub::{
     define::{
-        FrameData, NotifyInfo, PublishType, PublisherInfo, StreamHubEvent, StreamHubEventSender,
-        SubscribeType,
+        FrameData, NotifyInfo, PacketData, PublishType, PublisherInfo, StreamHubEvent,
+        StreamHubEventSender, SubscribeType,
     },
+    errors::{StreamHubError, StreamHubErrorValue},
     stream::StreamIdentifier,
     utils::{RandomDigitCount, Uuid},
 };
@@ -365,6 +367,7 @@ impl RtspClientSession {
             id,
             sub_type: SubscribeType::RtspRelay,
             sub_data_type: streamhub::define::SubDataType::Frame,
+            track_filter: streamhub::define::TrackFilter::All,
             notify_info: NotifyInfo {
                 request_url: String::from(""),
                 remote_addr: String::from(""),
@@ -382,7 +385,7 @@ impl RtspClientSession {
         PublisherInfo {
             id,
             pub_type: PublishType::RtspRelay,
-            pub_data_type: streamhub::define::PubDataType::Frame,
+            pub_data_type: streamhub::define::PubDataType::Both,
             notify_info: NotifyInfo {
                 request_url: String::from(""),
                 remote_addr: String::from(""),
@@ -527,10 +530,26 @@ impl RtspClientSession {
                             });
                         }
 
-                        let sender = event_result_receiver.await??.0.unwrap();
+                        let (Some(sender), Some(packet_sender), _) =
+                            event_result_receiver.await??
+                        else {
+                            return Err(SessionError {
+                                value: SessionErrorValue::ChannelError(StreamHubError {
+                                    value: StreamHubErrorValue::NotCorrectDataSenderType,
+                                }),
+                            });
+                        };
+                        let media_info = FrameData::MediaInfo {
+                            media_info: self.stream_handler.get_media_info().await,
+                        };
+                        if let Err(err) = sender.send(media_info) {
+                            log::error!("send media info error: {}", err);
+                        }
 
                         for track in self.tracks.values_mut() {
                             let sender_out = sender.clone();
+                            let packet_sender_out = packet_sender.clone();
+                            let track_type = track.track_type.clone();
 
                             let mut rtp_channel_guard = track.rtp_channel.lock().await;
                             rtp_channel_guard.on_frame_handler(Box::new(
@@ -545,6 +564,27 @@ impl RtspClientSession {
                             let rtcp_channel = Arc::clone(&track.rtcp_channel);
                             rtp_channel_guard.on_packet_for_rtcp_handler(Box::new(
                                 move |packet: RtpPacket| {
+                                    match packet.marshal() {
+                                        Ok(data) => {
+                                            let packet_data = match track_type {
+                                                TrackType::Video => PacketData::Video {
+                                                    timestamp: packet.header.timestamp,
+                                                    data: data.freeze(),
+                                                },
+                                                _ => PacketData::Audio {
+                                                    timestamp: packet.header.timestamp,
+                                                    data: data.freeze(),
+                                                },
+                                            };
+                                            if let Err(err) = packet_sender_out.send(packet_data) {
+                                                log::error!("send rtp packet error: {}", err);
+                                            }
+                                        }
+                                        Err(err) => {
+                                            log::error!("marshal rtp packet error: {}", err);
+                                        }
+                                    }
+
                                     let rtcp_channel_in = Arc::clone(&rtcp_channel);
                                     Box::pin(async move {
                                         rtcp_channel_in.lock().await.on_packet(packet);
@@ -53,8 +53,9 @@ use tokio::sync::mpsc;
 use commonlib::auth::Auth;
 use streamhub::{
     define::{
-        FrameData, Information, InformationSender, NotifyInfo, PublishType, PublisherInfo,
-        StreamHubEvent, StreamHubEventSender, SubscribeType, SubscriberInfo, TStreamHandler,
+        FrameData, Information, InformationSender, NotifyInfo, PacketData, PublishType,
+        PublisherInfo, StreamHubEvent, StreamHubEventSender, SubscribeType, SubscriberInfo,
+        TStreamHandler, TrackFilter,
     },
     errors::{StreamHubError, StreamHubErrorValue},
     statistics::StatisticsStream,
@@ -71,6 +72,8 @@ pub struct RtspServerSession {
 
     tracks: HashMap<TrackType, RtspTrack>,
     sdp: Sdp,
+    //the tracks selected by the query of the DESCRIBE url, e.g. rtsp://host/stream?only_audio=1
+    track_filter: TrackFilter,
     pub session_id: Option<Uuid>,
     pub session_type: define::ServerSessionType,
 
@@ -134,6 +137,7 @@ impl RtspServerSession {
             writer: AsyncBytesWriter::new(io),
             tracks: HashMap::new(),
             sdp: Sdp::default(),
+            track_filter: TrackFilter::All,
             session_id: None,
             session_type: define::ServerSessionType::Push,
             event_producer,
@@ -301,8 +305,18 @@ impl RtspServerSession {
             });
         }
 
+        if let Some(query) = &rtsp_request.uri.query {
+            self.track_filter = TrackFilter::from_request_url(&format!("?{query}"));
+        }
+
         if let Some(Information::Sdp { data }) = receiver.recv().await {
-            if let Some(sdp) = Sdp::unmarshal(&data) {
+            if let Some(mut sdp) = Sdp::unmarshal(&data) {
+                let track_filter = self.track_filter;
+                sdp.medias.retain(|media| match media.media_type.as_str() {
+                    "audio" => track_filter.has_audio(),
+                    "video" => track_filter.has_video(),
+                    _ => true,
+                });
                 self.sdp = sdp;
                 //it can new tracks when get the sdp information;
                 self.new_tracks()?;
@@ -364,10 +378,25 @@ impl RtspServerSession {
             });
         }
 
-        let sender = event_result_receiver.await??.0.unwrap();
+        let (Some(sender), Some(packet_sender), _) = event_result_receiver.await?? else {
+            return Err(SessionError {
+                value: SessionErrorValue::ChannelError(StreamHubError {
+                    value: StreamHubErrorValue::NotCorrectDataSenderType,
+                }),
+            });
+        };
+        //the rtp packets are parsed by the video codec of the media info in the stream hub
+        let media_info = FrameData::MediaInfo {
+            media_info: self.stream_handler.get_media_info().await,
+        };
+        if let Err(err) = sender.send(media_info) {
+            log::error!("send media info error: {}", err);
+        }
 
         for track in self.tracks.values_mut() {
             let sender_out = sender.clone();
+            let packet_sender_out = packet_sender.clone();
+            let track_type = track.track_type.clone();
             let mut rtp_channel_guard = track.rtp_channel.lock().await;
 
             rtp_channel_guard.on_frame_handler(Box::new(
@@ -381,6 +410,28 @@ impl RtspServerSession {
 
             let rtcp_channel = Arc::clone(&track.rtcp_channel);
             rtp_channel_guard.on_packet_for_rtcp_handler(Box::new(move |packet: RtpPacket| {
+                //the received rtp packets are forwarded to the subscribers on the packet path
+                match packet.marshal() {
+                    Ok(data) => {
+                        let packet_data = match track_type {
+                            TrackType::Video => PacketData::Video {
+                                timestamp: packet.header.timestamp,
+                                data: data.freeze(),
+                            },
+                            _ => PacketData::Audio {
+                                timestamp: packet.header.timestamp,
+                                data: data.freeze(),
+                            },
+                        };
+                        if let Err(err) = packet_sender_out.send(packet_data) {
+                            log::error!("send rtp packet error: {}", err);
+                        }
+                    }
+                    Err(err) => {
+                        log::error!("marshal rtp packet error: {}", err);
+                    }
+                }
+
                 let rtcp_channel_in = Arc::clone(&rtcp_channel);
                 Box::pin(async move {
                     rtcp_channel_in.lock().await.on_packet(packet);
@@ -496,51 +547,6 @@ impl RtspServerSession {
             )?;
         }
 
-        for track in self.tracks.values_mut() {
-            let protocol_type = track.transport.protocol_type.clone();
-
-            match protocol_type {
-                ProtocolType::TCP => {
-                    let channel_identifer = if let Some(interleaveds) = track.transport.interleaved
-                    {
-                        interleaveds[0]
-                    } else {
-                        log::error!("handle_play:should not be here!!!");
-                        0
-                    };
-
-                    track.rtp_channel.lock().await.on_packet_handler(Box::new(
-                        move |io: Arc<Mutex<Box<dyn TNetIO + Send + Sync>>>, packet: RtpPacket| {
-                            Box::pin(async move {
-                                let msg = packet.marshal()?;
-                                let mut bytes_writer = AsyncBytesWriter::new(io);
-                                bytes_writer.write_u8(0x24)?;
-                                bytes_writer.write_u8(channel_identifer)?;
-                                bytes_writer.write_u16::<BigEndian>(msg.len() as u16)?;
-                                bytes_writer.write(&msg)?;
-                                bytes_writer.flush().await?;
-                                Ok(())
-                            })
-                        },
-                    ));
-                }
-                ProtocolType::UDP => {
-                    track.rtp_channel.lock().await.on_packet_handler(Box::new(
-                        move |io: Arc<Mutex<Box<dyn TNetIO + Send + Sync>>>, packet: RtpPacket| {
-                            Box::pin(async move {
-                                let mut bytes_writer = AsyncBytesWriter::new(io);
-
-                                let msg = packet.marshal()?;
-                                bytes_writer.write(&msg)?;
-                                bytes_writer.flush().await?;
-                                Ok(())
-                            })
-                        },
-                    ));
-                }
-            }
-        }
-
         let status_code = http::StatusCode::OK;
         let response = Self::gen_response(status_code, rtsp_request);
 
@@ -564,39 +570,23 @@ impl RtspServerSession {
             });
         }
 
-        let mut receiver = event_result_receiver.await??.0.frame_receiver.unwrap();
+        let Some(mut receiver) = event_result_receiver.await??.0.packet_receiver else {
+            return Err(SessionError {
+                value: SessionErrorValue::CannotReceiveFrameData,
+            });
+        };
 
+        //the rtp packets of the publisher are sent as they are, the player starts
+        //with the cached packets of the last key frame group.
         let mut retry_times = 0;
         loop {
-            if let Some(frame_data) = receiver.recv().await {
-                match frame_data {
-                    FrameData::Audio {
-                        timestamp,
-                        mut data,
-                    } => {
-                        if let Some(audio_track) = self.tracks.get_mut(&TrackType::Audio) {
-                            audio_track
-                                .rtp_channel
-                                .lock()
-                                .await
-                                .on_frame(&mut data, timestamp)
-                                .await?;
-                        }
-                    }
-                    FrameData::Video {
-                        timestamp,
-                        mut data,
-                    } => {
-                        if let Some(video_track) = self.tracks.get_mut(&TrackType::Video) {
-                            video_track
-                                .rtp_channel
-                                .lock()
-                                .await
-                                .on_frame(&mut data, timestamp)
-                                .await?;
-                        }
-                    }
-                    _ => {}
+            if let Some(packet_data) = receiver.recv().await {
+                let (track_type, data) = match packet_data {
+                    PacketData::Video { timestamp: _, data } => (TrackType::Video, data),
+                    PacketData::Audio { timestamp: _, data } => (TrackType::Audio, data),
+                };
+                if let Some(track) = self.tracks.get(&track_type) {
+                    track.send_rtp_packet(&data).await?;
                 }
             } else {
                 retry_times += 1;
@@ -756,7 +746,8 @@ impl RtspServerSession {
         SubscriberInfo {
             id,
             sub_type: SubscribeType::RtspPull,
-            sub_data_type: streamhub::define::SubDataType::Frame,
+            sub_data_type: streamhub::define::SubDataType::Packet,
+            track_filter: self.track_filter,
             notify_info: NotifyInfo {
                 request_url: String::from(""),
                 remote_addr: String::from(""),
@@ -774,7 +765,7 @@ impl RtspServerSession {
         PublisherInfo {
             id,
             pub_type: PublishType::RtspPush,
-            pub_data_type: streamhub::define::PubDataType::Frame,
+            pub_data_type: streamhub::define::PubDataType::Both,
             notify_info: NotifyInfo {
                 request_url: String::from(""),
                 remote_addr: String::from(""),
@@ -804,6 +795,27 @@ impl RtspStreamHandler {
     pub async fn set_sdp(&self, sdp: Sdp) {
         *self.sdp.lock().await = sdp;
     }
+
+    pub async fn get_media_info(&self) -> MediaInfo {
+        Self::media_info(&*self.sdp.lock().await)
+    }
+
+    fn media_info(sdp: &Sdp) -> MediaInfo {
+        let mut media_info = MediaInfo {
+            audio_clock_rate: 0,
+            video_clock_rate: 0,
+            vcodec: VideoCodecType::H264,
+        };
+        for media in &sdp.medias {
+            match &media.fmtp {
+                Some(Fmtp::H264(_)) => media_info.video_clock_rate = media.rtpmap.clock_rate,
+                Some(Fmtp::H265(_)) => media_info.vcodec = VideoCodecType::H265,
+                Some(Fmtp::Mpeg4(_)) => media_info.audio_clock_rate = media.rtpmap.clock_rate,
+                None => {}
+            }
+        }
+        media_info
+    }
 }
 
 #[async_trait]
@@ -815,20 +827,14 @@ impl TStreamHandler for RtspStreamHandler {
     ) -> Result<(), StreamHubError> {
         let sender = match data_sender {
             DataSender::Frame { sender } => sender,
+            //the parameter sets of the packet subscribers are in the sdp
             DataSender::Packet { sender: _ } => {
-                return Err(StreamHubError {
-                    value: StreamHubErrorValue::NotCorrectDataSenderType,
-                });
+                return Ok(());
             }
         };
         match sub_type {
             SubscribeType::RtspRemux2Rtmp => {
                 let sdp_info = self.sdp.lock().await;
-                let mut video_clock_rate: u32 = 0;
-                let mut audio_clock_rate: u32 = 0;
-
-                let mut vcodec: VideoCodecType = VideoCodecType::H264;
-
                 for media in &sdp_info.medias {
                     let mut bytes_writer = BytesWriter::new();
                     if let Some(fmtp) = &media.fmtp {
@@ -841,12 +847,11 @@ impl TStreamHandler for RtspStreamHandler {
 
                                 let frame_data = FrameData::Video {
                                     timestamp: 0,
-                                    data: bytes_writer.extract_current_bytes(),
+                                    data: bytes_writer.extract_current_bytes().freeze(),
                                 };
-                                if let Err(err) = sender.send(frame_data) {
+                                if let Err(err) = sender.try_send(frame_data) {
                                     log::error!("send sps/pps error: {}", err);
                                 }
-                                video_clock_rate = media.rtpmap.clock_rate;
                             }
                             Fmtp::H265(data) => {
                                 bytes_writer.write(&ANNEXB_NALU_START_CODE)?;
@@ -858,37 +863,28 @@ impl TStreamHandler for RtspStreamHandler {
 
                                 let frame_data = FrameData::Video {
                                     timestamp: 0,
-                                    data: bytes_writer.extract_current_bytes(),
+                                    data: bytes_writer.extract_current_bytes().freeze(),
                                 };
-                                if let Err(err) = sender.send(frame_data) {
+                                if let Err(err) = sender.try_send(frame_data) {
                                     log::error!("send sps/pps/vps error: {}", err);
                                 }
-
-                                vcodec = VideoCodecType::H265;
                             }
                             Fmtp::Mpeg4(data) => {
                                 let frame_data = FrameData::Audio {
                                     timestamp: 0,
-                                    data: data.asc.clone(),
+                                    data: data.asc.clone().freeze(),
                                 };
 
-                                if let Err(err) = sender.send(frame_data) {
+                                if let Err(err) = sender.try_send(frame_data) {
                                     log::error!("send asc error: {}", err);
                                 }
-
-                                audio_clock_rate = media.rtpmap.clock_rate;
                             }
                         }
                     }
                 }
 
-                if let Err(err) = sender.send(FrameData::MediaInfo {
-                    media_info: MediaInfo {
-                        audio_clock_rate,
-                        video_clock_rate,
-
-                        vcodec,
-                    },
+                if let Err(err) = sender.try_send(FrameData::MediaInfo {
+                    media_info: Self::media_info(&sdp_info),
                 }) {
                     log::error!("send media info error: {}", err);
                 }
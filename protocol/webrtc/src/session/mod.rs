@@ -2,7 +2,7 @@ pub mod errors;
 use streamhub::{
     define::{
         DataSender, InformationSender, NotifyInfo, PublishType, PublisherInfo, StreamHubEvent,
-        StreamHubEventSender, SubscribeType, SubscriberInfo, TStreamHandler,
+        StreamHubEventSender, SubscribeType, SubscriberInfo, TStreamHandler, TrackFilter,
     },
     errors::StreamHubError,
     statistics::StatisticsStream,
@@ -374,7 +374,9 @@ impl WebRTCServerSession {
         path: String,
         offer: RTCSessionDescription,
     ) -> Result<(), SessionError> {
-        let subscriber_info = self.get_subscriber_info();
+        let mut subscriber_info = self.get_subscriber_info();
+        //e.g. /whep?app=live&stream=test&only_audio=1
+        subscriber_info.track_filter = TrackFilter::from_request_url(&path);
 
         let (event_result_sender, event_result_receiver) = oneshot::channel();
 
@@ -397,7 +399,14 @@ impl WebRTCServerSession {
 
         let (pc_state_sender, mut pc_state_receiver) = broadcast::channel(1);
 
-        let response = match handle_whep(offer, receiver, pc_state_sender).await {
+        let response = match handle_whep(
+            offer,
+            receiver,
+            pc_state_sender,
+            subscriber_info.track_filter,
+        )
+        .await
+        {
             Ok((session_description, peer_connection)) => {
                 let pc_clone = peer_connection.clone();
 
@@ -490,6 +499,7 @@ impl WebRTCServerSession {
             id,
             sub_type: SubscribeType::WhepPull,
             sub_data_type: streamhub::define::SubDataType::Packet,
+            track_filter: TrackFilter::All,
             notify_info: NotifyInfo {
                 request_url: String::from(""),
                 remote_addr: String::from(""),
@@ -3,7 +3,8 @@ use super::errors::WebRTCErrorValue;
 
 use std::sync::Arc;
 use streamhub::define::PacketData;
-use streamhub::define::PacketDataReceiver;
+use streamhub::define::SubPacketDataReceiver;
+use streamhub::define::TrackFilter;
 
 use webrtc::api::interceptor_registry::register_default_interceptors;
 use webrtc::api::media_engine::{MediaEngine, MIME_TYPE_H264, MIME_TYPE_OPUS};
@@ -27,8 +28,9 @@ use webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState;
 
 pub async fn handle_whep(
     offer: RTCSessionDescription,
-    mut receiver: PacketDataReceiver,
+    mut receiver: SubPacketDataReceiver,
     state_sender: broadcast::Sender<RTCPeerConnectionState>,
+    track_filter: TrackFilter,
 ) -> Result<(RTCSessionDescription, Arc<RTCPeerConnection>)> {
     // Everything below is the WebRTC-rs API! Thanks for using it ❤️.
 
@@ -84,23 +86,28 @@ pub async fn handle_whep(
         "webrtc-rs".to_owned(),
     ));
 
-    // Add this newly created track to the PeerConnection
-    let rtp_sender = peer_connection
-        .add_track(Arc::clone(&video_track) as Arc<dyn TrackLocal + Send + Sync>)
-        .await?;
-
-    let _ = peer_connection
-        .add_track(Arc::clone(&audio_track) as Arc<dyn TrackLocal + Send + Sync>)
-        .await?;
+    // Add the tracks selected by the subscriber to the PeerConnection, the media
+    // sections of the others are answered without a track.
+    let mut tracks: Vec<Arc<dyn TrackLocal + Send + Sync>> = Vec::new();
+    if track_filter.has_video() {
+        tracks.push(Arc::clone(&video_track) as Arc<dyn TrackLocal + Send + Sync>);
+    }
+    if track_filter.has_audio() {
+        tracks.push(Arc::clone(&audio_track) as Arc<dyn TrackLocal + Send + Sync>);
+    }
 
-    // Read incoming RTCP packets
-    // Before these packets are returned they are processed by interceptors. For things
-    // like NACK this needs to be called.
-    tokio::spawn(async move {
-        let mut rtcp_buf = vec![0u8; 1500];
-        while let Ok((_, _)) = rtp_sender.read(&mut rtcp_buf).await {}
-        Result::<()>::Ok(())
-    });
+    for track in tracks {
+        let rtp_sender = peer_connection.add_track(track).await?;
+
+        // Read incoming RTCP packets
+        // Before these packets are returned they are processed by interceptors. For things
+        // like NACK this needs to be called.
+        tokio::spawn(async move {
+            let mut rtcp_buf = vec![0u8; 1500];
+            while let Ok((_, _)) = rtp_sender.read(&mut rtcp_buf).await {}
+            Result::<()>::Ok(())
+        });
+    }
 
     // Set the handler for ICE connection state
     // This will notify you when the peer has connected/disconnected
@@ -2,7 +2,7 @@ use crate::opus2aac::Opus2AacTranscoder;
 
 use super::errors::WebRTCError;
 use super::errors::WebRTCErrorValue;
-use bytes::BytesMut;
+use bytes::Bytes;
 use std::sync::Arc;
 use streamhub::define::VideoCodecType;
 use streamhub::define::{FrameData, PacketData};
@@ -263,7 +263,7 @@ pub async fn handle_whip(
                     nal_payload_type::H264 => {
                         let video_packet = PacketData::Video {
                             timestamp: rtp_packet.header.timestamp,
-                            data: BytesMut::from(&b[..n]),
+                            data: Bytes::copy_from_slice(&b[..n]),
                         };
                         if let Err(err) = packet_sender_clone.send(video_packet) {
                             log::error!("send video packet error: {}", err);
@@ -275,13 +275,12 @@ pub async fn handle_whip(
                             match h264_packet.depacketize(&rtp_packet_ordered.payload) {
                                 Ok(rv) => {
                                     if !rv.is_empty() {
-                                        let byte_array = rv.to_vec();
-                                        let nal_type = byte_array[4] & 0x1F;
+                                        let nal_type = rv[4] & 0x1F;
 
                                         if nal_type != 0x0C {
                                             let video_frame = FrameData::Video {
                                                 timestamp: rtp_packet_ordered.header.timestamp,
-                                                data: BytesMut::from(&byte_array[..]),
+                                                data: rv,
                                             };
 
                                             if let Err(err) = frame_sender_clone.send(video_frame) {
@@ -307,7 +306,7 @@ pub async fn handle_whip(
                     nal_payload_type::OPUS => {
                         let audio_packet = PacketData::Audio {
                             timestamp: rtp_packet.header.timestamp,
-                            data: BytesMut::from(&b[..n]),
+                            data: Bytes::copy_from_slice(&b[..n]),
                         };
                         if let Err(err) = packet_sender_clone.send(audio_packet) {
                             log::error!("send audio packet error: {}", err);
@@ -318,7 +317,7 @@ pub async fn handle_whip(
                                 if let Ok(asc) = aac.gen_audio_specific_config() {
                                     let audio_frame = FrameData::Audio {
                                         timestamp: 0,
-                                        data: asc,
+                                        data: asc.freeze(),
                                     };
                                     if let Err(err) = frame_sender_clone.send(audio_frame) {
                                         log::error!("send audio frame error: {}", err);
@@ -338,7 +337,7 @@ pub async fn handle_whip(
                                             for data_val in data {
                                                 let audio_frame = FrameData::Audio {
                                                     timestamp: rtp_packet.header.timestamp,
-                                                    data: BytesMut::from(&data_val[..]),
+                                                    data: Bytes::copy_from_slice(&data_val[..]),
                                                 };
 
                                                 if let Err(err) =